@@ -15,11 +15,16 @@ extern crate sdl2;
 extern crate log;
 #[macro_use]
 extern crate lazy_static;
-extern crate simple_logger;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+#[cfg(feature = "discord")]
+extern crate discord_rpc_client;
+#[cfg(feature = "online")]
+extern crate ureq;
 
 mod rusty_kong;
 
 fn main() {
-    simple_logger::init().unwrap();
+    rusty_kong::logging_init();
     rusty_kong::game_run();
 }
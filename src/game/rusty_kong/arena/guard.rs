@@ -0,0 +1,194 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+#[cfg(debug_assertions)]
+use std::alloc::GlobalAlloc;
+#[cfg(debug_assertions)]
+use std::alloc::Layout;
+#[cfg(debug_assertions)]
+use std::alloc::System;
+use std::cell::Cell;
+#[cfg(debug_assertions)]
+use std::mem;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+// --alloc-check, debug builds only: the system allocator with a count of
+// what the main thread asks of it while a steady frame is armed.  only
+// atomics and const thread locals in here -- anything that allocated
+// itself would come straight back round
+//
+// every block also carries the tag of the subsystem that asked for it, in
+// a header in front of it, so what's live can be told apart by subsystem
+// and a block freed elsewhere still comes off the right count
+#[cfg(debug_assertions)]
+pub struct CheckedAllocator;
+
+// what a block's charged to; whatever isn't inside memory_tagged() is Other
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryTag {
+    Other,
+    Game,
+    Sound,
+    Video,
+    Rewind,
+    Io,
+}
+
+pub const MEMORY_TAGS: [MemoryTag; 6] = [
+    MemoryTag::Other,
+    MemoryTag::Game,
+    MemoryTag::Sound,
+    MemoryTag::Video,
+    MemoryTag::Rewind,
+    MemoryTag::Io,
+];
+
+impl MemoryTag {
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            &MemoryTag::Other  => "other",
+            &MemoryTag::Game   => "game",
+            &MemoryTag::Sound  => "sound",
+            &MemoryTag::Video  => "video",
+            &MemoryTag::Rewind => "rewind",
+            &MemoryTag::Io     => "io",
+        }
+    }
+}
+
+#[cfg(debug_assertions)]
+#[global_allocator]
+static ALLOCATOR: CheckedAllocator = CheckedAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+// live bytes a tag, by MemoryTag as usize
+static LIVE_BYTES: [AtomicUsize; 6] = [
+    AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0),
+    AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0),
+];
+
+thread_local!(
+    static ARMED: Cell<bool> = const { Cell::new(false) };
+    static TAG: Cell<MemoryTag> = const { Cell::new(MemoryTag::Other) };
+);
+
+// the block with its header in front: the header's a word or the block's
+// alignment, whichever's more, so the block stays aligned, and the tag's
+// its last byte
+#[cfg(debug_assertions)]
+fn tagged_layout(layout: &Layout) -> Option<(Layout, usize)> {
+    let header = layout.align().max(mem::size_of::<usize>());
+    let size = layout.size().checked_add(header)?;
+    return Layout::from_size_align(size, layout.align()).ok().map(|outer| (outer, header));
+}
+
+#[cfg(debug_assertions)]
+unsafe fn tagged_block(base: *mut u8, header: usize, size: usize) -> *mut u8 {
+    if base.is_null() {
+        return base;
+    }
+    let tag = TAG.try_with(|tag| tag.get()).unwrap_or(MemoryTag::Other);
+    *base.add(header - 1) = tag as u8;
+    LIVE_BYTES[tag as usize].fetch_add(size, Ordering::Relaxed);
+    return base.add(header);
+}
+
+#[cfg(debug_assertions)]
+fn checked_note(size: usize) {
+    if ARMED.try_with(|armed| armed.get()).unwrap_or(false) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(size, Ordering::Relaxed);
+    }
+}
+
+#[cfg(debug_assertions)]
+unsafe impl GlobalAlloc for CheckedAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        checked_note(layout.size());
+        match tagged_layout(&layout) {
+            Some((outer, header)) => tagged_block(System.alloc(outer), header, layout.size()),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        checked_note(layout.size());
+        match tagged_layout(&layout) {
+            Some((outer, header)) => tagged_block(System.alloc_zeroed(outer), header, layout.size()),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    // the block keeps the tag it was allocated under
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        checked_note(new_size);
+        let (outer, header) =
+            match tagged_layout(&layout) {
+                Some(tagged) => tagged,
+                None => return std::ptr::null_mut(),
+            };
+        let new_outer =
+            match new_size.checked_add(header) {
+                Some(size) => size,
+                None => return std::ptr::null_mut(),
+            };
+        let base = System.realloc(ptr.sub(header), outer, new_outer);
+        if base.is_null() {
+            return base;
+        }
+        let tag = *base.add(header - 1) as usize;
+        LIVE_BYTES[tag].fetch_sub(layout.size(), Ordering::Relaxed);
+        LIVE_BYTES[tag].fetch_add(new_size, Ordering::Relaxed);
+        return base.add(header);
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some((outer, header)) = tagged_layout(&layout) {
+            let tag = *ptr.sub(1) as usize;
+            LIVE_BYTES[tag].fetch_sub(layout.size(), Ordering::Relaxed);
+            System.dealloc(ptr.sub(header), outer);
+        }
+    }
+}
+
+// the frame's steady stretch, the ticks and the render, goes between these
+pub fn alloc_check_arm(armed: bool) {
+    ARMED.with(|cell| cell.set(armed));
+}
+
+// (allocations, bytes) since the last take
+pub fn alloc_check_take() -> (usize, usize) {
+    (ALLOCATIONS.swap(0, Ordering::Relaxed), ALLOCATED_BYTES.swap(0, Ordering::Relaxed))
+}
+
+// runs f with what it allocates charged to tag, on this thread; nests
+pub fn memory_tagged<R, F: FnOnce() -> R>(tag: MemoryTag, f: F) -> R {
+    let outer = TAG.with(|cell| cell.replace(tag));
+    let result = f();
+    TAG.with(|cell| cell.set(outer));
+    return result;
+}
+
+// live heap bytes by tag, in MEMORY_TAGS order; all nothing in a release
+// build, which has the plain system allocator
+pub fn memory_usage() -> [(MemoryTag, usize); 6] {
+    let mut usage = [(MemoryTag::Other, 0); 6];
+    for (entry, &tag) in usage.iter_mut().zip(MEMORY_TAGS.iter()) {
+        *entry = (tag, LIVE_BYTES[tag as usize].load(Ordering::Relaxed));
+    }
+    return usage;
+}
+
+pub fn memory_live() -> usize {
+    memory_usage().iter().map(|&(_, bytes)| bytes).sum()
+}
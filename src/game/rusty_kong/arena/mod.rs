@@ -0,0 +1,178 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::any::Any;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+
+mod guard;
+pub use self::guard::*;
+
+// scratch buffers for what a tick or a frame builds and throws away
+// (collision candidates, draw lists, event fan out).  a buffer's handed
+// out cleared and taken back with its capacity, so once the first few
+// frames have grown them the loop stops going to the heap for them
+struct Arena {
+    // free buffers a type, each a Box<Vec<T>>
+    free: HashMap<TypeId, Vec<Box<dyn Any>>>,
+    // handed out and not back yet
+    out: usize,
+    // capacity held by the free buffers
+    bytes: usize,
+    // this frame's most at once, and the most since the run started
+    frame_peak: usize,
+    peak: usize,
+}
+
+thread_local!(
+    static ARENA:RefCell<Arena> = RefCell::new(Arena {
+        free: HashMap::new(),
+        out: 0,
+        bytes: 0,
+        frame_peak: 0,
+        peak: 0
+    });
+);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ArenaStats {
+    pub buffers: usize,
+    pub bytes: usize,
+    pub frame_peak: usize,
+    pub peak: usize,
+}
+
+fn arena_take<T: 'static>() -> Box<Vec<T>> {
+    ARENA.with(|cell| {
+        let mut arena = cell.borrow_mut();
+        arena.out += 1;
+        arena.frame_peak = arena.frame_peak.max(arena.out);
+        arena.peak = arena.peak.max(arena.out);
+        let free = arena.free.get_mut(&TypeId::of::<T>()).and_then(|free| free.pop());
+        match free.map(|buffer| buffer.downcast::<Vec<T>>()) {
+            Some(Ok(buffer)) => {
+                arena.bytes -= buffer.capacity() * mem::size_of::<T>();
+                buffer
+            },
+            _ => Box::new(Vec::new()),
+        }
+    })
+}
+
+fn arena_give<T: 'static>(mut buffer: Box<Vec<T>>) {
+    buffer.clear();
+    ARENA.with(|cell| {
+        let mut arena = cell.borrow_mut();
+        arena.out -= 1;
+        arena.bytes += buffer.capacity() * mem::size_of::<T>();
+        arena.free.entry(TypeId::of::<T>()).or_insert_with(Vec::new).push(buffer as Box<dyn Any>);
+    });
+}
+
+// an empty Vec<T> for the length of f; calls can nest, each gets its own
+pub fn arena_vec<T: 'static, R, F: FnOnce(&mut Vec<T>) -> R>(f: F) -> R {
+    let mut buffer = arena_take::<T>();
+    let result = f(&mut buffer);
+    arena_give(buffer);
+    return result;
+}
+
+// once a frame, with nothing handed out
+pub fn arena_frame_reset() {
+    ARENA.with(|cell| {
+        let mut arena = cell.borrow_mut();
+        if arena.out != 0 {
+            warn!("arena: {} buffers still out at the end of the frame", arena.out);
+            arena.out = 0;
+        }
+        arena.frame_peak = 0;
+    });
+}
+
+pub fn arena_stats() -> ArenaStats {
+    ARENA.with(|cell| {
+        let arena = cell.borrow();
+        ArenaStats {
+            buffers: arena.free.values().map(|free| free.len()).sum(),
+            bytes: arena.bytes,
+            frame_peak: arena.frame_peak,
+            peak: arena.peak,
+        }
+    })
+}
+
+// a stage's first second still loads in, so it isn't held to it
+const ALLOC_CHECK_WARMUP_TICKS: u32 = 60;
+// the first few offending frames are all logged, then one in this many
+const ALLOC_CHECK_LOGGED: u32 = 10;
+const ALLOC_CHECK_EVERY: u32 = 600;
+
+struct AllocCheck {
+    enabled: bool,
+    steady: u32,
+    flagged: u32,
+}
+
+thread_local!(
+    static ALLOC_CHECK:RefCell<AllocCheck> = RefCell::new(AllocCheck {
+        enabled: false,
+        steady: 0,
+        flagged: 0
+    });
+);
+
+pub fn alloc_check_enable() {
+    ALLOC_CHECK.with(|cell| {cell.borrow_mut().enabled = true;});
+    info!("alloc check: heap allocations in steady play are flagged");
+}
+
+// true when this frame's ticks & render count; arm around those with
+// alloc_check_arm(steady)
+pub fn alloc_check_steady() -> bool {
+    use super::state_machine::GameState;
+    use super::state_machine::game_state_current;
+    use super::state_machine::get_state_ticks;
+
+    ALLOC_CHECK.with(|cell| cell.borrow().enabled)
+        && game_state_current() == GameState::GamePlay
+        && get_state_ticks() >= ALLOC_CHECK_WARMUP_TICKS
+}
+
+pub fn alloc_check_frame_end(steady: bool) {
+    alloc_check_arm(false);
+    let (allocations, bytes) = alloc_check_take();
+    if !steady {
+        return;
+    }
+    ALLOC_CHECK.with(|cell| {
+        let mut check = cell.borrow_mut();
+        check.steady += 1;
+        if allocations == 0 {
+            return;
+        }
+        check.flagged += 1;
+        if check.flagged <= ALLOC_CHECK_LOGGED || check.flagged % ALLOC_CHECK_EVERY == 0 {
+            warn!("alloc check: {} heap allocations ({} bytes) in steady frame {} ({} flagged)",
+                allocations, bytes, check.steady, check.flagged);
+        }
+    });
+}
+
+pub fn alloc_check_finish() {
+    ALLOC_CHECK.with(|cell| {
+        let check = cell.borrow();
+        if check.enabled {
+            info!("alloc check: {} of {} steady frames went to the heap", check.flagged, check.steady);
+        }
+    });
+}
@@ -0,0 +1,130 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+
+// a couple of workers is all boot's handful of wavs & pngs can use, and
+// the main thread keeps a core to itself for the frame
+const DECODE_WORKERS_MAX: usize = 4;
+
+type DecodeJob = Box<dyn FnOnce() + Send>;
+
+// the jobs only ever decode bytes they were handed into plain buffers;
+// what they make goes back over the queuer's own channel, and handing it
+// to sdl, the mixer or the atlas stays on the main thread
+struct DecodePool {
+    jobs: mpsc::Sender<DecodeJob>,
+    workers: Vec<thread::JoinHandle<()>>,
+    queued: usize,
+    done: Arc<AtomicUsize>,
+}
+
+thread_local!(
+    static POOL:RefCell<Option<DecodePool>> = RefCell::new(None);
+);
+
+fn decode_workers() -> usize {
+    let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    return cores.saturating_sub(1).max(1).min(DECODE_WORKERS_MAX);
+}
+
+fn decode_pool_start() -> DecodePool {
+    let (sender, receiver) = mpsc::channel::<DecodeJob>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let done = Arc::new(AtomicUsize::new(0));
+    let count = decode_workers();
+    let workers = (0..count)
+        .filter_map(|index| {
+            let receiver = receiver.clone();
+            let done = done.clone();
+            thread::Builder::new()
+                .name(format!("decode-{}", index))
+                .spawn(move || loop {
+                    // the lock's only held for the recv, not the decode
+                    let job = match receiver.lock() {
+                        Ok(r) => r.recv(),
+                        Err(_) => return,
+                    };
+                    match job {
+                        Ok(job) => {
+                            // one bad file mustn't leave boot waiting on a
+                            // count that never arrives
+                            if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                                warn!("a decode job panicked");
+                            }
+                            done.fetch_add(1, Ordering::SeqCst);
+                        },
+                        Err(_) => return,
+                    }
+                })
+                .map_err(|e| warn!("decode worker: {}", e))
+                .ok()
+        })
+        .collect();
+    debug!("decoding on {} threads", count);
+    return DecodePool { jobs: sender, workers: workers, queued: 0, done: done };
+}
+
+// runs job on a worker, starting the pool if this is the first; with no
+// workers to be had it runs here and now
+pub fn decode_spawn<F: FnOnce() + Send + 'static>(job: F) {
+    POOL.with(|cell| {
+        let mut pool = cell.borrow_mut();
+        if pool.is_none() {
+            *pool = Some(decode_pool_start());
+        }
+        let pool = pool.as_mut().unwrap();
+        pool.queued += 1;
+        if pool.workers.is_empty() {
+            job();
+            pool.done.fetch_add(1, Ordering::SeqCst);
+            return;
+        }
+        if let Err(mpsc::SendError(job)) = pool.jobs.send(Box::new(job)) {
+            job();
+            pool.done.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+}
+
+// (finished, queued) since the pool started; (0, 0) with no pool
+pub fn decode_progress() -> (usize, usize) {
+    POOL.with(|cell| {
+        match *cell.borrow() {
+            Some(ref pool) => (pool.done.load(Ordering::SeqCst), pool.queued),
+            None => (0, 0),
+        }
+    })
+}
+
+pub fn decode_is_busy() -> bool {
+    let (done, queued) = decode_progress();
+    return done < queued;
+}
+
+// the workers finish what's queued and go
+pub fn decode_pool_stop() {
+    let pool = POOL.with(|cell| cell.borrow_mut().take());
+    if let Some(pool) = pool {
+        drop(pool.jobs);
+        for worker in pool.workers {
+            let _ = worker.join();
+        }
+    }
+}
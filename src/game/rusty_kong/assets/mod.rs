@@ -0,0 +1,220 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::fs;
+use std::fs::File;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Write;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::hashing::StateHasher;
+use super::settings::KeyValue;
+
+mod decode;
+pub use self::decode::*;
+mod pack;
+pub use self::pack::*;
+
+pub const ASSETS_DIR: &str = "assets";
+pub const MODS_DIR:   &str = "mods";
+
+// "relative/path = fnv1a64" for every file in the base pack
+pub const ASSET_CHECKSUMS: &str = "checksums.txt";
+
+// every file-backed asset is looked up through here with a path relative to
+// the base pack (e.g. "locales/fr.txt").  a mod overrides a file by shipping
+// the same relative path under mods/<name>/; enabled mods are searched last
+// to first, so later entries in the settings list win.
+pub fn asset_path(relative: &str) -> PathBuf {
+    use super::settings::settings_read;
+
+    let overridden = settings_read(|s| {
+        s.mods
+            .iter()
+            .rev()
+            .map(|name| (name.clone(), Path::new(MODS_DIR).join(name).join(relative)))
+            .find(|&(_, ref candidate)| candidate.is_file())
+    });
+
+    match overridden {
+        Some((name, candidate)) => {
+            debug!("{} overridden by mod \"{}\"", relative, name);
+            candidate
+        },
+        None => Path::new(ASSETS_DIR).join(relative),
+    }
+}
+
+// a file on disk, or failing that the same file out of the loaded pack
+// when the path is under the base pack's directory
+pub fn asset_open(path: &Path) -> Option<Box<dyn Read>> {
+    if let Ok(file) = File::open(path) {
+        return Some(Box::new(file));
+    }
+    let relative =
+        match path.strip_prefix(ASSETS_DIR) {
+            Ok(r) => r.to_string_lossy().replace('\\', "/"),
+            Err(_) => return None,
+        };
+    pack_file(&relative).map(|bytes| Box::new(Cursor::new(bytes)) as Box<dyn Read>)
+}
+
+pub fn asset_exists(relative: &str) -> bool {
+    asset_open(&asset_path(relative)).is_some()
+}
+
+pub fn assets_init() {
+    use super::settings::settings_get;
+
+    let enabled = settings_get().mods;
+    for name in enabled.iter() {
+        if Path::new(MODS_DIR).join(name).is_dir() {
+            info!("mod \"{}\" enabled", name);
+        } else {
+            warn!("mod \"{}\" is enabled but {}/{} doesn't exist", name, MODS_DIR, name);
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(MODS_DIR) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() && !enabled.contains(&name) {
+                info!("mod \"{}\" is installed but disabled", name);
+            }
+        }
+    }
+}
+
+fn asset_checksum(path: &Path) -> Option<u64> {
+    let mut bytes = Vec::new();
+    let read = asset_open(path).map(|mut file| file.read_to_end(&mut bytes));
+    match read {
+        Some(Ok(_)) => {
+            let mut hasher = StateHasher::new();
+            hasher.write(&bytes);
+            Some(hasher.finish())
+        },
+        _ => None,
+    }
+}
+
+// relative paths of every file under dir, sorted so the manifest is stable
+fn assets_list(dir: &Path, prefix: &str, files: &mut Vec<String>) {
+    let entries =
+        match fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(_) => return,
+        };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let relative = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+        if entry.path().is_dir() {
+            assets_list(&entry.path(), &relative, files);
+        } else if relative != ASSET_CHECKSUMS {
+            files.push(relative);
+        }
+    }
+    files.sort();
+}
+
+// regenerates the manifest from what's on disk; run after changing the pack
+pub fn assets_write_checksums() -> Result<usize, String> {
+    let mut files = Vec::new();
+    assets_list(Path::new(ASSETS_DIR), "", &mut files);
+
+    let path = Path::new(ASSETS_DIR).join(ASSET_CHECKSUMS);
+    let mut manifest = File::create(&path).map_err(|e| format!("can't create {}: {}", path.display(), e))?;
+    writeln!(manifest, "# written by --write-checksums, checked at boot").map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    for relative in files.iter() {
+        let checksum =
+            match asset_checksum(&Path::new(ASSETS_DIR).join(relative)) {
+                Some(c) => c,
+                None => return Err(format!("can't read {}/{}", ASSETS_DIR, relative)),
+            };
+        writeln!(manifest, "{} = {:016x}", relative, checksum).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    }
+    return Ok(files.len());
+}
+
+// checks the base pack against its manifest.  mods are layered on top and
+// aren't covered.  returns one line per problem, empty when the pack is good.
+pub fn assets_verify() -> Vec<String> {
+    let problems = assets_verify_dir(Path::new(ASSETS_DIR));
+    for problem in problems.iter() {
+        error!("asset check: {}", problem);
+    }
+    return problems;
+}
+
+// a relative path that stays where it's put: no "..", no root, not empty.
+// for names out of a mod's manifest or a downloaded pack
+pub fn asset_path_is_inside(name: &str) -> bool {
+    use std::path::Component;
+
+    !name.is_empty() && Path::new(name).components().all(|c| match c { Component::Normal(_) => true, _ => false })
+}
+
+// a manifest's (file, checksum) lines; the ones that aren't go into problems
+fn assets_manifest_parse(pairs: &[KeyValue], path: &Path, problems: &mut Vec<String>) -> Vec<(String, u64)> {
+    let mut entries = Vec::new();
+    for pair in pairs.iter() {
+        if !asset_path_is_inside(&pair.key) {
+            problems.push(format!("{}:{}: \"{}\" isn't a path inside the directory", path.display(), pair.line, pair.key));
+            continue;
+        }
+        match u64::from_str_radix(&pair.value, 16) {
+            Ok(checksum) => entries.push((pair.key.clone(), checksum)),
+            Err(_) => problems.push(format!("{}:{}: bad checksum \"{}\"", path.display(), pair.line, pair.value)),
+        }
+    }
+    return entries;
+}
+
+// the same check for any directory with a manifest in it
+pub fn assets_verify_dir(dir: &Path) -> Vec<String> {
+    use super::settings::read_key_value_file;
+
+    let path = dir.join(ASSET_CHECKSUMS);
+    let pairs =
+        match read_key_value_file(&path) {
+            Some(p) => p,
+            None => return vec![format!("{} is missing", path.display())],
+        };
+
+    let mut problems = Vec::new();
+    for (relative, expected) in assets_manifest_parse(&pairs, &path, &mut problems) {
+        match asset_checksum(&dir.join(&relative)) {
+            Some(actual) if actual == expected => {},
+            Some(_) => problems.push(format!("{} is damaged", relative)),
+            None => problems.push(format!("{} is missing", relative)),
+        }
+    }
+    return problems;
+}
+
+// fuzz target: a checksums.txt, parsed but not checked against anything
+pub fn assets_manifest_fuzz(bytes: &[u8]) {
+    use super::settings::key_value_parse;
+
+    let path = Path::new(ASSET_CHECKSUMS);
+    assets_manifest_parse(&key_value_parse(bytes, path), path, &mut Vec::new());
+}
+
+// every file under dir, relative to it
+pub fn assets_files(dir: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    assets_list(dir, "", &mut files);
+    return files;
+}
@@ -0,0 +1,327 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use super::*;
+
+// the whole base pack in one file, for builds that would rather download
+// one thing than a directory.  loose files under assets/ still win over it.
+pub const ASSETS_PACK: &str = "assets.rkpak";
+
+// "RKPAK", version, compression, then the unpacked size of everything after
+// the header as a little endian u64.  after the header, optionally through
+// zstd: a u16 name length, the name, a u32 size and the bytes, per file.
+const PACK_MAGIC:   &[u8; 5] = b"RKPAK";
+const PACK_VERSION: u8 = 1;
+const PACK_HEADER_SIZE: usize = 15;
+
+const PACK_STORED: u8 = 0;
+const PACK_ZSTD:   u8 = 1;
+#[cfg(feature = "zstd")]
+const PACK_ZSTD_LEVEL: i32 = 19;
+
+// no pack unpacks to more than this, so a header or a zstd stream that says
+// otherwise can't run the game out of memory
+const PACK_UNPACKED_MAX: u64 = 256 * 1024 * 1024;
+
+// how much of the unpacked stream boot gets through a tick
+pub const PACK_BYTES_PER_TICK: usize = 64 * 1024;
+
+struct PackStream {
+    source: Box<dyn Read>,
+    total: u64,
+    read: u64,
+    // unpacked bytes not yet split into files
+    pending: Vec<u8>,
+    files: HashMap<String, Vec<u8>>,
+}
+
+pub enum PackLoad {
+    // no pack open, or it's all in
+    Idle,
+    Loading { read: u64, total: u64 },
+    Done,
+}
+
+thread_local!(
+    static STREAM:RefCell<Option<PackStream>> = RefCell::new(None);
+    static PACKED:RefCell<Option<HashMap<String, Vec<u8>>>> = RefCell::new(None);
+);
+
+fn pack_header(compression: u8, total: u64) -> Vec<u8> {
+    let mut header = PACK_MAGIC.to_vec();
+    header.push(PACK_VERSION);
+    header.push(compression);
+    for shift in 0..8 {
+        header.push((total >> (shift * 8)) as u8);
+    }
+    return header;
+}
+
+fn little_endian(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |value, &byte| (value << 8) | byte as u64)
+}
+
+#[cfg(feature = "zstd")]
+fn pack_compress(body: &[u8], out: &mut File) -> Result<(), String> {
+    use zstd::stream::write::Encoder;
+
+    let mut encoder = Encoder::new(out, PACK_ZSTD_LEVEL).map_err(|e| e.to_string())?;
+    encoder.write_all(body).map_err(|e| e.to_string())?;
+    encoder.finish().map(|_| ()).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn pack_compress(_body: &[u8], _out: &mut File) -> Result<(), String> {
+    Err("this build has no zstd, rebuild with --features zstd".to_string())
+}
+
+#[cfg(feature = "zstd")]
+fn pack_decompress<R: Read + 'static>(source: R) -> Result<Box<dyn Read>, String> {
+    use zstd::stream::read::Decoder;
+    Decoder::new(source).map(|d| Box::new(d) as Box<dyn Read>).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn pack_decompress<R: Read + 'static>(_source: R) -> Result<Box<dyn Read>, String> {
+    Err("the pack is zstd compressed and this build has no zstd".to_string())
+}
+
+// packs every file under dir, its manifest included
+pub fn pack_write(dir: &Path, path: &Path, compress: bool) -> Result<usize, String> {
+    let mut files = assets_files(dir);
+    if dir.join(ASSET_CHECKSUMS).is_file() {
+        files.push(ASSET_CHECKSUMS.to_string());
+    }
+
+    let mut body = Vec::new();
+    for relative in files.iter() {
+        let mut bytes = Vec::new();
+        File::open(dir.join(relative))
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|e| format!("can't read {}/{}: {}", dir.display(), relative, e))?;
+        if relative.len() > u16::max_value() as usize || bytes.len() > u32::max_value() as usize {
+            return Err(format!("{} is too big to pack", relative));
+        }
+        for shift in 0..2 {
+            body.push((relative.len() >> (shift * 8)) as u8);
+        }
+        body.extend_from_slice(relative.as_bytes());
+        for shift in 0..4 {
+            body.push((bytes.len() >> (shift * 8)) as u8);
+        }
+        body.extend_from_slice(&bytes);
+    }
+
+    let compression = if compress { PACK_ZSTD } else { PACK_STORED };
+    let mut out = File::create(path).map_err(|e| format!("can't create {}: {}", path.display(), e))?;
+    out.write_all(&pack_header(compression, body.len() as u64)).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    if compress {
+        pack_compress(&body, &mut out).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    } else {
+        out.write_all(&body).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    }
+    return Ok(files.len());
+}
+
+// reads the header and leaves the rest for pack_load_step to stream in
+pub fn pack_open(path: &Path) -> Result<(), String> {
+    let mut file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let mut header = [0u8; PACK_HEADER_SIZE];
+    file.read_exact(&mut header).map_err(|_| format!("{} is too short to be a pack", path.display()))?;
+    if &header[0..5] != PACK_MAGIC || header[5] != PACK_VERSION {
+        return Err(format!("{} isn't a version {} pack", path.display(), PACK_VERSION));
+    }
+    let total = little_endian(&header[7..15]);
+    if total > PACK_UNPACKED_MAX {
+        return Err(format!("{} says it unpacks to {} bytes, over {}", path.display(), total, PACK_UNPACKED_MAX));
+    }
+    let source: Box<dyn Read> =
+        match header[6] {
+            PACK_STORED => Box::new(BufReader::new(file)),
+            PACK_ZSTD   => pack_decompress(file).map_err(|e| format!("{}: {}", path.display(), e))?,
+            other       => return Err(format!("{}: unknown compression {}", path.display(), other)),
+        };
+
+    info!("streaming {} ({} bytes unpacked)", path.display(), total);
+    STREAM.with(|cell| {
+        *cell.borrow_mut() = Some(PackStream {
+            source: source,
+            total: total,
+            read: 0,
+            pending: Vec::new(),
+            files: HashMap::new()
+        });
+    });
+    return Ok(());
+}
+
+// splits whole files off the front of what's been unpacked so far
+fn pack_split(stream: &mut PackStream) {
+    loop {
+        if stream.pending.len() < 2 {
+            return;
+        }
+        let name_len = little_endian(&stream.pending[0..2]) as usize;
+        if stream.pending.len() < 2 + name_len + 4 {
+            return;
+        }
+        let size = little_endian(&stream.pending[2 + name_len..6 + name_len]) as usize;
+        let start = 6 + name_len;
+        if stream.pending.len() < start + size {
+            return;
+        }
+        let name = String::from_utf8_lossy(&stream.pending[2..2 + name_len]).into_owned();
+        let bytes = stream.pending[start..start + size].to_vec();
+        stream.pending.drain(..start + size);
+        stream.files.insert(name, bytes);
+    }
+}
+
+// unpacks up to budget more bytes; once everything is in, the files are
+// what asset_open falls back on
+pub fn pack_load_step(budget: usize) -> Result<PackLoad, String> {
+    let finished = STREAM.with(|cell| -> Result<Option<usize>, String> {
+        let mut borrowed = cell.borrow_mut();
+        let stream =
+            match *borrowed {
+                Some(ref mut s) => s,
+                None => return Ok(None),
+            };
+
+        let mut chunk = vec![0u8; budget];
+        let count = stream.source.read(&mut chunk).map_err(|e| format!("{}: {}", ASSETS_PACK, e))?;
+        stream.read += count as u64;
+        stream.pending.extend_from_slice(&chunk[..count]);
+        pack_split(stream);
+
+        if count == 0 || stream.read >= stream.total {
+            if stream.read != stream.total || !stream.pending.is_empty() {
+                return Err(format!("{} is cut short ({} of {} bytes)", ASSETS_PACK, stream.read, stream.total));
+            }
+            return Ok(Some(stream.files.len()));
+        }
+        return Ok(None);
+    });
+
+    let finished =
+        match finished {
+            Ok(f) => f,
+            Err(e) => {
+                STREAM.with(|cell| {*cell.borrow_mut() = None;});
+                return Err(e);
+            },
+        };
+    if let Some(count) = finished {
+        let stream = STREAM.with(|cell| cell.borrow_mut().take()).unwrap();
+        PACKED.with(|cell| {*cell.borrow_mut() = Some(stream.files);});
+        info!("{} files unpacked from {}", count, ASSETS_PACK);
+        return Ok(PackLoad::Done);
+    }
+
+    return Ok(STREAM.with(|cell| {
+        match *cell.borrow() {
+            Some(ref stream) => PackLoad::Loading { read: stream.read, total: stream.total },
+            None => PackLoad::Idle,
+        }
+    }));
+}
+
+// the files in a whole pack already in memory.  it's come off the network,
+// so the unpacked size is held to what the header says and a name that
+// would land outside the directory it's unpacked to is refused
+fn pack_read(bytes: &[u8]) -> Result<HashMap<String, Vec<u8>>, String> {
+    if bytes.len() < PACK_HEADER_SIZE || &bytes[0..5] != PACK_MAGIC || bytes[5] != PACK_VERSION {
+        return Err(format!("not a version {} pack", PACK_VERSION));
+    }
+    let total = little_endian(&bytes[7..15]);
+    if total > PACK_UNPACKED_MAX {
+        return Err(format!("says it unpacks to {} bytes, over {}", total, PACK_UNPACKED_MAX));
+    }
+    let body = Cursor::new(bytes[PACK_HEADER_SIZE..].to_vec());
+    let source: Box<dyn Read> =
+        match bytes[6] {
+            PACK_STORED => Box::new(body),
+            PACK_ZSTD   => pack_decompress(body)?,
+            other       => return Err(format!("unknown compression {}", other)),
+        };
+    // one byte past the total is enough to know it's lying
+    let mut source = source.take(total + 1);
+    let mut unpacked = Vec::new();
+    source.read_to_end(&mut unpacked).map_err(|e| e.to_string())?;
+    let read = unpacked.len() as u64;
+
+    let mut stream = PackStream {
+        source: Box::new(source),
+        total: total,
+        read: read,
+        pending: unpacked,
+        files: HashMap::new()
+    };
+    pack_split(&mut stream);
+    if stream.read != stream.total || !stream.pending.is_empty() {
+        return Err(format!("cut short ({} of {} bytes)", stream.read, stream.total));
+    }
+
+    for name in stream.files.keys() {
+        if !asset_path_is_inside(name) {
+            return Err(format!("\"{}\" isn't a path inside the pack", name));
+        }
+    }
+    return Ok(stream.files);
+}
+
+// a whole pack already in memory, written out file by file under dir; for
+// a community download going into mods/
+pub fn pack_unpack(bytes: &[u8], dir: &Path) -> Result<usize, String> {
+    let files = pack_read(bytes)?;
+    for (name, contents) in files.iter() {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("can't create {}: {}", parent.display(), e))?;
+        }
+        File::create(&path)
+            .and_then(|mut file| file.write_all(contents))
+            .map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    }
+    return Ok(files.len());
+}
+
+// fuzz target: a downloaded pack, read but written nowhere
+pub fn pack_fuzz(bytes: &[u8]) {
+    let _ = pack_read(bytes);
+}
+
+// (read, total) bytes while a pack is streaming in
+pub fn pack_progress() -> Option<(u64, u64)> {
+    STREAM.with(|cell| cell.borrow().as_ref().map(|stream| (stream.read, stream.total)))
+}
+
+pub fn pack_is_loaded() -> bool {
+    PACKED.with(|cell| cell.borrow().is_some())
+}
+
+// a copy of a packed file, by its path relative to the base pack
+pub fn pack_file(relative: &str) -> Option<Vec<u8>> {
+    PACKED.with(|cell| {
+        cell.borrow().as_ref().and_then(|files| files.get(relative).cloned())
+    })
+}
@@ -0,0 +1,170 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+
+use super::input::*;
+use super::state_machine::GameState;
+use super::playfield::playfield_col;
+use super::playfield::playfield_row;
+use super::video::TILE_COL_COUNT;
+use super::video::TILE_ROW_COUNT;
+
+const PLAYER_SIZE: u32 = 16;
+
+pub const BOT_HAZARD_JUMP_DISTANCE: u32 = 16;
+
+// a vertical run of ladder tiles, in tile coordinates
+pub struct Ladder {
+    pub col: u32,
+    pub top_row: u32,
+    pub bottom_row: u32,
+}
+
+// everything a bot is allowed to know: the ladders as they're drawn, and
+// where the player and whatever kills on contact are, the same things a
+// player reads off the screen, so bots can't depend on other internals.
+pub struct BotView {
+    pub state: GameState,
+    pub tick: u32,
+    pub player: (u32, u32),
+    pub ladders: Vec<Ladder>,
+    pub hazards: Vec<(u32, u32)>,
+}
+
+pub type BotBrain = fn(&BotView) -> u8;
+
+struct Bot {
+    brain: BotBrain,
+    tick: u32,
+}
+
+thread_local!(
+    static BOT:RefCell<Bot> = RefCell::new(Bot {
+        brain: climber_bot,
+        tick: 0
+    });
+);
+
+fn bot_find_ladders() -> Vec<Ladder> {
+    use super::video::video_bg_tile;
+    use super::video::tile_is_ladder;
+
+    let mut ladders = Vec::new();
+    for col in 0..TILE_COL_COUNT {
+        let mut top_row = None;
+        for row in 0..TILE_ROW_COUNT + 1 {
+            let is_ladder =
+                match video_bg_tile(col, row) {
+                    Some(tile) => tile_is_ladder(tile),
+                    None => false,
+                };
+            match (is_ladder, top_row) {
+                (true, None) => top_row = Some(row),
+                (false, Some(top)) => {
+                    ladders.push(Ladder { col: col, top_row: top, bottom_row: row - 1 });
+                    top_row = None;
+                },
+                _ => {},
+            }
+        }
+    }
+    return ladders;
+}
+
+fn bot_observe(tick: u32) -> BotView {
+    use super::enemies::enemies_hitboxes;
+    use super::player::player_position;
+    use super::state_machine::game_state_current;
+
+    let hazards = enemies_hitboxes()
+        .iter()
+        .map(|hitbox| (hitbox.x.max(0) as u32, hitbox.y.max(0) as u32))
+        .collect();
+
+    BotView {
+        state: game_state_current(),
+        tick: tick,
+        player: player_position(),
+        ladders: bot_find_ladders(),
+        hazards: hazards,
+    }
+}
+
+// produces this tick's input flags; feed the result to input_set()
+pub fn bot_update() -> u8 {
+    let (brain, tick) = BOT.with(|cell| {
+        let mut bot = cell.borrow_mut();
+        bot.tick = bot.tick.wrapping_add(1);
+        (bot.brain, bot.tick)
+    });
+    brain(&bot_observe(tick))
+}
+
+// coins up and starts from attract, then heads for the nearest ladder that
+// leads upward from the girder it's standing on, jumping anything that
+// comes close
+pub fn climber_bot(view: &BotView) -> u8 {
+    match view.state {
+        GameState::Attract => {
+            // inputs are edge triggered, so tap and release
+            match view.tick % 60 {
+                0..=4   => F_INPUT_COIN,
+                30..=34 => F_INPUT_START,
+                _       => F_INPUT_NONE,
+            }
+        },
+
+        // play is the first menu entry
+        GameState::MainMenu => {
+            match view.tick % 60 {
+                30..=34 => F_INPUT_START,
+                _       => F_INPUT_NONE,
+            }
+        },
+
+        GameState::GamePlay => {
+            let (x, y) = view.player;
+            let col = playfield_col((x + PLAYER_SIZE / 2) as i32);
+            let feet_row = playfield_row((y + PLAYER_SIZE - 1) as i32);
+
+            let ladder = view.ladders
+                .iter()
+                .filter(|l| l.top_row < feet_row && l.bottom_row + 1 >= feet_row)
+                .min_by_key(|l| (l.col as i32 - col as i32).abs());
+
+            let on_ladder =
+                match ladder {
+                    Some(l) => l.col == col,
+                    None => false,
+                };
+
+            let threatened = view.hazards.iter().any(|&(hx, hy)| {
+                (hx as i32 - x as i32).abs() < BOT_HAZARD_JUMP_DISTANCE as i32
+                    && (hy as i32 - y as i32).abs() < PLAYER_SIZE as i32
+            });
+
+            let mut flags = F_INPUT_NONE;
+            match ladder {
+                Some(l) if l.col == col => flags |= F_INPUT_UP,
+                Some(l) if l.col < col  => flags |= F_INPUT_LEFT,
+                _                       => flags |= F_INPUT_RIGHT,
+            }
+            if threatened && !on_ladder {
+                flags |= F_INPUT_JUMP;
+            }
+            flags
+        },
+
+        _ => F_INPUT_NONE,
+    }
+}
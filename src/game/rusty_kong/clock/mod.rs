@@ -0,0 +1,109 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::hash::Hash;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use super::hashing::StateHasher;
+use super::savestate::SaveState;
+
+mod pacing;
+pub use self::pacing::*;
+
+pub const TICKS_PER_SECOND: u32 = 60;
+
+// the one place time comes from.  the two tick counts are logic state,
+// hashed and saved like anything else; wall time is for people (split
+// times, timestamps, pacing) and never feeds back into the game.
+#[derive(Clone, Hash)]
+struct Clock {
+    // every logic tick since boot, whatever the state
+    ticks: u32,
+    // only the ticks GamePlay actually ran: the pause screen and the death
+    // & win sequences don't advance it
+    game_ticks: u32,
+}
+
+thread_local!(
+    static CLOCK:RefCell<Clock> = RefCell::new(Clock {
+        ticks: 0,
+        game_ticks: 0
+    });
+    static STARTED:RefCell<Option<Instant>> = RefCell::new(None);
+);
+
+pub fn clock_init() {
+    STARTED.with(|cell| {*cell.borrow_mut() = Some(Instant::now());});
+}
+
+// call once per logic tick
+pub fn clock_tick() {
+    CLOCK.with(|cell| {
+        let mut clock = cell.borrow_mut();
+        clock.ticks = clock.ticks.wrapping_add(1);
+    });
+}
+
+// call from GamePlay's update, once per tick it isn't paused
+pub fn clock_game_tick() {
+    CLOCK.with(|cell| {
+        let mut clock = cell.borrow_mut();
+        clock.game_ticks = clock.game_ticks.wrapping_add(1);
+    });
+}
+
+pub fn clock_ticks() -> u32 {
+    CLOCK.with(|cell| cell.borrow().ticks)
+}
+
+pub fn clock_game_ticks() -> u32 {
+    CLOCK.with(|cell| cell.borrow().game_ticks)
+}
+
+// replays that start partway into a session put the counts back to where
+// they were when recording began
+pub fn clock_set(ticks: u32, game_ticks: u32) {
+    CLOCK.with(|cell| {*cell.borrow_mut() = Clock { ticks: ticks, game_ticks: game_ticks };});
+}
+
+// monotonic, since clock_init(); zero in headless runs that never call it
+pub fn clock_wall() -> Duration {
+    STARTED.with(|cell| cell.borrow().map_or(Duration::from_secs(0), |started| started.elapsed()))
+}
+
+pub fn clock_wall_millis() -> u64 {
+    let wall = clock_wall();
+    return wall.as_secs() * 1000 + wall.subsec_millis() as u64;
+}
+
+// for dates and timestamps other programs read; it can jump, so nothing
+// is timed with it
+pub fn clock_unix_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
+
+pub fn clock_hash(hasher: &mut StateHasher) {
+    CLOCK.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn clock_save(savestate: &mut SaveState) {
+    savestate.put("clock", CLOCK.with(|cell| cell.borrow().clone()));
+}
+
+pub fn clock_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<Clock>("clock") {
+        CLOCK.with(|cell| {*cell.borrow_mut() = value;});
+    }
+}
@@ -0,0 +1,131 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::thread;
+use std::time::Duration;
+
+use super::*;
+
+// a tick's worth of wall time, in nanoseconds times game_speed percent
+const PACING_TICK_COST: u64 = 1_000_000_000 * 100 / TICKS_PER_SECOND as u64;
+const PACING_TICK_NANOS: u64 = 1_000_000_000 / TICKS_PER_SECOND as u64;
+// owed more than this at once -- the window was dragged, a breakpoint hit
+// -- and the rest is dropped instead of run in a burst; fast-forwarding
+// owes that many times more
+const PACING_CATCH_UP_MAX: u64 = 4;
+// --speed and fast_forward_speed's most logic ticks per tick of wall time
+pub const FAST_FORWARD_MAX: u32 = 20;
+// under vsync a frame this close to a whole number of ticks is taken as
+// exactly that, so a 60hz display's jitter doesn't alternate 0 & 2 ticks
+const PACING_SNAP_NANOS: u64 = 250_000;
+// sleep() overshoots by up to a scheduler slice; the last of the wait spins
+const PACING_SPIN_NANOS: u64 = 1_500_000;
+
+fn pacing_nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+// turns wall time into logic ticks for the main loop: TICKS_PER_SECOND times
+// a percentage -- game_speed, times the fast-forward -- whatever the display
+// refreshes at or however long a frame takes
+pub struct FramePacer {
+    // clock_wall() at the last due()
+    last: Duration,
+    // wall time not yet spent on a tick, like PACING_TICK_COST
+    banked: u64,
+    snap: bool,
+}
+
+impl FramePacer {
+    // snap when presents wait on vsync, the frame times are the refresh's
+    pub fn new(snap: bool) -> FramePacer {
+        FramePacer {
+            last: clock_wall(),
+            banked: 0,
+            snap: snap,
+        }
+    }
+
+    // the ticks to run this frame
+    pub fn due(self:&mut FramePacer, percent: u32) -> u32 {
+        let now = clock_wall();
+        let mut elapsed = pacing_nanos(now.checked_sub(self.last).unwrap_or_default());
+        self.last = now;
+        if self.snap {
+            let whole = (elapsed + PACING_TICK_NANOS / 2) / PACING_TICK_NANOS * PACING_TICK_NANOS;
+            if whole != 0 && (elapsed as i64 - whole as i64).abs() < PACING_SNAP_NANOS as i64 {
+                elapsed = whole;
+            }
+        }
+
+        self.banked += elapsed * percent as u64;
+        let due = self.banked / PACING_TICK_COST;
+        self.banked %= PACING_TICK_COST;
+        let most = (PACING_CATCH_UP_MAX * percent as u64 / 100).max(PACING_CATCH_UP_MAX);
+        if due > most {
+            debug!("pacing: {} ticks behind, dropping {}", due, due - most);
+            return most as u32;
+        }
+        return due as u32;
+    }
+
+    // clock_wall() when the next tick is due
+    pub fn next_due(self:&FramePacer, percent: u32) -> Duration {
+        let speed = percent.max(1) as u64;
+        let wait = (PACING_TICK_COST - self.banked + speed - 1) / speed;
+        return self.last + Duration::from_nanos(wait);
+    }
+}
+
+// frames shown per second at most, for rendering less than the logic runs
+pub struct FrameLimit {
+    // clock_wall() the next frame may go out at
+    next: Duration,
+}
+
+impl FrameLimit {
+    pub fn new() -> FrameLimit {
+        FrameLimit { next: Duration::from_secs(0) }
+    }
+
+    // whether to render now; 0 fps is no limit
+    pub fn allows(self:&mut FrameLimit, fps: u32) -> bool {
+        if fps == 0 {
+            return true;
+        }
+        let now = clock_wall();
+        if now < self.next {
+            return false;
+        }
+        let interval = Duration::from_nanos(1_000_000_000 / fps as u64);
+        // on the beat, unless this one was so late the next would be early
+        self.next = self.next + interval;
+        if self.next < now {
+            self.next = now + interval;
+        }
+        return true;
+    }
+}
+
+// waits until clock_wall() reaches the deadline as closely as the os lets
+// it: sleeps most of the way, then spins
+pub fn pacing_sleep_until(deadline: Duration) {
+    let now = clock_wall();
+    if let Some(wait) = deadline.checked_sub(now) {
+        let wait = pacing_nanos(wait);
+        if wait > PACING_SPIN_NANOS {
+            thread::sleep(Duration::from_nanos(wait - PACING_SPIN_NANOS));
+        }
+    }
+    while clock_wall() < deadline {
+        thread::yield_now();
+    }
+}
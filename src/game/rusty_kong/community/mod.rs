@@ -0,0 +1,317 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::fs;
+use std::hash::Hasher;
+use std::io::Read;
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+
+use super::assets::MODS_DIR;
+use super::hashing::StateHasher;
+use super::video::FrameBuffer;
+
+// the index is a text file, one pack a line, like checksums.txt:
+//
+//     # name = fnv1a64 url
+//     barrels-redux = 0123456789abcdef https://example.org/barrels-redux.rkpak
+//
+// a pack is an .rkpak (--write-pack) of the files a mod overrides, and
+// goes in as mods/<name>/.  an index or pack url without http:// or
+// https:// in front is read off disk instead, for trying one out.
+pub const COMMUNITY_SHOWN: usize = 10;
+// nothing bigger than the base pack has any business coming down
+const COMMUNITY_MAX_BYTES: u64 = 64 * 1024 * 1024;
+#[cfg(feature = "online")]
+const COMMUNITY_TIMEOUT_SECS: u64 = 30;
+// where the page's cursor & BACK are, by row; drawn by the main menu
+pub const COMMUNITY_ROW: u32 = 6;
+const COMMUNITY_COL: u32 = 4;
+const COMMUNITY_NAME_MAX: usize = 18;
+
+#[derive(Clone)]
+pub struct CommunityPack {
+    pub name: String,
+    checksum: u64,
+    url: String,
+}
+
+enum Job {
+    Index,
+    Pack(CommunityPack),
+}
+
+struct Community {
+    // only once game_init has run: a journal playing back, golden & --replay
+    // never go near the network
+    online: bool,
+    packs: Vec<CommunityPack>,
+    status: String,
+    job: Option<(Job, Receiver<Result<Vec<u8>, String>>)>,
+}
+
+thread_local!(
+    static COMMUNITY:RefCell<Community> = RefCell::new(Community {
+        online: false,
+        packs: Vec::new(),
+        status: String::new(),
+        job: None
+    });
+);
+
+#[cfg(feature = "online")]
+fn community_download(url: &str) -> Result<Box<dyn Read>, String> {
+    use std::time::Duration;
+
+    let response = ::ureq::get(url)
+        .timeout(Duration::from_secs(COMMUNITY_TIMEOUT_SECS))
+        .call()
+        .map_err(|e| format!("{}: {}", url, e))?;
+    return Ok(Box::new(response.into_reader()));
+}
+
+#[cfg(not(feature = "online"))]
+fn community_download(_url: &str) -> Result<Box<dyn Read>, String> {
+    Err("this build has no network code, rebuild with --features online".to_string())
+}
+
+fn community_fetch(url: &str) -> Result<Vec<u8>, String> {
+    let source: Box<dyn Read> =
+        if url.starts_with("http://") || url.starts_with("https://") {
+            community_download(url)?
+        } else {
+            Box::new(fs::File::open(url).map_err(|e| format!("can't open {}: {}", url, e))?)
+        };
+    let mut bytes = Vec::new();
+    source
+        .take(COMMUNITY_MAX_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("{}: {}", url, e))?;
+    if bytes.len() as u64 > COMMUNITY_MAX_BYTES {
+        return Err(format!("{} is over {} bytes", url, COMMUNITY_MAX_BYTES));
+    }
+    return Ok(bytes);
+}
+
+// the fetch runs on its own thread; community_poll picks the result up
+fn community_start(job: Job, url: String) {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(community_fetch(&url));
+    });
+    COMMUNITY.with(|cell| {cell.borrow_mut().job = Some((job, receiver));});
+}
+
+fn community_set_status(status: String) {
+    COMMUNITY.with(|cell| {cell.borrow_mut().status = status;});
+}
+
+// a directory name and nothing else: no separators, dots or spaces
+fn community_name_is_valid(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// fuzz target: an index as it comes down, which needn't even be text
+pub fn community_index_fuzz(bytes: &[u8]) {
+    community_parse(&String::from_utf8_lossy(bytes));
+}
+
+fn community_parse(index: &str) -> Vec<CommunityPack> {
+    let mut packs = Vec::new();
+    for (number, line) in index.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut halves = line.splitn(2, '=');
+        let name = halves.next().unwrap_or("").trim();
+        let mut fields = halves.next().unwrap_or("").split_whitespace();
+        let checksum = fields.next().and_then(|c| u64::from_str_radix(c, 16).ok());
+        let url = fields.next();
+        match (checksum, url) {
+            (Some(checksum), Some(url)) if community_name_is_valid(name) => {
+                packs.push(CommunityPack { name: name.to_string(), checksum: checksum, url: url.to_string() });
+            },
+            _ => warn!("community index line {}: expected \"name = fnv1a64 url\"", number + 1),
+        }
+    }
+    return packs;
+}
+
+// checked, unpacked next to where it's going and only then swapped in, so
+// a bad pack never leaves half a mod behind; then it's enabled right away
+fn community_install(pack: &CommunityPack, bytes: &[u8]) -> Result<usize, String> {
+    use super::assets::pack_unpack;
+    use super::settings::settings_get;
+    use super::settings::settings_save_mods;
+    use super::settings::settings_set;
+
+    let mut hasher = StateHasher::new();
+    hasher.write(bytes);
+    let checksum = hasher.finish();
+    if checksum != pack.checksum {
+        return Err(format!("checksum is {:016x}, the index says {:016x}", checksum, pack.checksum));
+    }
+
+    let target = Path::new(MODS_DIR).join(&pack.name);
+    let staging = Path::new(MODS_DIR).join(format!("{}.download", pack.name));
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(|e| format!("can't clear {}: {}", staging.display(), e))?;
+    }
+    let count =
+        match pack_unpack(bytes, &staging) {
+            Ok(count) => count,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&staging);
+                return Err(e);
+            },
+        };
+    if target.exists() {
+        fs::remove_dir_all(&target).map_err(|e| format!("can't replace {}: {}", target.display(), e))?;
+    }
+    fs::rename(&staging, &target).map_err(|e| format!("can't move {} into place: {}", staging.display(), e))?;
+
+    let mut settings = settings_get();
+    if !settings.mods.contains(&pack.name) {
+        settings.mods.push(pack.name.clone());
+        settings_set(settings);
+        settings_save_mods();
+    }
+    return Ok(count);
+}
+
+// call every frame; picks up whatever the fetch thread has finished
+pub fn community_poll() {
+    let finished = COMMUNITY.with(|cell| {
+        let mut community = cell.borrow_mut();
+        let result =
+            match community.job {
+                Some((_, ref receiver)) => {
+                    match receiver.try_recv() {
+                        Ok(result) => result,
+                        Err(TryRecvError::Empty) => return None,
+                        Err(TryRecvError::Disconnected) => Err("the download thread went away".to_string()),
+                    }
+                },
+                None => return None,
+            };
+        community.job.take().map(|(job, _)| (job, result))
+    });
+
+    match finished {
+        Some((Job::Index, Ok(bytes))) => {
+            let packs = community_parse(&String::from_utf8_lossy(&bytes));
+            info!("community index: {} packs", packs.len());
+            let status = if packs.is_empty() { "NO PACKS LISTED".to_string() } else { format!("{} PACKS", packs.len()) };
+            COMMUNITY.with(|cell| {cell.borrow_mut().packs = packs;});
+            community_set_status(status);
+        },
+        Some((Job::Pack(pack), Ok(bytes))) => {
+            match community_install(&pack, &bytes) {
+                Ok(count) => {
+                    info!("community pack \"{}\": {} files installed in {}/{}, enabled", pack.name, count, MODS_DIR, pack.name);
+                    community_set_status("INSTALLED".to_string());
+                },
+                Err(e) => {
+                    warn!("community pack \"{}\": {}", pack.name, e);
+                    community_set_status("BAD PACK".to_string());
+                },
+            }
+        },
+        Some((_, Err(e))) => {
+            warn!("community: {}", e);
+            community_set_status("DOWNLOAD FAILED".to_string());
+        },
+        None => {},
+    }
+}
+
+fn community_is_busy() -> bool {
+    COMMUNITY.with(|cell| cell.borrow().job.is_some())
+}
+
+// the page opening asks for the index again
+pub fn community_refresh() {
+    use super::settings::settings_read;
+
+    if !COMMUNITY.with(|cell| cell.borrow().online) || community_is_busy() {
+        return;
+    }
+    let url = settings_read(|s| s.community_index.clone());
+    COMMUNITY.with(|cell| {cell.borrow_mut().packs.clear();});
+    if url.is_empty() {
+        community_set_status("NO COMMUNITY_INDEX SET".to_string());
+        return;
+    }
+    info!("community index from {}", url);
+    community_set_status("FETCHING LIST".to_string());
+    community_start(Job::Index, url);
+}
+
+// the pack listed in slot, if there's one there and nothing else is going
+pub fn community_get(slot: usize) {
+    if community_is_busy() {
+        return;
+    }
+    let pack = COMMUNITY.with(|cell| cell.borrow().packs.get(slot).cloned());
+    if let Some(pack) = pack {
+        info!("community pack \"{}\" from {}", pack.name, pack.url);
+        community_set_status(format!("GETTING {}", pack.name.to_uppercase()));
+        let url = pack.url.clone();
+        community_start(Job::Pack(pack), url);
+    }
+}
+
+// the list is drawn over the page rather than on it: what comes back from
+// the network isn't the same from one run to the next, and the tilemaps
+// are part of the state a journal hashes.  the page itself only moves a
+// cursor over a fixed COMMUNITY_SHOWN slots & BACK
+fn community_overlay(framebuffer: &mut FrameBuffer) {
+    use super::state_machine::GameState;
+    use super::state_machine::game_state_current;
+    use super::state_machine::main_menu_community_slot;
+    use super::video::*;
+
+    if game_state_current() != GameState::MainMenu {
+        return;
+    }
+    let selected =
+        match main_menu_community_slot() {
+            Some(selected) => selected,
+            None => return,
+        };
+    let (packs, status) = COMMUNITY.with(|cell| {
+        let community = cell.borrow();
+        (community.packs.clone(), community.status.clone())
+    });
+    let x = COMMUNITY_COL * TILE_WIDTH;
+    text_draw(framebuffer, x, (COMMUNITY_ROW - 2) * TILE_HEIGHT, &status, TEXT_PALETTE_RED);
+    for (slot, pack) in packs.iter().take(COMMUNITY_SHOWN).enumerate() {
+        let installed = Path::new(MODS_DIR).join(&pack.name).is_dir();
+        let mut name: String = pack.name.to_uppercase().chars().take(COMMUNITY_NAME_MAX).collect();
+        if installed {
+            name.push_str(" *");
+        }
+        let palette = if slot == selected { TEXT_PALETTE_WHITE } else { TEXT_PALETTE_BLUE };
+        text_draw(framebuffer, x, (COMMUNITY_ROW + slot as u32 * 2) * TILE_HEIGHT, &name, palette);
+    }
+}
+
+pub fn community_init() {
+    use super::video::video_add_overlay;
+
+    COMMUNITY.with(|cell| {cell.borrow_mut().online = true;});
+    video_add_overlay(community_overlay);
+}
@@ -0,0 +1,263 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::events::GameEvent;
+use super::state_machine::GameState;
+
+// one scores file per day plus the replay of every run posted to it
+pub const DAILY_DIR: &str = "daily";
+// everyone plays the same campaign on the same day
+const DAILY_CAMPAIGN: usize = 0;
+
+pub struct DailyScore {
+    pub score: u32,
+    // its recording has inputs no hand could make (replay::turbo_check)
+    pub turbo: bool,
+}
+
+// the run in progress; the game state itself is in level's sequencer
+struct Daily {
+    day: Option<u32>,
+    // the run's replay under DAILY_DIR; None when --record was already
+    // taking the whole session
+    replay: Option<String>,
+}
+
+thread_local!(
+    static DAILY:RefCell<Daily> = RefCell::new(Daily {
+        day: None,
+        replay: None
+    });
+);
+
+// days since 1970-01-01, utc, so players in different time zones share
+// the day for as long as it lasts in greenwich
+pub fn daily_today() -> u32 {
+    use super::clock::clock_unix_secs;
+    (clock_unix_secs() / (24 * 60 * 60)) as u32
+}
+
+// yyyy-mm-dd, after howard hinnant's civil_from_days
+pub fn daily_date(day: u32) -> String {
+    let z = day as i64 + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let dom = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, dom)
+}
+
+// a day number scrambled into a seed; neighbouring days look nothing alike
+pub fn daily_seed(day: u32) -> u32 {
+    let mut x = day.wrapping_mul(0x9e37_79b9) ^ 0x5bd1_e995;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2_ae35);
+    x ^= x >> 16;
+    return x;
+}
+
+// every stage of the campaign once, shuffled by the day's seed.  drawn from
+// its own xorshift, so the game rng starts the run untouched.
+pub fn daily_order(day: u32) -> Vec<usize> {
+    use super::level::level_campaign_at;
+
+    let mut order: Vec<usize> = (0..level_campaign_at(DAILY_CAMPAIGN).stages.len()).collect();
+    let mut x = daily_seed(day) | 1;
+    for index in (1..order.len()).rev() {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        order.swap(index, x as usize % (index + 1));
+    }
+    return order;
+}
+
+// the game set up for the day's run; the menu and replay playback both
+// start the run from here
+pub fn daily_start(day: u32) {
+    use super::level::*;
+    use super::player::player_start_game;
+    use super::rng::rng_seed;
+    use super::state_machine::game_state_go;
+
+    level_select_campaign(DAILY_CAMPAIGN);
+    rng_seed(daily_seed(day));
+    player_start_game(1);
+    level_start_daily(daily_order(day));
+    info!("daily challenge for {}, seed {:08x}", daily_date(day), daily_seed(day));
+    game_state_go(level_campaign().first_state);
+}
+
+fn daily_scores_path(day: u32) -> PathBuf {
+    Path::new(DAILY_DIR).join(format!("{}.scores", daily_date(day)))
+}
+
+// "score = points, replay" per line, best first, with ", turbo" after a
+// flagged one
+pub fn daily_scores(day: u32) -> Vec<DailyScore> {
+    use super::settings::read_key_value_file;
+
+    let path = daily_scores_path(day);
+    let pairs =
+        match read_key_value_file(&path) {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+
+    let mut scores = Vec::new();
+    for pair in pairs.iter() {
+        let mut fields = pair.value.splitn(3, ',').map(|f| f.trim());
+        let score = fields.next().and_then(|f| f.parse::<u32>().ok());
+        match (pair.key.as_ref(), score) {
+            ("score", Some(score)) => scores.push(DailyScore {
+                score: score,
+                // the replay's name is skipped, the menu only shows the flag
+                turbo: fields.nth(1) == Some("turbo"),
+            }),
+            _ => warn!("{}:{}: expected score = points, replay", path.display(), pair.line),
+        }
+    }
+    scores.sort_by(|a, b| b.score.cmp(&a.score));
+    return scores;
+}
+
+// starts the day's run from the menu, recording it next to the scores file
+pub fn daily_play(day: u32) {
+    use super::replay::REPLAY_HASH_INTERVAL;
+    use super::replay::replay_record_daily;
+
+    let replay = format!("{}-{}.replay", daily_date(day), daily_scores(day).len() + 1);
+    let recording =
+        match fs::create_dir_all(DAILY_DIR) {
+            Ok(()) => {
+                let path = Path::new(DAILY_DIR).join(&replay);
+                replay_record_daily(&path.to_string_lossy(), day, daily_seed(day), REPLAY_HASH_INTERVAL)
+            },
+            Err(e) => {
+                warn!("can't create {}: {}, the run won't be recorded", DAILY_DIR, e);
+                false
+            },
+        };
+
+    daily_start(day);
+    DAILY.with(|cell| {
+        let mut daily = cell.borrow_mut();
+        daily.day = Some(day);
+        daily.replay = if recording { Some(replay) } else { None };
+    });
+}
+
+fn daily_post(day: u32, score: u32, replay: &str, turbo: bool) -> Result<(), String> {
+    let path = daily_scores_path(day);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let line = if turbo { format!("score = {}, {}, turbo\n", score, replay) } else { format!("score = {}, {}\n", score, replay) };
+    file.write_all(line.as_bytes())
+        .map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    return Ok(());
+}
+
+fn daily_finish() {
+    use super::player::player_score;
+    use super::replay::replay_record_finish;
+    use super::settings::*;
+
+    let (day, replay) = DAILY.with(|cell| {
+        let mut daily = cell.borrow_mut();
+        (daily.day.take(), daily.replay.take())
+    });
+    let day =
+        match day {
+            Some(d) => d,
+            None => return,
+        };
+
+    // a run that carried on past midnight still counts for the day it began
+    let score = player_score();
+    let mut turbo = false;
+    if let Some(ref replay) = replay {
+        use super::replay::turbo_check;
+
+        replay_record_finish();
+        match turbo_check(&Path::new(DAILY_DIR).join(replay)) {
+            Ok(flags) => turbo = !flags.is_empty(),
+            Err(e) => warn!("no turbo check: {}", e),
+        }
+    }
+    if turbo {
+        warn!("daily challenge run flagged for turbo");
+    }
+    // slowed down, it's not the same challenge as everyone else's
+    if !settings_is_standard_speed() {
+        warn!("daily challenge run at {}% speed isn't posted", settings_read(|s| s.game_speed));
+        return;
+    }
+    match daily_post(day, score, &replay.unwrap_or_default(), turbo) {
+        Ok(()) => info!("daily challenge for {} over with {} points", daily_date(day), score),
+        Err(e) => error!("{}", e),
+    }
+}
+
+// a reset run isn't posted, and its recording is thrown away
+fn daily_abandon() {
+    use super::replay::replay_record_discard;
+
+    let (day, replay) = DAILY.with(|cell| {
+        let mut daily = cell.borrow_mut();
+        (daily.day.take(), daily.replay.take())
+    });
+    if day.is_some() {
+        info!("daily challenge abandoned");
+        if replay.is_some() {
+            replay_record_discard();
+        }
+    }
+}
+
+// drops the run in progress without posting it or touching its recording;
+// a run played back to resume a suspended game isn't the player's to post
+pub fn daily_forget() {
+    DAILY.with(|cell| {
+        let mut daily = cell.borrow_mut();
+        daily.day = None;
+        daily.replay = None;
+    });
+}
+
+fn daily_on_event(event: &GameEvent) {
+    match event {
+        &GameEvent::Reset => daily_abandon(),
+        &GameEvent::StateChanged { to: GameState::Attract, .. } |
+        &GameEvent::StateChanged { to: GameState::MainMenu, .. } => daily_finish(),
+        _ => {},
+    }
+}
+
+pub fn daily_init() {
+    use super::events::events_subscribe;
+    events_subscribe(daily_on_event);
+}
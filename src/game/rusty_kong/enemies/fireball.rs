@@ -0,0 +1,329 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+use super::Hitbox;
+use rusty_kong::events::*;
+use rusty_kong::hashing::StateHasher;
+use rusty_kong::savestate::SaveState;
+use rusty_kong::inspector::*;
+use rusty_kong::level::*;
+use rusty_kong::physics::physics;
+use rusty_kong::playfield::*;
+use rusty_kong::video::*;
+
+pub const FIREBALL_SIZE: i32 = 16;
+pub const FIREBALL_MAX:  usize = 8;
+pub const FIREBALL_SPRITE_SLOT: usize = 40;
+
+// XXX: tile & palette by eye from the ripper output
+const FIREBALL_TILE:    u16 = 0x3d;
+const FIREBALL_PALETTE: u8 = 44;
+
+// ticks between spawns at each internal difficulty, 1 to 5
+const FIREBALL_CADENCE: [u32; DIFFICULTY_MAX as usize] = [600, 480, 360, 300, 240];
+
+// walks its girder back and forth, turning at the ends
+#[derive(Clone, Copy, Hash)]
+pub struct Fireball {
+    pub x: i32,
+    pub girder: usize,
+    // -1 left, 1 right
+    pub direction: i32,
+}
+
+// the live ones a column a field, so the step walks plain runs of i32s;
+// index n across the columns is the nth fireball, in spawn order
+#[derive(Clone)]
+struct Fireballs {
+    x: Vec<i32>,
+    girder: Vec<usize>,
+    direction: Vec<i32>,
+    // ticks since the last spawn
+    since_spawn: u32,
+    // round robin over the stage's spawn points
+    next_spawn: usize,
+    ticks: u32,
+}
+
+thread_local!(
+    static FIREBALLS:RefCell<Fireballs> = RefCell::new(Fireballs {
+        x: Vec::new(),
+        girder: Vec::new(),
+        direction: Vec::new(),
+        since_spawn: 0,
+        next_spawn: 0,
+        ticks: 0
+    });
+    static STAGE:RefCell<Option<&'static Stage>> = RefCell::new(None);
+);
+
+impl Fireballs {
+    fn len(self:&Fireballs) -> usize {
+        return self.x.len();
+    }
+
+    fn get(self:&Fireballs, index: usize) -> Fireball {
+        return Fireball { x: self.x[index], girder: self.girder[index], direction: self.direction[index] };
+    }
+
+    fn push(self:&mut Fireballs, fireball: Fireball) {
+        self.x.push(fireball.x);
+        self.girder.push(fireball.girder);
+        self.direction.push(fireball.direction);
+    }
+
+    fn remove(self:&mut Fireballs, index: usize) {
+        self.x.remove(index);
+        self.girder.remove(index);
+        self.direction.remove(index);
+    }
+
+    fn clear(self:&mut Fireballs) {
+        self.x.clear();
+        self.girder.clear();
+        self.direction.clear();
+    }
+}
+
+// the same stream a Vec<Fireball> hashed to, so journals and goldens from
+// before the columns still check
+impl Hash for Fireballs {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for index in 0..self.len() {
+            self.get(index).hash(state);
+        }
+        self.since_spawn.hash(state);
+        self.next_spawn.hash(state);
+        self.ticks.hash(state);
+    }
+}
+
+fn girder_surface(girder: &Girder, x: i32) -> i32 {
+    let center = (x + FIREBALL_SIZE / 2).max(girder.left()).min(girder.right() - 1);
+    girder.surface(center)
+}
+
+fn fireball_draw(slot: usize, fireball: Fireball, layout: &Layout) {
+    let girder = &layout.girders[fireball.girder];
+    let y = girder_surface(girder, fireball.x) - FIREBALL_SIZE;
+    video_sprite_set(FIREBALL_SPRITE_SLOT + slot, FIREBALL_TILE, fireball.x as u16, y as u16, FIREBALL_PALETTE);
+    video_sprite_flip(FIREBALL_SPRITE_SLOT + slot, fireball.direction < 0, false);
+}
+
+fn fireballs_hide() {
+    for slot in 0..FIREBALL_MAX {
+        video_sprite_hide(FIREBALL_SPRITE_SLOT + slot);
+    }
+}
+
+pub fn fireballs_stage_start(stage: &'static Stage) {
+    FIREBALLS.with(|cell| {
+        let mut fireballs = cell.borrow_mut();
+        fireballs.clear();
+        fireballs.since_spawn = 0;
+        fireballs.next_spawn = 0;
+        fireballs.ticks = 0;
+    });
+    STAGE.with(|cell| {*cell.borrow_mut() = Some(stage);});
+    fireballs_hide();
+}
+
+// no stage at all, for tearing a game down
+pub fn fireballs_clear() {
+    FIREBALLS.with(|cell| {cell.borrow_mut().clear();});
+    STAGE.with(|cell| {*cell.borrow_mut() = None;});
+    fireballs_hide();
+}
+
+fn fireballs_spawn(fireballs: &mut Fireballs, stage: &Stage, difficulty: u32) -> bool {
+    let rules = stage.fireball_rules();
+    let cap = rules.cap.min(FIREBALL_MAX);
+    if fireballs.len() >= cap {
+        debug!("fireball spawn skipped on {}: {} of {} alive", stage.name, fireballs.len(), cap);
+        return false;
+    }
+
+    let spawn = &rules.spawns[fireballs.next_spawn % rules.spawns.len()];
+    fireballs.next_spawn = fireballs.next_spawn.wrapping_add(1);
+    let x = playfield_col_left(spawn.col);
+    fireballs.push(Fireball { x: x, girder: spawn.girder, direction: 1 });
+    info!("fireball spawned on {} at col {} girder {} (difficulty {}, {} of {} alive, tick {})",
+        stage.name,
+        spawn.col,
+        spawn.girder,
+        difficulty,
+        fireballs.len(),
+        cap,
+        fireballs.ticks);
+    return true;
+}
+
+pub fn fireballs_update() {
+    let stage =
+        match STAGE.with(|cell| *cell.borrow()) {
+            Some(s) => s,
+            None => return,
+        };
+    if !stage.enemies.contains(&EnemyKind::Fireball) || stage.fireball_rules().spawns.is_empty() {
+        return;
+    }
+
+    let difficulty = level_difficulty();
+    let layout = stage.layout;
+    let spawned = FIREBALLS.with(|cell| {
+        let mut fireballs = cell.borrow_mut();
+        fireballs.ticks = fireballs.ticks.wrapping_add(1);
+        fireballs.since_spawn += 1;
+        let mut spawned = false;
+        if fireballs.since_spawn >= FIREBALL_CADENCE[(difficulty.max(1) - 1) as usize] {
+            fireballs.since_spawn = 0;
+            spawned = fireballs_spawn(&mut fireballs, stage, difficulty);
+        }
+
+        // one pixel along the girder every fireball_step_ticks
+        if fireballs.ticks % physics().fireball_step_ticks == 0 {
+            let fireballs = &mut *fireballs;
+            for (x, direction) in fireballs.x.iter_mut().zip(fireballs.direction.iter_mut()) {
+                *x += *direction;
+            }
+            for index in 0..fireballs.girder.len() {
+                let girder = &layout.girders[fireballs.girder[index]];
+                let (left, right) = (girder.left(), girder.right() - FIREBALL_SIZE);
+                let x = fireballs.x[index];
+                if x <= left || x >= right {
+                    fireballs.x[index] = x.max(left).min(right);
+                    fireballs.direction[index] = -fireballs.direction[index];
+                }
+            }
+        }
+        for slot in 0..fireballs.len() {
+            fireball_draw(slot, fireballs.get(slot), layout);
+        }
+        for slot in fireballs.len()..FIREBALL_MAX {
+            video_sprite_hide(FIREBALL_SPRITE_SLOT + slot);
+        }
+        spawned
+    });
+    if spawned {
+        events_publish(GameEvent::EnemySpawned { kind: EnemyKind::Fireball });
+    }
+}
+
+// takes out every fireball overlapping hitbox, returning where each one's
+// center was
+pub fn fireballs_remove_touching(hitbox: &Hitbox) -> Vec<(i32, i32)> {
+    let touching: Vec<(usize, Hitbox)> = fireballs_hitboxes()
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, ref fireball)| fireball.overlaps(hitbox))
+        .collect();
+    if touching.is_empty() {
+        return Vec::new();
+    }
+
+    FIREBALLS.with(|cell| {
+        let mut fireballs = cell.borrow_mut();
+        // touching is in index order, so back to front keeps the rest put
+        for &(hit, _) in touching.iter().rev() {
+            fireballs.remove(hit);
+        }
+    });
+    debug!("{} fireballs smashed", touching.len());
+    touching
+        .iter()
+        .map(|&(_, ref fireball)| (fireball.x + fireball.width / 2, fireball.y + fireball.height / 2))
+        .collect()
+}
+
+pub fn fireballs_live() -> Vec<Fireball> {
+    FIREBALLS.with(|cell| {
+        let fireballs = cell.borrow();
+        (0..fireballs.len()).map(|index| fireballs.get(index)).collect()
+    })
+}
+
+pub fn fireballs_hitboxes() -> Vec<Hitbox> {
+    let mut hitboxes = Vec::new();
+    fireballs_hitboxes_into(&mut hitboxes);
+    return hitboxes;
+}
+
+pub fn fireballs_hitboxes_into(hitboxes: &mut Vec<Hitbox>) {
+    let stage =
+        match STAGE.with(|cell| *cell.borrow()) {
+            Some(s) => s,
+            None => return,
+        };
+    FIREBALLS.with(|cell| {
+        let fireballs = cell.borrow();
+        hitboxes.extend(fireballs.x
+            .iter()
+            .zip(fireballs.girder.iter())
+            .map(|(&x, &girder)| {
+                let y = girder_surface(&stage.layout.girders[girder], x) - FIREBALL_SIZE;
+                Hitbox::new(x, y, FIREBALL_SIZE, FIREBALL_SIZE).inset(2)
+            }));
+    });
+}
+
+pub fn fireball_inspect() -> Vec<Vec<InspectField>> {
+    fireballs_live()
+        .iter()
+        .map(|fireball| vec![
+            InspectField::number("x", fireball.x),
+            InspectField::number("girder", fireball.girder as i32),
+            InspectField::number("direction", fireball.direction),
+            InspectField::label("state", "patrol"),
+            InspectField::label("clip", "flame"),
+        ])
+        .collect()
+}
+
+pub fn fireball_inspect_set(index: usize, field: &str, value: i32) -> Result<(), String> {
+    let girders = STAGE.with(|cell| cell.borrow().map_or(0, |stage| stage.layout.girders.len()));
+    FIREBALLS.with(|cell| {
+        let mut fireballs = cell.borrow_mut();
+        if index >= fireballs.len() {
+            return Err(format!("no fireball {}", index));
+        }
+        match field {
+            "x"         => fireballs.x[index] = value,
+            "girder"    => fireballs.girder[index] = (value.max(0) as usize).min(girders.max(1) - 1),
+            "direction" => fireballs.direction[index] = if value < 0 { -1 } else { 1 },
+            _ => return inspect_unknown("fireball", field),
+        }
+        return Ok(());
+    })
+}
+
+pub fn fireballs_hash(hasher: &mut StateHasher) {
+    FIREBALLS.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn fireballs_save(savestate: &mut SaveState) {
+    savestate.put("fireballs", FIREBALLS.with(|cell| cell.borrow().clone()));
+    savestate.put("fireballs_stage", STAGE.with(|cell| *cell.borrow()));
+}
+
+pub fn fireballs_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<Fireballs>("fireballs") {
+        FIREBALLS.with(|cell| {*cell.borrow_mut() = value;});
+    }
+    if let Some(stage) = savestate.get::<Option<&'static Stage>>("fireballs_stage") {
+        STAGE.with(|cell| {*cell.borrow_mut() = stage;});
+    }
+}
@@ -0,0 +1,307 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+use super::Hitbox;
+use rusty_kong::events::*;
+use rusty_kong::hashing::StateHasher;
+use rusty_kong::savestate::SaveState;
+use rusty_kong::inspector::*;
+use rusty_kong::level::*;
+use rusty_kong::physics::physics;
+use rusty_kong::playfield::*;
+use rusty_kong::sound::*;
+use rusty_kong::video::*;
+
+pub const JACK_SIZE: i32 = 16;
+pub const JACK_MAX:  usize = 4;
+pub const JACK_SPRITE_SLOT: usize = 48;
+
+// XXX: tile & palette by eye from the ripper output
+const JACK_TILE:    u16 = 0x3b;
+const JACK_PALETTE: u8 = 45;
+
+// every hop is HOP_TICKS long and HOP_HEIGHT pixels high at its peak,
+// moving a pixel right per tick
+const HOP_TICKS:  u32 = 32;
+const HOP_HEIGHT: i32 = 12;
+
+#[derive(Clone, Copy, Hash)]
+pub struct Jack {
+    pub x: i32,
+    pub y: i32,
+    hop: u32,
+    falling: bool,
+    // the girder it was dropped onto
+    girder: usize,
+}
+
+// a column a field, like the fireballs; index n is the nth jack dropped
+#[derive(Clone)]
+struct Jacks {
+    x: Vec<i32>,
+    y: Vec<i32>,
+    hop: Vec<u32>,
+    falling: Vec<bool>,
+    girder: Vec<usize>,
+    // per dropper, in stage.jack_rules() order
+    since_drop: Vec<u32>,
+}
+
+impl Jacks {
+    fn len(self:&Jacks) -> usize {
+        return self.x.len();
+    }
+
+    fn get(self:&Jacks, index: usize) -> Jack {
+        return Jack {
+            x: self.x[index],
+            y: self.y[index],
+            hop: self.hop[index],
+            falling: self.falling[index],
+            girder: self.girder[index],
+        };
+    }
+
+    fn push(self:&mut Jacks, jack: Jack) {
+        self.x.push(jack.x);
+        self.y.push(jack.y);
+        self.hop.push(jack.hop);
+        self.falling.push(jack.falling);
+        self.girder.push(jack.girder);
+    }
+
+    fn clear(self:&mut Jacks) {
+        self.x.clear();
+        self.y.clear();
+        self.hop.clear();
+        self.falling.clear();
+        self.girder.clear();
+    }
+
+    // keeps the ones keep says to, in order
+    fn retain<F: Fn(&Jacks, usize) -> bool>(self:&mut Jacks, keep: F) {
+        let mut kept = 0;
+        for index in 0..self.len() {
+            if !keep(self, index) {
+                continue;
+            }
+            self.x[kept] = self.x[index];
+            self.y[kept] = self.y[index];
+            self.hop[kept] = self.hop[index];
+            self.falling[kept] = self.falling[index];
+            self.girder[kept] = self.girder[index];
+            kept += 1;
+        }
+        self.x.truncate(kept);
+        self.y.truncate(kept);
+        self.hop.truncate(kept);
+        self.falling.truncate(kept);
+        self.girder.truncate(kept);
+    }
+}
+
+// the same stream a Vec<Jack> hashed to
+impl Hash for Jacks {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for index in 0..self.len() {
+            self.get(index).hash(state);
+        }
+        self.since_drop.hash(state);
+    }
+}
+
+thread_local!(
+    static JACKS:RefCell<Jacks> = RefCell::new(Jacks {
+        x: Vec::new(),
+        y: Vec::new(),
+        hop: Vec::new(),
+        falling: Vec::new(),
+        girder: Vec::new(),
+        since_drop: Vec::new()
+    });
+    static STAGE:RefCell<Option<&'static Stage>> = RefCell::new(None);
+);
+
+fn jacks_hide() {
+    for slot in 0..JACK_MAX {
+        video_sprite_hide(JACK_SPRITE_SLOT + slot);
+    }
+}
+
+pub fn jacks_stage_start(stage: &'static Stage) {
+    JACKS.with(|cell| {
+        let mut jacks = cell.borrow_mut();
+        jacks.clear();
+        jacks.since_drop = vec![0; stage.jack_rules().len()];
+    });
+    STAGE.with(|cell| {*cell.borrow_mut() = Some(stage);});
+    jacks_hide();
+}
+
+pub fn jacks_clear() {
+    JACKS.with(|cell| {cell.borrow_mut().clear();});
+    STAGE.with(|cell| {*cell.borrow_mut() = None;});
+    jacks_hide();
+}
+
+// the height of a hop at tick t into it
+fn hop_height(t: u32) -> i32 {
+    let t = t as i32;
+    let length = HOP_TICKS as i32;
+    4 * HOP_HEIGHT * t * (length - t) / (length * length)
+}
+
+// falling ones drop straight down, once off the end of their girder; the
+// rest hop a pixel right
+fn jacks_step(jacks: &mut Jacks, girders: &[Girder]) {
+    let drop_speed = physics().jack_drop_speed;
+    for (y, &falling) in jacks.y.iter_mut().zip(jacks.falling.iter()) {
+        if falling {
+            *y += drop_speed;
+        }
+    }
+    for index in 0..jacks.len() {
+        if jacks.falling[index] {
+            continue;
+        }
+        let girder = &girders[jacks.girder[index]];
+        jacks.x[index] += 1;
+        jacks.hop[index] = (jacks.hop[index] + 1) % HOP_TICKS;
+        if jacks.hop[index] == 0 {
+            sound_play(SoundId::Spring);
+        }
+        let center = jacks.x[index] + JACK_SIZE / 2;
+        if center >= girder.right() {
+            jacks.falling[index] = true;
+            continue;
+        }
+        jacks.y[index] = girder.surface(center) - JACK_SIZE - hop_height(jacks.hop[index]);
+    }
+}
+
+pub fn jacks_update() {
+    let stage =
+        match STAGE.with(|cell| *cell.borrow()) {
+            Some(s) => s,
+            None => return,
+        };
+    let droppers = stage.jack_rules();
+    if droppers.is_empty() {
+        return;
+    }
+
+    let difficulty = level_difficulty();
+    let girders = stage.layout.girders;
+    let dropped = JACKS.with(|cell| {
+        let mut jacks = cell.borrow_mut();
+        let mut dropped = 0;
+
+        // a savestate from before remix was switched on has fewer
+        jacks.since_drop.resize(droppers.len(), 0);
+        for (index, rules) in droppers.iter().enumerate() {
+            jacks.since_drop[index] += 1;
+            if jacks.since_drop[index] < rules.interval[(difficulty.max(1) - 1) as usize] {
+                continue;
+            }
+            jacks.since_drop[index] = 0;
+            if jacks.len() < JACK_MAX {
+                let y = playfield_row_top(girders[rules.girder].row) - JACK_SIZE;
+                jacks.push(Jack { x: rules.x, y: y, hop: 0, falling: false, girder: rules.girder });
+                dropped += 1;
+                debug!("jack dropped on {} by dropper {} (difficulty {}, {} live)", stage.name, index, difficulty, jacks.len());
+            } else {
+                debug!("jack drop skipped on {}: {} live", stage.name, jacks.len());
+            }
+        }
+
+        jacks_step(&mut jacks, girders);
+        jacks.retain(|jacks, index| !PLAYFIELD.is_below(jacks.y[index]));
+        dropped
+    });
+    for _ in 0..dropped {
+        events_publish(GameEvent::EnemySpawned { kind: EnemyKind::Spring });
+    }
+
+    jacks_hide();
+    for (slot, jack) in jacks_live().iter().enumerate() {
+        video_sprite_set(JACK_SPRITE_SLOT + slot, JACK_TILE, jack.x as u16, jack.y.max(0) as u16, JACK_PALETTE);
+    }
+}
+
+pub fn jacks_live() -> Vec<Jack> {
+    JACKS.with(|cell| {
+        let jacks = cell.borrow();
+        (0..jacks.len()).map(|index| jacks.get(index)).collect()
+    })
+}
+
+pub fn jacks_hitboxes_into(hitboxes: &mut Vec<Hitbox>) {
+    JACKS.with(|cell| {
+        let jacks = cell.borrow();
+        hitboxes.extend(jacks.x
+            .iter()
+            .zip(jacks.y.iter())
+            .map(|(&x, &y)| Hitbox::new(x, y, JACK_SIZE, JACK_SIZE)));
+    });
+}
+
+pub fn jack_inspect() -> Vec<Vec<InspectField>> {
+    jacks_live()
+        .iter()
+        .map(|jack| vec![
+            InspectField::number("x", jack.x),
+            InspectField::number("y", jack.y),
+            InspectField::number("hop", jack.hop as i32),
+            InspectField::label("state", if jack.falling { "falling" } else { "hopping" }),
+            InspectField::label("clip", "bounce"),
+        ])
+        .collect()
+}
+
+pub fn jack_inspect_set(index: usize, field: &str, value: i32) -> Result<(), String> {
+    JACKS.with(|cell| {
+        let mut jacks = cell.borrow_mut();
+        if index >= jacks.len() {
+            return Err(format!("no jack {}", index));
+        }
+        match field {
+            "x"   => jacks.x[index] = value,
+            "y"   => jacks.y[index] = value,
+            "hop" => jacks.hop[index] = value.max(0) as u32 % HOP_TICKS,
+            _ => return inspect_unknown("jack", field),
+        }
+        return Ok(());
+    })
+}
+
+pub fn jacks_hash(hasher: &mut StateHasher) {
+    JACKS.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn jacks_save(savestate: &mut SaveState) {
+    savestate.put("jacks", JACKS.with(|cell| cell.borrow().clone()));
+    savestate.put("jacks_stage", STAGE.with(|cell| *cell.borrow()));
+}
+
+pub fn jacks_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<Jacks>("jacks") {
+        JACKS.with(|cell| {*cell.borrow_mut() = value;});
+    }
+    if let Some(stage) = savestate.get::<Option<&'static Stage>>("jacks_stage") {
+        STAGE.with(|cell| {*cell.borrow_mut() = stage;});
+    }
+}
@@ -0,0 +1,124 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use super::hashing::StateHasher;
+use super::savestate::SaveState;
+use super::level::Stage;
+
+mod fireball;
+pub use self::fireball::*;
+
+mod jack;
+pub use self::jack::*;
+
+mod smash;
+pub use self::smash::*;
+
+// screen pixels; every enemy is boiled down to one of these per tick and
+// GamePlay tests the player's against them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Hitbox {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Hitbox {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Hitbox {
+        Hitbox { x: x, y: y, width: width, height: height }
+    }
+
+    // a sprite's box pulled in by margin on every side; the arcade is
+    // forgiving about corners
+    pub fn inset(self:&Hitbox, margin: i32) -> Hitbox {
+        Hitbox::new(self.x + margin, self.y + margin, self.width - margin * 2, self.height - margin * 2)
+    }
+
+    pub fn overlaps(self:&Hitbox, other: &Hitbox) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+}
+
+// enemies only ever move from here, and only GamePlay calls it, so every
+// other state freezes them for free
+pub fn enemies_stage_start(stage: &'static Stage) {
+    fireballs_stage_start(stage);
+    jacks_stage_start(stage);
+    smashes_clear();
+}
+
+pub fn enemies_clear() {
+    fireballs_clear();
+    jacks_clear();
+    smashes_clear();
+}
+
+pub fn enemies_update() {
+    fireballs_update();
+    jacks_update();
+    smashes_update();
+}
+
+// knocks out everything the hammer can break that's inside hitbox; returns
+// the contact point and points for each.  jacks can't be smashed.
+pub fn enemies_smash(hitbox: &Hitbox) -> Vec<(i32, i32, u32)> {
+    let mut smashed = Vec::new();
+    for (x, y) in fireballs_remove_touching(hitbox) {
+        let points = smash_points();
+        smash_spawn(x, y, points);
+        smashed.push((x, y, points));
+    }
+    return smashed;
+}
+
+// everything that kills on contact this tick
+pub fn enemies_hitboxes() -> Vec<Hitbox> {
+    let mut hitboxes = Vec::new();
+    enemies_hitboxes_into(&mut hitboxes);
+    return hitboxes;
+}
+
+fn enemies_hitboxes_into(hitboxes: &mut Vec<Hitbox>) {
+    fireballs_hitboxes_into(hitboxes);
+    jacks_hitboxes_into(hitboxes);
+}
+
+// every tick, so the candidates go in an arena buffer
+pub fn enemies_touching(hitbox: &Hitbox) -> bool {
+    use super::arena::arena_vec;
+    arena_vec(|hitboxes| {
+        enemies_hitboxes_into(hitboxes);
+        hitboxes.iter().any(|enemy| enemy.overlaps(hitbox))
+    })
+}
+
+pub fn enemies_hash(hasher: &mut StateHasher) {
+    fireballs_hash(hasher);
+    jacks_hash(hasher);
+    smashes_hash(hasher);
+}
+
+pub fn enemies_save(savestate: &mut SaveState) {
+    fireballs_save(savestate);
+    jacks_save(savestate);
+    smashes_save(savestate);
+}
+
+pub fn enemies_restore(savestate: &SaveState) {
+    fireballs_restore(savestate);
+    jacks_restore(savestate);
+    smashes_restore(savestate);
+}
@@ -0,0 +1,129 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use rusty_kong::hashing::StateHasher;
+use rusty_kong::savestate::SaveState;
+use rusty_kong::state_machine::Easing;
+use rusty_kong::video::*;
+
+pub const SMASH_MAX: usize = 4;
+pub const SMASH_SPRITE_SLOT: usize = 60;
+
+// XXX: tiles & palette by eye from the ripper output
+const SMASH_FRAMES:  [u16; 4] = [0x60, 0x61, 0x62, 0x63];
+const SMASH_PALETTE: u8 = 47;
+const POINTS_TILES:  [(u32, u16); 3] = [(300, 0x7b), (500, 0x7d), (800, 0x7f)];
+
+// the burst swaps frames every SMASH_FRAME_TICKS, then the points hang
+// there for POINTS_TICKS
+const SMASH_FRAME_TICKS: u32 = 6;
+const SMASH_TICKS:       u32 = SMASH_FRAME_TICKS * SMASH_FRAMES.len() as u32;
+const POINTS_TICKS:      u32 = 60;
+// the points drift up this far over the first POINTS_RISE_TICKS
+const POINTS_RISE:       i32 = 8;
+const POINTS_RISE_TICKS: u32 = 20;
+
+// the score a smash is worth, picked at random the way the arcade does
+pub fn smash_points() -> u32 {
+    use rusty_kong::rng::rng_below;
+    POINTS_TILES[rng_below("smash_points", POINTS_TILES.len() as u32) as usize].0
+}
+
+#[derive(Clone, Copy, Hash)]
+struct Smash {
+    x: i32,
+    y: i32,
+    points: u32,
+    ticks: u32,
+}
+
+// a fixed pool; each effect keeps its sprite slot from start to finish
+thread_local!(
+    static SMASHES:RefCell<[Option<Smash>; SMASH_MAX]> = RefCell::new([None; SMASH_MAX]);
+);
+
+pub fn smashes_clear() {
+    SMASHES.with(|cell| {*cell.borrow_mut() = [None; SMASH_MAX];});
+    for slot in 0..SMASH_MAX {
+        video_sprite_hide(SMASH_SPRITE_SLOT + slot);
+    }
+}
+
+// x/y is the contact point; with the pool full the oldest effect is cut
+// short rather than the new one going missing
+pub fn smash_spawn(x: i32, y: i32, points: u32) {
+    SMASHES.with(|cell| {
+        let mut smashes = cell.borrow_mut();
+        let slot = smashes
+            .iter()
+            .position(|smash| smash.is_none())
+            .unwrap_or_else(|| {
+                smashes
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|&(_, smash)| smash.map_or(0, |s| s.ticks))
+                    .map_or(0, |(index, _)| index)
+            });
+        smashes[slot] = Some(Smash { x: x - 8, y: y - 8, points: points, ticks: 0 });
+    });
+}
+
+pub fn smashes_update() {
+    SMASHES.with(|cell| {
+        let mut smashes = cell.borrow_mut();
+        for (slot, entry) in smashes.iter_mut().enumerate() {
+            let sprite = SMASH_SPRITE_SLOT + slot;
+            let finished =
+                match *entry {
+                    Some(ref mut smash) => {
+                        let (tile, rise) =
+                            if smash.ticks < SMASH_TICKS {
+                                (SMASH_FRAMES[(smash.ticks / SMASH_FRAME_TICKS) as usize], 0)
+                            } else {
+                                let tile = POINTS_TILES
+                                    .iter()
+                                    .find(|&&(points, _)| points == smash.points)
+                                    .map_or(POINTS_TILES[0].1, |&(_, tile)| tile);
+                                let shown = (smash.ticks - SMASH_TICKS).min(POINTS_RISE_TICKS);
+                                (tile, Easing::EaseOut.value(0, POINTS_RISE, shown, POINTS_RISE_TICKS))
+                            };
+                        let y = smash.y - rise;
+                        video_sprite_set(sprite, tile, smash.x.max(0) as u16, y.max(0) as u16, SMASH_PALETTE);
+                        smash.ticks += 1;
+                        smash.ticks >= SMASH_TICKS + POINTS_TICKS
+                    },
+                    None => false,
+                };
+            if finished {
+                *entry = None;
+                video_sprite_hide(sprite);
+            }
+        }
+    });
+}
+
+pub fn smashes_hash(hasher: &mut StateHasher) {
+    SMASHES.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn smashes_save(savestate: &mut SaveState) {
+    savestate.put("smashes", SMASHES.with(|cell| cell.borrow().clone()));
+}
+
+pub fn smashes_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<[Option<Smash>; SMASH_MAX]>("smashes") {
+        SMASHES.with(|cell| {*cell.borrow_mut() = value;});
+    }
+}
@@ -0,0 +1,168 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+
+use super::EVENT_LOG_DIR;
+use rusty_kong::video::FrameBuffer;
+use rusty_kong::video::PaletteEntry;
+use rusty_kong::video::SCREEN_HEIGHT;
+use rusty_kong::video::SCREEN_WIDTH;
+use rusty_kong::video::TILE_HEIGHT;
+use rusty_kong::video::TILE_WIDTH;
+
+// deaths are counted a tile to a cell, by where the middle of the player
+// was; the stage under it is drawn through the hottest cells
+const HEATMAP_COLS: usize = (SCREEN_WIDTH / TILE_WIDTH) as usize;
+const HEATMAP_ROWS: usize = (SCREEN_HEIGHT / TILE_HEIGHT) as usize;
+const HEATMAP_PLAYER_CENTER: u32 = 8;
+
+struct Heatmap {
+    shown: bool,
+    // stage name to deaths per cell, row by row
+    deaths: HashMap<String, Vec<u32>>,
+}
+
+thread_local!(
+    static HEATMAP:RefCell<Heatmap> = RefCell::new(Heatmap {
+        shown: false,
+        deaths: HashMap::new()
+    });
+);
+
+// XXX: only reads back what event_log writes, a flat object without
+// escapes in the values it needs, rather than json in general
+fn heatmap_field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let tag = format!("\"{}\":", key);
+    let start = line.find(&tag)? + tag.len();
+    let rest = &line[start..];
+    if rest.starts_with('"') {
+        let end = rest[1..].find('"')?;
+        return Some(&rest[1..end + 1]);
+    }
+    let end = rest.find(|c| c == ',' || c == '}').unwrap_or(rest.len());
+    return Some(&rest[..end]);
+}
+
+fn heatmap_read(path: &Path, deaths: &mut HashMap<String, Vec<u32>>) -> Result<u32, String> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let mut count = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        if heatmap_field(&line, "event") != Some("player_died") {
+            continue;
+        }
+        let stage = heatmap_field(&line, "stage");
+        let x = heatmap_field(&line, "x").and_then(|x| x.parse::<u32>().ok());
+        let y = heatmap_field(&line, "y").and_then(|y| y.parse::<u32>().ok());
+        if let (Some(stage), Some(x), Some(y)) = (stage, x, y) {
+            let col = ((x + HEATMAP_PLAYER_CENTER) / TILE_WIDTH) as usize;
+            let row = ((y + HEATMAP_PLAYER_CENTER) / TILE_HEIGHT) as usize;
+            if col >= HEATMAP_COLS || row >= HEATMAP_ROWS {
+                continue;
+            }
+            let cells = deaths
+                .entry(stage.to_string())
+                .or_insert_with(|| vec![0; HEATMAP_COLS * HEATMAP_ROWS]);
+            cells[row * HEATMAP_COLS + col] += 1;
+            count += 1;
+        }
+    }
+    return Ok(count);
+}
+
+// every session's deaths under event_logs/, this one's so far included
+fn heatmap_load() -> HashMap<String, Vec<u32>> {
+    let mut deaths = HashMap::new();
+    let entries =
+        match fs::read_dir(EVENT_LOG_DIR) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("death heatmap: can't read {}: {}", EVENT_LOG_DIR, e);
+                return deaths;
+            },
+        };
+    let mut sessions = 0;
+    let mut count = 0;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.extension().map_or(true, |extension| extension != "jsonl") {
+            continue;
+        }
+        match heatmap_read(&path, &mut deaths) {
+            Ok(read) => {
+                sessions += 1;
+                count += read;
+            },
+            Err(e) => warn!("death heatmap: {}", e),
+        }
+    }
+    info!("death heatmap: {} deaths on {} stages from {} sessions", count, deaths.len(), sessions);
+    return deaths;
+}
+
+// the logs are read again each time it's switched on
+pub fn heatmap_toggle() {
+    let shown = !HEATMAP.with(|cell| cell.borrow().shown);
+    let deaths = if shown { heatmap_load() } else { HashMap::new() };
+    HEATMAP.with(|cell| {
+        let mut heatmap = cell.borrow_mut();
+        heatmap.shown = shown;
+        heatmap.deaths = deaths;
+    });
+}
+
+// yellow for the odd death through to red for the most on the stage
+fn heatmap_color(count: u32, most: u32) -> PaletteEntry {
+    let heat = (count * 0xff / most) as u8;
+    return PaletteEntry { r: 0xff, g: 0xff - heat, b: 0x00, a: 0xff };
+}
+
+fn heatmap_overlay(framebuffer: &mut FrameBuffer) {
+    use rusty_kong::level::level_current_stage;
+    use rusty_kong::state_machine::game_state_in_game;
+
+    if !HEATMAP.with(|cell| cell.borrow().shown) || !game_state_in_game() {
+        return;
+    }
+    let stage = level_current_stage();
+    HEATMAP.with(|cell| {
+        let heatmap = cell.borrow();
+        let cells =
+            match heatmap.deaths.get(stage.name) {
+                Some(cells) => cells,
+                None => return,
+            };
+        let most = cells.iter().cloned().max().unwrap_or(0);
+        for (index, &count) in cells.iter().enumerate().filter(|&(_, &count)| count > 0) {
+            let color = heatmap_color(count, most);
+            let left = (index % HEATMAP_COLS) as u32 * TILE_WIDTH;
+            let top = (index / HEATMAP_COLS) as u32 * TILE_HEIGHT;
+            for y in top..top + TILE_HEIGHT {
+                for x in left..left + TILE_WIDTH {
+                    framebuffer.blend(x, y, color);
+                }
+            }
+        }
+    });
+}
+
+pub fn heatmap_init() {
+    use rusty_kong::video::video_add_overlay;
+
+    video_add_overlay(heatmap_overlay);
+}
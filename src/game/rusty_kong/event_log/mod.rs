@@ -0,0 +1,188 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::clock::clock_ticks;
+use super::events::GameEvent;
+
+mod heatmap;
+pub use self::heatmap::*;
+
+pub const EVENT_LOG_DIR: &str = "event_logs";
+
+// one line of json per event, tagged with the tick it was published on:
+//
+//     {"tick":1234,"event":"state_changed","from":"how_high","to":"game_play"}
+//
+// the first line says when the session started and the settings that
+// change how the game plays, so logs from different setups can be told apart
+thread_local!(
+    static LOG:RefCell<Option<File>> = RefCell::new(None);
+);
+
+// event_logs/yyyy-mm-dd-n.jsonl, with n the first one not taken
+fn event_log_path() -> PathBuf {
+    use super::daily::daily_date;
+    use super::daily::daily_today;
+
+    let date = daily_date(daily_today());
+    let mut n = 1;
+    loop {
+        let path = Path::new(EVENT_LOG_DIR).join(format!("{}-{}.jsonl", date, n));
+        if !path.exists() {
+            return path;
+        }
+        n += 1;
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    return quoted;
+}
+
+// the fields after "tick" and "event", already as json
+fn event_log_fields(event: &GameEvent) -> (&'static str, Vec<(&'static str, String)>) {
+    use super::level::level_current_stage;
+    use super::player::player_current;
+    use super::player::player_position;
+    use super::player::player_score;
+
+    match event {
+        &GameEvent::StateChanged { from, to } => ("state_changed", vec![
+            ("from", json_string(&from.to_string())),
+            ("to", json_string(&to.to_string())),
+        ]),
+        &GameEvent::GameStarted => ("game_started", Vec::new()),
+        &GameEvent::StageCleared { level, stage, ticks } => ("stage_cleared", vec![
+            ("level", level.number.to_string()),
+            ("stage", json_string(stage.name)),
+            ("ticks", ticks.to_string()),
+        ]),
+        &GameEvent::Reset => ("reset", Vec::new()),
+        &GameEvent::Tilted => ("tilted", Vec::new()),
+        &GameEvent::PlayerDied => {
+            let (x, y) = player_position();
+            ("player_died", vec![
+                ("player", (player_current() + 1).to_string()),
+                ("stage", json_string(level_current_stage().name)),
+                ("x", x.to_string()),
+                ("y", y.to_string()),
+            ])
+        },
+        &GameEvent::EnemySmashed => ("enemy_smashed", Vec::new()),
+        &GameEvent::KongStomped => ("kong_stomped", Vec::new()),
+        &GameEvent::EnemySpawned { kind } => ("enemy_spawned", vec![
+            ("kind", json_string(kind.name())),
+        ]),
+        &GameEvent::Scored { points } => ("scored", vec![
+            ("player", (player_current() + 1).to_string()),
+            ("points", points.to_string()),
+            ("score", player_score().to_string()),
+        ]),
+        &GameEvent::MenuMoved => ("menu_moved", Vec::new()),
+        &GameEvent::Persisted { job, ok } => ("persisted", vec![
+            ("job", json_string(job.name())),
+            ("ok", ok.to_string()),
+        ]),
+    }
+}
+
+fn event_log_line(name: &str, fields: &[(&'static str, String)]) -> String {
+    let mut line = format!("{{\"tick\":{},\"event\":{}", clock_ticks(), json_string(name));
+    for &(key, ref value) in fields.iter() {
+        line.push_str(&format!(",{}:{}", json_string(key), value));
+    }
+    line.push_str("}\n");
+    return line;
+}
+
+// written straight through, so a crash loses nothing; a failed write
+// stops the log rather than warning every event
+fn event_log_write(line: &str) {
+    LOG.with(|cell| {
+        let mut log = cell.borrow_mut();
+        let failed =
+            match log.as_mut() {
+                Some(file) => file.write_all(line.as_bytes()).is_err(),
+                None => false,
+            };
+        if failed {
+            warn!("event log write failed, no more events this session");
+            *log = None;
+        }
+    });
+}
+
+fn event_log_on_event(event: &GameEvent) {
+    let (name, fields) = event_log_fields(event);
+    event_log_write(&event_log_line(name, &fields));
+}
+
+fn event_log_open() -> Result<(File, PathBuf), String> {
+    fs::create_dir_all(EVENT_LOG_DIR).map_err(|e| format!("can't create {}: {}", EVENT_LOG_DIR, e))?;
+    let path = event_log_path();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    return Ok((file, path));
+}
+
+pub fn event_log_init() {
+    use super::clock::clock_unix_secs;
+    use super::events::events_subscribe;
+    use super::settings::settings_logic_pairs;
+    use super::settings::settings_read;
+
+    if !settings_read(|s| s.event_log) {
+        return;
+    }
+
+    match event_log_open() {
+        Ok((file, path)) => {
+            info!("logging gameplay events to {}", path.display());
+            LOG.with(|cell| {*cell.borrow_mut() = Some(file);});
+        },
+        Err(e) => {
+            warn!("no event log this session: {}", e);
+            return;
+        },
+    }
+
+    let settings: Vec<String> = settings_logic_pairs()
+        .iter()
+        .map(|&(key, ref value)| format!("{}:{}", json_string(key), json_string(value)))
+        .collect();
+    event_log_write(&event_log_line("session", &[
+        ("started", clock_unix_secs().to_string()),
+        ("settings", format!("{{{}}}", settings.join(","))),
+    ]));
+    events_subscribe(event_log_on_event);
+}
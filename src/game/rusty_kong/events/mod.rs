@@ -0,0 +1,69 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+
+use super::state_machine::GameState;
+use super::level::EnemyKind;
+use super::level::Level;
+use super::level::Stage;
+use super::persist::PersistJob;
+
+#[derive(Clone, Copy)]
+pub enum GameEvent {
+    StateChanged { from: GameState, to: GameState },
+    // a scoring game begins; practice doesn't publish this
+    GameStarted,
+    // published before the sequencer moves on, ticks is the time on the stage
+    StageCleared { level: Level, stage: &'static Stage, ticks: u32 },
+    // the operator reset; whatever was in progress is abandoned, not finished
+    Reset,
+    // the tilt, published after its Reset: the game was voided
+    Tilted,
+    // the moments worth feeling through a pad: the death sequence's hit,
+    // an enemy under the hammer and kong flattening a girder in the intro
+    PlayerDied,
+    EnemySmashed,
+    KongStomped,
+    // for the event log: one enemy in, and points onto the current
+    // player's score, from a smash or the bonus tally alike
+    EnemySpawned { kind: EnemyKind },
+    Scored { points: u32 },
+    // the main menu's cursor moved, or what's under it changed
+    MenuMoved,
+    // a write handed to the io worker is done, ok or not; it's already
+    // been logged
+    Persisted { job: PersistJob, ok: bool },
+}
+
+pub type EventSubscriber = fn(&GameEvent);
+
+thread_local!(
+    static SUBSCRIBERS:RefCell<Vec<EventSubscriber>> = RefCell::new(Vec::new());
+);
+
+pub fn events_subscribe(subscriber: EventSubscriber) {
+    SUBSCRIBERS.with(|cell| {cell.borrow_mut().push(subscriber);});
+}
+
+pub fn events_publish(event: GameEvent) {
+    use super::arena::arena_vec;
+
+    // work from a copy so subscribers are free to publish or subscribe
+    // themselves; an arena buffer, as there are several events a tick
+    arena_vec(|subscribers: &mut Vec<EventSubscriber>| {
+        SUBSCRIBERS.with(|cell| subscribers.extend(cell.borrow().iter().cloned()));
+        for subscriber in subscribers.iter() {
+            subscriber(&event);
+        }
+    });
+}
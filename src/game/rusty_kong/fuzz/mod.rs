@@ -0,0 +1,69 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+// the parsers that see files the game didn't write: each takes any bytes
+// at all and must come back, refusing what it can't use, without panicking
+// or allocating past its limits.  shaped for a cargo-fuzz fuzz_target!, and
+// run over the corpora under tests/fuzz/<name>/ by --fuzz-corpus.
+pub const FUZZ_TARGETS: [(&str, fn(&[u8])); 5] = [
+    ("replay",          super::replay::replay_fuzz),
+    ("manifest",        super::assets::assets_manifest_fuzz),
+    ("pack",            super::assets::pack_fuzz),
+    ("community_index", super::community::community_index_fuzz),
+    ("key_value",       super::settings::key_value_fuzz),
+];
+
+// every file in dir/<target>/ through its target; false when one panicked
+// or a target has no corpus
+pub fn fuzz_corpus(dir: &Path) -> bool {
+    let mut passed = true;
+    let mut total = 0;
+    for &(name, target) in FUZZ_TARGETS.iter() {
+        let corpus = dir.join(name);
+        let entries =
+            match fs::read_dir(&corpus) {
+                Ok(e) => e,
+                Err(e) => {
+                    error!("{}: {}", corpus.display(), e);
+                    passed = false;
+                    continue;
+                },
+            };
+        let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_file()).collect();
+        paths.sort();
+        for path in paths.iter() {
+            let bytes =
+                match fs::read(path) {
+                    Ok(b) => b,
+                    Err(e) => {
+                        error!("{}: {}", path.display(), e);
+                        passed = false;
+                        continue;
+                    },
+                };
+            if panic::catch_unwind(|| target(&bytes)).is_err() {
+                error!("{}: {} panicked", path.display(), name);
+                passed = false;
+            }
+            total += 1;
+        }
+        info!("{}: {} inputs", name, paths.len());
+    }
+    if passed {
+        info!("{}: all {} inputs came back", dir.display(), total);
+    }
+    return passed;
+}
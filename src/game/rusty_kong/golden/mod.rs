@@ -0,0 +1,427 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+
+use super::hashing::StateHasher;
+use super::rng::RNG_DEFAULT_SEED;
+use super::video::FrameBuffer;
+
+pub const GOLDEN_KEYFRAME_INTERVAL: u32 = 60;
+
+// a script is a list of directives, one per line ('#' starts a comment):
+//
+//      frames 600          how many logic ticks to run
+//      keyframes 60        hash the framebuffer every N ticks (optional)
+//      seed 1234           seed the rng before the first tick (optional)
+//      120 coin            hold inputs on a single tick...
+//      130-145 start jump  ...or over an inclusive range of ticks
+//
+// the goldens live next to the script with a .golden extension; a script
+// without one fails until it's blessed.
+#[derive(Clone)]
+struct GoldenScript {
+    frames: u32,
+    keyframe_interval: u32,
+    seed: u32,
+    inputs: Vec<(u32, u32, u8)>,
+}
+
+impl GoldenScript {
+    fn input_at(self:&GoldenScript, frame:u32) -> u8 {
+        use super::input::F_INPUT_NONE;
+
+        self.inputs
+            .iter()
+            .filter(|&&(first, last, _)| frame >= first && frame <= last)
+            .fold(F_INPUT_NONE, |flags, &(_, _, input)| flags | input)
+    }
+}
+
+fn parse_frame(text: &str) -> Result<u32, String> {
+    text.parse::<u32>().map_err(|_| format!("expected a frame number, got \"{}\"", text))
+}
+
+fn golden_parse_script(path: &Path) -> Result<GoldenScript, String> {
+    use super::input::input_name_to_flag;
+    use super::input::F_INPUT_NONE;
+
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let mut script = GoldenScript {
+        frames: 0,
+        keyframe_interval: GOLDEN_KEYFRAME_INTERVAL,
+        seed: RNG_DEFAULT_SEED,
+        inputs: Vec::new(),
+    };
+
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let location = format!("{}:{}", path.display(), number + 1);
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap();
+        match first {
+            "seed" => {
+                script.seed =
+                    match words.next() {
+                        Some(v) => v.parse::<u32>().map_err(|_| format!("{}: seed expects a number, got \"{}\"", location, v))?,
+                        None => return Err(format!("{}: seed expects a number", location)),
+                    };
+            },
+            "frames" | "keyframes" => {
+                let value =
+                    match words.next() {
+                        Some(v) => parse_frame(v).map_err(|e| format!("{}: {}", location, e))?,
+                        None => return Err(format!("{}: {} expects a count", location, first)),
+                    };
+                if first == "frames" {
+                    script.frames = value;
+                } else if value == 0 {
+                    return Err(format!("{}: keyframes must be at least 1", location));
+                } else {
+                    script.keyframe_interval = value;
+                }
+            },
+            _ => {
+                let mut range = first.splitn(2, '-');
+                let from = parse_frame(range.next().unwrap()).map_err(|e| format!("{}: {}", location, e))?;
+                let to =
+                    match range.next() {
+                        Some(t) => parse_frame(t).map_err(|e| format!("{}: {}", location, e))?,
+                        None => from,
+                    };
+                if to < from {
+                    return Err(format!("{}: range {} ends before it starts", location, first));
+                }
+
+                let mut flags = F_INPUT_NONE;
+                for word in words {
+                    match input_name_to_flag(word) {
+                        Some(flag) => flags |= flag,
+                        None => return Err(format!("{}: unknown input \"{}\"", location, word)),
+                    }
+                }
+                script.inputs.push((from, to, flags));
+            },
+        }
+    }
+
+    if script.frames == 0 {
+        return Err(format!("{}: missing \"frames\" directive", path.display()));
+    }
+    return Ok(script);
+}
+
+fn golden_read(path: &Path) -> Result<Vec<(u32, u64)>, String> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let mut keyframes = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let mut words = line.split_whitespace();
+        let parsed =
+            match (words.next(), words.next()) {
+                (Some(frame), Some(hash)) => {
+                    match (frame.parse::<u32>(), u64::from_str_radix(hash, 16)) {
+                        (Ok(f), Ok(h)) => Some((f, h)),
+                        _ => None,
+                    }
+                },
+                _ => None,
+            };
+        match parsed {
+            Some(keyframe) => keyframes.push(keyframe),
+            None => return Err(format!("{}:{}: expected \"frame hash\"", path.display(), number + 1)),
+        }
+    }
+    return Ok(keyframes);
+}
+
+fn golden_write(path: &Path, keyframes: &[(u32, u64)]) -> Result<(), String> {
+    let mut file = File::create(path).map_err(|e| format!("can't create {}: {}", path.display(), e))?;
+    for &(frame, hash) in keyframes.iter() {
+        writeln!(file, "{} {:016x}", frame, hash).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    }
+    return Ok(());
+}
+
+// headless: no window, no controller, default settings so a local
+// settings.cfg can't change the outcome, and held so the script's trips
+// through the menus don't write one either
+fn golden_start(script: &GoldenScript) {
+    use super::locale::locale_init;
+    use super::physics::physics_init;
+    use super::sequence::sequence_init;
+    use super::rng::rng_seed;
+    use super::settings::settings_hold;
+    use super::state_machine::game_state_init;
+
+    settings_hold(true);
+    locale_init();
+    physics_init();
+    sequence_init();
+    rng_seed(script.seed);
+    game_state_init();
+}
+
+fn golden_record(script: &GoldenScript) -> Vec<(u32, u64)> {
+    use super::game_update;
+    use super::input::input_set;
+    use super::video::video_compose;
+
+    golden_start(script);
+
+    let mut framebuffer = FrameBuffer::new();
+    let mut keyframes = Vec::new();
+    for frame in 0..script.frames {
+        input_set(script.input_at(frame));
+        let report = game_update();
+        if report.transitioned {
+            info!("frame {}: {} -> {}", frame, report.from, report.to);
+        }
+
+        if frame % script.keyframe_interval == 0 {
+            video_compose(&mut framebuffer);
+            let mut hasher = StateHasher::new();
+            hasher.write(&framebuffer.pixels);
+            keyframes.push((frame, hasher.finish()));
+        }
+    }
+    return keyframes;
+}
+
+pub fn golden_run(script_path: &Path, bless: bool) -> bool {
+    let script =
+        match golden_parse_script(script_path) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("{}", e);
+                return false;
+            },
+        };
+
+    let golden_path = script_path.with_extension("golden");
+    if !bless && !golden_path.is_file() {
+        error!("{}: no {} to check against (run with --bless to create it)", script_path.display(), golden_path.display());
+        return false;
+    }
+    let actual = golden_record(&script);
+
+    if bless {
+        return match golden_write(&golden_path, &actual) {
+            Ok(()) => {
+                info!("blessed {} keyframes into {}", actual.len(), golden_path.display());
+                true
+            },
+            Err(e) => {
+                error!("{}", e);
+                false
+            },
+        };
+    }
+
+    let expected =
+        match golden_read(&golden_path) {
+            Ok(k) => k,
+            Err(e) => {
+                error!("{} (run with --bless to recreate the goldens)", e);
+                return false;
+            },
+        };
+
+    if expected.len() != actual.len() {
+        error!(
+            "{}: expected {} keyframes, recorded {}",
+            golden_path.display(),
+            expected.len(),
+            actual.len());
+        return false;
+    }
+
+    for (&(expected_frame, expected_hash), &(actual_frame, actual_hash)) in expected.iter().zip(actual.iter()) {
+        if expected_frame != actual_frame || expected_hash != actual_hash {
+            error!(
+                "{}: mismatch at frame {}: expected {:016x}, got {:016x}",
+                script_path.display(),
+                actual_frame,
+                expected_hash,
+                actual_hash);
+            return false;
+        }
+    }
+
+    info!("{}: all {} keyframes match", script_path.display(), actual.len());
+    return true;
+}
+
+// every subsystem's hash after every tick of one run
+fn golden_audit_pass(script: GoldenScript) -> Vec<Vec<(&'static str, u64)>> {
+    use super::game_state_subsystem_hashes;
+    use super::game_update;
+    use super::input::input_set;
+
+    golden_start(&script);
+    let mut frames = Vec::new();
+    for frame in 0..script.frames {
+        input_set(script.input_at(frame));
+        game_update();
+        frames.push(game_state_subsystem_hashes());
+    }
+    return frames;
+}
+
+// runs the script twice at once, each on its own thread with its own
+// thread locals, then reports the first tick and the subsystems where they
+// stopped agreeing
+pub fn golden_audit(script_path: &Path) -> bool {
+    use std::thread;
+
+    let script =
+        match golden_parse_script(script_path) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("{}", e);
+                return false;
+            },
+        };
+
+    let passes: Vec<_> = (0..2)
+        .map(|_| {
+            let script = script.clone();
+            thread::spawn(move || golden_audit_pass(script))
+        })
+        .collect();
+    let mut results = Vec::new();
+    for pass in passes {
+        match pass.join() {
+            Ok(frames) => results.push(frames),
+            Err(_) => {
+                error!("{}: an audit run panicked", script_path.display());
+                return false;
+            },
+        }
+    }
+
+    for (frame, (first, second)) in results[0].iter().zip(results[1].iter()).enumerate() {
+        let diverged: Vec<&str> = first
+            .iter()
+            .zip(second.iter())
+            .filter(|&(a, b)| a.1 != b.1)
+            .map(|(a, _)| a.0)
+            .collect();
+        if !diverged.is_empty() {
+            error!(
+                "{}: runs diverge at frame {} in {}",
+                script_path.display(),
+                frame,
+                diverged.join(", "));
+            return false;
+        }
+    }
+
+    info!("{}: both runs agree on all {} frames", script_path.display(), script.frames);
+    return true;
+}
+
+// plays the script with rewind taking its snapshots, then checks they never
+// took up more than rewind_budget_kb and that every one still held decodes
+// back to the state it was taken from
+pub fn golden_rewind_bench(script_path: &Path) -> bool {
+    use std::time::Duration;
+    use std::time::Instant;
+    use super::game_state_hash;
+    use super::game_update;
+    use super::input::input_set;
+    use super::rewind::*;
+
+    let script =
+        match golden_parse_script(script_path) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("{}", e);
+                return false;
+            },
+        };
+
+    golden_start(&script);
+    rewind_enable();
+
+    // the state hash as each snapshot was taken
+    let mut hashes = Vec::new();
+    let mut recording = Duration::from_secs(0);
+    let mut slowest = Duration::from_secs(0);
+    for frame in 0..script.frames {
+        input_set(script.input_at(frame));
+        game_update();
+        let started = Instant::now();
+        let taken = rewind_record();
+        let spent = started.elapsed();
+        recording += spent;
+        slowest = slowest.max(spent);
+        if taken {
+            hashes.push(game_state_hash());
+        }
+    }
+
+    let stats = rewind_stats();
+    info!(
+        "{}: {} snapshots taken, the last {} kept in {} bytes ({} without the deltas); at most {} of a {} byte budget",
+        script_path.display(),
+        hashes.len(),
+        stats.snapshots,
+        stats.bytes,
+        stats.raw,
+        stats.peak,
+        stats.budget);
+    info!(
+        "recording: {}us all told, {}us at worst",
+        recording.as_micros(),
+        slowest.as_micros());
+    if stats.peak > stats.budget {
+        error!("{}: the snapshots went {} bytes over the budget", script_path.display(), stats.peak - stats.budget);
+        return false;
+    }
+
+    // the ring holds the newest of them
+    let first = hashes.len() - stats.snapshots;
+    let mut restoring = Duration::from_secs(0);
+    for index in 0..stats.snapshots {
+        let started = Instant::now();
+        if let Err(e) = rewind_restore(index) {
+            error!("{}: {}", script_path.display(), e);
+            return false;
+        }
+        restoring += started.elapsed();
+        if game_state_hash() != hashes[first + index] {
+            error!(
+                "{}: snapshot {} of {} doesn't restore to the state it was taken from",
+                script_path.display(),
+                index,
+                stats.snapshots);
+            return false;
+        }
+    }
+    info!(
+        "{}: all {} snapshots restore, {}us all told",
+        script_path.display(),
+        stats.snapshots,
+        restoring.as_micros());
+    return true;
+}
@@ -0,0 +1,100 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use super::*;
+use rusty_kong::video::FrameBuffer;
+
+// the latched flags as logic saw them, for streams & checking a tas frame
+// by frame: one row per run of identical ticks, newest at the bottom
+const INPUT_DISPLAY_ROWS: usize = 6;
+const INPUT_DISPLAY_COL:  u32 = 18;
+
+// a letter per flag, '.' while it's up; start & coin aren't shown
+const INPUT_DISPLAY_FLAGS: [(u8, char); 5] = [
+    (F_INPUT_UP,    'U'),
+    (F_INPUT_DOWN,  'D'),
+    (F_INPUT_LEFT,  'L'),
+    (F_INPUT_RIGHT, 'R'),
+    (F_INPUT_JUMP,  'J'),
+];
+
+struct InputDisplay {
+    shown: bool,
+    // (flags, ticks they were held for), oldest first
+    runs: Vec<(u8, u32)>,
+}
+
+thread_local!(
+    static DISPLAY:RefCell<InputDisplay> = RefCell::new(InputDisplay {
+        shown: false,
+        runs: Vec::new()
+    });
+);
+
+// input_set() calls this with what it latched
+pub fn input_display_record(flags: u8) {
+    DISPLAY.with(|cell| {
+        let mut display = cell.borrow_mut();
+        let same = display.runs.last().map_or(false, |&(last, _)| last == flags);
+        if same {
+            display.runs.last_mut().unwrap().1 += 1;
+            return;
+        }
+        display.runs.push((flags, 1));
+        let excess = display.runs.len().saturating_sub(INPUT_DISPLAY_ROWS);
+        display.runs.drain(..excess);
+    });
+}
+
+pub fn input_display_toggle() {
+    let shown = DISPLAY.with(|cell| {
+        let mut display = cell.borrow_mut();
+        display.shown = !display.shown;
+        display.shown
+    });
+    info!("input display {}", if shown { "on" } else { "off" });
+}
+
+fn input_display_text(flags: u8, ticks: u32) -> String {
+    let letters: String = INPUT_DISPLAY_FLAGS
+        .iter()
+        .map(|&(flag, letter)| if flags & flag != 0 { letter } else { '.' })
+        .collect();
+    format!("{} {:>4}", letters, ticks.min(9999))
+}
+
+fn input_display_overlay(framebuffer: &mut FrameBuffer) {
+    use rusty_kong::video::*;
+
+    let (shown, runs) = DISPLAY.with(|cell| {
+        let display = cell.borrow();
+        (display.shown, display.runs.clone())
+    });
+    if !shown {
+        return;
+    }
+
+    let bottom = SCREEN_HEIGHT - TILE_HEIGHT;
+    let x = INPUT_DISPLAY_COL * TILE_WIDTH;
+    for (index, &(flags, ticks)) in runs.iter().rev().enumerate() {
+        let palette = if index == 0 { TEXT_PALETTE_WHITE } else { TEXT_PALETTE_BLUE };
+        text_draw(framebuffer, x, bottom - index as u32 * TILE_HEIGHT, &input_display_text(flags, ticks), palette);
+    }
+}
+
+pub fn input_display_init() {
+    use rusty_kong::settings::settings_read;
+    use rusty_kong::video::video_add_overlay;
+
+    let shown = settings_read(|s| s.input_display);
+    DISPLAY.with(|cell| {cell.borrow_mut().shown = shown;});
+    video_add_overlay(input_display_overlay);
+}
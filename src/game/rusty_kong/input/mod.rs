@@ -0,0 +1,267 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use sdl2::controller::Axis;
+use sdl2::controller::Button;
+use sdl2::controller::GameController;
+use sdl2::keyboard::KeyboardState;
+use sdl2::keyboard::Scancode;
+
+mod display;
+pub use self::display::*;
+
+mod rumble;
+pub use self::rumble::*;
+
+pub const F_INPUT_NONE:  u8 = 0b00000000;
+pub const F_INPUT_UP:    u8 = 0b00000001;
+pub const F_INPUT_DOWN:  u8 = 0b00000010;
+pub const F_INPUT_LEFT:  u8 = 0b00000100;
+pub const F_INPUT_RIGHT: u8 = 0b00001000;
+pub const F_INPUT_JUMP:  u8 = 0b00010000;
+pub const F_INPUT_START: u8 = 0b00100000;
+pub const F_INPUT_COIN:  u8 = 0b01000000;
+pub const F_INPUT_RESTART:u8 = 0b10000000;
+
+// ticks of latched flags kept for bug reports
+pub const INPUT_HISTORY_TICKS: usize = 10 * 60;
+
+// a player's claim on the controllers page, as settings.cfg keeps it in
+// player1_pad / player2_pad: empty for whatever pad, this for none at all,
+// or a pad's InputPad::id
+pub const INPUT_PAD_KEYBOARD: &str = "keyboard";
+
+// an opened pad and what a claim calls it: the joystick guid, and after a
+// '#' how many pads with the same guid were opened before it, so two of
+// the same model are told apart as long as they're plugged in the same way
+pub struct InputPad {
+    pub controller: GameController,
+    pub id: String,
+}
+
+// logic only ever sees these flags, latched once per tick, so a scripted or
+// recorded stream is indistinguishable from real devices
+struct InputFrame {
+    previous: u8,
+    current: u8,
+    // swap left/right and up/down as they're latched, to go with a picture
+    // turned 180 degrees
+    mirrored: bool,
+}
+
+// what drives each flag, with names the font can draw for the input test
+pub struct InputBinding {
+    pub flag: u8,
+    pub name: &'static str,
+    pub key: Scancode,
+    pub key_label: &'static str,
+    pub button: Button,
+    pub button_label: &'static str,
+}
+
+pub const INPUT_BINDINGS: [InputBinding; 8] = [
+    InputBinding { flag: F_INPUT_UP,      name: "up",      key: Scancode::Up,    key_label: "UP",    button: Button::DPadUp,    button_label: "DPAD UP" },
+    InputBinding { flag: F_INPUT_DOWN,    name: "down",    key: Scancode::Down,  key_label: "DOWN",  button: Button::DPadDown,  button_label: "DPAD DOWN" },
+    InputBinding { flag: F_INPUT_LEFT,    name: "left",    key: Scancode::Left,  key_label: "LEFT",  button: Button::DPadLeft,  button_label: "DPAD LEFT" },
+    InputBinding { flag: F_INPUT_RIGHT,   name: "right",   key: Scancode::Right, key_label: "RIGHT", button: Button::DPadRight, button_label: "DPAD RIGHT" },
+    InputBinding { flag: F_INPUT_JUMP,    name: "jump",    key: Scancode::Space, key_label: "SPACE", button: Button::A,         button_label: "A" },
+    InputBinding { flag: F_INPUT_START,   name: "start",   key: Scancode::Num1,  key_label: "1",     button: Button::Start,     button_label: "START" },
+    InputBinding { flag: F_INPUT_COIN,    name: "coin",    key: Scancode::Num5,  key_label: "5",     button: Button::Back,      button_label: "BACK" },
+    InputBinding { flag: F_INPUT_RESTART, name: "restart", key: Scancode::R,     key_label: "R",     button: Button::Y,         button_label: "Y" },
+];
+
+// the devices as last read, before anything is latched; only the input
+// test looks at this, logic never does
+struct RawInput {
+    // flags whose key / button is held
+    keys: u8,
+    buttons: u8,
+    // the left stick, which nothing is bound to
+    stick: (i16, i16),
+    // one line per joystick found at start up
+    devices: Vec<String>,
+    // (id, label) per pad opened, for the controllers page
+    pads: Vec<(String, String)>,
+    // the first device with start held, as a claim would name it; none
+    // held is None
+    start_held_by: Option<String>,
+}
+
+thread_local!(
+    static INPUT:RefCell<InputFrame> = RefCell::new(InputFrame {
+        previous: F_INPUT_NONE,
+        current:  F_INPUT_NONE,
+        mirrored: false
+    });
+    static RAW:RefCell<RawInput> = RefCell::new(RawInput {
+        keys: F_INPUT_NONE,
+        buttons: F_INPUT_NONE,
+        stick: (0, 0),
+        devices: Vec::new(),
+        pads: Vec::new(),
+        start_held_by: None
+    });
+    // the flags handed to input_set(), before mirroring, oldest first
+    static HISTORY:RefCell<VecDeque<u8>> = RefCell::new(VecDeque::with_capacity(INPUT_HISTORY_TICKS));
+);
+
+pub fn input_name_to_flag(name: &str) -> Option<u8> {
+    INPUT_BINDINGS.iter().find(|binding| binding.name == name).map(|binding| binding.flag)
+}
+
+// the claim that decides which pads drive the flags: the current player's
+// in a two player game, None (every pad) otherwise
+fn input_pad_claim() -> Option<String> {
+    use super::player::player_count;
+    use super::player::player_current;
+    use super::settings::settings_read;
+    use super::state_machine::game_state_in_game;
+
+    if !game_state_in_game() || player_count() < 2 {
+        return None;
+    }
+    let claim = settings_read(|s| s.player_pads.get(player_current()).cloned().unwrap_or_default());
+    if claim.is_empty() {
+        return None;
+    }
+    return Some(claim);
+}
+
+// the keyboard's always read, whoever's turn it is; pads are all read
+// unless the player up has claimed one, when only theirs is.  pads are
+// optional, without one the keyboard is the only device
+pub fn input_read_devices(keyboard: &KeyboardState, pads: &[InputPad]) -> u8 {
+    // a claimed pad that isn't plugged in claims nothing
+    let claim = input_pad_claim().filter(|claim| claim == INPUT_PAD_KEYBOARD || pads.iter().any(|pad| pad.id == *claim));
+    let (mut keys, mut buttons) = (F_INPUT_NONE, F_INPUT_NONE);
+    let mut stick = None;
+
+    for binding in INPUT_BINDINGS.iter() {
+        if keyboard.is_scancode_pressed(binding.key) { keys |= binding.flag; }
+    }
+    let mut start_held_by = None;
+    for pad in pads.iter() {
+        let controller = &pad.controller;
+        if start_held_by.is_none() && controller.button(Button::Start) {
+            start_held_by = Some(pad.id.clone());
+        }
+        if claim.as_ref().map_or(false, |claim| *claim != pad.id) {
+            continue;
+        }
+        for binding in INPUT_BINDINGS.iter() {
+            if controller.button(binding.button) { buttons |= binding.flag; }
+        }
+        if stick.is_none() {
+            stick = Some((controller.axis(Axis::LeftX), controller.axis(Axis::LeftY)));
+        }
+    }
+    if start_held_by.is_none() && keys & F_INPUT_START != 0 {
+        start_held_by = Some(INPUT_PAD_KEYBOARD.to_string());
+    }
+
+    RAW.with(|cell| {
+        let mut raw = cell.borrow_mut();
+        raw.keys = keys;
+        raw.buttons = buttons;
+        raw.stick = stick.unwrap_or((0, 0));
+        raw.start_held_by = start_held_by;
+    });
+    return keys | buttons;
+}
+
+// for the controllers page: which device is holding start down, pads
+// before the keyboard.  never part of logic, a playback has no devices
+pub fn input_start_held_by() -> Option<String> {
+    RAW.with(|cell| cell.borrow().start_held_by.clone())
+}
+
+// (keys, buttons, left stick) from the last input_read_devices()
+pub fn input_raw() -> (u8, u8, (i16, i16)) {
+    RAW.with(|cell| {
+        let raw = cell.borrow();
+        (raw.keys, raw.buttons, raw.stick)
+    })
+}
+
+pub fn input_set_devices(devices: Vec<String>) {
+    RAW.with(|cell| {cell.borrow_mut().devices = devices;});
+}
+
+pub fn input_devices() -> Vec<String> {
+    RAW.with(|cell| cell.borrow().devices.clone())
+}
+
+pub fn input_set_pads(pads: Vec<(String, String)>) {
+    RAW.with(|cell| {cell.borrow_mut().pads = pads;});
+}
+
+// how the controllers page shows a pad's id; None when it isn't plugged in
+pub fn input_pad_label(id: &str) -> Option<String> {
+    RAW.with(|cell| {
+        cell.borrow().pads.iter().find(|&&(ref pad, _)| pad == id).map(|&(_, ref label)| label.clone())
+    })
+}
+
+// call once per logic tick, before the state machine runs
+pub fn input_mirror(flags: u8) -> u8 {
+    let swap = |flags: u8, a: u8, b: u8| {
+        let mut swapped = flags & !(a | b);
+        if flags & a != 0 { swapped |= b; }
+        if flags & b != 0 { swapped |= a; }
+        swapped
+    };
+    return swap(swap(flags, F_INPUT_LEFT, F_INPUT_RIGHT), F_INPUT_UP, F_INPUT_DOWN);
+}
+
+// takes effect from the next input_set()
+pub fn input_set_mirrored(mirrored: bool) {
+    INPUT.with(|cell| {cell.borrow_mut().mirrored = mirrored;});
+}
+
+pub fn input_set(flags: u8) {
+    INPUT.with(|cell| {
+        let mut frame = cell.borrow_mut();
+        frame.previous = frame.current;
+        frame.current = if frame.mirrored { input_mirror(flags) } else { flags };
+        input_display_record(frame.current);
+    });
+    HISTORY.with(|cell| {
+        let mut history = cell.borrow_mut();
+        if history.len() == INPUT_HISTORY_TICKS {
+            history.pop_front();
+        }
+        history.push_back(flags);
+    });
+}
+
+pub fn input_history() -> Vec<u8> {
+    HISTORY.with(|cell| cell.borrow().iter().cloned().collect())
+}
+
+pub fn input_get() -> u8 {
+    INPUT.with(|cell| cell.borrow().current)
+}
+
+pub fn input_is_down(flag: u8) -> bool {
+    input_get() & flag != 0
+}
+
+// true only on the tick the input went down
+pub fn input_is_pressed(flag: u8) -> bool {
+    INPUT.with(|cell| {
+        let frame = cell.borrow();
+        frame.current & flag != 0 && frame.previous & flag == 0
+    })
+}
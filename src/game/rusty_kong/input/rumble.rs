@@ -0,0 +1,77 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use sdl2::Sdl;
+use sdl2::haptic::Haptic;
+
+use super::*;
+use rusty_kong::events::GameEvent;
+
+// (strength, ms) at full rumble_strength for each event that's felt
+const RUMBLE_DEATH: (f32, u32) = (1.0, 500);
+const RUMBLE_SMASH: (f32, u32) = (0.5, 120);
+const RUMBLE_STOMP: (f32, u32) = (0.8, 200);
+
+thread_local!(
+    // the pad in use's motors, when it has any
+    static RUMBLE:RefCell<Option<Haptic>> = RefCell::new(None);
+);
+
+// opened next to the controller; plenty of pads have no motors, which
+// isn't worth more than a line in the log
+pub fn input_rumble_open(sdl_context: &Sdl, joystick: u32) {
+    let haptic = sdl_context
+        .haptic()
+        .map_err(|e| e.to_string())
+        .and_then(|subsystem| subsystem.open_from_joystick_id(joystick).map_err(|e| e.to_string()));
+    match haptic {
+        Ok(h) => {
+            info!("rumble on joystick {}", joystick);
+            RUMBLE.with(|cell| {*cell.borrow_mut() = Some(h);});
+        },
+        Err(e) => info!("no rumble on joystick {}: {}", joystick, e),
+    }
+}
+
+fn input_rumble(effect: (f32, u32)) {
+    use rusty_kong::settings::settings_read;
+
+    let (enabled, percent) = settings_read(|s| (s.rumble, s.rumble_strength));
+    if !enabled || percent == 0 {
+        return;
+    }
+    let (strength, ms) = effect;
+    RUMBLE.with(|cell| {
+        if let Some(ref mut haptic) = *cell.borrow_mut() {
+            haptic.rumble_play(strength * percent.min(100) as f32 / 100.0, ms);
+        }
+    });
+}
+
+fn input_rumble_on_event(event: &GameEvent) {
+    match event {
+        &GameEvent::PlayerDied   => input_rumble(RUMBLE_DEATH),
+        &GameEvent::EnemySmashed => input_rumble(RUMBLE_SMASH),
+        &GameEvent::KongStomped  => input_rumble(RUMBLE_STOMP),
+        // a reset mid-rumble shouldn't leave the pad buzzing
+        &GameEvent::Reset => RUMBLE.with(|cell| {
+            if let Some(ref mut haptic) = *cell.borrow_mut() {
+                haptic.rumble_stop();
+            }
+        }),
+        _ => {},
+    }
+}
+
+pub fn input_rumble_init() {
+    use rusty_kong::events::events_subscribe;
+    events_subscribe(input_rumble_on_event);
+}
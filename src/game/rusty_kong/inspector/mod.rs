@@ -0,0 +1,92 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+// a reflection-ish view of the live entities for the debug window: every
+// entity kind lists its instances as named fields and takes edits back by
+// field name.  nothing in the game reads through here, it only pokes the
+// owning module's state the same way its own update would.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum InspectValue {
+    // editable
+    Number(i32),
+    // read only: states, animation clips and the like
+    Label(&'static str),
+}
+
+#[derive(Clone, Debug)]
+pub struct InspectField {
+    pub name: &'static str,
+    pub value: InspectValue,
+}
+
+impl InspectField {
+    pub fn number(name: &'static str, value: i32) -> InspectField {
+        InspectField { name: name, value: InspectValue::Number(value) }
+    }
+
+    pub fn label(name: &'static str, value: &'static str) -> InspectField {
+        InspectField { name: name, value: InspectValue::Label(value) }
+    }
+}
+
+// one live instance of a kind; the index is what set() is called with
+#[derive(Clone, Debug)]
+pub struct InspectEntity {
+    pub kind: &'static str,
+    pub index: usize,
+    pub fields: Vec<InspectField>,
+}
+
+struct Inspectable {
+    kind: &'static str,
+    list: fn() -> Vec<Vec<InspectField>>,
+    set: fn(usize, &str, i32) -> Result<(), String>,
+}
+
+fn inspectables() -> [Inspectable; 4] {
+    use rusty_kong::enemies::*;
+    use rusty_kong::player::*;
+
+    [
+        Inspectable { kind: "player",   list: player_inspect,   set: player_inspect_set },
+        Inspectable { kind: "hammer",   list: hammer_inspect,   set: hammer_inspect_set },
+        Inspectable { kind: "fireball", list: fireball_inspect, set: fireball_inspect_set },
+        Inspectable { kind: "jack",     list: jack_inspect,     set: jack_inspect_set },
+    ]
+}
+
+pub fn inspector_entities() -> Vec<InspectEntity> {
+    let mut entities = Vec::new();
+    for inspectable in inspectables().iter() {
+        for (index, fields) in (inspectable.list)().into_iter().enumerate() {
+            entities.push(InspectEntity { kind: inspectable.kind, index: index, fields: fields });
+        }
+    }
+    return entities;
+}
+
+pub fn inspector_set(kind: &str, index: usize, field: &str, value: i32) -> Result<(), String> {
+    match inspectables().iter().find(|inspectable| inspectable.kind == kind) {
+        Some(inspectable) => {
+            (inspectable.set)(index, field, value)?;
+            info!("inspector: {} {} {} = {}", kind, index, field, value);
+            return Ok(());
+        },
+        None => return Err(format!("nothing inspectable called \"{}\"", kind)),
+    }
+}
+
+// shared by the set() hooks for a field name they don't have
+pub fn inspect_unknown(kind: &str, field: &str) -> Result<(), String> {
+    Err(format!("{} has no editable \"{}\"", kind, field))
+}
@@ -0,0 +1,148 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use super::video::video_sprite_set;
+use super::video::video_sprite_hide;
+use super::state_machine::tween_sprite;
+use super::state_machine::Easing;
+
+mod routine;
+pub use self::routine::*;
+
+pub const KONG_WIDTH:  u32 = 32;
+pub const KONG_HEIGHT: u32 = 32;
+
+// sprite slots: kong is a 2x2 block, pauline is two tall
+pub const KONG_SPRITE_SLOT:    usize = 0;
+pub const PAULINE_SPRITE_SLOT: usize = 4;
+pub const KONG_SPRITE_COUNT:   usize = 4;
+// past the player's and the how high stack's slots
+pub const HEART_SPRITE_SLOT:   usize = 32;
+// the upright barrels next to kong on 25m, then the one in his hands
+pub const BARREL_STACK_SPRITE_SLOT: usize = 33;
+pub const BARREL_HELD_SPRITE_SLOT:  usize = 37;
+
+// where kong & pauline stand at the top of the stages kong retreats from
+pub const KONG_PERCH_X:    i32 = 88;
+pub const KONG_PERCH_Y:    i32 = 52;
+pub const PAULINE_PERCH_X: i32 = 120;
+pub const PAULINE_PERCH_Y: i32 = 20;
+
+// XXX: tile & palette numbers come from eyeballing the ripper output and
+//      need checking against the real ROM sprite layout
+const KONG_PALETTE:    u8 = 40;
+const PAULINE_PALETTE: u8 = 41;
+const HEART_PALETTE:   u8 = 41;
+const HEART_TILE:        u16 = 0x76;
+const HEART_BROKEN_TILE: u16 = 0x77;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KongPose {
+    Standing,
+    ClimbLeft,
+    ClimbRight,
+    // reaching left for a barrel off the stack
+    Grab,
+    // barrel held out to the right, about to let go
+    Throw,
+}
+
+impl KongPose {
+    pub fn from_name(name: &str) -> Option<KongPose> {
+        match name {
+            "standing"      => Some(KongPose::Standing),
+            "climb_left"    => Some(KongPose::ClimbLeft),
+            "climb_right"   => Some(KongPose::ClimbRight),
+            "grab"          => Some(KongPose::Grab),
+            "throw"         => Some(KongPose::Throw),
+            _               => None,
+        }
+    }
+}
+
+// top-left, top-right, bottom-left, bottom-right
+fn kong_tiles(pose: KongPose) -> [u16; 4] {
+    match pose {
+        KongPose::Standing      => [0x2c, 0x2d, 0x2e, 0x2f],
+        KongPose::ClimbLeft     => [0x30, 0x31, 0x32, 0x33],
+        KongPose::ClimbRight    => [0x34, 0x35, 0x36, 0x37],
+        KongPose::Grab          => [0x38, 0x39, 0x3a, 0x3b],
+        KongPose::Throw         => [0x3c, 0x3d, 0x3e, 0x3f],
+    }
+}
+
+// x/y is kong's top-left corner in screen pixels.  anything above the top
+// of the screen is hidden rather than wrapped.
+pub fn kong_draw(x: i32, y: i32, pose: KongPose) {
+    kong_draw_at(KONG_SPRITE_SLOT, x, y, pose);
+}
+
+pub fn kong_hide() {
+    kong_hide_at(KONG_SPRITE_SLOT);
+}
+
+// extra kongs (the how high stack) in their own KONG_SPRITE_COUNT slots
+// starting at slot
+pub fn kong_draw_at(slot: usize, x: i32, y: i32, pose: KongPose) {
+    let tiles = kong_tiles(pose);
+    for (index, &tile) in tiles.iter().enumerate() {
+        let sx = x + (index as i32 % 2) * 16;
+        let sy = y + (index as i32 / 2) * 16;
+        if sx < 0 || sy < 0 {
+            video_sprite_hide(slot + index);
+        } else {
+            video_sprite_set(slot + index, tile, sx as u16, sy as u16, KONG_PALETTE);
+        }
+    }
+}
+
+pub fn kong_hide_at(slot: usize) {
+    for index in 0..KONG_SPRITE_COUNT {
+        video_sprite_hide(slot + index);
+    }
+}
+
+pub fn pauline_draw(x: i32, y: i32) {
+    let tiles = [0x4b, 0x4a];
+    for (index, &tile) in tiles.iter().enumerate() {
+        let sy = y + index as i32 * 16;
+        if x < 0 || sy < 0 {
+            video_sprite_hide(PAULINE_SPRITE_SLOT + index);
+        } else {
+            video_sprite_set(PAULINE_SPRITE_SLOT + index, tile, x as u16, sy as u16, PAULINE_PALETTE);
+        }
+    }
+}
+
+pub fn pauline_hide() {
+    for index in 0..2 {
+        video_sprite_hide(PAULINE_SPRITE_SLOT + index);
+    }
+}
+
+// the heart over pauline when the player reaches her
+pub fn heart_draw(broken: bool) {
+    let tile = if broken { HEART_BROKEN_TILE } else { HEART_TILE };
+    let x = PAULINE_PERCH_X - 16;
+    video_sprite_set(HEART_SPRITE_SLOT, tile, x as u16, PAULINE_PERCH_Y as u16, HEART_PALETTE);
+}
+
+// the heart floats up into place from behind pauline's head
+pub fn heart_rise(frames: u32) {
+    let x = PAULINE_PERCH_X - 16;
+    video_sprite_set(HEART_SPRITE_SLOT, HEART_TILE, x as u16, (PAULINE_PERCH_Y + 16) as u16, HEART_PALETTE);
+    tween_sprite(HEART_SPRITE_SLOT, (x as u16, PAULINE_PERCH_Y as u16), frames, Easing::EaseOut, None);
+}
+
+pub fn heart_hide() {
+    video_sprite_hide(HEART_SPRITE_SLOT);
+}
@@ -0,0 +1,154 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use super::*;
+use rusty_kong::hashing::StateHasher;
+use rusty_kong::savestate::SaveState;
+use rusty_kong::level::EnemyKind;
+use rusty_kong::level::Stage;
+use rusty_kong::state_machine::schedule_after;
+use rusty_kong::video::BLUE_BARREL_PALETTE;
+use rusty_kong::video::video_sprite_set;
+use rusty_kong::video::video_sprite_hide;
+
+// kong's spot on the stages he throws barrels from, with the stack of
+// upright barrels filling the gap to his left
+pub const KONG_BARRELS_X: i32 = 32;
+pub const KONG_BARRELS_Y: i32 = 52;
+
+const BARREL_STACK_SIZE: u32 = 4;
+const BARREL_SIZE:       i32 = 16;
+
+// XXX: barrel tiles and palette come from the ripper output by eye, like
+//      kong's own
+const BARREL_UPRIGHT_TILE: u16 = 0x17;
+const BARREL_HELD_TILE:    u16 = 0x15;
+const BARREL_PALETTE:      u8 = 43;
+
+// one throw every THROW_TICKS: stand, reach for the stack, lift the barrel
+// over, then let go to the right.  each step schedules the next, so the
+// cycle stops with GamePlay and waits out the pause screen with it.
+const THROW_TICKS: u32 = 120;
+const GRAB_AT:     u32 = THROW_TICKS - 48;
+const LIFT_AT:     u32 = THROW_TICKS - 32;
+const THROW_AT:    u32 = THROW_TICKS - 16;
+
+#[derive(Clone, Hash)]
+struct Routine {
+    // barrels left on the stack; it's restocked once the last one is taken
+    stack: u32,
+    // thrown since the stage started; the first is the blue one
+    thrown: u32,
+}
+
+thread_local!(
+    static ROUTINE:RefCell<Routine> = RefCell::new(Routine {
+        stack: BARREL_STACK_SIZE,
+        thrown: 0
+    });
+);
+
+fn kong_draw_stack(count: u32) {
+    for index in 0..BARREL_STACK_SIZE {
+        let slot = BARREL_STACK_SPRITE_SLOT + index as usize;
+        if index >= count {
+            video_sprite_hide(slot);
+            continue;
+        }
+        // bottom row fills first, right to left, so the one nearest kong
+        // is always the next to go
+        let x = (1 - (index as i32 % 2)) * BARREL_SIZE;
+        let y = KONG_BARRELS_Y + KONG_HEIGHT as i32 - (index as i32 / 2 + 1) * BARREL_SIZE;
+        video_sprite_set(slot, BARREL_UPRIGHT_TILE, x as u16, y as u16, BARREL_PALETTE);
+    }
+}
+
+fn kong_draw_held(x: i32, y: i32) {
+    let blue = ROUTINE.with(|cell| cell.borrow().thrown == 0);
+    let palette = if blue { BLUE_BARREL_PALETTE } else { BARREL_PALETTE };
+    video_sprite_set(BARREL_HELD_SPRITE_SLOT, BARREL_HELD_TILE, x as u16, y as u16, palette);
+}
+
+// sets kong up for a stage; only stages with barrels in their enemy set get
+// the stack and the throw cycle.  call from GamePlay, which owns the cycle.
+pub fn kong_stage_start(stage: &Stage) {
+    let active = stage.enemies.contains(&EnemyKind::Barrel);
+    ROUTINE.with(|cell| {*cell.borrow_mut() = Routine { stack: BARREL_STACK_SIZE, thrown: 0 };});
+
+    video_sprite_hide(BARREL_HELD_SPRITE_SLOT);
+    if active {
+        kong_draw(KONG_BARRELS_X, KONG_BARRELS_Y, KongPose::Standing);
+        kong_draw_stack(BARREL_STACK_SIZE);
+        schedule_after(GRAB_AT, kong_grab);
+    } else {
+        kong_draw_stack(0);
+    }
+}
+
+fn kong_grab() {
+    let stack = ROUTINE.with(|cell| {
+        let mut routine = cell.borrow_mut();
+        routine.stack = if routine.stack == 0 { BARREL_STACK_SIZE - 1 } else { routine.stack - 1 };
+        routine.stack
+    });
+    let (x, y) = (KONG_BARRELS_X, KONG_BARRELS_Y);
+    kong_draw(x, y, KongPose::Grab);
+    kong_draw_stack(stack);
+    kong_draw_held(x - BARREL_SIZE / 2, y + BARREL_SIZE);
+    schedule_after(LIFT_AT - GRAB_AT, kong_lift);
+}
+
+fn kong_lift() {
+    let (x, y) = (KONG_BARRELS_X, KONG_BARRELS_Y);
+    kong_draw(x, y, KongPose::Standing);
+    kong_draw_held(x + BARREL_SIZE / 2, y - BARREL_SIZE / 2);
+    schedule_after(THROW_AT - LIFT_AT, kong_throw);
+}
+
+fn kong_throw() {
+    let (x, y) = (KONG_BARRELS_X, KONG_BARRELS_Y);
+    kong_draw(x, y, KongPose::Throw);
+    kong_draw_held(x + KONG_WIDTH as i32, y + BARREL_SIZE);
+    schedule_after(THROW_TICKS - 1 - THROW_AT, kong_release);
+}
+
+// it leaves his hands on the last tick of the cycle
+fn kong_release() {
+    // XXX: nothing rolls the barrel yet, or turns the blue one into a
+    //      fireball
+    ROUTINE.with(|cell| {cell.borrow_mut().thrown += 1;});
+    debug!("kong threw a barrel");
+    schedule_after(1, kong_stand);
+}
+
+fn kong_stand() {
+    kong_draw(KONG_BARRELS_X, KONG_BARRELS_Y, KongPose::Standing);
+    video_sprite_hide(BARREL_HELD_SPRITE_SLOT);
+    schedule_after(GRAB_AT, kong_grab);
+}
+
+pub fn kong_hash(hasher: &mut StateHasher) {
+    ROUTINE.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn kong_save(savestate: &mut SaveState) {
+    savestate.put("kong", ROUTINE.with(|cell| cell.borrow().clone()));
+}
+
+pub fn kong_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<Routine>("kong") {
+        ROUTINE.with(|cell| {*cell.borrow_mut() = value;});
+    }
+}
@@ -0,0 +1,491 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use super::super::playfield::*;
+use super::super::video::*;
+
+// girder tiles: 0xf0 + n is a girder n pixels down from the top of its
+// cell, 0xe0 + n is the bit of it that spills into the cell below
+const TILE_GIRDER:       u16 = 0xf0;
+const TILE_GIRDER_SPILL: u16 = 0xe0;
+const TILE_LADDER:       u16 = 0xc0;
+
+const GIRDER_PALETTE: u8 = 0;
+const LADDER_PALETTE: u8 = 1;
+
+// a run of girder in tile coordinates.  row is where the highest end sits;
+// rise is how many pixels it climbs every two columns going right (negative
+// falls), so 0 is flat.
+pub struct Girder {
+    pub row: u32,
+    pub col_start: u32,
+    pub col_end: u32,
+    pub rise: i32,
+}
+
+pub struct LadderSpan {
+    pub col: u32,
+    pub top_row: u32,
+    pub bottom_row: u32,
+}
+
+// a ladder whose top part slides down to retracted_row and back up again
+// on a fixed cycle, offset by phase ticks
+pub struct RetractingLadder {
+    pub col: u32,
+    pub top_row: u32,
+    pub bottom_row: u32,
+    pub retracted_row: u32,
+    pub phase: u32,
+}
+
+// a girder that's a belt, carrying whoever stands on it direction pixels
+// (-1 left, 1 right) a step
+pub struct Conveyor {
+    pub girder: usize,
+    pub direction: i32,
+}
+
+pub struct Layout {
+    // top of the screen first
+    pub girders: &'static [Girder],
+    pub ladders: &'static [LadderSpan],
+    pub retracting: &'static [RetractingLadder],
+    pub conveyors: &'static [Conveyor],
+    // junior's ladders are vines, which are allowed to hang free
+    pub vines: bool,
+}
+
+// somewhere the player can climb, in pixels: top and bottom are where his
+// feet are at either end
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Climb {
+    pub x: i32,
+    pub top: i32,
+    pub bottom: i32,
+    // the top can't be climbed off: a retracting ladder that's partly in, or
+    // a vine that doesn't reach a girder
+    pub blocked: bool,
+    // index into retracting, for ladders that move
+    pub retracting: Option<usize>,
+}
+
+// extended, sliding in, retracted, sliding out
+const RETRACT_EXTENDED_TICKS:  u32 = 4 * 60;
+const RETRACT_RETRACTED_TICKS: u32 = 3 * 60;
+// one pixel every RETRACT_STEP_TICKS while sliding
+const RETRACT_STEP_TICKS:      u32 = 2;
+
+impl Climb {
+    // where he stands to climb it
+    pub fn center(&self) -> i32 {
+        playfield_col_center(playfield_col(self.x))
+    }
+}
+
+impl RetractingLadder {
+    fn travel(&self) -> u32 {
+        (self.retracted_row - self.top_row) * TILE_HEIGHT
+    }
+
+    // pixels the top has slid down at stage tick ticks
+    pub fn retracted_by(&self, ticks: u32) -> u32 {
+        let slide = self.travel() * RETRACT_STEP_TICKS;
+        let cycle = RETRACT_EXTENDED_TICKS + slide + RETRACT_RETRACTED_TICKS + slide;
+        let t = (ticks + self.phase) % cycle;
+        if t < RETRACT_EXTENDED_TICKS {
+            0
+        } else if t < RETRACT_EXTENDED_TICKS + slide {
+            (t - RETRACT_EXTENDED_TICKS) / RETRACT_STEP_TICKS
+        } else if t < RETRACT_EXTENDED_TICKS + slide + RETRACT_RETRACTED_TICKS {
+            self.travel()
+        } else {
+            self.travel() - (t - RETRACT_EXTENDED_TICKS - slide - RETRACT_RETRACTED_TICKS) / RETRACT_STEP_TICKS
+        }
+    }
+}
+
+impl Girder {
+    // pixels below row at a column
+    pub fn offset(&self, col: u32) -> u32 {
+        if self.rise > 0 {
+            (self.col_end - col) / 2 * self.rise as u32
+        } else {
+            (col - self.col_start) / 2 * (-self.rise) as u32
+        }
+    }
+
+    // pixel edges; right is one past the last column
+    pub fn left(&self) -> i32 {
+        playfield_col_left(self.col_start)
+    }
+
+    pub fn right(&self) -> i32 {
+        playfield_col_right(self.col_end)
+    }
+
+    pub fn spans(&self, col: u32) -> bool {
+        col >= self.col_start && col <= self.col_end
+    }
+
+    // the top of the girder over pixel column x, which must be in a
+    // column it spans
+    pub fn surface(&self, x: i32) -> i32 {
+        playfield_row_top(self.row) + self.offset(playfield_col(x)) as i32
+    }
+}
+
+// clears everything between the girder's row and its lowest point, so
+// it works whether it was drawn flat or sloped
+pub fn layout_erase_girder(girder: &Girder) {
+    for col in girder.col_start..girder.col_end + 1 {
+        let bottom = girder.row + girder.offset(col) / TILE_HEIGHT + 1;
+        for row in girder.row..bottom + 1 {
+            video_bg_erase(col, row);
+        }
+    }
+}
+
+// flat draws the girder level along its row whatever its rise; the intro
+// starts from these before kong bends them
+pub fn layout_draw_girder(girder: &Girder, flat: bool) {
+    for col in girder.col_start..girder.col_end + 1 {
+        let offset = if flat { 0 } else { girder.offset(col) };
+        let row = girder.row + offset / TILE_HEIGHT;
+        let shift = (offset % TILE_HEIGHT) as u16;
+        video_bg_put(col, row, TILE_GIRDER + shift, GIRDER_PALETTE);
+        if shift != 0 {
+            video_bg_put(col, row + 1, TILE_GIRDER_SPILL + shift, GIRDER_PALETTE);
+        }
+    }
+}
+
+// only fills empty cells, so the girders stay whole where a ladder meets them
+pub fn layout_draw_ladder(ladder: &LadderSpan) {
+    for row in ladder.top_row..ladder.bottom_row + 1 {
+        if video_bg_tile(ladder.col, row).is_none() {
+            video_bg_put(ladder.col, row, TILE_LADDER, LADDER_PALETTE);
+        }
+    }
+}
+
+pub fn layout_erase_ladder(ladder: &LadderSpan) {
+    for row in ladder.top_row..ladder.bottom_row + 1 {
+        if video_bg_tile(ladder.col, row) == Some(TILE_LADDER) {
+            video_bg_erase(ladder.col, row);
+        }
+    }
+}
+
+// redraws a retracting ladder at its extent for stage tick ticks; call every
+// tick the stage runs
+pub fn layout_draw_retracting(ladder: &RetractingLadder, ticks: u32) {
+    let top = ladder.top_row * TILE_HEIGHT + ladder.retracted_by(ticks);
+    for row in ladder.top_row..ladder.bottom_row + 1 {
+        let tile = video_bg_tile(ladder.col, row);
+        if row * TILE_HEIGHT + TILE_HEIGHT <= top {
+            if tile == Some(TILE_LADDER) {
+                video_bg_erase(ladder.col, row);
+            }
+        } else if tile.is_none() {
+            video_bg_put(ladder.col, row, TILE_LADDER, LADDER_PALETTE);
+        }
+    }
+}
+
+pub fn layout_draw(layout: &Layout) {
+    for girder in layout.girders.iter() {
+        layout_draw_girder(girder, false);
+    }
+    for ladder in layout.ladders.iter() {
+        layout_draw_ladder(ladder);
+    }
+    for ladder in layout.retracting.iter() {
+        layout_draw_retracting(ladder, 0);
+    }
+}
+
+// the top of each girder crossing the pixel column x, in pixels
+pub fn layout_surfaces(layout: &Layout, x: i32) -> Vec<i32> {
+    use super::layout_grid::layout_grid;
+    layout_grid(layout).surfaces(layout, x).collect()
+}
+
+// the surface at x closest to y, if one is within reach
+pub fn layout_surface_near(layout: &Layout, x: i32, y: i32, reach: i32) -> Option<i32> {
+    use super::layout_grid::layout_grid;
+    layout_grid(layout).surface_near(layout, x, y, reach)
+}
+
+// which way the conveyor whose surface is under feet at x runs, if any
+pub fn layout_conveyor_under(layout: &Layout, x: i32, feet: i32) -> Option<i32> {
+    let col = playfield_col(x);
+    layout.conveyors
+        .iter()
+        .find(|conveyor| layout.girders.get(conveyor.girder).map_or(false, |girder| girder.spans(col) && girder.surface(x) == feet))
+        .map(|conveyor| conveyor.direction)
+}
+
+// the ladder whose left edge is x, as it stands at stage tick ticks
+pub fn layout_ladder_at(layout: &Layout, x: i32, ticks: u32) -> Option<Climb> {
+    use super::layout_grid::layout_grid;
+    let col = playfield_col(x);
+    layout_grid(layout).climb_find(layout, col, col, ticks, |climb| climb.x == x)
+}
+
+// the first ladder, in layout order, within reach pixels of x that f
+// takes; only the columns that reach covers are looked at
+pub fn layout_ladder_near<F>(layout: &Layout, x: i32, reach: i32, ticks: u32, f: F) -> Option<Climb>
+    where F: Fn(&Climb) -> bool {
+    use super::layout_grid::layout_grid;
+    let (first, last) = (playfield_col(x - reach), playfield_col(x + reach));
+    layout_grid(layout).climb_find(layout, first, last, ticks, |climb| (climb.center() - x).abs() <= reach && f(climb))
+}
+
+// one line per problem, each prefixed with name.  ladders whose top misses
+// every girder are fine, that's how the broken ones are made; a foot that
+// misses leaves the ladder hanging, which only vines may do.
+pub fn layout_check(name: &str, layout: &Layout) -> Vec<String> {
+    use super::layout_grid::layout_grid;
+
+    let mut problems = Vec::new();
+    for (index, girder) in layout.girders.iter().enumerate() {
+        if girder.col_start > girder.col_end || girder.col_end >= TEXT_COL_COUNT {
+            problems.push(format!("{}: girder {} runs from col {} to {}, outside 0-{}", name, index, girder.col_start, girder.col_end, TEXT_COL_COUNT - 1));
+        }
+        if girder.rise.abs() > 1 {
+            problems.push(format!("{}: girder {} rises {}, the tiles only slope by -1, 0 or 1", name, index, girder.rise));
+        }
+        if girder.row >= TILE_ROW_COUNT {
+            problems.push(format!("{}: girder {} is on row {}, past the bottom of the screen", name, index, girder.row));
+        }
+    }
+    if !problems.is_empty() {
+        // the ladder checks measure against the girders
+        return problems;
+    }
+
+    let spans = layout.ladders
+        .iter()
+        .map(|ladder| ("ladder", ladder.col, ladder.top_row, ladder.bottom_row))
+        .chain(layout.retracting.iter().map(|ladder| ("retracting ladder", ladder.col, ladder.top_row, ladder.bottom_row)));
+    let grid = layout_grid(layout);
+    for (index, (kind, col, top_row, bottom_row)) in spans.enumerate() {
+        if col >= TEXT_COL_COUNT || top_row >= bottom_row {
+            problems.push(format!("{}: {} at col {} rows {}-{} is back to front or off screen", name, kind, col, top_row, bottom_row));
+            continue;
+        }
+        let climb = grid.climb_base(index);
+        let foot = layout_surface_near(layout, playfield_col_center(col), playfield_row_top(bottom_row) + 4, TILE_HEIGHT as i32 + 4);
+        if foot.is_none() && !layout.vines {
+            problems.push(format!("{}: {} at col {} ends on row {} with no girder under it (bottom at y {})", name, kind, col, bottom_row, climb.bottom));
+        }
+    }
+    for conveyor in layout.conveyors.iter() {
+        match layout.girders.get(conveyor.girder) {
+            None => problems.push(format!("{}: conveyor on girder {}, there are {}", name, conveyor.girder, layout.girders.len())),
+            Some(girder) if girder.rise != 0 => problems.push(format!("{}: conveyor on girder {}, which slopes", name, conveyor.girder)),
+            _ => (),
+        }
+        if conveyor.direction.abs() != 1 {
+            problems.push(format!("{}: conveyor on girder {} runs {}, it has to be -1 or 1", name, conveyor.girder, conveyor.direction));
+        }
+    }
+    for ladder in layout.retracting.iter() {
+        if ladder.retracted_row < ladder.top_row || ladder.retracted_row > ladder.bottom_row {
+            problems.push(format!("{}: retracting ladder at col {} retracts to row {}, outside rows {}-{}", name, ladder.col, ladder.retracted_row, ladder.top_row, ladder.bottom_row));
+        }
+    }
+    return problems;
+}
+
+// --------------------------------------------------------------------------
+// layouts
+//
+// XXX: hand placed to roughly match the boards until the arcade tile maps
+//      are decoded (see todo.txt)
+// --------------------------------------------------------------------------
+
+pub static BARRELS_LAYOUT: Layout = Layout {
+    girders: &[
+        Girder { row: 5,  col_start: 10, col_end: 17, rise: 0 },
+        Girder { row: 9,  col_start: 0,  col_end: 25, rise: -1 },
+        Girder { row: 13, col_start: 2,  col_end: 27, rise: 1 },
+        Girder { row: 17, col_start: 0,  col_end: 25, rise: -1 },
+        Girder { row: 21, col_start: 2,  col_end: 27, rise: 1 },
+        Girder { row: 25, col_start: 0,  col_end: 25, rise: -1 },
+        Girder { row: 29, col_start: 0,  col_end: 27, rise: 1 },
+    ],
+    ladders: &[
+        LadderSpan { col: 16, top_row: 5,  bottom_row: 9 },
+        LadderSpan { col: 22, top_row: 10, bottom_row: 14 },
+        LadderSpan { col: 4,  top_row: 14, bottom_row: 18 },
+        LadderSpan { col: 14, top_row: 14, bottom_row: 18 },
+        LadderSpan { col: 22, top_row: 18, bottom_row: 22 },
+        LadderSpan { col: 4,  top_row: 22, bottom_row: 26 },
+        LadderSpan { col: 11, top_row: 22, bottom_row: 26 },
+        LadderSpan { col: 22, top_row: 26, bottom_row: 30 },
+    ],
+    retracting: &[],
+    conveyors: &[],
+    vines: false,
+};
+
+pub static CONVEYORS_LAYOUT: Layout = Layout {
+    girders: &[
+        Girder { row: 5,  col_start: 10, col_end: 17, rise: 0 },
+        Girder { row: 9,  col_start: 0,  col_end: 27, rise: 0 },
+        Girder { row: 14, col_start: 0,  col_end: 27, rise: 0 },
+        Girder { row: 19, col_start: 0,  col_end: 27, rise: 0 },
+        Girder { row: 24, col_start: 0,  col_end: 27, rise: 0 },
+        Girder { row: 29, col_start: 0,  col_end: 27, rise: 0 },
+    ],
+    ladders: &[
+        LadderSpan { col: 16, top_row: 5,  bottom_row: 9 },
+        LadderSpan { col: 13, top_row: 14, bottom_row: 19 },
+        LadderSpan { col: 3,  top_row: 19, bottom_row: 24 },
+        LadderSpan { col: 24, top_row: 19, bottom_row: 24 },
+        LadderSpan { col: 13, top_row: 24, bottom_row: 29 },
+    ],
+    // the two ladders up to the top conveyor come and go
+    retracting: &[
+        RetractingLadder { col: 3,  top_row: 9, bottom_row: 14, retracted_row: 12, phase: 0 },
+        RetractingLadder { col: 24, top_row: 9, bottom_row: 14, retracted_row: 12, phase: 120 },
+    ],
+    // XXX: the belts run one way for good and aren't drawn moving; the
+    //      arcade's turn round now and then
+    conveyors: &[
+        Conveyor { girder: 1, direction: 1 },
+        Conveyor { girder: 2, direction: -1 },
+        Conveyor { girder: 4, direction: 1 },
+    ],
+    vines: false,
+};
+
+pub static ELEVATORS_LAYOUT: Layout = Layout {
+    girders: &[
+        Girder { row: 5,  col_start: 10, col_end: 17, rise: 0 },
+        Girder { row: 9,  col_start: 0,  col_end: 15, rise: 0 },
+        Girder { row: 14, col_start: 22, col_end: 27, rise: 0 },
+        Girder { row: 17, col_start: 0,  col_end: 3,  rise: 0 },
+        Girder { row: 21, col_start: 12, col_end: 15, rise: 0 },
+        Girder { row: 24, col_start: 22, col_end: 27, rise: 0 },
+        Girder { row: 29, col_start: 0,  col_end: 27, rise: 0 },
+    ],
+    ladders: &[
+        LadderSpan { col: 15, top_row: 5,  bottom_row: 9 },
+        LadderSpan { col: 25, top_row: 14, bottom_row: 24 },
+        LadderSpan { col: 1,  top_row: 17, bottom_row: 29 },
+    ],
+    retracting: &[],
+    conveyors: &[],
+    vines: false,
+};
+
+pub static RIVETS_LAYOUT: Layout = Layout {
+    girders: &[
+        Girder { row: 5,  col_start: 10, col_end: 17, rise: 0 },
+        Girder { row: 9,  col_start: 5,  col_end: 22, rise: 0 },
+        Girder { row: 13, col_start: 4,  col_end: 23, rise: 0 },
+        Girder { row: 17, col_start: 3,  col_end: 24, rise: 0 },
+        Girder { row: 21, col_start: 2,  col_end: 25, rise: 0 },
+        Girder { row: 25, col_start: 1,  col_end: 26, rise: 0 },
+        Girder { row: 29, col_start: 0,  col_end: 27, rise: 0 },
+    ],
+    ladders: &[
+        LadderSpan { col: 6,  top_row: 9,  bottom_row: 13 },
+        LadderSpan { col: 21, top_row: 9,  bottom_row: 13 },
+        LadderSpan { col: 5,  top_row: 13, bottom_row: 17 },
+        LadderSpan { col: 22, top_row: 13, bottom_row: 17 },
+        LadderSpan { col: 4,  top_row: 17, bottom_row: 21 },
+        LadderSpan { col: 13, top_row: 17, bottom_row: 21 },
+        LadderSpan { col: 23, top_row: 17, bottom_row: 21 },
+        LadderSpan { col: 3,  top_row: 21, bottom_row: 25 },
+        LadderSpan { col: 24, top_row: 21, bottom_row: 25 },
+        LadderSpan { col: 2,  top_row: 25, bottom_row: 29 },
+        LadderSpan { col: 13, top_row: 25, bottom_row: 29 },
+        LadderSpan { col: 25, top_row: 25, bottom_row: 29 },
+    ],
+    retracting: &[],
+    conveyors: &[],
+    vines: false,
+};
+
+// junior's stages are mostly vines, which climb like ladders
+pub static JUNGLE_LAYOUT: Layout = Layout {
+    girders: &[
+        Girder { row: 6,  col_start: 16, col_end: 27, rise: 0 },
+        Girder { row: 29, col_start: 0,  col_end: 9,  rise: 0 },
+    ],
+    ladders: &[
+        LadderSpan { col: 3,  top_row: 10, bottom_row: 28 },
+        LadderSpan { col: 7,  top_row: 8,  bottom_row: 28 },
+        LadderSpan { col: 12, top_row: 8,  bottom_row: 24 },
+        LadderSpan { col: 16, top_row: 7,  bottom_row: 24 },
+        LadderSpan { col: 21, top_row: 7,  bottom_row: 20 },
+    ],
+    retracting: &[],
+    conveyors: &[],
+    vines: true,
+};
+
+pub static SPRINGBOARD_LAYOUT: Layout = Layout {
+    girders: &[
+        Girder { row: 6,  col_start: 14, col_end: 27, rise: 0 },
+        Girder { row: 14, col_start: 6,  col_end: 12, rise: 0 },
+        Girder { row: 22, col_start: 16, col_end: 22, rise: 0 },
+        Girder { row: 29, col_start: 0,  col_end: 27, rise: 0 },
+    ],
+    ladders: &[
+        LadderSpan { col: 9,  top_row: 6,  bottom_row: 13 },
+        LadderSpan { col: 24, top_row: 7,  bottom_row: 21 },
+    ],
+    retracting: &[],
+    conveyors: &[],
+    vines: true,
+};
+
+pub static CHAINS_LAYOUT: Layout = Layout {
+    girders: &[
+        Girder { row: 6,  col_start: 0,  col_end: 27, rise: 0 },
+        Girder { row: 29, col_start: 0,  col_end: 27, rise: 0 },
+    ],
+    ladders: &[
+        LadderSpan { col: 4,  top_row: 7, bottom_row: 26 },
+        LadderSpan { col: 9,  top_row: 7, bottom_row: 26 },
+        LadderSpan { col: 14, top_row: 7, bottom_row: 26 },
+        LadderSpan { col: 19, top_row: 7, bottom_row: 26 },
+        LadderSpan { col: 24, top_row: 7, bottom_row: 26 },
+    ],
+    retracting: &[],
+    conveyors: &[],
+    vines: true,
+};
+
+pub static HIDEOUT_LAYOUT: Layout = Layout {
+    girders: &[
+        Girder { row: 6,  col_start: 18, col_end: 27, rise: 0 },
+        Girder { row: 12, col_start: 0,  col_end: 20, rise: 0 },
+        Girder { row: 18, col_start: 6,  col_end: 27, rise: 0 },
+        Girder { row: 24, col_start: 0,  col_end: 20, rise: 0 },
+        Girder { row: 29, col_start: 0,  col_end: 27, rise: 0 },
+    ],
+    ladders: &[
+        LadderSpan { col: 20, top_row: 6,  bottom_row: 12 },
+        LadderSpan { col: 8,  top_row: 12, bottom_row: 18 },
+        LadderSpan { col: 18, top_row: 18, bottom_row: 24 },
+        LadderSpan { col: 4,  top_row: 24, bottom_row: 29 },
+    ],
+    retracting: &[],
+    conveyors: &[],
+    vines: true,
+};
@@ -0,0 +1,154 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::layout::*;
+use super::super::playfield::*;
+use super::super::video::*;
+
+// a stage's girders & ladders by tile column, so what's under or beside
+// the player is one lookup instead of a walk through the whole layout.
+// built when a stage starts; anything asking about another layout (the
+// checks, the bot's planning) gets one built on the spot
+pub struct LayoutGrid {
+    // per column, the girders over it, in layout order
+    girders: Vec<Vec<usize>>,
+    // every climb with nothing retracted, in layout order
+    climbs: Vec<Climb>,
+    // per column, indices into climbs
+    climbs_by_col: Vec<Vec<usize>>,
+}
+
+thread_local!(
+    static GRID:RefCell<Option<(*const Layout, Rc<LayoutGrid>)>> = RefCell::new(None);
+);
+
+impl LayoutGrid {
+    fn build(layout: &Layout) -> LayoutGrid {
+        let mut grid = LayoutGrid {
+            girders: vec![Vec::new(); TEXT_COL_COUNT as usize],
+            climbs: Vec::new(),
+            climbs_by_col: vec![Vec::new(); TEXT_COL_COUNT as usize],
+        };
+        for (index, girder) in layout.girders.iter().enumerate() {
+            for col in girder.col_start..girder.col_end.min(TEXT_COL_COUNT - 1) + 1 {
+                grid.girders[col as usize].push(index);
+            }
+        }
+        let spans = layout.ladders
+            .iter()
+            .map(|ladder| (ladder.col, ladder.top_row, ladder.bottom_row, None))
+            .chain(layout.retracting
+                .iter()
+                .enumerate()
+                .map(|(index, ladder)| (ladder.col, ladder.top_row, ladder.bottom_row, Some(index))));
+        let spans: Vec<_> = spans.collect();
+        for (col, top_row, bottom_row, retracting) in spans {
+            let mut climb = grid.climb(layout, col, top_row, bottom_row);
+            climb.retracting = retracting;
+            if col < TEXT_COL_COUNT {
+                grid.climbs_by_col[col as usize].push(grid.climbs.len());
+            }
+            grid.climbs.push(climb);
+        }
+        return grid;
+    }
+
+    // the girders over pixel column x
+    fn girders_at<'a>(self:&'a LayoutGrid, layout: &'a Layout, x: i32) -> impl Iterator<Item=&'a Girder> + 'a {
+        let col = if PLAYFIELD.contains(x, 0) { playfield_col(x) as usize } else { self.girders.len() };
+        self.girders
+            .get(col)
+            .map_or(&[][..], |girders| &girders[..])
+            .iter()
+            .map(move |&index| &layout.girders[index])
+    }
+
+    pub fn surfaces<'a>(self:&'a LayoutGrid, layout: &'a Layout, x: i32) -> impl Iterator<Item=i32> + 'a {
+        self.girders_at(layout, x).map(move |girder| girder.surface(x))
+    }
+
+    pub fn surface_near(self:&LayoutGrid, layout: &Layout, x: i32, y: i32, reach: i32) -> Option<i32> {
+        self.surfaces(layout, x)
+            .filter(|&surface| (surface - y).abs() <= reach)
+            .min_by_key(|&surface| (surface - y).abs())
+    }
+
+    fn climb(self:&LayoutGrid, layout: &Layout, col: u32, top_row: u32, bottom_row: u32) -> Climb {
+        let x = playfield_col_left(col);
+        let center = playfield_col_center(col);
+        let top_guess = playfield_row_top(top_row) + 4;
+        let bottom_guess = playfield_row_top(bottom_row) + 4;
+        let top = self.surface_near(layout, center, top_guess, TILE_HEIGHT as i32);
+        let bottom = self.surface_near(layout, center, bottom_guess, TILE_HEIGHT as i32 + 4);
+        Climb {
+            x: x,
+            top: top.unwrap_or(playfield_row_top(top_row)),
+            bottom: bottom.unwrap_or(playfield_row_bottom(bottom_row)),
+            blocked: top.is_none(),
+            retracting: None,
+        }
+    }
+
+    // climb index as it stands at stage tick ticks
+    pub fn climb_at(self:&LayoutGrid, layout: &Layout, index: usize, ticks: u32) -> Climb {
+        let mut climb = self.climbs[index];
+        if let Some(retracting) = climb.retracting {
+            let retracted_by = layout.retracting[retracting].retracted_by(ticks) as i32;
+            climb.top += retracted_by;
+            climb.blocked = climb.blocked || retracted_by > 0;
+        }
+        return climb;
+    }
+
+    // nothing retracted, the way the checks measure it
+    pub fn climb_base(self:&LayoutGrid, index: usize) -> Climb {
+        return self.climbs[index];
+    }
+
+    // the first, in layout order, of the climbs in the columns from
+    // first_col to last_col that f takes
+    pub fn climb_find<F>(self:&LayoutGrid, layout: &Layout, first_col: u32, last_col: u32, ticks: u32, f: F) -> Option<Climb>
+        where F: Fn(&Climb) -> bool {
+        let last_col = last_col.min(TEXT_COL_COUNT - 1);
+        let mut found: Option<usize> = None;
+        for col in first_col..last_col + 1 {
+            for &index in self.climbs_by_col[col as usize].iter() {
+                if found.map_or(false, |found| found < index) {
+                    continue;
+                }
+                if f(&self.climb_at(layout, index, ticks)) {
+                    found = Some(index);
+                }
+            }
+        }
+        found.map(|index| self.climb_at(layout, index, ticks))
+    }
+}
+
+// the grid for layout, building it if the last one was for another
+pub fn layout_grid(layout: &Layout) -> Rc<LayoutGrid> {
+    let key = layout as *const Layout;
+    let cached = GRID.with(|cell| {
+        match *cell.borrow() {
+            Some((for_layout, ref grid)) if for_layout == key => Some(grid.clone()),
+            _ => None,
+        }
+    });
+    if let Some(grid) = cached {
+        return grid;
+    }
+    let grid = Rc::new(LayoutGrid::build(layout));
+    GRID.with(|cell| {*cell.borrow_mut() = Some((key, grid.clone()));});
+    return grid;
+}
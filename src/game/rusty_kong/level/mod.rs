@@ -10,8 +10,629 @@
 //
 // --------------------------------------------------------------------------
 
-#[allow(dead_code)]
-#[derive(Clone, Copy)]
-struct Level {
-    number: u32,
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use super::clock::clock_game_ticks;
+use super::hashing::StateHasher;
+use super::savestate::SaveState;
+use super::events::GameEvent;
+use super::events::events_publish;
+use super::state_machine::GameState;
+
+mod layout;
+pub use self::layout::*;
+
+mod layout_grid;
+pub use self::layout_grid::layout_grid;
+
+mod region;
+pub use self::region::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EnemyKind {
+    Barrel,
+    Fireball,
+    Pie,
+    Spring,
+    Snapjaw,
+    Nitpicker,
+    Spark,
+}
+
+impl EnemyKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &EnemyKind::Barrel    => "barrel",
+            &EnemyKind::Fireball  => "fireball",
+            &EnemyKind::Pie       => "pie",
+            &EnemyKind::Spring    => "spring",
+            &EnemyKind::Snapjaw   => "snapjaw",
+            &EnemyKind::Nitpicker => "nitpicker",
+            &EnemyKind::Spark     => "spark",
+        }
+    }
+}
+
+// where fireballs come in: a column on one of the layout's girders
+pub struct FireballSpawn {
+    pub col: u32,
+    pub girder: usize,
+}
+
+pub struct FireballRules {
+    // most fireballs alive at once
+    pub cap: usize,
+    pub spawns: &'static [FireballSpawn],
+}
+
+// jacks drop in at x on one girder, bounce along it and fall off its right
+// end; interval is ticks between drops at each internal difficulty
+pub struct JackRules {
+    pub girder: usize,
+    pub x: i32,
+    pub interval: [u32; DIFFICULTY_MAX as usize],
+}
+
+pub static NO_FIREBALLS: FireballRules = FireballRules { cap: 0, spawns: &[] };
+
+// what remix = on layers over a stage's arcade rules; everything extra is
+// data here, the enemies don't know which difficulty they're on
+pub struct StageRemix {
+    // in place of the stage's own fireball rules
+    pub fireballs: Option<&'static FireballRules>,
+    // more droppers, on top of the stage's own
+    pub jacks: &'static [JackRules],
+    // in place of physics' conveyor_step_ticks
+    pub conveyor_step_ticks: Option<u32>,
+}
+
+pub static NO_REMIX: StageRemix = StageRemix { fireballs: None, jacks: &[], conveyor_step_ticks: None };
+
+pub struct Stage {
+    pub name: &'static str,
+    pub height: u32,
+    pub enemies: &'static [EnemyKind],
+    pub layout: &'static Layout,
+    pub fireballs: &'static FireballRules,
+    pub jacks: Option<&'static JackRules>,
+    // top-left pixel of each hammer on the stage; they hang a jump's height
+    // over a girder
+    pub hammers: &'static [(i32, i32)],
+    // where the machine goes once PlayerWins has played out
+    pub after_win: GameState,
+    pub remix: &'static StageRemix,
+}
+
+fn level_is_remix() -> bool {
+    use super::settings::settings_read;
+    settings_read(|s| s.remix)
+}
+
+impl Stage {
+    pub fn fireball_rules(&self) -> &'static FireballRules {
+        match self.remix.fireballs {
+            Some(rules) if level_is_remix() => rules,
+            _ => self.fireballs,
+        }
+    }
+
+    // every jack dropper on the stage, its own first
+    pub fn jack_rules(&self) -> Vec<&'static JackRules> {
+        let mut rules: Vec<&'static JackRules> = self.jacks.iter().cloned().collect();
+        if level_is_remix() {
+            rules.extend(self.remix.jacks.iter());
+        }
+        return rules;
+    }
+
+    // a conveyor carries whoever's on it a pixel every this many ticks
+    pub fn conveyor_step_ticks(&self) -> u32 {
+        use super::physics::physics;
+        match self.remix.conveyor_step_ticks {
+            Some(ticks) if level_is_remix() => ticks,
+            _ => physics().conveyor_step_ticks,
+        }
+    }
+}
+
+pub struct Campaign {
+    pub name: &'static str,
+    pub stages: &'static [Stage],
+    // the state a fresh game starts in
+    pub first_state: GameState,
+    // indices into stages for each round of the given level (1-based)
+    pub rounds: fn(u32) -> &'static [usize],
+}
+
+#[derive(Clone, Copy, Hash)]
+pub struct Level {
+    pub number: u32,
+    pub round: usize,
+}
+
+// --------------------------------------------------------------------------
+// Donkey Kong
+// --------------------------------------------------------------------------
+
+const DK_BARRELS:    usize = 0;
+const DK_CONVEYORS:  usize = 1;
+const DK_ELEVATORS:  usize = 2;
+const DK_RIVETS:     usize = 3;
+
+// XXX: caps follow the arcade where known (five on rivets); the spawn
+//      points sit on the hand placed layouts' girders, bottom girder first
+static BARRELS_FIREBALLS: FireballRules = FireballRules {
+    cap: 4,
+    spawns: &[FireballSpawn { col: 2, girder: 6 }],
+};
+
+static CONVEYORS_FIREBALLS: FireballRules = FireballRules {
+    cap: 3,
+    spawns: &[FireballSpawn { col: 13, girder: 3 }],
+};
+
+static ELEVATORS_FIREBALLS: FireballRules = FireballRules {
+    cap: 2,
+    spawns: &[FireballSpawn { col: 24, girder: 5 }, FireballSpawn { col: 2, girder: 3 }],
+};
+
+// dropped from kong's level onto the top girder
+static ELEVATORS_JACKS: JackRules = JackRules {
+    girder: 1,
+    x: 56,
+    interval: [240, 210, 180, 150, 120],
+};
+
+static RIVETS_FIREBALLS: FireballRules = FireballRules {
+    cap: 5,
+    spawns: &[
+        FireballSpawn { col: 2,  girder: 6 },
+        FireballSpawn { col: 25, girder: 6 },
+        FireballSpawn { col: 4,  girder: 4 },
+        FireballSpawn { col: 23, girder: 4 },
+    ],
+};
+
+// remix: more and faster fireballs, a quicker conveyor and a second spring
+// dropper on the elevators
+static BARRELS_REMIX_FIREBALLS: FireballRules = FireballRules {
+    cap: 6,
+    spawns: &[FireballSpawn { col: 2, girder: 6 }, FireballSpawn { col: 25, girder: 4 }],
+};
+
+static BARRELS_REMIX: StageRemix = StageRemix {
+    fireballs: Some(&BARRELS_REMIX_FIREBALLS),
+    jacks: &[],
+    conveyor_step_ticks: None,
+};
+
+static CONVEYORS_REMIX_FIREBALLS: FireballRules = FireballRules {
+    cap: 5,
+    spawns: &[FireballSpawn { col: 13, girder: 3 }, FireballSpawn { col: 2, girder: 5 }],
+};
+
+static CONVEYORS_REMIX: StageRemix = StageRemix {
+    fireballs: Some(&CONVEYORS_REMIX_FIREBALLS),
+    jacks: &[],
+    conveyor_step_ticks: Some(1),
+};
+
+// out of step with the first so the two don't land together
+static ELEVATORS_REMIX: StageRemix = StageRemix {
+    fireballs: None,
+    jacks: &[JackRules { girder: 1, x: 8, interval: [300, 270, 230, 190, 150] }],
+    conveyor_step_ticks: None,
+};
+
+static RIVETS_REMIX_FIREBALLS: FireballRules = FireballRules {
+    cap: 7,
+    spawns: &[
+        FireballSpawn { col: 2,  girder: 6 },
+        FireballSpawn { col: 25, girder: 6 },
+        FireballSpawn { col: 4,  girder: 4 },
+        FireballSpawn { col: 23, girder: 4 },
+        FireballSpawn { col: 13, girder: 2 },
+    ],
+};
+
+static RIVETS_REMIX: StageRemix = StageRemix {
+    fireballs: Some(&RIVETS_REMIX_FIREBALLS),
+    jacks: &[],
+    conveyor_step_ticks: None,
+};
+
+static DONKEY_KONG_STAGES: [Stage; 4] = [
+    Stage {
+        name: "barrels",
+        height: 25,
+        enemies: &[EnemyKind::Barrel, EnemyKind::Fireball],
+        layout: &BARRELS_LAYOUT,
+        fireballs: &BARRELS_FIREBALLS,
+        jacks: None,
+        hammers: &[(136, 108), (32, 166)],
+        after_win: GameState::KongRetreats,
+        remix: &BARRELS_REMIX,
+    },
+    Stage {
+        name: "conveyors",
+        height: 50,
+        enemies: &[EnemyKind::Pie, EnemyKind::Fireball],
+        layout: &CONVEYORS_LAYOUT,
+        fireballs: &CONVEYORS_FIREBALLS,
+        jacks: None,
+        hammers: &[(16, 76), (200, 156)],
+        after_win: GameState::KongRetreats,
+        remix: &CONVEYORS_REMIX,
+    },
+    Stage {
+        name: "elevators",
+        height: 75,
+        enemies: &[EnemyKind::Spring, EnemyKind::Fireball],
+        layout: &ELEVATORS_LAYOUT,
+        fireballs: &ELEVATORS_FIREBALLS,
+        jacks: Some(&ELEVATORS_JACKS),
+        hammers: &[],
+        after_win: GameState::KongRetreats,
+        remix: &ELEVATORS_REMIX,
+    },
+    Stage {
+        name: "rivets",
+        height: 100,
+        enemies: &[EnemyKind::Fireball],
+        layout: &RIVETS_LAYOUT,
+        fireballs: &RIVETS_FIREBALLS,
+        jacks: None,
+        hammers: &[(40, 68), (184, 132)],
+        after_win: GameState::HowHigh,
+        remix: &RIVETS_REMIX,
+    },
+];
+
+// the board order is the region's
+fn donkey_kong_rounds(level: u32) -> &'static [usize] {
+    (level_region().rounds)(level)
+}
+
+// --------------------------------------------------------------------------
+// Junior: a small sample campaign showing the engine isn't tied to the
+// four Donkey Kong boards -- its own enemies, no retreat sequence, shorter intro
+// --------------------------------------------------------------------------
+
+static JUNIOR_STAGES: [Stage; 4] = [
+    Stage {
+        name: "jungle",
+        height: 25,
+        enemies: &[EnemyKind::Snapjaw],
+        layout: &JUNGLE_LAYOUT,
+        fireballs: &NO_FIREBALLS,
+        jacks: None,
+        hammers: &[],
+        after_win: GameState::HowHigh,
+        remix: &NO_REMIX,
+    },
+    Stage {
+        name: "springboard",
+        height: 50,
+        enemies: &[EnemyKind::Nitpicker],
+        layout: &SPRINGBOARD_LAYOUT,
+        fireballs: &NO_FIREBALLS,
+        jacks: None,
+        hammers: &[],
+        after_win: GameState::HowHigh,
+        remix: &NO_REMIX,
+    },
+    Stage {
+        name: "chains",
+        height: 75,
+        enemies: &[EnemyKind::Snapjaw, EnemyKind::Nitpicker],
+        layout: &CHAINS_LAYOUT,
+        fireballs: &NO_FIREBALLS,
+        jacks: None,
+        hammers: &[],
+        after_win: GameState::HowHigh,
+        remix: &NO_REMIX,
+    },
+    Stage {
+        name: "hideout",
+        height: 100,
+        enemies: &[EnemyKind::Spark],
+        layout: &HIDEOUT_LAYOUT,
+        fireballs: &NO_FIREBALLS,
+        jacks: None,
+        hammers: &[],
+        after_win: GameState::HowHigh,
+        remix: &NO_REMIX,
+    },
+];
+
+static JUNIOR_ROUNDS: [usize; 4] = [0, 1, 2, 3];
+
+fn junior_rounds(_level: u32) -> &'static [usize] {
+    &JUNIOR_ROUNDS
+}
+
+static CAMPAIGNS: [Campaign; 2] = [
+    Campaign {
+        name: "donkey kong",
+        stages: &DONKEY_KONG_STAGES,
+        first_state: GameState::LongIntroduction,
+        rounds: donkey_kong_rounds,
+    },
+    Campaign {
+        name: "junior",
+        stages: &JUNIOR_STAGES,
+        first_state: GameState::HowHigh,
+        rounds: junior_rounds,
+    },
+];
+
+// --------------------------------------------------------------------------
+// level sequencer
+// --------------------------------------------------------------------------
+
+#[derive(Clone, Hash)]
+struct Sequencer {
+    campaign: usize,
+    level: Level,
+    // practice pins a single stage that loops forever and never scores
+    practice: Option<usize>,
+    // the daily challenge's stage order, played every level in place of
+    // the campaign's rounds
+    order: Option<Vec<usize>>,
+    // clock_game_ticks() when the current attempt at this stage began
+    started: u32,
+}
+
+thread_local!(
+    static SEQUENCER:RefCell<Sequencer> = RefCell::new(Sequencer {
+        campaign: 0,
+        level: Level { number: 1, round: 0 },
+        practice: None,
+        order: None,
+        started: 0
+    });
+);
+
+fn fireball_rules_check(name: &str, rules: &FireballRules, girders: &[Girder]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for spawn in rules.spawns.iter() {
+        match girders.get(spawn.girder) {
+            Some(girder) if girder.spans(spawn.col) => {},
+            Some(_) => problems.push(format!("{}: fireball spawn at col {} is off the end of girder {}", name, spawn.col, spawn.girder)),
+            None => problems.push(format!("{}: fireball spawn names girder {}, there are {}", name, spawn.girder, girders.len())),
+        }
+    }
+    if rules.cap > 0 && rules.spawns.is_empty() {
+        problems.push(format!("{}: fireballs are allowed but there's nowhere for them to spawn", name));
+    }
+    return problems;
+}
+
+fn jack_rules_check(name: &str, jacks: &JackRules, girders: &[Girder]) -> Vec<String> {
+    let mut problems = Vec::new();
+    if jacks.girder >= girders.len() {
+        problems.push(format!("{}: jacks drop onto girder {}, there are {}", name, jacks.girder, girders.len()));
+    }
+    if jacks.interval.iter().any(|&ticks| ticks == 0) {
+        problems.push(format!("{}: a jack interval of 0 ticks would drop one every tick", name));
+    }
+    return problems;
+}
+
+// the built-in campaigns' stages checked against their layouts, one line
+// per problem; the remix data is checked whether or not it's switched on
+pub fn level_check() -> Vec<String> {
+    use super::playfield::PLAYFIELD;
+
+    let mut problems = Vec::new();
+    for campaign in CAMPAIGNS.iter() {
+        for stage in campaign.stages.iter() {
+            let name = format!("{} / {}", campaign.name, stage.name);
+            problems.extend(layout_check(&name, stage.layout));
+
+            let girders = stage.layout.girders;
+            problems.extend(fireball_rules_check(&name, stage.fireballs, girders));
+            if let Some(jacks) = stage.jacks {
+                problems.extend(jack_rules_check(&name, jacks, girders));
+            }
+
+            let remix = format!("{} (remix)", name);
+            if let Some(rules) = stage.remix.fireballs {
+                problems.extend(fireball_rules_check(&remix, rules, girders));
+            }
+            for jacks in stage.remix.jacks.iter() {
+                problems.extend(jack_rules_check(&remix, jacks, girders));
+            }
+            if stage.remix.conveyor_step_ticks == Some(0) {
+                problems.push(format!("{}: a conveyor step of 0 ticks never moves", remix));
+            }
+            for &(x, y) in stage.hammers.iter() {
+                if !PLAYFIELD.contains(x, y) {
+                    problems.push(format!("{}: hammer at {},{} is off the playfield", name, x, y));
+                }
+            }
+        }
+    }
+    return problems;
+}
+
+// the first stage of that name in any campaign
+pub fn level_stage_named(name: &str) -> Option<&'static Stage> {
+    CAMPAIGNS
+        .iter()
+        .flat_map(|campaign| campaign.stages.iter())
+        .find(|stage| stage.name == name)
+}
+
+pub fn level_campaign() -> &'static Campaign {
+    &CAMPAIGNS[SEQUENCER.with(|cell| cell.borrow().campaign)]
+}
+
+pub fn level_campaign_at(index: usize) -> &'static Campaign {
+    &CAMPAIGNS[index % CAMPAIGNS.len()]
+}
+
+pub fn level_campaign_count() -> usize {
+    CAMPAIGNS.len()
+}
+
+pub fn level_campaign_index() -> usize {
+    SEQUENCER.with(|cell| cell.borrow().campaign)
+}
+
+pub fn level_select_campaign(index: usize) {
+    let index = index % CAMPAIGNS.len();
+    SEQUENCER.with(|cell| {cell.borrow_mut().campaign = index;});
+    info!("campaign \"{}\" selected", CAMPAIGNS[index].name);
+}
+
+pub fn level_current() -> Level {
+    SEQUENCER.with(|cell| cell.borrow().level)
+}
+
+pub fn level_current_stage() -> &'static Stage {
+    let campaign = level_campaign();
+    if let Some(stage) = SEQUENCER.with(|cell| cell.borrow().practice) {
+        return &campaign.stages[stage];
+    }
+    SEQUENCER.with(|cell| {
+        let sequencer = cell.borrow();
+        let rounds = sequencer_rounds(&sequencer, campaign);
+        &campaign.stages[rounds[sequencer.level.round % rounds.len()]]
+    })
+}
+
+fn sequencer_rounds<'a>(sequencer: &'a Sequencer, campaign: &'static Campaign) -> &'a [usize] {
+    match sequencer.order {
+        Some(ref order) => order,
+        None => (campaign.rounds)(sequencer.level.number),
+    }
+}
+
+pub fn level_start_game() {
+    SEQUENCER.with(|cell| {
+        let mut sequencer = cell.borrow_mut();
+        sequencer.level = Level { number: 1, round: 0 };
+        sequencer.practice = None;
+        sequencer.order = None;
+        sequencer.started = clock_game_ticks();
+    });
+    events_publish(GameEvent::GameStarted);
+}
+
+// a scoring game that goes through order, indices into the campaign's
+// stages, every level
+pub fn level_start_daily(order: Vec<usize>) {
+    let stage_count = level_campaign().stages.len();
+    let order: Vec<usize> = order.into_iter().map(|stage| stage % stage_count).collect();
+    if order.is_empty() {
+        warn!("empty daily stage order, playing the campaign's");
+        return level_start_game();
+    }
+    SEQUENCER.with(|cell| {
+        let mut sequencer = cell.borrow_mut();
+        sequencer.level = Level { number: 1, round: 0 };
+        sequencer.practice = None;
+        sequencer.order = Some(order);
+        sequencer.started = clock_game_ticks();
+    });
+    events_publish(GameEvent::GameStarted);
+}
+
+pub fn level_start_practice(stage: usize) {
+    let stage = stage % level_campaign().stages.len();
+    SEQUENCER.with(|cell| {
+        let mut sequencer = cell.borrow_mut();
+        sequencer.level = Level { number: 1, round: 0 };
+        sequencer.practice = Some(stage);
+        sequencer.order = None;
+        sequencer.started = clock_game_ticks();
+    });
+    info!("practicing \"{}\"", level_current_stage().name);
+}
+
+pub fn level_is_practice() -> bool {
+    SEQUENCER.with(|cell| cell.borrow().practice.is_some())
+}
+
+// high scores, bonus tallies and the like only count outside practice
+pub fn level_is_scoring() -> bool {
+    !level_is_practice()
+}
+
+// gameplay ticks spent on the current attempt at this stage; pausing and
+// the death sequence don't count
+pub fn level_stage_ticks() -> u32 {
+    clock_game_ticks().wrapping_sub(SEQUENCER.with(|cell| cell.borrow().started))
+}
+
+// the arcade's internal difficulty, 1 to 5: starts at the level number and
+// creeps up the longer an attempt at a stage goes on
+pub const DIFFICULTY_MAX:        u32 = 5;
+const DIFFICULTY_STEP_TICKS:     u32 = 30 * 60;
+
+pub fn level_difficulty() -> u32 {
+    let start = level_current().number.max(1);
+    (start + level_stage_ticks() / DIFFICULTY_STEP_TICKS).min(DIFFICULTY_MAX)
+}
+
+// the bonus timer: starts higher on later levels and drops 100 points every
+// BONUS_STEP_TICKS of the attempt, down to nothing
+const BONUS_STEP:       u32 = 100;
+const BONUS_STEP_TICKS: u32 = 2 * 60;
+
+pub fn level_bonus_start(level: u32) -> u32 {
+    (level_region().bonus_start)(level)
+}
+
+pub fn level_bonus() -> u32 {
+    let start = level_bonus_start(level_current().number);
+    start.saturating_sub(level_stage_ticks() / BONUS_STEP_TICKS * BONUS_STEP)
+}
+
+// starts the current stage over without moving the sequence
+pub fn level_restart_stage() {
+    SEQUENCER.with(|cell| {cell.borrow_mut().started = clock_game_ticks();});
+}
+
+// moves to the next round, rolling over into the next level after the last
+// one.  practice just goes around the same stage again.
+pub fn level_advance() {
+    let campaign = level_campaign();
+    events_publish(GameEvent::StageCleared {
+        level: level_current(),
+        stage: level_current_stage(),
+        ticks: level_stage_ticks()
+    });
+    SEQUENCER.with(|cell| {
+        let mut sequencer = cell.borrow_mut();
+        sequencer.started = clock_game_ticks();
+        if sequencer.practice.is_some() {
+            return;
+        }
+        let rounds = sequencer_rounds(&sequencer, campaign).len();
+        if sequencer.level.round + 1 < rounds {
+            sequencer.level.round += 1;
+        } else {
+            sequencer.level.number += 1;
+            sequencer.level.round = 0;
+        }
+    });
+    let level = level_current();
+    debug!("level {} round {}: {}", level.number, level.round + 1, level_current_stage().name);
+}
+
+pub fn level_hash(hasher: &mut StateHasher) {
+    SEQUENCER.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn level_save(savestate: &mut SaveState) {
+    savestate.put("level", SEQUENCER.with(|cell| cell.borrow().clone()));
+}
+
+pub fn level_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<Sequencer>("level") {
+        SEQUENCER.with(|cell| {*cell.borrow_mut() = value;});
+    }
 }
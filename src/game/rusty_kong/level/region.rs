@@ -0,0 +1,100 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use super::*;
+use rusty_kong::locale::StringId;
+
+// which cabinet's rom the donkey kong campaign follows.  a region's rules
+// are a strategy the sequencer and the how high screen ask, so a region
+// only has to say where it differs.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Region {
+    Us,
+    Japan,
+}
+
+pub struct RegionRules {
+    pub name: &'static str,
+    // indices into the donkey kong stages for each round of the level
+    pub rounds: fn(u32) -> &'static [usize],
+    // the banner on the how high screen
+    pub how_high: StringId,
+    // the bonus timer's starting value for the level
+    pub bonus_start: fn(u32) -> u32,
+}
+
+// the US board order: each level adds rounds until level 5, which repeats
+static US_LEVEL_1: [usize; 2] = [DK_BARRELS, DK_RIVETS];
+static US_LEVEL_2: [usize; 3] = [DK_BARRELS, DK_ELEVATORS, DK_RIVETS];
+static US_LEVEL_3: [usize; 4] = [DK_BARRELS, DK_CONVEYORS, DK_ELEVATORS, DK_RIVETS];
+static US_LEVEL_4: [usize; 5] = [DK_BARRELS, DK_CONVEYORS, DK_BARRELS, DK_ELEVATORS, DK_RIVETS];
+static US_LEVEL_5: [usize; 6] = [DK_BARRELS, DK_CONVEYORS, DK_BARRELS, DK_ELEVATORS, DK_BARRELS, DK_RIVETS];
+
+fn us_rounds(level: u32) -> &'static [usize] {
+    match level {
+        0 | 1 => &US_LEVEL_1,
+        2     => &US_LEVEL_2,
+        3     => &US_LEVEL_3,
+        4     => &US_LEVEL_4,
+        _     => &US_LEVEL_5,
+    }
+}
+
+// XXX: the japanese rom plays all four boards in height order every level,
+//      from what the board guides say; not checked against a dump
+static JAPAN_LEVEL: [usize; 4] = [DK_BARRELS, DK_CONVEYORS, DK_ELEVATORS, DK_RIVETS];
+
+fn japan_rounds(_level: u32) -> &'static [usize] {
+    &JAPAN_LEVEL
+}
+
+// XXX: no scoring difference between the roms is known yet, both count the
+//      bonus down from the same values
+fn arcade_bonus_start(level: u32) -> u32 {
+    4000 + level.max(1).min(4) * 1000
+}
+
+static US_RULES: RegionRules = RegionRules {
+    name: "us",
+    rounds: us_rounds,
+    how_high: StringId::HowHighCanYouGet,
+    bonus_start: arcade_bonus_start,
+};
+
+static JAPAN_RULES: RegionRules = RegionRules {
+    name: "japan",
+    rounds: japan_rounds,
+    how_high: StringId::HowHighCanYouTry,
+    bonus_start: arcade_bonus_start,
+};
+
+impl Region {
+    pub fn rules(&self) -> &'static RegionRules {
+        match self {
+            &Region::Us     => &US_RULES,
+            &Region::Japan  => &JAPAN_RULES,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.rules().name
+    }
+
+    pub fn from_name(name: &str) -> Option<Region> {
+        [Region::Us, Region::Japan].iter().find(|region| region.name() == name).cloned()
+    }
+}
+
+pub fn level_region() -> &'static RegionRules {
+    use rusty_kong::settings::settings_read;
+    settings_read(|s| s.region).rules()
+}
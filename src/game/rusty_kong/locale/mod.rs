@@ -0,0 +1,193 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const LOCALE_DIR: &str = "locales";
+
+// limited to scripts the bundled fonts can actually draw
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    English,
+    French,
+    German,
+    Spanish,
+}
+
+impl Language {
+    pub fn code(&self) -> &'static str {
+        match self {
+            &Language::English => "en",
+            &Language::French  => "fr",
+            &Language::German  => "de",
+            &Language::Spanish => "es",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Language> {
+        match code {
+            "en" => Some(Language::English),
+            "fr" => Some(Language::French),
+            "de" => Some(Language::German),
+            "es" => Some(Language::Spanish),
+            _    => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StringId {
+    InsertCoin,
+    HowHighCanYouGet,
+    // the japanese rom's phrasing
+    HowHighCanYouTry,
+    GameOver,
+    NarrateGamePlay,
+    NarratePlayerDies,
+    NarratePlayerWins,
+    // said before the score it's reached
+    NarrateScore,
+}
+
+const ALL_STRINGS: [StringId; 8] = [
+    StringId::InsertCoin,
+    StringId::HowHighCanYouGet,
+    StringId::HowHighCanYouTry,
+    StringId::GameOver,
+    StringId::NarrateGamePlay,
+    StringId::NarratePlayerDies,
+    StringId::NarratePlayerWins,
+    StringId::NarrateScore,
+];
+
+impl StringId {
+    pub fn key(&self) -> &'static str {
+        match self {
+            &StringId::InsertCoin           => "insert_coin",
+            &StringId::HowHighCanYouGet     => "how_high",
+            &StringId::HowHighCanYouTry     => "how_high_try",
+            &StringId::GameOver             => "game_over",
+            &StringId::NarrateGamePlay      => "narrate_game_play",
+            &StringId::NarratePlayerDies    => "narrate_player_dies",
+            &StringId::NarratePlayerWins    => "narrate_player_wins",
+            &StringId::NarrateScore         => "narrate_score",
+        }
+    }
+
+    // built-in English, used when a locale file is missing or incomplete
+    fn fallback(&self) -> &'static str {
+        match self {
+            &StringId::InsertCoin           => "INSERT COIN",
+            &StringId::HowHighCanYouGet     => "HOW HIGH CAN YOU GET?",
+            &StringId::HowHighCanYouTry     => "HOW HIGH CAN YOU TRY?",
+            &StringId::GameOver             => "GAME OVER",
+            &StringId::NarrateGamePlay      => "Go",
+            &StringId::NarratePlayerDies    => "Player down",
+            &StringId::NarratePlayerWins    => "Stage clear",
+            &StringId::NarrateScore         => "Score",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<StringId> {
+        ALL_STRINGS.iter().find(|id| id.key() == key).map(|id| *id)
+    }
+}
+
+thread_local!(
+    static STRINGS:RefCell<HashMap<StringId, String>> = RefCell::new(HashMap::new());
+);
+
+pub fn locale_text(id: StringId) -> String {
+    STRINGS.with(|cell| {
+        match cell.borrow().get(&id) {
+            Some(text) => text.clone(),
+            None => id.fallback().to_string(),
+        }
+    })
+}
+
+// the strings in a locale file and one line per problem with it; None when
+// the file can't be opened
+fn locale_read(path: &Path) -> Option<(HashMap<StringId, String>, Vec<String>)> {
+    use super::settings::read_key_value_file;
+
+    let pairs = read_key_value_file(path)?;
+    let mut strings = HashMap::new();
+    let mut problems = Vec::new();
+    for pair in pairs.iter() {
+        match StringId::from_key(&pair.key) {
+            Some(id) => { strings.insert(id, pair.value.clone()); },
+            None => problems.push(format!("{}:{}: unknown string \"{}\"", path.display(), pair.line, pair.key)),
+        }
+    }
+
+    for id in ALL_STRINGS.iter() {
+        if !strings.contains_key(id) {
+            problems.push(format!("{} has no \"{}\", using built-in English", path.display(), id.key()));
+        }
+    }
+    return Some((strings, problems));
+}
+
+// locale_read()'s problems plus anything the tile font can't draw; the
+// narration lines are spoken, not drawn, so they can say what they like
+pub fn locale_check(path: &Path) -> Vec<String> {
+    use super::video::text_tile;
+
+    let (strings, mut problems) =
+        match locale_read(path) {
+            Some(r) => r,
+            None => return vec![format!("can't open {}", path.display())],
+        };
+    for id in ALL_STRINGS.iter() {
+        if id.key().starts_with("narrate_") {
+            continue;
+        }
+        if let Some(text) = strings.get(id) {
+            let missing: String = text.chars().filter(|&ch| ch != ' ' && text_tile(ch) == text_tile(' ')).collect();
+            if !missing.is_empty() {
+                problems.push(format!("{}: \"{}\" uses \"{}\", which the font can't draw (only A-Z, 0-9, '.', '-', ''' and '?', no accents)", path.display(), id.key(), missing));
+            }
+        }
+    }
+    return problems;
+}
+
+pub fn locale_load(language: Language) {
+    use super::assets::asset_path;
+
+    let path_buf = asset_path(&format!("{}/{}.txt", LOCALE_DIR, language.code()));
+    let path = path_buf.as_path();
+    let strings =
+        match locale_read(path) {
+            Some((strings, problems)) => {
+                for problem in problems.iter() {
+                    warn!("{}", problem);
+                }
+                strings
+            },
+            None => {
+                error!("can't open locale file {}, falling back to built-in English", path.display());
+                return;
+            },
+        };
+
+    info!("loaded {} strings for language \"{}\"", strings.len(), language.code());
+    STRINGS.with(|cell| {*cell.borrow_mut() = strings;});
+}
+
+pub fn locale_init() {
+    use super::settings::settings_read;
+    locale_load(settings_read(|s| s.language));
+}
@@ -0,0 +1,79 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use log;
+use log::Level;
+use log::LevelFilter;
+use log::Log;
+use log::Metadata;
+use log::Record;
+
+// lines kept for bug reports; everything still goes to stdout
+pub const LOG_TAIL_LINES: usize = 200;
+
+// prints what simple_logger used to, but with the time since startup and a
+// copy of the last few lines in memory
+struct TailLogger {
+    started: Instant,
+}
+
+lazy_static! {
+    static ref TAIL: Mutex<VecDeque<String>> = Mutex::new(VecDeque::with_capacity(LOG_TAIL_LINES));
+    static ref LOGGER: TailLogger = TailLogger { started: Instant::now() };
+}
+
+impl Log for TailLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Trace
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let elapsed = self.started.elapsed();
+        let line = format!("{:>5}.{:03} {:<5} [{}] {}",
+                           elapsed.as_secs(),
+                           elapsed.subsec_millis(),
+                           record.level(),
+                           record.module_path().unwrap_or(""),
+                           record.args());
+        println!("{}", line);
+        if let Ok(mut tail) = TAIL.lock() {
+            if tail.len() == LOG_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+    }
+
+    fn flush(&self) {
+    }
+}
+
+pub fn logging_init() {
+    match log::set_logger(&*LOGGER) {
+        Ok(()) => log::set_max_level(LevelFilter::Trace),
+        Err(e) => eprintln!("can't install the logger: {}", e),
+    }
+}
+
+// oldest first
+pub fn logging_tail() -> Vec<String> {
+    match TAIL.lock() {
+        Ok(tail) => tail.iter().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
@@ -11,110 +11,849 @@
 // --------------------------------------------------------------------------
 
 mod video;
+mod playfield;
 mod sound;
 mod player;
+mod kong;
+mod enemies;
+mod physics;
+mod inspector;
+mod rng;
+mod clock;
+mod arena;
+mod savestate;
+mod rewind;
+mod replay;
+mod suspend;
+mod hashing;
+mod settings;
+mod assets;
+mod locale;
+mod events;
+mod narration;
+mod event_log;
+mod speedrun;
+mod persist;
+mod daily;
+mod community;
+mod status;
+mod reports;
+mod logging;
+mod input;
+mod options;
+mod golden;
+mod soak;
+mod properties;
+mod fuzz;
+mod validate;
+mod bot;
+mod level;
+mod sequence;
+mod scripting;
 mod state_machine;
 
 use sdl2;
 
+use std::env;
+use std::path::Path;
+use std::process;
+
 use sdl2::Sdl;
 use sdl2::event::Event;
+use sdl2::event::WindowEvent;
 use sdl2::keyboard::Keycode;
-use sdl2::render::WindowCanvas;
-use sdl2::controller::GameController;
+use self::input::InputPad;
+use self::savestate::SaveState;
+use self::video::VideoSystem;
+use self::state_machine::StateReport;
+
+pub use self::logging::logging_init;
 
 struct SystemInterfaces {
-    controller: GameController,
-    canvas: WindowCanvas,
+    controllers: Vec<InputPad>,
+    video: VideoSystem,
 }
 
-fn controller_init(sdl_context: &Sdl) -> GameController {
-    let subsystem = sdl_context.game_controller().unwrap();
+// a missing controller isn't fatal, the keyboard still works.  every pad
+// with a mapping is opened, for two players to claim one each
+fn controller_init(sdl_context: &Sdl) -> Result<Vec<InputPad>, String> {
+    let subsystem = sdl_context.game_controller()?;
+    let joysticks = sdl_context.joystick()?;
 
     let available =
         match subsystem.num_joysticks() {
             Ok(n) => n,
-            Err(e) => {
-                error!("can't enumerate joysticks: {}", e);
-                panic!();
-            },
+            Err(e) => return Err(format!("can't enumerate joysticks: {}", e)),
         };
 
     info!("{} joysticks available", available);
 
-    let mut controller = None;
+    let mut controllers: Vec<InputPad> = Vec::new();
+    // what the input test lists under devices
+    let mut devices = Vec::new();
+    // and what the controllers page calls each pad
+    let mut pads = Vec::new();
 
     for id in 0..available {
+        let name = subsystem.name_for_index(id).unwrap_or_else(|_| "unknown".to_string());
         if subsystem.is_game_controller(id) {
             info!("Attempting to open controller {}", id);
 
             match subsystem.open(id) {
                 Ok(c) => {
+                    use self::input::input_rumble_open;
                     info!("Success: opened \"{}\"", c.name());
-                    controller = Some(c);
-                    break;
+                    devices.push(format!("{} {} - IN USE", id, c.name()));
+                    // XXX: only the first pad's motors are driven
+                    if controllers.is_empty() {
+                        input_rumble_open(sdl_context, id);
+                    }
+                    let guid = joysticks.device_guid(id).map(|guid| guid.string()).unwrap_or_default();
+                    let twins = controllers.iter().filter(|pad| pad.id.starts_with(&format!("{}#", guid))).count();
+                    let pad = InputPad { controller: c, id: format!("{}#{}", guid, twins) };
+                    pads.push((pad.id.clone(), format!("PAD {} {}", id, pad.controller.name())));
+                    controllers.push(pad);
                 },
                 Err(e) => {
                     error!("failed: {:?}", e);
-                    panic!();
+                    devices.push(format!("{} {} - WONT OPEN", id, name));
                 },
             }
         } else {
             warn!("{} is not a game controller", id);
+            devices.push(format!("{} {} - NO MAPPING", id, name));
         }
     }
+    {
+        use self::input::*;
+        input_set_devices(devices);
+        input_set_pads(pads);
+    }
 
-    let controller =
-        match controller {
-            Some(c) => c,
-            None => {
-                error!("Couldn't open any controller");
-                panic!();
-            },
-        };
+    if controllers.is_empty() {
+        warn!("Couldn't open any controller, keyboard only");
+    }
+    for pad in controllers.iter() {
+        info!("Controller mapping: {} ({})", pad.controller.mapping(), pad.id);
+    }
+
+    return Ok(controllers);
+}
+
+// Covers every stateful subsystem; anything new that carries state between
+// frames needs a *_hash() call here or desync checks will miss it.
+pub fn game_state_subsystem_hashes() -> Vec<(&'static str, u64)> {
+    use std::hash::Hasher;
+    use self::hashing::StateHasher;
+    use self::state_machine::state_machine_hash;
+    use self::player::player_hash;
+    use self::video::video_hash;
+    use self::level::level_hash;
+    use self::kong::kong_hash;
+    use self::enemies::enemies_hash;
+    use self::physics::physics_hash;
+    use self::rng::rng_hash;
+    use self::clock::clock_hash;
+    use self::sequence::sequence_hash;
+
+    let subsystems: [(&'static str, fn(&mut StateHasher)); 10] = [
+        ("state_machine", state_machine_hash),
+        ("player",        player_hash),
+        ("video",         video_hash),
+        ("level",         level_hash),
+        ("kong",          kong_hash),
+        ("enemies",       enemies_hash),
+        ("physics",       physics_hash),
+        ("rng",           rng_hash),
+        ("clock",         clock_hash),
+        ("sequence",      sequence_hash),
+    ];
+    subsystems
+        .iter()
+        .map(|&(name, hash)| {
+            let mut hasher = StateHasher::new();
+            hash(&mut hasher);
+            (name, hasher.finish())
+        })
+        .collect()
+}
+
+pub fn game_state_hash() -> u64 {
+    use std::hash::Hasher;
+    use self::hashing::StateHasher;
+
+    let mut hasher = StateHasher::new();
+    for &(_, hash) in game_state_subsystem_hashes().iter() {
+        hasher.write_u64(hash);
+    }
+    return hasher.finish();
+}
 
-    info!("Controller mapping: {}", controller.mapping());
+// the same subsystems as game_state_hash(), as a snapshot that
+// game_state_restore() puts back; anything hashed should be saved too
+pub fn game_state_save() -> SaveState {
+    use self::state_machine::state_machine_save;
+    use self::player::player_save;
+    use self::video::video_save;
+    use self::level::level_save;
+    use self::kong::kong_save;
+    use self::enemies::enemies_save;
+    use self::physics::physics_save;
+    use self::rng::rng_save;
+    use self::clock::clock_save;
+    use self::sequence::sequence_save;
 
-    return controller;
+    let mut savestate = SaveState::new();
+    state_machine_save(&mut savestate);
+    player_save(&mut savestate);
+    video_save(&mut savestate);
+    level_save(&mut savestate);
+    kong_save(&mut savestate);
+    enemies_save(&mut savestate);
+    physics_save(&mut savestate);
+    rng_save(&mut savestate);
+    clock_save(&mut savestate);
+    sequence_save(&mut savestate);
+    return savestate;
+}
+
+pub fn game_state_restore(savestate: &SaveState) {
+    use self::state_machine::state_machine_restore;
+    use self::player::player_restore;
+    use self::video::video_restore;
+    use self::level::level_restore;
+    use self::kong::kong_restore;
+    use self::enemies::enemies_restore;
+    use self::physics::physics_restore;
+    use self::rng::rng_restore;
+    use self::clock::clock_restore;
+    use self::sequence::sequence_restore;
+
+    state_machine_restore(savestate);
+    player_restore(savestate);
+    video_restore(savestate);
+    level_restore(savestate);
+    kong_restore(savestate);
+    enemies_restore(savestate);
+    physics_restore(savestate);
+    rng_restore(savestate);
+    clock_restore(savestate);
+    sequence_restore(savestate);
 }
 
 pub fn game_run() {
-    let context = sdl2::init().unwrap();
-    let mut system_interfaces = game_init(&context).unwrap();
-    let mut event_pump = context.event_pump().unwrap();
+    use self::settings::settings_read;
+    use self::options::options_parse;
+    use self::options::USAGE;
+    use self::golden::golden_run;
+    use self::input::input_read_devices;
+    use self::input::input_set;
+    use self::bot::bot_update;
+    use self::state_machine::game_state_quit_requested;
+
+    let options =
+        match options_parse(env::args().skip(1)) {
+            Ok(o) => o,
+            Err(e) => {
+                error!("{}", e);
+                println!("{}", USAGE);
+                process::exit(2);
+            },
+        };
+
+    if options.write_checksums {
+        use self::assets::assets_write_checksums;
+        match assets_write_checksums() {
+            Ok(count) => {
+                info!("wrote checksums for {} asset files", count);
+                process::exit(0);
+            },
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            },
+        }
+    }
+
+    if let Some(ref path) = options.write_pack {
+        use self::assets::ASSETS_DIR;
+        use self::assets::pack_write;
+        match pack_write(Path::new(ASSETS_DIR), Path::new(path), options.zstd) {
+            Ok(count) => {
+                info!("packed {} asset files into {}", count, path);
+                process::exit(0);
+            },
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            },
+        }
+    }
+
+    if let Some(target) = options.validate_assets {
+        use self::validate::validate_assets;
+        let passed = validate_assets(&target);
+        process::exit(if passed { 0 } else { 1 });
+    }
+    if options.rng_trace.is_some() {
+        use self::rng::rng_trace_enable;
+        rng_trace_enable();
+    }
+    if let Some(script) = options.golden {
+        let passed = golden_run(Path::new(&script), options.bless);
+        rng_trace_finish(&options.rng_trace);
+        state_graph_finish(&options.state_graph);
+        process::exit(if passed { 0 } else { 1 });
+    }
+    if let Some(path) = options.replay {
+        use self::replay::replay_verify;
+        let passed = replay_verify(Path::new(&path));
+        rng_trace_finish(&options.rng_trace);
+        state_graph_finish(&options.state_graph);
+        process::exit(if passed { 0 } else { 1 });
+    }
+    if let Some(path) = options.turbo_check {
+        use self::replay::turbo_check;
+        use self::settings::settings_init;
+        settings_init();
+        let passed =
+            match turbo_check(Path::new(&path)) {
+                Ok(flags) => {
+                    if flags.is_empty() {
+                        info!("{}: no turbo", path);
+                    }
+                    flags.is_empty()
+                },
+                Err(e) => {
+                    error!("{}", e);
+                    false
+                },
+            };
+        process::exit(if passed { 0 } else { 1 });
+    }
+    if let Some(script) = options.audit {
+        use self::golden::golden_audit;
+        let passed = golden_audit(Path::new(&script));
+        process::exit(if passed { 0 } else { 1 });
+    }
+    if let Some(dir) = options.fuzz_corpus {
+        use self::fuzz::fuzz_corpus;
+        let passed = fuzz_corpus(Path::new(&dir));
+        process::exit(if passed { 0 } else { 1 });
+    }
+    if let Some(minutes) = options.soak {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        use self::soak::soak;
+        let seed = options.seed.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.subsec_nanos() ^ t.as_secs() as u32).unwrap_or(0)
+        });
+        let passed = soak(minutes, seed);
+        process::exit(if passed { 0 } else { 1 });
+    }
+    if let Some(seeds) = options.properties {
+        use self::properties::properties;
+        let passed = properties(seeds, options.seed.unwrap_or(1));
+        process::exit(if passed { 0 } else { 1 });
+    }
+    if let Some(script) = options.rewind_bench {
+        use self::golden::golden_rewind_bench;
+        use self::settings::settings_init;
+        settings_init();
+        let passed = golden_rewind_bench(Path::new(&script));
+        process::exit(if passed { 0 } else { 1 });
+    }
+
+    // home sessions are journaled so closing mid-game can suspend it; --dev's
+    // rewinds and --state's start aren't anything a journal can play back
+    // neither is a suspended game a spectator's viewers never saw, nor a
+    // watched session the viewer's to resume
+    let mut journaled = !options.arcade && !options.dev && options.state.is_none() && options.spectate.is_none() && options.watch.is_none();
+    if journaled {
+        use self::suspend::suspend_load;
+        suspend_load();
+    }
+
+    // without sdl or a window there's nowhere to show the error screen, so
+    // these are the only failures that still end up on the terminal
+    let context =
+        match sdl2::init() {
+            Ok(c) => c,
+            Err(e) => {
+                error!("can't initialize SDL: {}", e);
+                process::exit(1);
+            },
+        };
+    let mut system_interfaces =
+        match game_init(&context, options.display) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            },
+        };
+    if options.arcade {
+        use self::settings::settings_get;
+        use self::settings::settings_set;
+        let mut settings = settings_get();
+        settings.arcade = true;
+        settings_set(settings);
+    }
+    if let Some(state) = options.state {
+        use self::state_machine::game_state_go;
+        info!("starting in {}", state);
+        game_state_go(state);
+    }
+    if options.dev {
+        use self::event_log::heatmap_init;
+        use self::rewind::rewind_enable;
+        use self::video::video_atlas_load;
+        use self::video::video_debug_open;
+        rewind_enable();
+        heatmap_init();
+        if let Err(e) = video_atlas_load() {
+            warn!("loose sprites: {}", e);
+        }
+        // the game runs fine without it
+        if let Err(e) = video_debug_open(&context, &mut system_interfaces.video) {
+            warn!("{}", e);
+        }
+    }
+    if options.alloc_check {
+        use self::arena::alloc_check_enable;
+        alloc_check_enable();
+    }
+    // boot does the decoding, so a run that starts past it waits on it here
+    if options.state.is_some() {
+        use self::state_machine::boot_decode_wait;
+        boot_decode_wait();
+    }
+    let reset_key = {
+        let name = settings_read(|s| s.reset_key.clone());
+        let key = Keycode::from_name(&name);
+        if key.is_none() {
+            warn!("unknown reset_key \"{}\", reset is off", name);
+        }
+        key
+    };
+    let tilt_key = {
+        let name = settings_read(|s| s.tilt_key.clone());
+        let key = Keycode::from_name(&name);
+        if key.is_none() {
+            warn!("unknown tilt_key \"{}\", tilt is off", name);
+        }
+        key
+    };
+    let fast_forward_key = {
+        let name = settings_read(|s| s.fast_forward_key.clone());
+        let key = Keycode::from_name(&name);
+        if key.is_none() {
+            warn!("unknown fast_forward_key \"{}\", fast-forward is off", name);
+        }
+        key
+    };
+    let capture_key = {
+        let name = settings_read(|s| s.capture_key.clone());
+        let key = Keycode::from_name(&name);
+        if key.is_none() {
+            warn!("unknown capture_key \"{}\", capture mode can't be toggled", name);
+        }
+        key
+    };
+    if options.speed > 1 {
+        info!("running {} logic ticks to every tick", options.speed);
+    }
+    if let Some(ref path) = options.record {
+        use self::replay::replay_record_start;
+        replay_record_start(path, options.replay_hashes);
+    }
+    if let Some(ref address) = options.spectate {
+        use self::replay::replay_spectate_start;
+        if let Err(e) = replay_spectate_start(address, options.replay_hashes) {
+            error!("{}", e);
+            process::exit(1);
+        }
+    }
+    if let Some(ref address) = options.watch {
+        use self::replay::replay_watch_start;
+        if let Err(e) = replay_watch_start(address) {
+            error!("{}", e);
+            process::exit(1);
+        }
+    }
+    journaled = journaled && !settings_read(|s| s.arcade);
+    if journaled {
+        use self::suspend::suspend_init;
+        use self::suspend::suspend_journal_start;
+        suspend_init();
+        suspend_journal_start();
+    }
+    let mut event_pump =
+        match context.event_pump() {
+            Ok(p) => p,
+            Err(e) => {
+                error!("can't get the SDL event pump: {}", e);
+                process::exit(1);
+            },
+        };
+
+    // logic runs off wall time, TICKS_PER_SECOND at game_speed percent, so
+    // the display's refresh and the vsync setting only change how many
+    // frames show it
+    let mut pacer = {
+        use self::clock::FramePacer;
+        use self::video::Vsync;
+        FramePacer::new(settings_read(|s| s.vsync) != Vsync::Off)
+    };
+    let mut frame_limit = {
+        use self::clock::FrameLimit;
+        FrameLimit::new()
+    };
+    // none of the game's windows has focus, or the game's is minimized
+    let mut background = false;
+    // fast_forward_key is held down
+    let mut fast_forward = false;
     'running: loop {
+        use self::arena::*;
+        use self::clock::clock_wall;
+        use self::video::video_close_window;
+        use self::video::video_debug_frame_time;
+        use self::video::video_debug_key;
+        use self::video::video_debug_next_panel;
+        use self::video::video_is_debug_window;
+        use self::rewind::REWIND_STEP;
+        use self::rewind::rewind_back;
+        use self::rewind::rewind_record;
+        use self::replay::replay_record_tick;
+
+        let frame_start = clock_wall();
         for event in event_pump.poll_iter() {
             match event {
+                // with the debug window up, closing a window doesn't quit
+                // by itself, so the game window's close has to
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } => {
+                    if !video_close_window(&mut system_interfaces.video, window_id) {
+                        break 'running
+                    }
+                },
+                Event::Window { window_id, win_event: WindowEvent::SizeChanged(..), .. } => {
+                    use self::video::video_window_resized;
+                    video_window_resized(&mut system_interfaces.video, window_id);
+                },
+                Event::MouseButtonDown { window_id, x, y, .. } if options.dev => {
+                    use self::video::video_debug_click;
+                    video_debug_click(&system_interfaces.video, window_id, x, y);
+                },
+                Event::Window { win_event: WindowEvent::FocusLost, .. } |
+                Event::Window { win_event: WindowEvent::Minimized, .. } => background = true,
+                Event::Window { win_event: WindowEvent::FocusGained, .. } |
+                Event::Window { win_event: WindowEvent::Restored, .. } => background = false,
+                Event::KeyDown { window_id, keycode: Some(keycode), .. }
+                    if video_is_debug_window(&system_interfaces.video, window_id) => {
+                    match keycode {
+                        Keycode::Escape => break 'running,
+                        Keycode::Tab => video_debug_next_panel(),
+                        _ => video_debug_key(keycode),
+                    }
+                },
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } if Some(keycode) == fast_forward_key => fast_forward = true,
+                Event::KeyUp { keycode: Some(keycode), .. } if Some(keycode) == fast_forward_key => fast_forward = false,
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } if Some(keycode) == capture_key => {
+                    use self::video::video_capture_toggle;
+                    video_capture_toggle(&mut system_interfaces.video);
+                },
+                // a watched game's resets & tilts come with it
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } if Some(keycode) == reset_key && options.watch.is_none() => {
+                    use self::replay::replay_record_reset;
+                    use self::state_machine::game_state_reset;
+                    replay_record_reset();
+                    game_state_reset();
+                },
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } if Some(keycode) == tilt_key && options.watch.is_none() => {
+                    use self::replay::replay_record_tilt;
+                    use self::state_machine::game_state_tilt;
+                    replay_record_tilt();
+                    game_state_tilt();
+                },
+                Event::AudioDeviceRemoved { iscapture: false, .. } => {
+                    use self::sound::sound_device_removed;
+                    sound_device_removed();
+                },
+                Event::AudioDeviceAdded { iscapture: false, .. } => {
+                    use self::sound::sound_device_added;
+                    sound_device_added();
+                },
+                Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } if journaled => {
+                    use self::suspend::suspend_continue;
+                    suspend_continue();
+                },
+                Event::KeyDown { keycode: Some(Keycode::F4), repeat: false, .. } => {
+                    use self::input::input_display_toggle;
+                    input_display_toggle();
+                },
+                Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, .. } => {
+                    use self::reports::report_write;
+                    match report_write(&system_interfaces.video) {
+                        Ok(path) => info!("bug report written to {}", path.display()),
+                        Err(e) => warn!("bug report: {}", e),
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F6), repeat: false, .. } if options.dev => {
+                    use self::event_log::heatmap_toggle;
+                    heatmap_toggle();
+                },
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } if options.dev => {
+                    rewind_back(REWIND_STEP);
+                },
                 Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
                 _ => {}
             }
         }
-        game_update(&mut system_interfaces.controller);
-        game_render(&mut system_interfaces.canvas);
+        let input = input_read_devices(&event_pump.keyboard_state(), &system_interfaces.controllers);
+        let speed =
+            if fast_forward {
+                options.speed.max(settings_read(|s| s.fast_forward_speed))
+            } else {
+                options.speed
+            };
+        {
+            use self::sound::sound_set_fast_forward;
+            sound_set_fast_forward(speed > 1);
+        }
+        let percent = settings_read(|s| s.game_speed) as u32 * speed;
+        let steady = alloc_check_steady();
+        alloc_check_arm(steady);
+        if options.watch.is_some() {
+            use self::replay::replay_watch_update;
+            replay_watch_update(pacer.due(percent));
+        } else {
+            for _ in 0..pacer.due(percent) {
+                let flags = if options.bot { bot_update() } else { input };
+                input_set(flags);
+                game_update();
+                replay_record_tick(flags);
+                memory_tagged(MemoryTag::Rewind, rewind_record);
+            }
+        }
+        alloc_check_arm(false);
+        if journaled {
+            use self::suspend::suspend_poll;
+            suspend_poll();
+        }
+        {
+            use self::community::community_poll;
+            use self::persist::persist_poll;
+            use self::replay::replay_spectate_poll;
+            community_poll();
+            persist_poll();
+            replay_spectate_poll();
+        }
+        {
+            use self::sound::sound_device_poll;
+            sound_device_poll();
+        }
+        if game_state_quit_requested() {
+            break 'running;
+        }
+        {
+            use self::sound::sound_set_background;
+            sound_set_background(background && settings_read(|s| s.energy_saver && s.energy_saver_mute));
+        }
+        let shown = frame_limit.allows(energy_saver_fps(background));
+        if shown {
+            alloc_check_arm(steady);
+            game_render(&mut system_interfaces.video);
+            alloc_check_arm(false);
+        }
+        {
+            use self::status::status_poll;
+            use self::video::video_window_status;
+            if let Some(status) = status_poll() {
+                video_window_status(&mut system_interfaces.video, &status);
+            }
+        }
+        if shown {
+            use self::clock::clock_wall_millis;
+            use self::sound::sound_present;
+            sound_present(clock_wall_millis());
+
+            let elapsed = clock_wall() - frame_start;
+            video_debug_frame_time(elapsed.as_secs() as u32 * 1_000_000 + elapsed.subsec_nanos() / 1000);
+        }
+        alloc_check_frame_end(steady);
+        arena_frame_reset();
+        // nothing presented means nothing waited on vsync, so sit out the
+        // rest of the tick rather than spin
+        if !shown || settings_read(|s| s.frame_pacing) {
+            use self::clock::pacing_sleep_until;
+            pacing_sleep_until(pacer.next_due(percent));
+        }
+    }
+    {
+        use self::arena::alloc_check_finish;
+        alloc_check_finish();
+    }
+    rng_trace_finish(&options.rng_trace);
+    state_graph_finish(&options.state_graph);
+    if journaled {
+        use self::suspend::suspend_close;
+        suspend_close();
+    }
+    {
+        use self::replay::replay_record_finish;
+        replay_record_finish();
+    }
+    {
+        use self::persist::persist_flush;
+        persist_flush();
+    }
+}
+
+// energy saver's caps on rendered frames
+const ENERGY_SAVER_BACKGROUND_FPS: u32 = 10;
+const ENERGY_SAVER_ATTRACT_FPS:     u32 = 30;
+
+// the most frames a second worth rendering right now, 0 for every one
+fn energy_saver_fps(background: bool) -> u32 {
+    use self::settings::settings_read;
+    use self::state_machine::GameState;
+    use self::state_machine::game_state_current;
+
+    if !settings_read(|s| s.energy_saver) {
+        return 0;
+    }
+    if background {
+        return ENERGY_SAVER_BACKGROUND_FPS;
     }
+    if game_state_current() == GameState::Attract {
+        return ENERGY_SAVER_ATTRACT_FPS;
+    }
+    return 0;
 }
 
-fn game_render(canvas: &mut WindowCanvas) {
+fn game_render(video: &mut VideoSystem) {
+    use rusty_kong::arena::MemoryTag;
+    use rusty_kong::arena::memory_tagged;
     use rusty_kong::video::video_update;
-    video_update(canvas);
+    memory_tagged(MemoryTag::Video, || video_update(video));
+}
+
+fn rng_trace_finish(path: &Option<String>) {
+    use self::rng::rng_trace_write_csv;
+
+    if let &Some(ref path) = path {
+        match rng_trace_write_csv(Path::new(path)) {
+            Ok(count) => info!("wrote {} rng draws to {}", count, path),
+            Err(e) => error!("{}", e),
+        }
+    }
 }
 
-fn game_update(controller: &GameController) {
+fn state_graph_finish(path: &Option<String>) {
+    use self::state_machine::state_graph_write;
+
+    if let &Some(ref path) = path {
+        match state_graph_write(Path::new(path)) {
+            Ok(0) => info!("wrote the state graph to {}", path),
+            Ok(unlisted) => warn!("wrote the state graph to {}; {} transitions this run aren't in the table", path, unlisted),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+// one logic tick; input must already be latched with input_set()
+fn game_update() -> StateReport {
     //use self::state_machine::game_state_go;
     use self::state_machine::game_state_update;
+    use self::clock::clock_tick;
+    use self::sound::sound_update;
+
+    use self::state_machine::game_state_cocktail;
+
+    use self::rng::rng_trace_tick;
 
-    game_state_update();
+    use self::arena::MemoryTag;
+    use self::arena::memory_tagged;
+
+    let report = memory_tagged(MemoryTag::Game, || {
+        let report = game_state_update();
+        rng_trace_tick();
+        game_state_cocktail();
+        report
+    });
+    clock_tick();
+    memory_tagged(MemoryTag::Sound, sound_update);
+    return report;
 }
 
-fn game_init(context:&Sdl) -> Result<SystemInterfaces, String> {
+fn game_init(context:&Sdl, display: Option<u32>) -> Result<SystemInterfaces, String> {
     use self::state_machine::game_state_init;
+    use self::state_machine::game_state_fail;
+    use self::settings::settings_init;
+    use self::assets::assets_init;
+    use self::locale::locale_init;
+    use self::physics::physics_init;
+    use self::sequence::sequence_init;
+    use self::scripting::scripting_init;
+    use self::narration::narration_init;
+    use self::event_log::event_log_init;
+    use self::speedrun::speedrun_init;
+    use self::daily::daily_init;
+    use self::community::community_init;
+    use self::state_machine::boot_init;
+    use self::replay::ghost_init;
+    use self::input::input_display_init;
+    use self::input::input_rumble_init;
+    use self::status::status_init;
+    use self::sound::sound_init;
+    use self::clock::clock_init;
     use rusty_kong::video::video_init;
 
+    clock_init();
+    settings_init();
+    if let Some(display) = display {
+        use self::settings::settings_get;
+        use self::settings::settings_set;
+        let mut settings = settings_get();
+        settings.display = display;
+        settings_set(settings);
+    }
+    assets_init();
+    locale_init();
+    physics_init();
+    sequence_init();
+    scripting_init();
+    narration_init();
+    event_log_init();
+    speedrun_init();
+    daily_init();
+    community_init();
+    boot_init();
+    ghost_init();
+    input_display_init();
+    input_rumble_init();
+    status_init();
+    sound_init();
     game_state_init();
+
+    let video = video_init(&context)?;
+    {
+        use self::sound::sound_device_init;
+        if let Err(e) = sound_device_init(&context) {
+            game_state_fail(&format!("SOUND NG {}", e));
+        }
+    }
+    let controllers =
+        match controller_init(&context) {
+            Ok(c) => c,
+            Err(e) => {
+                game_state_fail(&format!("INPUT NG {}", e));
+                Vec::new()
+            },
+        };
     return Ok(SystemInterfaces {
-        controller: controller_init(&context),
-        canvas: video_init(&context)
+        controllers: controllers,
+        video: video
     });
 }
@@ -0,0 +1,98 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+
+use super::events::GameEvent;
+use super::state_machine::GameState;
+use super::locale::StringId;
+use super::locale::locale_text;
+
+// a platform text-to-speech backend plugs in here; until one does, the
+// announcements go to the log where a terminal screen reader can pick them up
+pub type Announcer = fn(&str);
+
+fn log_announcer(text: &str) {
+    info!("narration: {}", text);
+}
+
+thread_local!(
+    static ANNOUNCER:RefCell<Option<Announcer>> = RefCell::new(None);
+);
+
+pub fn narration_set_announcer(announcer: Announcer) {
+    ANNOUNCER.with(|cell| {*cell.borrow_mut() = Some(announcer);});
+}
+
+pub fn narration_announce(text: &str) {
+    if let Some(announcer) = ANNOUNCER.with(|cell| *cell.borrow()) {
+        announcer(text);
+    }
+}
+
+fn state_announcement(state: GameState) -> Option<StringId> {
+    match state {
+        GameState::Attract      => Some(StringId::InsertCoin),
+        GameState::HowHigh      => Some(StringId::HowHighCanYouGet),
+        GameState::GamePlay     => Some(StringId::NarrateGamePlay),
+        GameState::PlayerDies   => Some(StringId::NarratePlayerDies),
+        GameState::PlayerWins   => Some(StringId::NarratePlayerWins),
+        _                       => None,
+    }
+}
+
+// a milestone every this many points
+const SCORE_MILESTONE: u32 = 10_000;
+
+// subscribed whether or not it's on, so the settings page can turn it on
+// and off as it likes
+fn narration_on_event(event: &GameEvent) {
+    use super::settings::settings_read;
+
+    if !settings_read(|s| s.narration) {
+        return;
+    }
+    match event {
+        &GameEvent::StateChanged { to, .. } => {
+            if let Some(id) = state_announcement(to) {
+                narration_announce(&locale_text(id));
+            }
+        },
+        &GameEvent::MenuMoved => {
+            use super::state_machine::main_menu_selected_label;
+
+            if let Some(label) = main_menu_selected_label() {
+                narration_announce(&label);
+            }
+        },
+        // published once the points are on, so the score's already past
+        &GameEvent::Scored { points } => {
+            use super::player::player_score;
+
+            let score = player_score();
+            let milestone = score / SCORE_MILESTONE * SCORE_MILESTONE;
+            if milestone > 0 && score.saturating_sub(points) < milestone {
+                narration_announce(&format!("{} {}", locale_text(StringId::NarrateScore), milestone));
+            }
+        },
+        _ => {},
+    }
+}
+
+pub fn narration_init() {
+    use super::events::events_subscribe;
+
+    if ANNOUNCER.with(|cell| cell.borrow().is_none()) {
+        narration_set_announcer(log_announcer);
+    }
+    events_subscribe(narration_on_event);
+}
@@ -0,0 +1,268 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use super::clock::FAST_FORWARD_MAX;
+use super::replay::REPLAY_HASH_INTERVAL;
+use super::state_machine::GameState;
+
+pub const USAGE: &str = "Usage: rusty-kong validate-assets <dir or .rkpak>\n       rusty-kong [--arcade] [--bot] [--dev] [--speed <n>] [--display <n>] [--state <name>] [--golden /path/to/script [--bless]] [--audit /path/to/script] [--rewind-bench /path/to/script] [--soak <minutes> [--seed <n>]] [--properties <seeds> [--seed <n>]] [--fuzz-corpus <dir>] [--rng-trace /path/to/file.csv] [--state-graph /path/to/file.dot] [--record /path/to/file.replay [--replay-hashes <ticks>]] [--replay /path/to/file.replay] [--spectate [<address>:]<port>] [--watch [<host>:]<port>] [--turbo-check /path/to/file.replay] [--alloc-check] [--write-checksums] [--write-pack /path/to/file.rkpak [--zstd]]";
+
+pub struct Options {
+    pub golden: Option<String>,
+    pub bless: bool,
+    // run a golden script twice side by side and diff the state hashes
+    pub audit: Option<String>,
+    // play a golden script with rewind on and check its snapshots against
+    // settings.cfg's rewind_budget_kb
+    pub rewind_bench: Option<String>,
+    // minutes of the bot headless, checked for leaks & stuck states; the
+    // seed's from the clock unless given
+    pub soak: Option<u32>,
+    // that many seeds through the invariant checks, from --seed or 1
+    pub properties: Option<u32>,
+    pub seed: Option<u32>,
+    // every fuzz target over the corpora under this directory
+    pub fuzz_corpus: Option<String>,
+    pub bot: bool,
+    // start here instead of boot
+    pub state: Option<GameState>,
+    pub write_checksums: bool,
+    // packs assets/ into one .rkpak file, zstd compressed with --zstd
+    pub write_pack: Option<String>,
+    pub zstd: bool,
+    // same as arcade = on in settings.cfg
+    pub arcade: bool,
+    // opens the debug panels window next to the game
+    pub dev: bool,
+    // logic ticks per tick of wall time, all session; 1 is normal
+    pub speed: u32,
+    // flags heap allocations in steady play; debug builds only
+    pub alloc_check: bool,
+    // every rng draw is logged and written here as csv on the way out
+    pub rng_trace: Option<String>,
+    // the states & transitions as graphviz, written on the way out with
+    // the ones the run went through marked
+    pub state_graph: Option<String>,
+    // write the session's inputs out as a replay
+    pub record: Option<String>,
+    // ticks between state hashes embedded in the recording, 0 for none
+    pub replay_hashes: u32,
+    // play a replay back headless and check its hashes
+    pub replay: Option<String>,
+    // serve the session to viewers as a replay, live, on this address
+    pub spectate: Option<String>,
+    // play the session a --spectate is serving at this address
+    pub watch: Option<String>,
+    // check a replay's inputs against settings.cfg's turbo thresholds
+    pub turbo_check: Option<String>,
+    // the display the window opens on, over settings.cfg's
+    pub display: Option<u32>,
+    // the validate-assets subcommand's directory or pack
+    pub validate_assets: Option<String>,
+}
+
+pub fn options_parse<I: Iterator<Item=String>>(args: I) -> Result<Options, String> {
+    let mut options = Options {
+        golden: None,
+        bless: false,
+        audit: None,
+        rewind_bench: None,
+        soak: None,
+        properties: None,
+        seed: None,
+        fuzz_corpus: None,
+        bot: false,
+        state: None,
+        write_checksums: false,
+        write_pack: None,
+        zstd: false,
+        arcade: false,
+        dev: false,
+        speed: 1,
+        alloc_check: false,
+        rng_trace: None,
+        state_graph: None,
+        record: None,
+        replay_hashes: REPLAY_HASH_INTERVAL,
+        replay: None,
+        spectate: None,
+        watch: None,
+        turbo_check: None,
+        display: None,
+        validate_assets: None,
+    };
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--golden" => {
+                match args.next() {
+                    Some(path) => options.golden = Some(path),
+                    None => return Err("--golden expects a script path".to_string()),
+                }
+            },
+            "--audit" => {
+                match args.next() {
+                    Some(path) => options.audit = Some(path),
+                    None => return Err("--audit expects a script path".to_string()),
+                }
+            },
+            "--rewind-bench" => {
+                match args.next() {
+                    Some(path) => options.rewind_bench = Some(path),
+                    None => return Err("--rewind-bench expects a script path".to_string()),
+                }
+            },
+            "--soak" => {
+                match args.next().map(|n| n.parse::<u32>()) {
+                    Some(Ok(minutes)) if minutes > 0 => options.soak = Some(minutes),
+                    _ => return Err("--soak expects a number of minutes".to_string()),
+                }
+            },
+            "--properties" => {
+                match args.next().map(|n| n.parse::<u32>()) {
+                    Some(Ok(seeds)) if seeds > 0 => options.properties = Some(seeds),
+                    _ => return Err("--properties expects a number of seeds".to_string()),
+                }
+            },
+            "--fuzz-corpus" => {
+                match args.next() {
+                    Some(dir) => options.fuzz_corpus = Some(dir),
+                    None => return Err("--fuzz-corpus expects a directory".to_string()),
+                }
+            },
+            "--seed" => {
+                match args.next().map(|n| u32::from_str_radix(n.trim_start_matches("0x"), 16)) {
+                    Some(Ok(seed)) => options.seed = Some(seed),
+                    _ => return Err("--seed expects a seed in hex, as the soak prints it".to_string()),
+                }
+            },
+            "validate-assets" => {
+                match args.next() {
+                    Some(target) => options.validate_assets = Some(target),
+                    None => return Err("validate-assets expects a directory or a pack".to_string()),
+                }
+            },
+            "--rng-trace" => {
+                match args.next() {
+                    Some(path) => options.rng_trace = Some(path),
+                    None => return Err("--rng-trace expects a csv path".to_string()),
+                }
+            },
+            "--state-graph" => {
+                match args.next() {
+                    Some(path) => options.state_graph = Some(path),
+                    None => return Err("--state-graph expects a dot path".to_string()),
+                }
+            },
+            "--record" => {
+                match args.next() {
+                    Some(path) => options.record = Some(path),
+                    None => return Err("--record expects a replay path".to_string()),
+                }
+            },
+            "--replay-hashes" => {
+                match args.next().map(|n| n.parse::<u32>()) {
+                    Some(Ok(ticks)) => options.replay_hashes = ticks,
+                    _ => return Err("--replay-hashes expects a tick count".to_string()),
+                }
+            },
+            "--replay" => {
+                match args.next() {
+                    Some(path) => options.replay = Some(path),
+                    None => return Err("--replay expects a replay path".to_string()),
+                }
+            },
+            // a bare port is every interface for --spectate, this machine
+            // for --watch
+            "--spectate" => {
+                match args.next() {
+                    Some(ref port) if port.parse::<u16>().is_ok() => options.spectate = Some(format!("0.0.0.0:{}", port)),
+                    Some(address) => options.spectate = Some(address),
+                    None => return Err("--spectate expects a port or an address:port".to_string()),
+                }
+            },
+            "--watch" => {
+                match args.next() {
+                    Some(ref port) if port.parse::<u16>().is_ok() => options.watch = Some(format!("127.0.0.1:{}", port)),
+                    Some(address) => options.watch = Some(address),
+                    None => return Err("--watch expects a port or a host:port".to_string()),
+                }
+            },
+            "--turbo-check" => {
+                match args.next() {
+                    Some(path) => options.turbo_check = Some(path),
+                    None => return Err("--turbo-check expects a replay path".to_string()),
+                }
+            },
+            "--speed" => {
+                match args.next().map(|n| n.parse::<u32>()) {
+                    Some(Ok(speed)) if speed >= 1 && speed <= FAST_FORWARD_MAX => options.speed = speed,
+                    _ => return Err(format!("--speed expects 1 to {} ticks a tick", FAST_FORWARD_MAX)),
+                }
+            },
+            "--display" => {
+                match args.next().map(|n| n.parse::<u32>()) {
+                    Some(Ok(display)) => options.display = Some(display),
+                    _ => return Err("--display expects a display number".to_string()),
+                }
+            },
+            "--write-pack" => {
+                match args.next() {
+                    Some(path) => options.write_pack = Some(path),
+                    None => return Err("--write-pack expects a pack path".to_string()),
+                }
+            },
+            "--state" => {
+                match args.next() {
+                    Some(name) => options.state = Some(name.parse()?),
+                    None => return Err("--state expects a state name".to_string()),
+                }
+            },
+            "--bless" => options.bless = true,
+            "--arcade" => options.arcade = true,
+            "--bot" => options.bot = true,
+            "--dev" => options.dev = true,
+            "--alloc-check" => options.alloc_check = true,
+            "--write-checksums" => options.write_checksums = true,
+            "--zstd" => options.zstd = true,
+            _ => return Err(format!("unknown argument \"{}\"", arg)),
+        }
+    }
+
+    if options.bless && options.golden.is_none() {
+        return Err("--bless only makes sense with --golden".to_string());
+    }
+    if options.seed.is_some() && options.soak.is_none() && options.properties.is_none() {
+        return Err("--seed only makes sense with --soak or --properties".to_string());
+    }
+    if options.zstd && options.write_pack.is_none() {
+        return Err("--zstd only makes sense with --write-pack".to_string());
+    }
+    // the counting allocator is only in debug builds
+    if options.alloc_check && !cfg!(debug_assertions) {
+        return Err("--alloc-check needs a debug build".to_string());
+    }
+    // rewinds and the inspector change the game behind the input stream's
+    // back, and a state other than boot isn't where playback starts
+    if options.record.is_some() && (options.dev || options.state.is_some()) {
+        return Err("--record can't be combined with --dev or --state".to_string());
+    }
+    if options.spectate.is_some() && (options.dev || options.state.is_some()) {
+        return Err("--spectate can't be combined with --dev or --state".to_string());
+    }
+    // the watched session's inputs are the only ones played
+    if options.watch.is_some() && (options.dev || options.state.is_some() || options.bot || options.record.is_some() || options.spectate.is_some()) {
+        return Err("--watch can't be combined with --dev, --state, --bot, --record or --spectate".to_string());
+    }
+    return Ok(options);
+}
@@ -0,0 +1,161 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+
+use super::events::GameEvent;
+
+// what a write was for, so whoever's listening knows which one finished
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PersistJob {
+    Settings,
+    PersonalBest,
+    LiveSplit,
+    Ghost,
+}
+
+impl PersistJob {
+    pub fn name(self:&PersistJob) -> &'static str {
+        match *self {
+            PersistJob::Settings     => "settings",
+            PersistJob::PersonalBest => "personal best",
+            PersistJob::LiveSplit    => "livesplit export",
+            PersistJob::Ghost        => "ghost",
+        }
+    }
+}
+
+// the work, done on the worker; Ok is what to log once it's done
+pub type PersistWork = Box<dyn FnOnce() -> Result<String, String> + Send>;
+
+enum Command {
+    Run(PersistJob, PersistWork),
+    // answered once everything sent before it is done
+    Flush(Sender<()>),
+}
+
+struct Worker {
+    commands: Sender<Command>,
+    results: Receiver<(PersistJob, Result<String, String>)>,
+    // sent and not yet heard back from
+    pending: usize,
+}
+
+thread_local!(
+    static WORKER:RefCell<Option<Worker>> = RefCell::new(None);
+    static HELD:RefCell<bool> = RefCell::new(false);
+);
+
+// for watching someone else's game: what it'd save isn't the viewer's
+pub fn persist_hold(held: bool) {
+    HELD.with(|cell| {*cell.borrow_mut() = held;});
+}
+
+// one thread, so writes to the same file land in the order they were
+// asked for; it's started with the first of them
+fn persist_worker_start() -> Worker {
+    let (commands, inbox) = mpsc::channel::<Command>();
+    let (outbox, results) = mpsc::channel();
+    thread::spawn(move || {
+        use super::arena::MemoryTag;
+        use super::arena::memory_tagged;
+
+        // the whole thread's allocations are the io's
+        memory_tagged(MemoryTag::Io, || {
+            for command in inbox {
+                match command {
+                    Command::Run(job, work) => {
+                        if outbox.send((job, work())).is_err() {
+                            break;
+                        }
+                    },
+                    Command::Flush(done) => {
+                        let _ = done.send(());
+                    },
+                }
+            }
+        });
+    });
+    Worker {
+        commands: commands,
+        results: results,
+        pending: 0,
+    }
+}
+
+// file writes (and later, network calls) for anything that'd otherwise
+// hold the frame up; it's done in the background and reported through
+// persist_poll()
+pub fn persist_run<F: FnOnce() -> Result<String, String> + Send + 'static>(job: PersistJob, work: F) {
+    if HELD.with(|cell| *cell.borrow()) {
+        return;
+    }
+    WORKER.with(|cell| {
+        let mut worker = cell.borrow_mut();
+        if worker.is_none() {
+            *worker = Some(persist_worker_start());
+        }
+        if let Some(ref mut worker) = *worker {
+            match worker.commands.send(Command::Run(job, Box::new(work))) {
+                Ok(()) => worker.pending += 1,
+                Err(_) => error!("the io worker is gone, the {} isn't saved", job.name()),
+            }
+        }
+    });
+}
+
+// call every frame: what the worker finished is logged and published
+pub fn persist_poll() {
+    use super::events::events_publish;
+
+    let finished: Vec<(PersistJob, Result<String, String>)> = WORKER.with(|cell| {
+        match *cell.borrow_mut() {
+            Some(ref mut worker) => {
+                let finished: Vec<_> = worker.results.try_iter().collect();
+                worker.pending -= finished.len();
+                finished
+            },
+            None => Vec::new(),
+        }
+    });
+    for (job, result) in finished {
+        match result {
+            Ok(ref message) => info!("{}", message),
+            Err(ref e) => error!("{}", e),
+        }
+        events_publish(GameEvent::Persisted { job: job, ok: result.is_ok() });
+    }
+}
+
+// on the way out: waits for whatever's still being written
+pub fn persist_flush() {
+    let waiting = WORKER.with(|cell| {
+        match *cell.borrow() {
+            Some(ref worker) if worker.pending > 0 => {
+                let (done, wait) = mpsc::channel();
+                match worker.commands.send(Command::Flush(done)) {
+                    Ok(()) => Some((wait, worker.pending)),
+                    Err(_) => None,
+                }
+            },
+            _ => None,
+        }
+    });
+    if let Some((wait, pending)) = waiting {
+        info!("waiting on {} writes", pending);
+        let _ = wait.recv();
+    }
+    persist_poll();
+}
@@ -0,0 +1,255 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::hash::Hash;
+use std::path::Path;
+
+use rusty_kong::hashing::StateHasher;
+use rusty_kong::savestate::SaveState;
+
+// the tuning file; "preset.key = value" per line, anything left out keeps
+// the built-in value below
+pub const PHYSICS_FILE: &str = "physics.txt";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PhysicsPreset {
+    // frame counts & speeds as close to the cabinet as we know them
+    Arcade,
+    // snappier walking & climbing, more forgiving falls
+    Modernized,
+}
+
+impl PhysicsPreset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &PhysicsPreset::Arcade     => "arcade",
+            &PhysicsPreset::Modernized => "modernized",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PhysicsPreset> {
+        match name {
+            "arcade"     => Some(PhysicsPreset::Arcade),
+            "modernized" => Some(PhysicsPreset::Modernized),
+            _            => None,
+        }
+    }
+
+    fn builtin(&self) -> Physics {
+        match self {
+            &PhysicsPreset::Arcade     => PHYSICS_ARCADE,
+            &PhysicsPreset::Modernized => PHYSICS_MODERNIZED,
+        }
+    }
+}
+
+// vertical speeds are in sixteenths of a pixel per tick, everything else in
+// whole pixels or ticks
+pub const SUBPIXELS: i32 = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Physics {
+    // walking skips every walk_skip_ticks'th tick
+    pub walk_skip_ticks: u32,
+    // climbing moves a pixel every climb_step_ticks
+    pub climb_step_ticks: u32,
+    pub jump_velocity: i32,
+    pub jump_speed_x: i32,
+    pub gravity: i32,
+    pub fall_velocity_max: i32,
+    // landing more than this far below where the fall started kills him
+    pub fall_death_height: i32,
+    // fireballs move a pixel every fireball_step_ticks
+    pub fireball_step_ticks: u32,
+    pub jack_drop_speed: i32,
+    // XXX: nothing rolls barrels yet
+    pub barrel_roll_speed: i32,
+    pub conveyor_step_ticks: u32,
+    // a jump pressed this many ticks before landing still goes off when he
+    // lands; 0 is arcade strict
+    pub jump_buffer_ticks: u32,
+    // ticks after walking off an edge that a jump is still allowed; 0 is
+    // arcade strict
+    pub coyote_ticks: u32,
+}
+
+// XXX: fall_death_height & the barrel/conveyor numbers are by feel
+pub const PHYSICS_ARCADE: Physics = Physics {
+    walk_skip_ticks:     4,
+    climb_step_ticks:    2,
+    jump_velocity:       30,
+    jump_speed_x:        1,
+    gravity:             2,
+    fall_velocity_max:   48,
+    fall_death_height:   20,
+    fireball_step_ticks: 2,
+    jack_drop_speed:     3,
+    barrel_roll_speed:   1,
+    conveyor_step_ticks: 2,
+    jump_buffer_ticks:   0,
+    coyote_ticks:        0,
+};
+
+pub const PHYSICS_MODERNIZED: Physics = Physics {
+    walk_skip_ticks:     8,
+    climb_step_ticks:    1,
+    jump_velocity:       32,
+    jump_speed_x:        1,
+    gravity:             2,
+    fall_velocity_max:   48,
+    fall_death_height:   32,
+    fireball_step_ticks: 2,
+    jack_drop_speed:     3,
+    barrel_roll_speed:   1,
+    conveyor_step_ticks: 2,
+    jump_buffer_ticks:   6,
+    coyote_ticks:        5,
+};
+
+impl Physics {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let number =
+            match value.parse::<i32>() {
+                Ok(n) => n,
+                Err(_) => return Err(format!("{} expects a number, got \"{}\"", key, value)),
+            };
+        // these are used as a modulus, so they can't be 0
+        let ticks = || {
+            if number < 1 {
+                Err(format!("{} must be at least 1", key))
+            } else {
+                Ok(number as u32)
+            }
+        };
+        // windows, where 0 turns the leniency off
+        let window = || {
+            if number < 0 {
+                Err(format!("{} can't be negative", key))
+            } else {
+                Ok(number as u32)
+            }
+        };
+        match key {
+            "walk_skip_ticks"     => self.walk_skip_ticks = ticks()?,
+            "climb_step_ticks"    => self.climb_step_ticks = ticks()?,
+            "jump_velocity"       => self.jump_velocity = number,
+            "jump_speed_x"        => self.jump_speed_x = number,
+            "gravity"             => self.gravity = number,
+            "fall_velocity_max"   => self.fall_velocity_max = number,
+            "fall_death_height"   => self.fall_death_height = number,
+            "fireball_step_ticks" => self.fireball_step_ticks = ticks()?,
+            "jack_drop_speed"     => self.jack_drop_speed = number,
+            "barrel_roll_speed"   => self.barrel_roll_speed = number,
+            "conveyor_step_ticks" => self.conveyor_step_ticks = ticks()?,
+            "jump_buffer_ticks"   => self.jump_buffer_ticks = window()?,
+            "coyote_ticks"        => self.coyote_ticks = window()?,
+            _ => return Err(format!("unknown physics constant \"{}\"", key)),
+        }
+        return Ok(());
+    }
+}
+
+thread_local!(
+    static PHYSICS:RefCell<Physics> = RefCell::new(PHYSICS_ARCADE);
+);
+
+// the constants in effect; small enough to copy out every lookup
+pub fn physics() -> Physics {
+    PHYSICS.with(|cell| *cell.borrow())
+}
+
+// preset's lines from the file at path over its built-in values, with one
+// line per problem; None when the file can't be opened
+fn physics_read(path: &Path, preset: PhysicsPreset) -> Option<(Physics, Vec<String>)> {
+    use rusty_kong::settings::read_key_value_file;
+
+    let pairs = read_key_value_file(path)?;
+    let mut physics = preset.builtin();
+    let mut problems = Vec::new();
+    let prefix = format!("{}.", preset.name());
+    for pair in pairs.iter() {
+        let mut parts = pair.key.splitn(2, '.');
+        let (name, key) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+        if PhysicsPreset::from_name(name).is_none() {
+            problems.push(format!("{}:{}: unknown physics preset \"{}\"", path.display(), pair.line, name));
+            continue;
+        }
+        if !pair.key.starts_with(&prefix) {
+            continue;
+        }
+        if let Err(e) = physics.set(key, &pair.value) {
+            problems.push(format!("{}:{}: {}", path.display(), pair.line, e));
+        }
+    }
+    return Some((physics, problems));
+}
+
+// every problem with a physics file, across all presets
+pub fn physics_check(path: &Path) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    for preset in [PhysicsPreset::Arcade, PhysicsPreset::Modernized].iter() {
+        match physics_read(path, *preset) {
+            Some((_, found)) => {
+                for problem in found {
+                    if !problems.contains(&problem) {
+                        problems.push(problem);
+                    }
+                }
+            },
+            None => return vec![format!("can't open {}", path.display())],
+        }
+    }
+    return problems;
+}
+
+pub fn physics_load(preset: PhysicsPreset) {
+    use rusty_kong::assets::asset_path;
+
+    let path_buf = asset_path(PHYSICS_FILE);
+    let path = path_buf.as_path();
+    let physics =
+        match physics_read(path, preset) {
+            Some((physics, problems)) => {
+                for problem in problems.iter() {
+                    warn!("{}", problem);
+                }
+                physics
+            },
+            None => {
+                warn!("can't open {}, using built-in {} physics", path.display(), preset.name());
+                preset.builtin()
+            },
+        };
+
+    info!("{} physics loaded", preset.name());
+    PHYSICS.with(|cell| {*cell.borrow_mut() = physics;});
+}
+
+pub fn physics_init() {
+    use rusty_kong::settings::settings_read;
+    physics_load(settings_read(|s| s.physics));
+}
+
+pub fn physics_hash(hasher: &mut StateHasher) {
+    PHYSICS.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn physics_save(savestate: &mut SaveState) {
+    savestate.put("physics", PHYSICS.with(|cell| cell.borrow().clone()));
+}
+
+pub fn physics_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<Physics>("physics") {
+        PHYSICS.with(|cell| {*cell.borrow_mut() = value;});
+    }
+}
@@ -0,0 +1,264 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use super::*;
+use rusty_kong::enemies::Hitbox;
+use rusty_kong::hashing::StateHasher;
+use rusty_kong::savestate::SaveState;
+use rusty_kong::inspector::*;
+use rusty_kong::level::Stage;
+use rusty_kong::sound::*;
+use rusty_kong::state_machine::schedule_after;
+
+pub const HAMMER_SIZE: i32 = 16;
+// one slot per hammer lying on the stage
+pub const HAMMER_SPRITE_SLOT: usize = 56;
+const HAMMER_MAX: usize = 2;
+
+// how long a hammer lasts once it's picked up
+const HAMMER_TICKS:         u32 = 10 * 60;
+// it swings between over the head and out in front every HAMMER_SWING_TICKS
+const HAMMER_SWING_TICKS:   u32 = 8;
+// for the last HAMMER_WARNING_TICKS the head flashes, every
+// HAMMER_FLASH_TICKS, so the player knows it's about to go
+const HAMMER_WARNING_TICKS: u32 = 2 * 60;
+const HAMMER_FLASH_TICKS:   u32 = 4;
+
+// XXX: tiles & palettes by eye from the ripper output
+const HAMMER_UP_TILE:         u16 = 0x1e;
+const HAMMER_DOWN_TILE:       u16 = 0x1f;
+const HAMMER_PALETTE:         u8 = 46;
+const HAMMER_WARNING_PALETTE: u8 = 47;
+
+#[derive(Clone, Hash)]
+struct Hammers {
+    // hammers already used up this attempt at the stage
+    taken: [bool; HAMMER_MAX],
+    // the one the player is swinging, and for how much longer
+    held: Option<usize>,
+    ticks_left: u32,
+    // the one held is nearly used up
+    warning: bool,
+}
+
+thread_local!(
+    static HAMMERS:RefCell<Hammers> = RefCell::new(Hammers {
+        taken: [false; HAMMER_MAX],
+        held: None,
+        ticks_left: 0,
+        warning: false
+    });
+    static STAGE:RefCell<Option<&'static Stage>> = RefCell::new(None);
+);
+
+pub fn hammers_stage_start(stage: &'static Stage) {
+    HAMMERS.with(|cell| {
+        let mut hammers = cell.borrow_mut();
+        hammers.taken = [false; HAMMER_MAX];
+        hammers.held = None;
+        hammers.ticks_left = 0;
+        hammers.warning = false;
+    });
+    STAGE.with(|cell| {*cell.borrow_mut() = Some(stage);});
+
+    for slot in 0..HAMMER_MAX {
+        match stage.hammers.get(slot) {
+            Some(&(x, y)) => video_sprite_set(HAMMER_SPRITE_SLOT + slot, HAMMER_UP_TILE, x as u16, y as u16, HAMMER_PALETTE),
+            None => video_sprite_hide(HAMMER_SPRITE_SLOT + slot),
+        }
+    }
+}
+
+pub fn hammers_clear() {
+    HAMMERS.with(|cell| {
+        let mut hammers = cell.borrow_mut();
+        hammers.held = None;
+        hammers.ticks_left = 0;
+        hammers.warning = false;
+    });
+    STAGE.with(|cell| {*cell.borrow_mut() = None;});
+    for slot in 0..HAMMER_MAX {
+        video_sprite_hide(HAMMER_SPRITE_SLOT + slot);
+    }
+}
+
+// whatever's being swung goes with the player, used up; the ones still
+// lying about stay for the restart
+pub fn hammer_drop() {
+    let held = HAMMERS.with(|cell| {
+        let mut hammers = cell.borrow_mut();
+        hammers.ticks_left = 0;
+        hammers.warning = false;
+        hammers.held.take()
+    });
+    if let Some(slot) = held {
+        video_sprite_hide(HAMMER_SPRITE_SLOT + slot);
+    }
+}
+
+pub fn hammer_is_held() -> bool {
+    HAMMERS.with(|cell| cell.borrow().held.is_some())
+}
+
+fn hammer_warn() {
+    HAMMERS.with(|cell| {
+        let mut hammers = cell.borrow_mut();
+        hammers.warning = hammers.held.is_some();
+    });
+}
+
+fn hammer_swung_up() -> bool {
+    HAMMERS.with(|cell| (cell.borrow().ticks_left / HAMMER_SWING_TICKS) % 2 == 0)
+}
+
+// where the swinging head is: over the player, or out in front of him
+pub fn hammer_hitbox() -> Option<Hitbox> {
+    if !hammer_is_held() {
+        return None;
+    }
+    let (x, y) = player_position();
+    let (x, y) = (x as i32, y as i32);
+    let facing = player_facing();
+    if hammer_swung_up() {
+        Some(Hitbox::new(x, y - HAMMER_SIZE, HAMMER_SIZE, HAMMER_SIZE))
+    } else {
+        Some(Hitbox::new(x + facing * HAMMER_SIZE, y, HAMMER_SIZE, HAMMER_SIZE))
+    }
+}
+
+// a hammer is only taken by jumping into it, and only when the player
+// isn't already holding one
+fn hammers_pickup(stage: &Stage) {
+    let airborne =
+        match player_motion() {
+            Motion::Airborne { .. } => true,
+            _ => false,
+        };
+    if !airborne || hammer_is_held() {
+        return;
+    }
+
+    let player = player_hitbox();
+    let touched = stage.hammers
+        .iter()
+        .enumerate()
+        .take(HAMMER_MAX)
+        .find(|&(index, &(x, y))| {
+            !HAMMERS.with(|cell| cell.borrow().taken[index])
+                && Hitbox::new(x, y, HAMMER_SIZE, HAMMER_SIZE).overlaps(&player)
+        })
+        .map(|(index, _)| index);
+
+    if let Some(index) = touched {
+        HAMMERS.with(|cell| {
+            let mut hammers = cell.borrow_mut();
+            hammers.taken[index] = true;
+            hammers.held = Some(index);
+            hammers.ticks_left = HAMMER_TICKS;
+            hammers.warning = false;
+        });
+        // this update takes the first tick off, so it's due with
+        // HAMMER_WARNING_TICKS left
+        schedule_after(HAMMER_TICKS - 1 - HAMMER_WARNING_TICKS, hammer_warn);
+        info!("player {} picked up hammer {} on {}", player_current() + 1, index, stage.name);
+        music_play(MusicId::HammerTime);
+    }
+}
+
+pub fn hammers_update() {
+    let stage =
+        match STAGE.with(|cell| *cell.borrow()) {
+            Some(s) => s,
+            None => return,
+        };
+
+    hammers_pickup(stage);
+
+    let (held, ticks_left, warning) = HAMMERS.with(|cell| {
+        let mut hammers = cell.borrow_mut();
+        if hammers.held.is_some() {
+            hammers.ticks_left -= 1;
+            if hammers.ticks_left == 0 {
+                hammers.held = None;
+                hammers.warning = false;
+                music_play(MusicId::Stage);
+            }
+        }
+        (hammers.held, hammers.ticks_left, hammers.warning)
+    });
+
+    for slot in 0..HAMMER_MAX {
+        let taken = HAMMERS.with(|cell| cell.borrow().taken[slot]);
+        if taken && held != Some(slot) {
+            video_sprite_hide(HAMMER_SPRITE_SLOT + slot);
+        }
+    }
+
+    if let (Some(slot), Some(hitbox)) = (held, hammer_hitbox()) {
+        let tile = if (ticks_left / HAMMER_SWING_TICKS) % 2 == 0 { HAMMER_UP_TILE } else { HAMMER_DOWN_TILE };
+        let flash = warning && (ticks_left / HAMMER_FLASH_TICKS) % 2 == 0;
+        let palette = if flash { HAMMER_WARNING_PALETTE } else { HAMMER_PALETTE };
+        video_sprite_set(HAMMER_SPRITE_SLOT + slot, tile, hitbox.x.max(0) as u16, hitbox.y.max(0) as u16, palette);
+        video_sprite_flip(HAMMER_SPRITE_SLOT + slot, player_facing() < 0, false);
+    }
+}
+
+// the hammer being swung, if any; the ones lying around are scenery
+pub fn hammer_inspect() -> Vec<Vec<InspectField>> {
+    HAMMERS.with(|cell| {
+        let hammers = cell.borrow();
+        match hammers.held {
+            Some(held) => vec![vec![
+                InspectField::number("which", held as i32),
+                InspectField::number("ticks_left", hammers.ticks_left as i32),
+                InspectField::label("warning", if hammers.warning { "yes" } else { "no" }),
+                InspectField::label("clip", if hammer_swung_up() { "up" } else { "down" }),
+            ]],
+            None => Vec::new(),
+        }
+    })
+}
+
+pub fn hammer_inspect_set(index: usize, field: &str, value: i32) -> Result<(), String> {
+    HAMMERS.with(|cell| {
+        let mut hammers = cell.borrow_mut();
+        if index != 0 || hammers.held.is_none() {
+            return Err("no hammer is held".to_string());
+        }
+        match field {
+            "ticks_left" => hammers.ticks_left = value.max(1) as u32,
+            _ => return inspect_unknown("hammer", field),
+        }
+        return Ok(());
+    })
+}
+
+pub fn hammers_hash(hasher: &mut StateHasher) {
+    HAMMERS.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn hammers_save(savestate: &mut SaveState) {
+    savestate.put("hammers", HAMMERS.with(|cell| cell.borrow().clone()));
+    savestate.put("hammers_stage", STAGE.with(|cell| *cell.borrow()));
+}
+
+pub fn hammers_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<Hammers>("hammers") {
+        HAMMERS.with(|cell| {*cell.borrow_mut() = value;});
+    }
+    if let Some(stage) = savestate.get::<Option<&'static Stage>>("hammers_stage") {
+        STAGE.with(|cell| {*cell.borrow_mut() = stage;});
+    }
+}
@@ -10,8 +10,34 @@
 //
 // --------------------------------------------------------------------------
 
-#[allow(dead_code)]
-#[derive(Clone, Copy)]
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use super::hashing::StateHasher;
+use super::events::GameEvent;
+use super::events::events_publish;
+use super::savestate::SaveState;
+use super::video::video_sprite_set;
+use super::video::video_sprite_flip;
+use super::video::video_sprite_hide;
+
+mod movement;
+pub use self::movement::*;
+
+mod hammer;
+pub use self::hammer::*;
+
+pub const PLAYER_LIVES:  u32 = 3;
+pub const PLAYER_MAX:    usize = 2;
+
+// mario and the halo over him when he dies
+pub const PLAYER_SPRITE_SLOT: usize = 6;
+pub const HALO_SPRITE_SLOT:   usize = 7;
+
+// XXX: palette picked by eye from the ripper output like kong's
+const PLAYER_PALETTE: u8 = 42;
+
+#[derive(Clone, Copy, Hash)]
 struct JumpMan {
     y: u32,
     x: u32,
@@ -19,17 +45,158 @@ struct JumpMan {
     score: u32
 }
 
-static PLAYER1: JumpMan = JumpMan {
+const JUMP_MAN_START: JumpMan = JumpMan {
     y: 0,
     x: 0,
-    lives: 3,
+    lives: PLAYER_LIVES,
     score: 0
 };
 
-pub fn player_draw() {
+// the lives manager: one JumpMan per player taking turns, like the cabinet
+#[derive(Clone, Hash)]
+struct Players {
+    players: [JumpMan; PLAYER_MAX],
+    count: usize,
+    current: usize,
+}
+
+thread_local!(
+    static PLAYERS:RefCell<Players> = RefCell::new(Players {
+        players: [JUMP_MAN_START; PLAYER_MAX],
+        count: 1,
+        current: 0
+    });
+);
+
+pub fn player_start_game(count: usize) {
+    let count = count.max(1).min(PLAYER_MAX);
+    PLAYERS.with(|cell| {
+        let mut players = cell.borrow_mut();
+        players.players = [JUMP_MAN_START; PLAYER_MAX];
+        players.count = count;
+        players.current = 0;
+    });
+    info!("{} player game", count);
+}
+
+pub fn player_count() -> usize {
+    PLAYERS.with(|cell| cell.borrow().count)
+}
+
+// 0-based index of the player whose turn it is
+pub fn player_current() -> usize {
+    PLAYERS.with(|cell| cell.borrow().current)
+}
 
+pub fn player_lives() -> u32 {
+    PLAYERS.with(|cell| {
+        let players = cell.borrow();
+        players.players[players.current].lives
+    })
 }
 
-pub fn player_update() {
+// takes a life from the current player and returns how many are left
+pub fn player_lose_life() -> u32 {
+    PLAYERS.with(|cell| {
+        let mut players = cell.borrow_mut();
+        let current = players.current;
+        let jump_man = &mut players.players[current];
+        jump_man.lives = jump_man.lives.saturating_sub(1);
+        debug!("player {} has {} lives left", current + 1, jump_man.lives);
+        jump_man.lives
+    })
+}
+
+// the next player in turn that still has lives, if it isn't the current one
+pub fn player_next_with_lives() -> Option<usize> {
+    PLAYERS.with(|cell| {
+        let players = cell.borrow();
+        (1..players.count)
+            .map(|offset| (players.current + offset) % players.count)
+            .find(|&index| players.players[index].lives > 0)
+    })
+}
+
+pub fn player_switch_to(index: usize) {
+    PLAYERS.with(|cell| {
+        let mut players = cell.borrow_mut();
+        if index < players.count {
+            players.current = index;
+        }
+    });
+    info!("player {} up", index + 1);
+}
 
-}
\ No newline at end of file
+pub fn player_score() -> u32 {
+    PLAYERS.with(|cell| {
+        let players = cell.borrow();
+        players.players[players.current].score
+    })
+}
+
+pub fn player_add_score(points: u32) {
+    PLAYERS.with(|cell| {
+        let mut players = cell.borrow_mut();
+        let current = players.current;
+        let jump_man = &mut players.players[current];
+        jump_man.score = jump_man.score.saturating_add(points);
+    });
+    if points > 0 {
+        events_publish(GameEvent::Scored { points: points });
+    }
+}
+
+pub fn player_position() -> (u32, u32) {
+    PLAYERS.with(|cell| {
+        let players = cell.borrow();
+        let jump_man = &players.players[players.current];
+        (jump_man.x, jump_man.y)
+    })
+}
+
+// a raw sprite frame for the current player at his position; the death
+// sequence drives this directly
+pub fn player_draw_frame(tile: u16, flip_horizontal: bool, flip_vertical: bool) {
+    let (x, y) = player_position();
+    video_sprite_set(PLAYER_SPRITE_SLOT, tile, x as u16, y as u16, PLAYER_PALETTE);
+    video_sprite_flip(PLAYER_SPRITE_SLOT, flip_horizontal, flip_vertical);
+}
+
+pub fn player_draw_halo(tile: u16) {
+    let (x, y) = player_position();
+    video_sprite_set(HALO_SPRITE_SLOT, tile, x as u16, y.saturating_sub(16) as u16, PLAYER_PALETTE);
+}
+
+pub fn player_hide() {
+    video_sprite_hide(PLAYER_SPRITE_SLOT);
+    video_sprite_hide(HALO_SPRITE_SLOT);
+}
+
+fn player_set_position(x: i32, y: i32) {
+    PLAYERS.with(|cell| {
+        let mut players = cell.borrow_mut();
+        let current = players.current;
+        players.players[current].x = x.max(0) as u32;
+        players.players[current].y = y.max(0) as u32;
+    });
+}
+
+pub fn player_hash(hasher: &mut StateHasher) {
+    PLAYERS.with(|cell| cell.borrow().hash(hasher));
+    player_motion_hash(hasher);
+    hammers_hash(hasher);
+}
+
+pub fn player_save(savestate: &mut SaveState) {
+    savestate.put("players", PLAYERS.with(|cell| cell.borrow().clone()));
+    player_motion_save(savestate);
+    hammers_save(savestate);
+}
+
+pub fn player_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<Players>("players") {
+        PLAYERS.with(|cell| {*cell.borrow_mut() = value;});
+    }
+    player_motion_restore(savestate);
+    hammers_restore(savestate);
+}
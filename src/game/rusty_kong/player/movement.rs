@@ -0,0 +1,417 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use super::*;
+use rusty_kong::enemies::Hitbox;
+use rusty_kong::hashing::StateHasher;
+use rusty_kong::savestate::SaveState;
+use rusty_kong::inspector::*;
+use rusty_kong::input::*;
+use rusty_kong::level::*;
+use rusty_kong::physics::*;
+use rusty_kong::sound::*;
+use rusty_kong::playfield::*;
+
+pub const PLAYER_SIZE: i32 = 16;
+
+// speeds & fall rules come from physics::constants
+const WALK_SOUND_TICKS: u32 = 16;
+
+// how far off a ladder's center or end the player can still get on it
+const LADDER_GRAB: i32 = 3;
+// how far a girder can rise or fall under his feet in one step
+const STEP_REACH:  i32 = 4;
+
+// XXX: frames picked from the ripper output by eye
+const MARIO_WALK:  [u16; 3] = [0x00, 0x01, 0x02];
+const MARIO_CLIMB: u16 = 0x03;
+const MARIO_JUMP:  u16 = 0x0e;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Motion {
+    Walking,
+    // on the ladder whose left edge is at x
+    Climbing { x: i32 },
+    // jumping or falling; from is the height (feet) it started at
+    Airborne { vx: i32, vy: i32, sub: i32, from: i32 },
+}
+
+#[derive(Clone, Hash)]
+struct Movement {
+    motion: Motion,
+    // -1 left, 1 right
+    facing: i32,
+    ticks: u32,
+    // fell too far or off the bottom of the playfield
+    fell: bool,
+    // ticks left on a jump pressed in the air, taken on landing
+    jump_buffer: u32,
+    // ticks left to jump after walking off an edge
+    coyote: u32,
+}
+
+thread_local!(
+    static MOVEMENT:RefCell<Movement> = RefCell::new(Movement {
+        motion: Motion::Walking,
+        facing: 1,
+        ticks: 0,
+        fell: false,
+        jump_buffer: 0,
+        coyote: 0
+    });
+    static STAGE:RefCell<Option<&'static Stage>> = RefCell::new(None);
+);
+
+pub fn player_motion() -> Motion {
+    MOVEMENT.with(|cell| cell.borrow().motion)
+}
+
+// -1 left, 1 right
+pub fn player_facing() -> i32 {
+    MOVEMENT.with(|cell| cell.borrow().facing)
+}
+
+// true once a fall has killed him, with or without an enemy involved
+pub fn player_fell() -> bool {
+    MOVEMENT.with(|cell| cell.borrow().fell)
+}
+
+fn player_feet() -> (i32, i32) {
+    let (x, y) = player_position();
+    (x as i32 + PLAYER_SIZE / 2, y as i32 + PLAYER_SIZE)
+}
+
+fn player_set_feet(center: i32, feet: i32) {
+    player_set_position(center - PLAYER_SIZE / 2, feet - PLAYER_SIZE);
+}
+
+// puts the player at the left end of the stage's bottom girder
+pub fn player_stage_start(stage: &'static Stage) {
+    STAGE.with(|cell| {*cell.borrow_mut() = Some(stage);});
+    MOVEMENT.with(|cell| {
+        let mut movement = cell.borrow_mut();
+        movement.motion = Motion::Walking;
+        movement.facing = 1;
+        movement.ticks = 0;
+        movement.fell = false;
+        movement.jump_buffer = 0;
+        movement.coyote = 0;
+    });
+
+    if let Some(girder) = stage.layout.girders.last() {
+        let center = girder.left() + PLAYER_SIZE;
+        let surfaces = layout_surfaces(stage.layout, center);
+        let feet = surfaces.iter().cloned().max().unwrap_or(0);
+        player_set_feet(center, feet);
+    }
+    player_draw_frame(MARIO_WALK[0], false, false);
+}
+
+fn input_direction() -> i32 {
+    if input_is_down(F_INPUT_LEFT) {
+        -1
+    } else if input_is_down(F_INPUT_RIGHT) {
+        1
+    } else {
+        0
+    }
+}
+
+fn player_jump(direction: i32, motion: &mut Motion) {
+    let (_, feet) = player_feet();
+    let physics = physics();
+    sound_play(SoundId::Jump);
+    *motion = Motion::Airborne { vx: direction * physics.jump_speed_x, vy: -physics.jump_velocity, sub: 0, from: feet };
+}
+
+// jump is a press this tick or one buffered from the air
+fn player_walk(layout: &Layout, ticks: u32, jump: bool, motion: &mut Motion, facing: &mut i32, coyote: &mut u32) {
+    let (center, feet) = player_feet();
+    let stage_ticks = level_stage_ticks();
+
+    // with a hammer he can only walk
+    let armed = hammer_is_held();
+    if input_is_down(F_INPUT_UP) && !armed {
+        if let Some(climb) = layout_ladder_near(layout, center, LADDER_GRAB, stage_ticks, |climb| (climb.bottom - feet).abs() <= LADDER_GRAB) {
+            *motion = Motion::Climbing { x: climb.x };
+            player_set_feet(climb.center(), feet);
+            return;
+        }
+    }
+    if input_is_down(F_INPUT_DOWN) && !armed {
+        if let Some(climb) = layout_ladder_near(layout, center, LADDER_GRAB, stage_ticks, |climb| !climb.blocked && (climb.top - feet).abs() <= LADDER_GRAB) {
+            *motion = Motion::Climbing { x: climb.x };
+            player_set_feet(climb.center(), feet);
+            return;
+        }
+    }
+
+    let direction = input_direction();
+    if direction != 0 {
+        *facing = direction;
+    }
+
+    if jump && !armed {
+        player_jump(direction, motion);
+        return;
+    }
+
+    if direction == 0 || ticks % physics().walk_skip_ticks == 0 {
+        return;
+    }
+
+    if ticks % WALK_SOUND_TICKS == 0 {
+        sound_play(SoundId::Walk);
+    }
+    player_step(layout, direction, motion, coyote);
+}
+
+// a pixel along whatever he's standing on, walked or carried
+fn player_step(layout: &Layout, direction: i32, motion: &mut Motion, coyote: &mut u32) {
+    let (center, feet) = player_feet();
+    let center = PLAYFIELD.clamp_x(center + direction, PLAYER_SIZE / 2);
+    match layout_surface_near(layout, center, feet, STEP_REACH) {
+        Some(surface) => player_set_feet(center, surface),
+        None => {
+            // walked off the end
+            player_set_feet(center, feet);
+            *motion = Motion::Airborne { vx: 0, vy: 0, sub: 0, from: feet };
+            *coyote = physics().coyote_ticks;
+        },
+    }
+}
+
+// a conveyor under him carries him along, walking or standing still
+fn player_convey(stage: &Stage, motion: &mut Motion, coyote: &mut u32) {
+    let (center, feet) = player_feet();
+    let direction =
+        match layout_conveyor_under(stage.layout, center, feet) {
+            Some(d) => d,
+            None => return,
+        };
+    if level_stage_ticks() % stage.conveyor_step_ticks() == 0 {
+        player_step(stage.layout, direction, motion, coyote);
+    }
+}
+
+fn player_climb(layout: &Layout, ticks: u32, x: i32, motion: &mut Motion) {
+    let (_, feet) = player_feet();
+    let center = playfield_col_center(playfield_col(x));
+    let climb =
+        match layout_ladder_at(layout, x, level_stage_ticks()) {
+            Some(c) => c,
+            None => {
+                *motion = Motion::Walking;
+                return;
+            },
+        };
+
+    // a retracting ladder carries anyone above its top down with it
+    if climb.retracting.is_some() && feet < climb.top {
+        player_set_feet(center, climb.top);
+        return;
+    }
+
+    if ticks % physics().climb_step_ticks != 0 {
+        return;
+    }
+
+    if input_is_down(F_INPUT_UP) {
+        let feet = (feet - 1).max(climb.top);
+        player_set_feet(center, feet);
+        if feet == climb.top && !climb.blocked {
+            *motion = Motion::Walking;
+        }
+    } else if input_is_down(F_INPUT_DOWN) {
+        let feet = (feet + 1).min(climb.bottom);
+        player_set_feet(center, feet);
+        if feet == climb.bottom {
+            *motion = Motion::Walking;
+        }
+    }
+}
+
+fn player_fly(layout: &Layout, motion: &mut Motion, fell: &mut bool) {
+    let (vx, vy, sub, from) =
+        match *motion {
+            Motion::Airborne { vx, vy, sub, from } => (vx, vy, sub, from),
+            _ => return,
+        };
+    let (center, feet) = player_feet();
+    let physics = physics();
+
+    let center = PLAYFIELD.clamp_x(center + vx, PLAYER_SIZE / 2);
+    let sub = sub + vy;
+    let moved = sub / SUBPIXELS;
+    let sub = sub - moved * SUBPIXELS;
+    let new_feet = feet + moved;
+    let vy = (vy + physics.gravity).min(physics.fall_velocity_max);
+
+    // lands on the first girder crossed on the way down
+    if moved > 0 {
+        let landing = layout_surfaces(layout, center)
+            .into_iter()
+            .filter(|&surface| surface >= feet && surface <= new_feet)
+            .min();
+        if let Some(surface) = landing {
+            player_set_feet(center, surface);
+            *motion = Motion::Walking;
+            if surface - from > physics.fall_death_height {
+                *fell = true;
+            }
+            return;
+        }
+    }
+
+    // nothing left under him
+    if PLAYFIELD.is_below(new_feet - PLAYER_SIZE) {
+        *fell = true;
+    }
+    player_set_feet(center, new_feet);
+    *motion = Motion::Airborne { vx: vx, vy: vy, sub: sub, from: from };
+}
+
+// one GamePlay tick of walking, climbing and jumping
+pub fn player_update() {
+    let stage =
+        match STAGE.with(|cell| *cell.borrow()) {
+            Some(s) => s,
+            None => return,
+        };
+
+    let (mut motion, mut facing, mut fell, mut jump_buffer, mut coyote, ticks) = MOVEMENT.with(|cell| {
+        let mut movement = cell.borrow_mut();
+        movement.ticks = movement.ticks.wrapping_add(1);
+        (movement.motion, movement.facing, movement.fell, movement.jump_buffer, movement.coyote, movement.ticks)
+    });
+    if fell {
+        return;
+    }
+
+    let pressed = input_is_pressed(F_INPUT_JUMP);
+    match motion {
+        Motion::Walking             => {
+            // only a walk off an edge this tick opens the window
+            coyote = 0;
+            player_walk(stage.layout, ticks, pressed || jump_buffer > 0, &mut motion, &mut facing, &mut coyote);
+            jump_buffer = 0;
+            if motion == Motion::Walking {
+                player_convey(stage, &mut motion, &mut coyote);
+            }
+        },
+        Motion::Climbing { x }      => player_climb(stage.layout, ticks, x, &mut motion),
+        Motion::Airborne { .. }     => {
+            jump_buffer = jump_buffer.saturating_sub(1);
+            if pressed && coyote > 0 && !hammer_is_held() {
+                player_jump(input_direction(), &mut motion);
+                coyote = 0;
+            } else {
+                if pressed {
+                    jump_buffer = physics().jump_buffer_ticks;
+                }
+                coyote = coyote.saturating_sub(1);
+                player_fly(stage.layout, &mut motion, &mut fell);
+            }
+        },
+    }
+
+    MOVEMENT.with(|cell| {
+        let mut movement = cell.borrow_mut();
+        movement.motion = motion;
+        movement.facing = facing;
+        movement.fell = fell;
+        movement.jump_buffer = jump_buffer;
+        movement.coyote = coyote;
+    });
+
+    let (x, _) = player_position();
+    match motion {
+        Motion::Walking             => player_draw_frame(MARIO_WALK[(x as usize / 4) % MARIO_WALK.len()], facing > 0, false),
+        Motion::Climbing { .. }     => {
+            let (_, y) = player_position();
+            player_draw_frame(MARIO_CLIMB, (y / 4) % 2 == 0, false);
+        },
+        Motion::Airborne { .. }     => player_draw_frame(MARIO_JUMP, facing > 0, false),
+    }
+}
+
+// the current player's box for enemy contact, a little inside his sprite
+pub fn player_hitbox() -> Hitbox {
+    let (x, y) = player_position();
+    Hitbox::new(x as i32 + 3, y as i32 + 2, PLAYER_SIZE - 6, PLAYER_SIZE - 2)
+}
+
+pub fn player_inspect() -> Vec<Vec<InspectField>> {
+    let (x, y) = player_position();
+    let (motion, facing) = MOVEMENT.with(|cell| {
+        let movement = cell.borrow();
+        (movement.motion, movement.facing)
+    });
+    let (state, clip, vx, vy) =
+        match motion {
+            Motion::Walking                     => ("walking", "walk", 0, 0),
+            Motion::Climbing { .. }             => ("climbing", "climb", 0, 0),
+            Motion::Airborne { vx, vy, .. }     => ("airborne", "jump", vx, vy),
+        };
+    let clip = if hammer_is_held() { "hammer" } else { clip };
+    vec![vec![
+        InspectField::number("x", x as i32),
+        InspectField::number("y", y as i32),
+        InspectField::number("vx", vx),
+        InspectField::number("vy", vy),
+        InspectField::number("facing", facing),
+        InspectField::label("state", state),
+        InspectField::label("clip", clip),
+    ]]
+}
+
+// velocity only sticks while airborne, everything else works out its own
+pub fn player_inspect_set(index: usize, field: &str, value: i32) -> Result<(), String> {
+    if index != 0 {
+        return Err(format!("no player {}", index));
+    }
+    let (x, y) = player_position();
+    match field {
+        "x" => player_set_position(value, y as i32),
+        "y" => player_set_position(x as i32, value),
+        "facing" => MOVEMENT.with(|cell| {cell.borrow_mut().facing = if value < 0 { -1 } else { 1 };}),
+        "vx" | "vy" => MOVEMENT.with(|cell| {
+            let mut movement = cell.borrow_mut();
+            if let Motion::Airborne { ref mut vx, ref mut vy, .. } = movement.motion {
+                if field == "vx" { *vx = value; } else { *vy = value; }
+            }
+        }),
+        _ => return inspect_unknown("player", field),
+    }
+    return Ok(());
+}
+
+pub fn player_motion_hash(hasher: &mut StateHasher) {
+    MOVEMENT.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn player_motion_save(savestate: &mut SaveState) {
+    savestate.put("movement", MOVEMENT.with(|cell| cell.borrow().clone()));
+    savestate.put("movement_stage", STAGE.with(|cell| *cell.borrow()));
+}
+
+pub fn player_motion_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<Movement>("movement") {
+        MOVEMENT.with(|cell| {*cell.borrow_mut() = value;});
+    }
+    if let Some(stage) = savestate.get::<Option<&'static Stage>>("movement_stage") {
+        STAGE.with(|cell| {*cell.borrow_mut() = stage;});
+    }
+}
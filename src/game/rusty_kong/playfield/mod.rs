@@ -0,0 +1,120 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+
+use rusty_kong::video::SCREEN_HEIGHT;
+use rusty_kong::video::SCREEN_WIDTH;
+use rusty_kong::video::TILE_HEIGHT;
+use rusty_kong::video::TILE_WIDTH;
+
+// the logical space every entity lives in: pixels, origin top left, y
+// down, the same grid as the framebuffer.  tile coordinates are u32
+// columns & rows of it; entity positions are i32 pixels so they can step
+// off an edge.  nothing outside the camera knows about the window.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Playfield {
+    pub width: i32,
+    pub height: i32,
+}
+
+pub const PLAYFIELD: Playfield = Playfield {
+    width: SCREEN_WIDTH as i32,
+    height: SCREEN_HEIGHT as i32,
+};
+
+impl Playfield {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    // keeps something margin pixels either side of x on the playfield
+    pub fn clamp_x(&self, x: i32, margin: i32) -> i32 {
+        x.max(margin).min(self.width - margin)
+    }
+
+    // entirely gone off the bottom
+    pub fn is_below(&self, top: i32) -> bool {
+        top >= self.height
+    }
+}
+
+// the column or row a pixel falls in; anything left of or above the
+// playfield is clamped into column or row 0
+pub fn playfield_col(x: i32) -> u32 {
+    x.max(0) as u32 / TILE_WIDTH
+}
+
+pub fn playfield_row(y: i32) -> u32 {
+    y.max(0) as u32 / TILE_HEIGHT
+}
+
+// pixel edges of a column or row; right and bottom are one past the cell
+pub fn playfield_col_left(col: u32) -> i32 {
+    (col * TILE_WIDTH) as i32
+}
+
+pub fn playfield_col_right(col: u32) -> i32 {
+    ((col + 1) * TILE_WIDTH) as i32
+}
+
+pub fn playfield_col_center(col: u32) -> i32 {
+    playfield_col_left(col) + TILE_WIDTH as i32 / 2
+}
+
+pub fn playfield_row_top(row: u32) -> i32 {
+    (row * TILE_HEIGHT) as i32
+}
+
+pub fn playfield_row_bottom(row: u32) -> i32 {
+    ((row + 1) * TILE_HEIGHT) as i32
+}
+
+// --------------------------------------------------------------------------
+// camera
+// --------------------------------------------------------------------------
+
+// the playfield is drawn upright, or turned half way round for the other
+// side of a cocktail cabinet
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Rotation {
+    Upright,
+    Flipped,
+}
+
+// how the playfield sits in the window: integer scale, then rotation,
+// then over by the letterbox round it
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Camera {
+    pub scale: u32,
+    pub rotation: Rotation,
+    // where the playfield's top left is in the window
+    pub origin: (i32, i32),
+}
+
+pub const CAMERA_SCALE_DEFAULT: u32 = 4;
+
+thread_local!(
+    static CAMERA:RefCell<Camera> = RefCell::new(Camera {
+        scale: CAMERA_SCALE_DEFAULT,
+        rotation: Rotation::Upright,
+        origin: (0, 0)
+    });
+);
+
+pub fn playfield_camera() -> Camera {
+    CAMERA.with(|cell| *cell.borrow())
+}
+
+pub fn playfield_set_camera(camera: Camera) {
+    CAMERA.with(|cell| {*cell.borrow_mut() = camera;});
+}
@@ -0,0 +1,275 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::thread;
+
+use super::clock::TICKS_PER_SECOND;
+use super::state_machine::GameState;
+use super::state_machine::StateReport;
+
+// --properties: the invariants the simulation has to keep whatever it's
+// fed, checked every tick headless over a run of seeds.  each seed plays a
+// practice stage picked from it, starting somewhere on it picked from it
+// too, on mashed inputs of its own; the game's rng gets the same seed.
+//
+//      player never clips a girder: walking, his feet are on one; falling,
+//      they never pass one without landing
+//      hammer never survives a death: nothing's held from the death on,
+//      nor on the restart after it
+//
+// XXX: barrels never leave the playfield waits on barrels rolling
+const PROPERTY_TICKS: u32 = 60 * TICKS_PER_SECOND;
+// how long each mashed input's held for, in ticks
+const MASH_HOLD_MIN:  u32 = 8;
+const MASH_HOLD_MAX:  u32 = 64;
+// one start in this many is under a hammer, so there are some to lose
+const UNDER_HAMMER:   u32 = 4;
+
+// xorshift, the inputs' own so they don't draw on the game's rng sites
+struct Mash {
+    state: u32,
+    input: u8,
+    hold: u32,
+}
+
+impl Mash {
+    fn new(seed: u32) -> Mash {
+        // spread out so neighbouring seeds mash differently; never 0
+        let state = seed.wrapping_mul(0x9e37_79b9) ^ 0x7f4a_7c15;
+        Mash { state: state.max(1), input: 0, hold: 0 }
+    }
+
+    fn next(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        return x;
+    }
+
+    fn below(&mut self, bound: u32) -> u32 {
+        self.next() % bound
+    }
+
+    // a direction (or none) held for a while, jump pressed on its first
+    // tick one time in four; never start, that'd pause
+    fn input(&mut self) -> u8 {
+        use super::input::*;
+
+        const DIRECTIONS: [u8; 5] = [F_INPUT_NONE, F_INPUT_LEFT, F_INPUT_RIGHT, F_INPUT_UP, F_INPUT_DOWN];
+        if self.hold > 0 {
+            self.hold -= 1;
+            return self.input & !F_INPUT_JUMP;
+        }
+        self.hold = MASH_HOLD_MIN + self.below(MASH_HOLD_MAX - MASH_HOLD_MIN);
+        self.input = DIRECTIONS[self.below(DIRECTIONS.len() as u32) as usize];
+        if self.below(4) == 0 {
+            self.input |= F_INPUT_JUMP;
+        }
+        return self.input;
+    }
+}
+
+#[derive(Default)]
+struct Tally {
+    ticks: u32,
+    deaths: u32,
+    // deaths with a hammer in hand, the ones the hammer invariant is about
+    armed_deaths: u32,
+    pickups: u32,
+}
+
+struct Run {
+    problems: Vec<String>,
+    tally: Tally,
+}
+
+// the player's center & feet, for the girder check
+fn property_feet() -> (i32, i32) {
+    use super::player::*;
+    let (x, y) = player_position();
+    (x as i32 + PLAYER_SIZE / 2, y as i32 + PLAYER_SIZE)
+}
+
+// somewhere on the stage to start from: on a girder picked from mash, or
+// on whatever's under one of its hammers
+fn property_place(mash: &mut Mash) {
+    use super::level::*;
+    use super::player::*;
+    use super::playfield::*;
+
+    let stage = level_current_stage();
+    let layout = stage.layout;
+    let hammer = if mash.below(UNDER_HAMMER) == 0 && !stage.hammers.is_empty() {
+        Some(stage.hammers[mash.below(stage.hammers.len() as u32) as usize])
+    } else {
+        None
+    };
+    let (center, feet) =
+        match hammer {
+            Some((x, y)) => {
+                let center = x + HAMMER_SIZE / 2;
+                match layout_surfaces(layout, center).into_iter().filter(|&surface| surface >= y + HAMMER_SIZE).min() {
+                    Some(feet) => (center, feet),
+                    None => return,
+                }
+            },
+            None => {
+                let girder = &layout.girders[mash.below(layout.girders.len() as u32) as usize];
+                let col = girder.col_start + mash.below(girder.col_end - girder.col_start + 1);
+                let center = PLAYFIELD.clamp_x(playfield_col_center(col), PLAYER_SIZE / 2);
+                (center, girder.surface(center))
+            },
+        };
+    let _ = player_inspect_set(0, "x", center - PLAYER_SIZE / 2);
+    let _ = player_inspect_set(0, "y", feet - PLAYER_SIZE);
+}
+
+// one tick's checks; before is where his feet were after the last tick of
+// play, None when the last tick wasn't one
+fn property_check(run: &mut Run, tick: u32, report: &StateReport, before: Option<(i32, i32)>, dying: bool) {
+    use super::level::*;
+    use super::player::*;
+
+    if report.to != GameState::GamePlay && report.to != GameState::PlayerDies {
+        return;
+    }
+    if hammer_is_held() && (dying || (report.transitioned && report.to == GameState::GamePlay)) {
+        run.problems.push(format!("tick {}: a hammer's still held after a death", tick));
+    }
+    if report.to != GameState::GamePlay || report.transitioned {
+        return;
+    }
+
+    let layout = level_current_stage().layout;
+    let (center, feet) = property_feet();
+    let surfaces = layout_surfaces(layout, center);
+    match player_motion() {
+        Motion::Walking => {
+            if !surfaces.contains(&feet) {
+                run.problems.push(format!("tick {}: walking at {},{} with no girder under his feet", tick, center, feet));
+            }
+        },
+        Motion::Airborne { .. } => {
+            if let Some((_, from)) = before {
+                if let Some(surface) = surfaces.iter().find(|&&surface| surface > from && surface < feet) {
+                    run.problems.push(format!("tick {}: fell from {} to {} at x {} through the girder at {}", tick, from, feet, center, surface));
+                }
+            }
+        },
+        Motion::Climbing { .. } => {},
+    }
+}
+
+fn property_run(seed: u32) -> Run {
+    use super::arena::arena_frame_reset;
+    use super::game_update;
+    use super::input::input_set;
+    use super::level::*;
+    use super::locale::locale_init;
+    use super::physics::physics_init;
+    use super::player::*;
+    use super::rng::rng_seed;
+    use super::sequence::sequence_init;
+    use super::settings::settings_hold;
+    use super::state_machine::game_state_go;
+    use super::state_machine::game_state_init;
+
+    settings_hold(true);
+    locale_init();
+    physics_init();
+    sequence_init();
+    rng_seed(seed);
+    game_state_init();
+
+    let mut mash = Mash::new(seed);
+    level_select_campaign(mash.below(level_campaign_count() as u32) as usize);
+    let stage = mash.below(level_campaign().stages.len() as u32) as usize;
+    player_start_game(1);
+    level_start_practice(stage);
+    game_state_go(GameState::GamePlay);
+
+    let mut run = Run { problems: Vec::new(), tally: Tally::default() };
+    let mut placed = false;
+    let mut dying = false;
+    let mut before = None;
+    let mut held = false;
+    for tick in 0..PROPERTY_TICKS {
+        input_set(mash.input());
+        let report = game_update();
+        arena_frame_reset();
+        run.tally.ticks += 1;
+
+        if report.transitioned && report.to == GameState::GamePlay && !placed {
+            property_place(&mut mash);
+            placed = true;
+            continue;
+        }
+        if report.transitioned && report.to == GameState::PlayerDies {
+            run.tally.deaths += 1;
+            if held {
+                run.tally.armed_deaths += 1;
+            }
+            dying = true;
+        }
+        property_check(&mut run, tick, &report, before, dying);
+        if report.to == GameState::GamePlay {
+            dying = false;
+        }
+        if !held && hammer_is_held() {
+            run.tally.pickups += 1;
+        }
+        held = hammer_is_held();
+        before = if report.to == GameState::GamePlay { Some(property_feet()) } else { None };
+        if !run.problems.is_empty() {
+            break;
+        }
+    }
+    return run;
+}
+
+// seeds first_seed on, each on a thread of its own so every one starts from
+// nothing and a panic is put down to its seed; false when any broke an
+// invariant
+pub fn properties(seeds: u32, first_seed: u32) -> bool {
+    let mut failed = Vec::new();
+    let mut total = Tally::default();
+    for seed in first_seed..first_seed.wrapping_add(seeds) {
+        match thread::spawn(move || property_run(seed)).join() {
+            Ok(run) => {
+                total.ticks += run.tally.ticks;
+                total.deaths += run.tally.deaths;
+                total.armed_deaths += run.tally.armed_deaths;
+                total.pickups += run.tally.pickups;
+                for problem in run.problems.iter() {
+                    error!("properties: seed {:08x}: {}", seed, problem);
+                }
+                if !run.problems.is_empty() {
+                    failed.push(seed);
+                }
+            },
+            Err(_) => {
+                error!("properties: seed {:08x} panicked", seed);
+                failed.push(seed);
+            },
+        }
+    }
+    info!("properties: {} seeds from {:08x}, {} ticks", seeds, first_seed, total.ticks);
+    info!("properties: {} deaths, {} of them holding a hammer; {} hammers picked up", total.deaths, total.armed_deaths, total.pickups);
+    if failed.is_empty() {
+        info!("properties: every invariant held");
+    } else {
+        error!("properties: {} of {} seeds failed", failed.len(), seeds);
+    }
+    return failed.is_empty();
+}
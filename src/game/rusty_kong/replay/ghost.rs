@@ -0,0 +1,236 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::fs;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use rusty_kong::events::GameEvent;
+use rusty_kong::level::Stage;
+use rusty_kong::video::FrameBuffer;
+use rusty_kong::video::SpriteFrame;
+
+// the fastest practice clear of each stage, one file per campaign & stage:
+//
+//      # rusty kong ghost
+//      0 40 e8 0 0f 0          tick, x, y, tile, palette, flip bits
+//      12 -                    the player wasn't drawn that tick
+//
+// it's the player sprite as it was drawn rather than inputs, so it plays
+// back next to live play without running a second game
+pub const GHOSTS_DIR: &str = "ghosts";
+
+struct Ghosts {
+    // the stage the best run belongs to, by file
+    path: Option<PathBuf>,
+    best: Vec<Option<SpriteFrame>>,
+    // the attempt going on now, a frame per GamePlay tick
+    attempt: Vec<Option<SpriteFrame>>,
+}
+
+// none of this feeds back into the game, so it stays out of the hashes,
+// savestates and golden frames
+thread_local!(
+    static GHOSTS:RefCell<Ghosts> = RefCell::new(Ghosts {
+        path: None,
+        best: Vec::new(),
+        attempt: Vec::new()
+    });
+);
+
+fn ghost_path(stage: &Stage) -> PathBuf {
+    use rusty_kong::level::level_campaign;
+    let name = format!("{}-{}.ghost", level_campaign().name, stage.name);
+    Path::new(GHOSTS_DIR).join(name.replace(' ', "_"))
+}
+
+fn ghost_parse(path: &Path) -> Result<Vec<Option<SpriteFrame>>, String> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let mut frames = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let bad = || format!("{}:{}: can't make sense of \"{}\"", path.display(), number + 1, line);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let tick = words[0].parse::<usize>().map_err(|_| bad())?;
+        if tick != frames.len() {
+            return Err(format!("{}:{}: expected tick {}", path.display(), number + 1, frames.len()));
+        }
+        let frame =
+            match words.len() {
+                2 if words[1] == "-" => None,
+                6 => {
+                    let hex = |word: &str| u16::from_str_radix(word, 16).map_err(|_| bad());
+                    let flips = hex(words[5])?;
+                    Some(SpriteFrame {
+                        x: hex(words[1])?,
+                        y: hex(words[2])?,
+                        tile: hex(words[3])?,
+                        palette: hex(words[4])? as u8,
+                        flip_horizontal: flips & 1 != 0,
+                        flip_vertical: flips & 2 != 0,
+                    })
+                },
+                _ => return Err(bad()),
+            };
+        frames.push(frame);
+    }
+    return Ok(frames);
+}
+
+fn ghost_write(path: &Path, frames: &[Option<SpriteFrame>]) -> Result<(), String> {
+    let mut contents = String::from("# rusty kong ghost\n");
+    for (tick, frame) in frames.iter().enumerate() {
+        match frame {
+            &Some(ref f) => {
+                let flips = if f.flip_horizontal { 1 } else { 0 } | if f.flip_vertical { 2 } else { 0 };
+                contents.push_str(&format!("{} {:x} {:x} {:x} {:x} {}\n", tick, f.x, f.y, f.tile, f.palette, flips));
+            },
+            &None => contents.push_str(&format!("{} -\n", tick)),
+        }
+    }
+    fs::create_dir_all(GHOSTS_DIR).map_err(|e| format!("can't create {}: {}", GHOSTS_DIR, e))?;
+    let mut file = File::create(path).map_err(|e| format!("can't create {}: {}", path.display(), e))?;
+    file.write_all(contents.as_bytes()).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    return Ok(());
+}
+
+// picks up the stage's best run the first time it's practiced
+fn ghost_load(stage: &Stage) {
+    let path = ghost_path(stage);
+    if GHOSTS.with(|cell| cell.borrow().path.as_ref() == Some(&path)) {
+        return;
+    }
+    let best =
+        if path.exists() {
+            match ghost_parse(&path) {
+                Ok(frames) => {
+                    info!("ghost for {}: {} ticks", stage.name, frames.len());
+                    frames
+                },
+                Err(e) => {
+                    warn!("{}", e);
+                    Vec::new()
+                },
+            }
+        } else {
+            Vec::new()
+        };
+    GHOSTS.with(|cell| {
+        let mut ghosts = cell.borrow_mut();
+        ghosts.path = Some(path);
+        ghosts.best = best;
+        ghosts.attempt.clear();
+    });
+}
+
+// call at the end of every GamePlay tick; records the player as drawn
+pub fn ghost_update() {
+    use rusty_kong::level::*;
+    use rusty_kong::player::PLAYER_SPRITE_SLOT;
+    use rusty_kong::video::video_sprite_frame;
+
+    if !level_is_practice() {
+        return;
+    }
+    ghost_load(level_current_stage());
+
+    // the first tick of an attempt is 1; a death or restart starts over
+    let tick = level_stage_ticks() as usize;
+    let frame = video_sprite_frame(PLAYER_SPRITE_SLOT);
+    GHOSTS.with(|cell| {
+        let mut ghosts = cell.borrow_mut();
+        if tick != ghosts.attempt.len() + 1 {
+            ghosts.attempt.clear();
+        }
+        ghosts.attempt.push(frame);
+    });
+}
+
+// a clear quicker than the best, or the first one, becomes the ghost
+fn ghost_stage_cleared(stage: &Stage) {
+    use rusty_kong::level::level_is_practice;
+
+    if !level_is_practice() {
+        return;
+    }
+    let path = ghost_path(stage);
+    let improved = GHOSTS.with(|cell| {
+        let mut ghosts = cell.borrow_mut();
+        if ghosts.path.as_ref() != Some(&path) || ghosts.attempt.is_empty() {
+            return None;
+        }
+        if !ghosts.best.is_empty() && ghosts.attempt.len() >= ghosts.best.len() {
+            ghosts.attempt.clear();
+            return None;
+        }
+        let attempt = ghosts.attempt.split_off(0);
+        ghosts.best = attempt.clone();
+        Some(attempt)
+    });
+    if let Some(frames) = improved {
+        use rusty_kong::persist::PersistJob;
+        use rusty_kong::persist::persist_run;
+
+        let name = stage.name;
+        persist_run(PersistJob::Ghost, move || {
+            ghost_write(&path, &frames)?;
+            Ok(format!("new best on {}: {} ticks, ghost written to {}", name, frames.len(), path.display()))
+        });
+    }
+}
+
+fn ghost_on_event(event: &GameEvent) {
+    match event {
+        &GameEvent::StageCleared { stage, .. } => ghost_stage_cleared(stage),
+        _ => {},
+    }
+}
+
+// the best run's frame for the tick the live attempt is on
+fn ghost_overlay(framebuffer: &mut FrameBuffer) {
+    use rusty_kong::level::*;
+    use rusty_kong::state_machine::GameState;
+    use rusty_kong::state_machine::game_state_current;
+    use rusty_kong::video::video_draw_translucent;
+
+    if !level_is_practice() || game_state_current() != GameState::GamePlay {
+        return;
+    }
+    let tick = level_stage_ticks() as usize;
+    let frame = GHOSTS.with(|cell| {
+        let ghosts = cell.borrow();
+        match tick.checked_sub(1).and_then(|index| ghosts.best.get(index)) {
+            Some(&Some(frame)) => Some(frame),
+            _ => None,
+        }
+    });
+    if let Some(frame) = frame {
+        video_draw_translucent(framebuffer, &frame);
+    }
+}
+
+pub fn ghost_init() {
+    use rusty_kong::events::events_subscribe;
+    use rusty_kong::video::video_add_overlay;
+
+    events_subscribe(ghost_on_event);
+    video_add_overlay(ghost_overlay);
+}
@@ -0,0 +1,541 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+
+mod ghost;
+pub use self::ghost::*;
+mod turbo;
+pub use self::turbo::*;
+mod spectate;
+pub use self::spectate::*;
+
+// a replay is the seed, the settings the logic depends on and the input
+// stream from boot, so it plays back headless exactly as it was played:
+//
+//      seed 2f6b1c4d           rng seed, hex
+//      daily 20740             starts at that day's daily challenge, not boot
+//      clock 8210 3011         clock_ticks() & clock_game_ticks() at the start
+//      set physics = arcade    a settings.cfg line (settings_logic_pairs)
+//      frames 5400             logic ticks recorded
+//      0 00                    from this tick on the inputs are these flags, hex
+//      reset 3120              the operator reset, before this tick runs
+//      hash 60 0123456789abcdef  game_state_hash() after this tick
+//
+// hashes are optional; with them a replay can't be edited without the
+// playback noticing, which is what makes it usable as leaderboard evidence.
+pub const REPLAY_HASH_INTERVAL: u32 = 60;
+// a day's play with an input change every tick is well under this
+const REPLAY_BYTES_MAX: u64 = 128 * 1024 * 1024;
+
+struct Recording {
+    path: String,
+    seed: u32,
+    // days since 1970-01-01 for a daily challenge run
+    daily: Option<u32>,
+    // the clock's counts when recording began; 0 0 from boot
+    clock: (u32, u32),
+    settings: Vec<(&'static str, String)>,
+    // 0 embeds no hashes
+    hash_interval: u32,
+    ticks: u32,
+    // (tick, flags) wherever the flags changed
+    inputs: Vec<(u32, u8)>,
+    resets: Vec<u32>,
+    tilts: Vec<u32>,
+    hashes: Vec<(u32, u64)>,
+}
+
+thread_local!(
+    static RECORDING:RefCell<Option<Recording>> = RefCell::new(None);
+    // started partway through a tick, recording from the next one
+    static PENDING:RefCell<Option<Recording>> = RefCell::new(None);
+    // the home build's whole session, kept apart from the recording so a
+    // daily run or --record still gets a file of its own
+    static JOURNAL:RefCell<Option<Recording>> = RefCell::new(None);
+);
+
+// call before the first tick.  the rng is reseeded so the recording doesn't
+// depend on anything that ran before it.
+pub fn replay_record_start(path: &str, hash_interval: u32) {
+    use super::rng::RNG_DEFAULT_SEED;
+    use super::rng::rng_seed;
+    use super::settings::settings_logic_pairs;
+
+    rng_seed(RNG_DEFAULT_SEED);
+    RECORDING.with(|cell| {
+        *cell.borrow_mut() = Some(Recording {
+            path: path.to_string(),
+            seed: RNG_DEFAULT_SEED,
+            daily: None,
+            clock: (0, 0),
+            settings: settings_logic_pairs(),
+            hash_interval: hash_interval,
+            ticks: 0,
+            inputs: Vec::new(),
+            resets: Vec::new(),
+            tilts: Vec::new(),
+            hashes: Vec::new(),
+        });
+    });
+    info!("recording a replay to {}", path);
+}
+
+// records a daily challenge from the tick after this one, once
+// daily_start() has set it up.  false when a recording is already going,
+// which has the daily run in it anyway.
+pub fn replay_record_daily(path: &str, day: u32, seed: u32, hash_interval: u32) -> bool {
+    use super::settings::settings_logic_pairs;
+
+    if RECORDING.with(|cell| cell.borrow().is_some()) {
+        return false;
+    }
+    PENDING.with(|cell| {
+        *cell.borrow_mut() = Some(Recording {
+            path: path.to_string(),
+            seed: seed,
+            daily: Some(day),
+            clock: (0, 0),
+            settings: settings_logic_pairs(),
+            hash_interval: hash_interval,
+            ticks: 0,
+            inputs: Vec::new(),
+            resets: Vec::new(),
+            tilts: Vec::new(),
+            hashes: Vec::new(),
+        });
+    });
+    info!("recording the daily challenge to {}", path);
+    return true;
+}
+
+// drops the recording without writing it
+pub fn replay_record_discard() {
+    RECORDING.with(|cell| {cell.borrow_mut().take();});
+    PENDING.with(|cell| {cell.borrow_mut().take();});
+}
+
+// call after every logic tick with the flags it was given
+pub fn replay_record_tick(flags: u8) {
+    use super::game_state_hash;
+
+    for slot in [&RECORDING, &JOURNAL].iter() {
+        slot.with(|cell| {
+            if let Some(ref mut recording) = *cell.borrow_mut() {
+                let tick = recording.ticks;
+                if recording.inputs.last().map_or(true, |&(_, last)| last != flags) {
+                    recording.inputs.push((tick, flags));
+                }
+                if recording.hash_interval != 0 && tick % recording.hash_interval == 0 {
+                    recording.hashes.push((tick, game_state_hash()));
+                }
+                recording.ticks += 1;
+            }
+        });
+    }
+    replay_spectate_tick(flags);
+    if let Some(mut pending) = PENDING.with(|cell| cell.borrow_mut().take()) {
+        use super::clock::*;
+        pending.clock = (clock_ticks(), clock_game_ticks());
+        RECORDING.with(|cell| {*cell.borrow_mut() = Some(pending);});
+    }
+}
+
+// the reset key happens between ticks, outside the input stream
+pub fn replay_record_reset() {
+    for slot in [&RECORDING, &JOURNAL].iter() {
+        slot.with(|cell| {
+            if let Some(ref mut recording) = *cell.borrow_mut() {
+                recording.resets.push(recording.ticks);
+            }
+        });
+    }
+    replay_spectate_key("reset");
+}
+
+// as is the tilt key
+pub fn replay_record_tilt() {
+    for slot in [&RECORDING, &JOURNAL].iter() {
+        slot.with(|cell| {
+            if let Some(ref mut recording) = *cell.borrow_mut() {
+                recording.tilts.push(recording.ticks);
+            }
+        });
+    }
+    replay_spectate_key("tilt");
+}
+
+fn replay_write(recording: &Recording) -> Result<(), String> {
+    let path = Path::new(&recording.path);
+    let mut contents = String::from("# rusty kong replay\n");
+    contents.push_str(&format!("seed {:08x}\n", recording.seed));
+    if let Some(day) = recording.daily {
+        contents.push_str(&format!("daily {}\n", day));
+    }
+    if recording.clock != (0, 0) {
+        contents.push_str(&format!("clock {} {}\n", recording.clock.0, recording.clock.1));
+    }
+    for &(key, ref value) in recording.settings.iter() {
+        contents.push_str(&format!("set {} = {}\n", key, value));
+    }
+    contents.push_str(&format!("frames {}\n", recording.ticks));
+    for &(tick, flags) in recording.inputs.iter() {
+        contents.push_str(&format!("{} {:02x}\n", tick, flags));
+    }
+    for tick in recording.resets.iter() {
+        contents.push_str(&format!("reset {}\n", tick));
+    }
+    for tick in recording.tilts.iter() {
+        contents.push_str(&format!("tilt {}\n", tick));
+    }
+    for &(tick, hash) in recording.hashes.iter() {
+        contents.push_str(&format!("hash {} {:016x}\n", tick, hash));
+    }
+
+    let mut file = File::create(path).map_err(|e| format!("can't create {}: {}", path.display(), e))?;
+    file.write_all(contents.as_bytes()).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    return Ok(());
+}
+
+// writes the replay out, if one is being recorded
+pub fn replay_record_finish() {
+    let recording = RECORDING.with(|cell| cell.borrow_mut().take());
+    if let Some(recording) = recording {
+        match replay_write(&recording) {
+            Ok(()) => info!("wrote {} ticks & {} hashes to {}", recording.ticks, recording.hashes.len(), recording.path),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+// the journal is recorded like replay_record_start()'s replay, from before
+// the first tick, whatever else is being recorded
+pub fn replay_journal_start(path: &str, hash_interval: u32) {
+    use super::rng::RNG_DEFAULT_SEED;
+    use super::rng::rng_seed;
+    use super::settings::settings_logic_pairs;
+
+    rng_seed(RNG_DEFAULT_SEED);
+    JOURNAL.with(|cell| {
+        *cell.borrow_mut() = Some(Recording {
+            path: path.to_string(),
+            seed: RNG_DEFAULT_SEED,
+            daily: None,
+            clock: (0, 0),
+            settings: settings_logic_pairs(),
+            hash_interval: hash_interval,
+            ticks: 0,
+            inputs: Vec::new(),
+            resets: Vec::new(),
+            tilts: Vec::new(),
+            hashes: Vec::new(),
+        });
+    });
+}
+
+// writes the journal out sealed: the last tick gets a hash even when the
+// interval didn't land on it, so the end is checked as well as the way there
+pub fn replay_journal_finish() -> Result<u32, String> {
+    use super::game_state_hash;
+
+    let mut journal =
+        match JOURNAL.with(|cell| cell.borrow_mut().take()) {
+            Some(j) => j,
+            None => return Ok(0),
+        };
+    if journal.ticks == 0 {
+        return Ok(0);
+    }
+    let last = journal.ticks - 1;
+    if journal.hashes.last().map_or(true, |&(tick, _)| tick != last) {
+        journal.hashes.push((last, game_state_hash()));
+    }
+    replay_write(&journal)?;
+    return Ok(journal.ticks);
+}
+
+pub fn replay_journal_discard() {
+    JOURNAL.with(|cell| {cell.borrow_mut().take();});
+}
+
+struct Replay {
+    seed: u32,
+    daily: Option<u32>,
+    clock: (u32, u32),
+    settings: Vec<(String, String)>,
+    ticks: u32,
+    inputs: Vec<(u32, u8)>,
+    resets: Vec<u32>,
+    tilts: Vec<u32>,
+    hashes: Vec<(u32, u64)>,
+}
+
+impl Replay {
+    fn new() -> Replay {
+        Replay {
+            seed: 0,
+            daily: None,
+            clock: (0, 0),
+            settings: Vec::new(),
+            ticks: 0,
+            inputs: Vec::new(),
+            resets: Vec::new(),
+            tilts: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    fn input_at(self:&Replay, tick: u32) -> u8 {
+        use super::input::F_INPUT_NONE;
+
+        self.inputs
+            .iter()
+            .take_while(|&&(from, _)| from <= tick)
+            .last()
+            .map_or(F_INPUT_NONE, |&(_, flags)| flags)
+    }
+}
+
+fn replay_parse(path: &Path) -> Result<Replay, String> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    if size > REPLAY_BYTES_MAX {
+        return Err(format!("{} is {} bytes, more than any replay", path.display(), size));
+    }
+    return replay_parse_from(BufReader::new(file), path);
+}
+
+// path is only for the messages
+fn replay_parse_from<R: BufRead>(source: R, path: &Path) -> Result<Replay, String> {
+    let mut replay = Replay::new();
+    for (number, line) in source.lines().enumerate() {
+        let line = line.map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        replay_parse_line(&mut replay, &line, &format!("{}:{}", path.display(), number + 1))?;
+    }
+    return Ok(replay);
+}
+
+// one line onto what's been read so far; location is only for the messages
+fn replay_parse_line(replay: &mut Replay, line: &str, location: &str) -> Result<(), String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(());
+    }
+
+    let bad = || format!("{}: can't make sense of \"{}\"", location, line);
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words[0] {
+        "set" => {
+            let mut parts = line["set".len()..].splitn(2, '=');
+            let key = parts.next().unwrap().trim();
+            let value = parts.next().ok_or_else(&bad)?.trim();
+            replay.settings.push((key.to_string(), value.to_string()));
+        },
+        "seed" if words.len() == 2 => {
+            replay.seed = u32::from_str_radix(words[1], 16).map_err(|_| bad())?;
+        },
+        "daily" if words.len() == 2 => {
+            replay.daily = Some(words[1].parse::<u32>().map_err(|_| bad())?);
+        },
+        "clock" if words.len() == 3 => {
+            let ticks = words[1].parse::<u32>().map_err(|_| bad())?;
+            let game_ticks = words[2].parse::<u32>().map_err(|_| bad())?;
+            replay.clock = (ticks, game_ticks);
+        },
+        "frames" if words.len() == 2 => {
+            replay.ticks = words[1].parse::<u32>().map_err(|_| bad())?;
+        },
+        "reset" if words.len() == 2 => {
+            replay.resets.push(words[1].parse::<u32>().map_err(|_| bad())?);
+        },
+        "tilt" if words.len() == 2 => {
+            replay.tilts.push(words[1].parse::<u32>().map_err(|_| bad())?);
+        },
+        "hash" if words.len() == 3 => {
+            let tick = words[1].parse::<u32>().map_err(|_| bad())?;
+            let hash = u64::from_str_radix(words[2], 16).map_err(|_| bad())?;
+            replay.hashes.push((tick, hash));
+        },
+        _ if words.len() == 2 => {
+            let tick = words[0].parse::<u32>().map_err(|_| bad())?;
+            let flags = u8::from_str_radix(words[1], 16).map_err(|_| bad())?;
+            if replay.inputs.last().map_or(false, |&(last, _)| last >= tick) {
+                return Err(format!("{}: input ticks must go up", location));
+            }
+            replay.inputs.push((tick, flags));
+        },
+        _ => return Err(bad()),
+    }
+    return Ok(());
+}
+
+// fuzz target: a replay file, parsed but not played
+pub fn replay_fuzz(bytes: &[u8]) {
+    let _ = replay_parse_from(bytes, Path::new("fuzz"));
+}
+
+// plays a replay back headless from default settings plus the recorded
+// ones, checking every embedded hash.  false on the first mismatch.
+pub fn replay_verify(path: &Path) -> bool {
+    let replay =
+        match replay_parse(path) {
+            Ok(r) => r,
+            Err(e) => {
+                error!("{}", e);
+                return false;
+            },
+        };
+    match replay_play(&replay, path) {
+        Ok(()) => {
+            if replay.hashes.is_empty() {
+                warn!("{}: played {} ticks, but there are no hashes to check", path.display(), replay.ticks);
+            } else {
+                info!("{}: all {} hashes over {} ticks match", path.display(), replay.hashes.len(), replay.ticks);
+            }
+            return true;
+        },
+        Err(e) => {
+            error!("{}", e);
+            return false;
+        },
+    }
+}
+
+// the game as it was on the replay's first tick
+fn replay_play_start(replay: &Replay, source: &str) -> Result<(), String> {
+    use super::clock::clock_set;
+    use super::daily::daily_start;
+    use super::locale::locale_init;
+    use super::physics::physics_init;
+    use super::sequence::sequence_init;
+    use super::rng::rng_seed;
+    use super::settings::settings_apply_pairs;
+    use super::state_machine::game_state_init;
+
+    settings_apply_pairs(&replay.settings).map_err(|e| format!("{}: {}", source, e))?;
+    locale_init();
+    physics_init();
+    sequence_init();
+    rng_seed(replay.seed);
+    game_state_init();
+    if let Some(day) = replay.daily {
+        daily_start(day);
+    }
+    clock_set(replay.clock.0, replay.clock.1);
+    return Ok(());
+}
+
+fn replay_play(replay: &Replay, path: &Path) -> Result<(), String> {
+    use super::game_state_hash;
+    use super::game_update;
+    use super::input::input_set;
+    use super::state_machine::game_state_reset;
+    use super::state_machine::game_state_tilt;
+
+    replay_play_start(replay, &path.display().to_string())?;
+    let mut hashes = replay.hashes.iter().peekable();
+    for tick in 0..replay.ticks {
+        if replay.resets.contains(&tick) {
+            game_state_reset();
+        }
+        if replay.tilts.contains(&tick) {
+            game_state_tilt();
+        }
+        input_set(replay.input_at(tick));
+        game_update();
+
+        while let Some(&&(at, expected)) = hashes.peek() {
+            if at > tick {
+                break;
+            }
+            hashes.next();
+            let actual = game_state_hash();
+            if at != tick || actual != expected {
+                return Err(format!("{}: first mismatch at tick {}: expected {:016x}, got {:016x}", path.display(), at, expected, actual));
+            }
+        }
+    }
+
+    if let Some(&(at, _)) = hashes.next() {
+        return Err(format!("{}: hash at tick {} is past the end of the recording", path.display(), at));
+    }
+    return Ok(());
+}
+
+// a sealed journal played back to its end, to be journaled onto from
+// there with replay_journal_resume()
+pub struct ReplayResumed {
+    journal: Recording,
+}
+
+impl ReplayResumed {
+    pub fn ticks(self:&ReplayResumed) -> u32 {
+        return self.journal.ticks;
+    }
+
+    pub fn settings(self:&ReplayResumed) -> Vec<(String, String)> {
+        return self.journal.settings
+            .iter()
+            .map(|&(key, ref value)| (key.to_string(), value.clone()))
+            .collect();
+    }
+}
+
+// plays a journal back like replay_verify(), leaving the game wherever it
+// ends.  it has to be sealed, with a hash on its last tick, or a truncated
+// file would pass for a shorter session.
+pub fn replay_resume(path: &str, hash_interval: u32) -> Result<ReplayResumed, String> {
+    use super::settings::settings_logic_pairs;
+
+    let replay = replay_parse(Path::new(path))?;
+    if replay.ticks == 0 || replay.hashes.last().map(|&(tick, _)| tick) != Some(replay.ticks - 1) {
+        return Err(format!("{}: isn't sealed, the last tick has no hash", path));
+    }
+
+    // the recording's keys are the static ones settings_logic_pairs() has
+    let known = settings_logic_pairs();
+    let mut settings = Vec::new();
+    for &(ref key, ref value) in replay.settings.iter() {
+        match known.iter().find(|&&(k, _)| k == key) {
+            Some(&(k, _)) => settings.push((k, value.clone())),
+            None => return Err(format!("{}: \"{}\" isn't a setting the logic reads", path, key)),
+        }
+    }
+
+    replay_play(&replay, Path::new(path))?;
+    return Ok(ReplayResumed {
+        journal: Recording {
+            path: path.to_string(),
+            seed: replay.seed,
+            daily: replay.daily,
+            clock: replay.clock,
+            settings: settings,
+            hash_interval: hash_interval,
+            ticks: replay.ticks,
+            inputs: replay.inputs,
+            resets: replay.resets,
+            tilts: replay.tilts,
+            hashes: replay.hashes,
+        },
+    });
+}
+
+// journals on from the end of a resumed journal, in place of this
+// session's, once the game is back where it ended
+pub fn replay_journal_resume(resumed: ReplayResumed) {
+    JOURNAL.with(|cell| {*cell.borrow_mut() = Some(resumed.journal);});
+}
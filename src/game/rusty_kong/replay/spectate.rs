@@ -0,0 +1,346 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+
+use super::REPLAY_BYTES_MAX;
+use super::Replay;
+use super::replay_parse_line;
+use super::replay_play_start;
+
+// --spectate serves the session as a replay while it's played, the same
+// lines --record writes but in the order they happen, with a "frames" line
+// after each frame's ticks to say how far it's got.  a viewer that joins
+// late is sent everything from boot and plays it through to catch up.
+//
+// XXX: the whole session's kept for late joiners, it's a day at well under
+// REPLAY_BYTES_MAX.  a daily challenge is picked off the viewer's own
+// clock, so one started across midnight from the viewer goes out of step
+struct Viewer {
+    stream: TcpStream,
+    // how much of the backlog it's been sent
+    sent: usize,
+}
+
+struct Spectating {
+    listener: TcpListener,
+    viewers: Vec<Viewer>,
+    backlog: String,
+    hash_interval: u32,
+    ticks: u32,
+    // the ticks the last frames line covered
+    announced: u32,
+    flags: Option<u8>,
+}
+
+// a viewer catches up this many ticks a frame at most; further behind than
+// WATCH_BEHIND_MAX it stops keeping time and catches up
+const WATCH_CATCH_UP:   u32 = 600;
+const WATCH_BEHIND_MAX: u32 = 30;
+
+struct Watching {
+    address: String,
+    lines: Receiver<String>,
+    replay: Replay,
+    started: bool,
+    tick: u32,
+    // where the next input change, reset, tilt & hash are in the replay
+    input: usize,
+    flags: u8,
+    reset: usize,
+    tilt: usize,
+    hash: usize,
+    // nothing more's coming; what's been sent is still played out
+    over: bool,
+}
+
+thread_local!(
+    static SPECTATING:RefCell<Option<Spectating>> = RefCell::new(None);
+    static WATCHING:RefCell<Option<Watching>> = RefCell::new(None);
+);
+
+// call before the first tick, like replay_record_start(); the rng is
+// reseeded so a viewer's playback starts from the same place
+pub fn replay_spectate_start(address: &str, hash_interval: u32) -> Result<(), String> {
+    use super::super::rng::RNG_DEFAULT_SEED;
+    use super::super::rng::rng_seed;
+    use super::super::settings::settings_logic_pairs;
+
+    let listener = TcpListener::bind(address).map_err(|e| format!("can't listen on {}: {}", address, e))?;
+    listener.set_nonblocking(true).map_err(|e| format!("can't listen on {}: {}", address, e))?;
+    rng_seed(RNG_DEFAULT_SEED);
+
+    let mut backlog = String::from("# rusty kong replay\n");
+    backlog.push_str(&format!("seed {:08x}\n", RNG_DEFAULT_SEED));
+    for &(key, ref value) in settings_logic_pairs().iter() {
+        backlog.push_str(&format!("set {} = {}\n", key, value));
+    }
+    SPECTATING.with(|cell| {
+        *cell.borrow_mut() = Some(Spectating {
+            listener: listener,
+            viewers: Vec::new(),
+            backlog: backlog,
+            hash_interval: hash_interval,
+            ticks: 0,
+            announced: 0,
+            flags: None,
+        });
+    });
+    info!("spectators can watch on {}", address);
+    return Ok(());
+}
+
+// from replay_record_tick(), after every logic tick
+pub fn replay_spectate_tick(flags: u8) {
+    use super::super::game_state_hash;
+
+    SPECTATING.with(|cell| {
+        if let Some(ref mut spectating) = *cell.borrow_mut() {
+            let tick = spectating.ticks;
+            if spectating.flags != Some(flags) {
+                spectating.backlog.push_str(&format!("{} {:02x}\n", tick, flags));
+                spectating.flags = Some(flags);
+            }
+            if spectating.hash_interval != 0 && tick % spectating.hash_interval == 0 {
+                spectating.backlog.push_str(&format!("hash {} {:016x}\n", tick, game_state_hash()));
+            }
+            spectating.ticks += 1;
+        }
+    });
+}
+
+// the reset & tilt keys, before the tick they're on
+pub fn replay_spectate_key(what: &str) {
+    SPECTATING.with(|cell| {
+        if let Some(ref mut spectating) = *cell.borrow_mut() {
+            let tick = spectating.ticks;
+            spectating.backlog.push_str(&format!("{} {}\n", what, tick));
+        }
+    });
+}
+
+// call every frame: lets viewers in and sends each what it hasn't had.  a
+// viewer that can't keep up is sent what it'll take and the rest later;
+// one that's gone is let go of
+pub fn replay_spectate_poll() {
+    SPECTATING.with(|cell| {
+        let mut spectating = cell.borrow_mut();
+        let spectating =
+            match *spectating {
+                Some(ref mut s) => s,
+                None => return,
+            };
+        loop {
+            match spectating.listener.accept() {
+                Ok((stream, address)) => {
+                    if stream.set_nonblocking(true).is_ok() {
+                        let _ = stream.set_nodelay(true);
+                        info!("spectator at {} watching", address);
+                        spectating.viewers.push(Viewer { stream: stream, sent: 0 });
+                    }
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("spectators: {}", e);
+                    break;
+                },
+            }
+        }
+
+        if spectating.ticks != spectating.announced {
+            let ticks = spectating.ticks;
+            spectating.backlog.push_str(&format!("frames {}\n", ticks));
+            spectating.announced = ticks;
+        }
+        let backlog = spectating.backlog.as_bytes();
+        let before = spectating.viewers.len();
+        let mut index = 0;
+        while index < spectating.viewers.len() {
+            if spectate_send(&mut spectating.viewers[index], backlog) {
+                index += 1;
+            } else {
+                spectating.viewers.swap_remove(index);
+            }
+        }
+        if spectating.viewers.len() < before {
+            info!("{} spectators left, {} still watching", before - spectating.viewers.len(), spectating.viewers.len());
+        }
+    });
+}
+
+// as much as it'll take; false when it's gone
+fn spectate_send(viewer: &mut Viewer, backlog: &[u8]) -> bool {
+    while viewer.sent < backlog.len() {
+        match viewer.stream.write(&backlog[viewer.sent..]) {
+            Ok(0) => return false,
+            Ok(written) => viewer.sent += written,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(_) => return false,
+        }
+    }
+    return true;
+}
+
+// the lines come in on a thread of their own so a slow network never holds
+// the frame up; settings & the rest of the watched session are the
+// spectated game's, none of it is the viewer's to keep
+pub fn replay_watch_start(address: &str) -> Result<(), String> {
+    use super::super::persist::persist_hold;
+    use super::super::settings::settings_hold;
+
+    let stream = TcpStream::connect(address).map_err(|e| format!("can't watch {}: {}", address, e))?;
+    let (lines, inbox) = mpsc::channel();
+    thread::spawn(move || {
+        let mut read = 0;
+        for line in BufReader::new(stream).lines() {
+            match line {
+                Ok(line) => {
+                    read += line.len() as u64 + 1;
+                    if read > REPLAY_BYTES_MAX || lines.send(line).is_err() {
+                        break;
+                    }
+                },
+                Err(_) => break,
+            }
+        }
+    });
+    settings_hold(true);
+    persist_hold(true);
+    WATCHING.with(|cell| {
+        *cell.borrow_mut() = Some(Watching {
+            address: address.to_string(),
+            lines: inbox,
+            replay: Replay::new(),
+            started: false,
+            tick: 0,
+            input: 0,
+            flags: 0,
+            reset: 0,
+            tilt: 0,
+            hash: 0,
+            over: false,
+        });
+    });
+    info!("watching {}", address);
+    return Ok(());
+}
+
+fn watch_read(watching: &mut Watching) {
+    loop {
+        match watching.lines.try_recv() {
+            Ok(line) => {
+                let location = format!("{} tick {}", watching.address, watching.replay.ticks);
+                if let Err(e) = replay_parse_line(&mut watching.replay, &line, &location) {
+                    watch_stop(watching, &e);
+                    return;
+                }
+            },
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {
+                info!("{} has stopped, {} ticks watched", watching.address, watching.tick);
+                watching.over = true;
+                return;
+            },
+        }
+    }
+}
+
+// one tick of the watched session, the way replay_play() plays one
+fn watch_tick(watching: &mut Watching) -> Result<(), String> {
+    use super::super::game_state_hash;
+    use super::super::game_update;
+    use super::super::daily::daily_forget;
+    use super::super::input::input_set;
+    use super::super::state_machine::game_state_reset;
+    use super::super::state_machine::game_state_tilt;
+    use super::replay_record_discard;
+
+    let tick = watching.tick;
+    let replay = &watching.replay;
+    while watching.reset < replay.resets.len() && replay.resets[watching.reset] <= tick {
+        game_state_reset();
+        watching.reset += 1;
+    }
+    while watching.tilt < replay.tilts.len() && replay.tilts[watching.tilt] <= tick {
+        game_state_tilt();
+        watching.tilt += 1;
+    }
+    while watching.input < replay.inputs.len() && replay.inputs[watching.input].0 <= tick {
+        watching.flags = replay.inputs[watching.input].1;
+        watching.input += 1;
+    }
+    input_set(watching.flags);
+    game_update();
+    // a daily run played from the menu records & posts itself; this one's
+    // someone else's
+    daily_forget();
+    replay_record_discard();
+    watching.tick += 1;
+
+    while watching.hash < replay.hashes.len() && replay.hashes[watching.hash].0 <= tick {
+        let (at, expected) = replay.hashes[watching.hash];
+        watching.hash += 1;
+        let actual = game_state_hash();
+        if at != tick || actual != expected {
+            return Err(format!("{}: out of step at tick {}: expected {:016x}, got {:016x}", watching.address, at, expected, actual));
+        }
+    }
+    return Ok(());
+}
+
+// out of step or past making sense of: it stops where it is, on screen
+fn watch_stop(watching: &mut Watching, problem: &str) {
+    error!("{}", problem);
+    watching.over = true;
+    watching.replay.ticks = watching.tick;
+}
+
+// call every frame with the ticks the pacer says are due, in place of
+// playing the player's own
+pub fn replay_watch_update(due: u32) {
+    WATCHING.with(|cell| {
+        let mut watching = cell.borrow_mut();
+        let watching =
+            match *watching {
+                Some(ref mut w) => w,
+                None => return,
+            };
+        if !watching.over {
+            watch_read(watching);
+        }
+        if !watching.started && watching.replay.ticks > 0 {
+            watching.started = true;
+            if let Err(e) = replay_play_start(&watching.replay, &watching.address) {
+                watch_stop(watching, &e);
+            }
+        }
+
+        let behind = watching.replay.ticks.saturating_sub(watching.tick);
+        let ticks = if behind > WATCH_BEHIND_MAX { behind.min(WATCH_CATCH_UP) } else { due.min(behind) };
+        for _ in 0..ticks {
+            if let Err(e) = watch_tick(watching) {
+                watch_stop(watching, &e);
+                break;
+            }
+        }
+    });
+}
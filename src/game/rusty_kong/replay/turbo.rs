@@ -0,0 +1,77 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::path::Path;
+
+use super::replay_parse;
+use rusty_kong::input::INPUT_BINDINGS;
+
+// a button pressed faster than turbo_max_presses a turbo_window_ticks; the
+// window is the busiest one found for it, starting at tick
+pub struct TurboFlag {
+    pub button: &'static str,
+    pub tick: u32,
+    pub presses: u32,
+}
+
+// XXX: rate only.  a turbo switched down to a human rate gets through, and
+// a controller's latency smearing presses together isn't modelled
+pub fn turbo_analyze(inputs: &[(u32, u8)], max_presses: u32, window_ticks: u32) -> Vec<TurboFlag> {
+    use rusty_kong::input::F_INPUT_NONE;
+
+    let mut flags = Vec::new();
+    for binding in INPUT_BINDINGS.iter() {
+        // the ticks it went down on; inputs only has the changes
+        let mut previous = F_INPUT_NONE;
+        let mut presses = Vec::new();
+        for &(tick, input) in inputs.iter() {
+            if input & binding.flag != 0 && previous & binding.flag == 0 {
+                presses.push(tick);
+            }
+            previous = input;
+        }
+
+        let mut busiest = (0, 0);
+        let mut end = 0;
+        for (start, &tick) in presses.iter().enumerate() {
+            while end < presses.len() && presses[end] < tick.saturating_add(window_ticks) {
+                end += 1;
+            }
+            let count = (end - start) as u32;
+            if count > busiest.1 {
+                busiest = (tick, count);
+            }
+        }
+        if busiest.1 > max_presses {
+            flags.push(TurboFlag { button: binding.name, tick: busiest.0, presses: busiest.1 });
+        }
+    }
+    return flags;
+}
+
+// a replay file's input stream against the settings' thresholds
+pub fn turbo_check(path: &Path) -> Result<Vec<TurboFlag>, String> {
+    use rusty_kong::settings::settings_read;
+
+    let replay = replay_parse(path)?;
+    let (max_presses, window_ticks) = settings_read(|s| (s.turbo_max_presses, s.turbo_window_ticks));
+    let flags = turbo_analyze(&replay.inputs, max_presses, window_ticks);
+    for flag in flags.iter() {
+        warn!("{}: {} pressed {} times in {} ticks from tick {}, over the {} allowed",
+            path.display(),
+            flag.button,
+            flag.presses,
+            window_ticks,
+            flag.tick,
+            max_presses);
+    }
+    return Ok(flags);
+}
@@ -0,0 +1,139 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::video::VideoSystem;
+
+mod zip;
+use self::zip::ZipWriter;
+
+pub const REPORTS_DIR: &str = "reports";
+
+// where the game is: what the window title would say, the rng, the
+// subsystem hashes a desync check compares and everything the inspector
+// can see.  savestates only live in memory, so this is what stands in.
+fn report_state() -> String {
+    use super::game_state_subsystem_hashes;
+    use super::inspector::InspectValue;
+    use super::inspector::inspector_entities;
+    use super::rng::rng_state;
+    use super::status::status_current;
+    use super::status::status_text;
+
+    let mut contents = String::from("# rusty kong state\n");
+    contents.push_str(&format!("status {}\n", status_text(&status_current())));
+    // rng_seed() with this carries on from where the report was taken
+    contents.push_str(&format!("seed {:08x}\n", rng_state()));
+    for (name, hash) in game_state_subsystem_hashes() {
+        contents.push_str(&format!("hash {} {:016x}\n", name, hash));
+    }
+    for entity in inspector_entities() {
+        let fields: Vec<String> = entity.fields
+            .iter()
+            .map(|field| {
+                match field.value {
+                    InspectValue::Number(value) => format!("{} = {}", field.name, value),
+                    InspectValue::Label(label) => format!("{} = {}", field.name, label),
+                }
+            })
+            .collect();
+        contents.push_str(&format!("{} {}: {}\n", entity.kind, entity.index, fields.join(", ")));
+    }
+    return contents;
+}
+
+// the same "tick flags" lines as a replay, one per change, counted from
+// the oldest tick kept
+fn report_inputs() -> String {
+    use super::input::input_history;
+
+    let history = input_history();
+    let mut contents = format!("# last {} ticks of input\n", history.len());
+    let mut previous = None;
+    for (tick, &flags) in history.iter().enumerate() {
+        if previous != Some(flags) {
+            contents.push_str(&format!("{} {:02x}\n", tick, flags));
+            previous = Some(flags);
+        }
+    }
+    return contents;
+}
+
+// the logic settings as the game sees them, then settings.cfg as written
+fn report_settings() -> String {
+    use super::settings::SETTINGS_FILE;
+    use super::settings::settings_logic_pairs;
+
+    let mut contents = String::from("# in effect\n");
+    for (key, value) in settings_logic_pairs() {
+        contents.push_str(&format!("{} = {}\n", key, value));
+    }
+    let mut file = String::new();
+    match File::open(SETTINGS_FILE).and_then(|mut f| f.read_to_string(&mut file)) {
+        Ok(_) => {
+            contents.push_str(&format!("# {}\n", SETTINGS_FILE));
+            contents.push_str(&file);
+        },
+        Err(_) => contents.push_str(&format!("# no {}\n", SETTINGS_FILE)),
+    }
+    return contents;
+}
+
+fn report_log() -> String {
+    use super::logging::logging_tail;
+
+    let mut contents = String::new();
+    for line in logging_tail() {
+        contents.push_str(&line);
+        contents.push('\n');
+    }
+    return contents;
+}
+
+// reports/yyyy-mm-dd-n.zip, with n the first one not taken
+fn report_path() -> PathBuf {
+    use super::daily::daily_date;
+    use super::daily::daily_today;
+
+    let date = daily_date(daily_today());
+    let mut n = 1;
+    loop {
+        let path = Path::new(REPORTS_DIR).join(format!("{}-{}.zip", date, n));
+        if !path.exists() {
+            return path;
+        }
+        n += 1;
+    }
+}
+
+// everything someone chasing a bug would ask for, in one zip under reports/
+pub fn report_write(video: &VideoSystem) -> Result<PathBuf, String> {
+    use super::video::video_screenshot;
+
+    let mut zip = ZipWriter::new();
+    zip.add("screenshot.bmp", &video_screenshot(video));
+    zip.add("state.txt", report_state().as_bytes());
+    zip.add("inputs.txt", report_inputs().as_bytes());
+    zip.add("settings.txt", report_settings().as_bytes());
+    zip.add("log.txt", report_log().as_bytes());
+
+    fs::create_dir_all(REPORTS_DIR).map_err(|e| format!("can't create {}: {}", REPORTS_DIR, e))?;
+    let path = report_path();
+    let mut file = File::create(&path).map_err(|e| format!("can't create {}: {}", path.display(), e))?;
+    file.write_all(&zip.finish()).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    return Ok(path);
+}
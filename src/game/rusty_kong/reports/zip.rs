@@ -0,0 +1,106 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+// just enough of the zip format to bundle a handful of small files: every
+// entry is stored, not deflated, so there's nothing to pull in for it
+pub struct ZipWriter {
+    bytes: Vec<u8>,
+    directory: Vec<u8>,
+    count: u16,
+}
+
+// 1980-01-01 00:00, the earliest a dos timestamp can say; the report's
+// name carries the date anyway
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+fn zip_put16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.push(value as u8);
+    bytes.push((value >> 8) as u8);
+}
+
+fn zip_put32(bytes: &mut Vec<u8>, value: u32) {
+    zip_put16(bytes, value as u16);
+    zip_put16(bytes, (value >> 16) as u16);
+}
+
+// the ieee crc32 zip wants, bit at a time; reports are rare and small
+fn zip_crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data.iter() {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    return !crc;
+}
+
+impl ZipWriter {
+    pub fn new() -> ZipWriter {
+        ZipWriter {
+            bytes: Vec::new(),
+            directory: Vec::new(),
+            count: 0,
+        }
+    }
+
+    pub fn add(self:&mut ZipWriter, name: &str, data: &[u8]) {
+        let offset = self.bytes.len() as u32;
+        let crc = zip_crc32(data);
+
+        // the local header and the central directory entry share the middle
+        let mut common = Vec::new();
+        zip_put16(&mut common, 20);
+        zip_put16(&mut common, 0);
+        zip_put16(&mut common, 0);
+        zip_put16(&mut common, DOS_TIME);
+        zip_put16(&mut common, DOS_DATE);
+        zip_put32(&mut common, crc);
+        zip_put32(&mut common, data.len() as u32);
+        zip_put32(&mut common, data.len() as u32);
+        zip_put16(&mut common, name.len() as u16);
+        zip_put16(&mut common, 0);
+
+        zip_put32(&mut self.bytes, 0x0403_4b50);
+        self.bytes.extend_from_slice(&common);
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.bytes.extend_from_slice(data);
+
+        zip_put32(&mut self.directory, 0x0201_4b50);
+        zip_put16(&mut self.directory, 20);
+        self.directory.extend_from_slice(&common);
+        // comment, disk, internal & external attributes
+        zip_put16(&mut self.directory, 0);
+        zip_put16(&mut self.directory, 0);
+        zip_put16(&mut self.directory, 0);
+        zip_put32(&mut self.directory, 0);
+        zip_put32(&mut self.directory, offset);
+        self.directory.extend_from_slice(name.as_bytes());
+
+        self.count += 1;
+    }
+
+    pub fn finish(self:ZipWriter) -> Vec<u8> {
+        let mut bytes = self.bytes;
+        let offset = bytes.len() as u32;
+        bytes.extend_from_slice(&self.directory);
+        zip_put32(&mut bytes, 0x0605_4b50);
+        zip_put16(&mut bytes, 0);
+        zip_put16(&mut bytes, 0);
+        zip_put16(&mut bytes, self.count);
+        zip_put16(&mut bytes, self.count);
+        zip_put32(&mut bytes, self.directory.len() as u32);
+        zip_put32(&mut bytes, offset);
+        zip_put16(&mut bytes, 0);
+        return bytes;
+    }
+}
@@ -0,0 +1,119 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+// a packed part as the rewind ring keeps it: xor'd against the same part
+// of the snapshot after it, which leaves zeros wherever nothing changed,
+// and squeezed.  a part with nothing to line up against is kept whole.
+const DELTA_XOR:   u8 = 0;
+const DELTA_WHOLE: u8 = 1;
+
+use std::io;
+
+// fast beats small, a snapshot is squeezed every second
+#[cfg(feature = "zstd")]
+const DELTA_ZSTD_LEVEL: i32 = 1;
+
+pub fn delta_encode(part: &[u8], newer: Option<&[u8]>) -> io::Result<Vec<u8>> {
+    let mut delta = Vec::new();
+    match newer {
+        Some(newer) if newer.len() == part.len() => {
+            delta.push(DELTA_XOR);
+            let xor: Vec<u8> = part.iter().zip(newer.iter()).map(|(a, b)| a ^ b).collect();
+            delta_squeeze(&xor, &mut delta)?;
+        },
+        _ => {
+            delta.push(DELTA_WHOLE);
+            delta_squeeze(part, &mut delta)?;
+        },
+    }
+    return Ok(delta);
+}
+
+// the part back from delta_encode(), given the same newer part
+pub fn delta_decode(delta: &[u8], newer: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    let (&kind, squeezed) =
+        match delta.split_first() {
+            Some(split) => split,
+            None => return Err("an empty delta".to_string()),
+        };
+    let bytes = delta_expand(squeezed)?;
+    match (kind, newer) {
+        (DELTA_WHOLE, _) => Ok(bytes),
+        (DELTA_XOR, Some(newer)) if newer.len() == bytes.len() => {
+            Ok(bytes.iter().zip(newer.iter()).map(|(a, b)| a ^ b).collect())
+        },
+        (DELTA_XOR, _) => Err("a delta against a part that isn't there".to_string()),
+        _ => Err(format!("unknown delta kind {}", kind)),
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn delta_squeeze(bytes: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+    use std::io::Write;
+    use zstd::stream::write::Encoder;
+
+    let mut encoder = Encoder::new(out, DELTA_ZSTD_LEVEL)?;
+    encoder.write_all(bytes)?;
+    encoder.finish()?;
+    return Ok(());
+}
+
+#[cfg(feature = "zstd")]
+fn delta_expand(squeezed: &[u8]) -> Result<Vec<u8>, String> {
+    use std::io::Read;
+    use zstd::stream::read::Decoder;
+
+    let mut bytes = Vec::new();
+    let mut decoder = Decoder::new(squeezed).map_err(|e| e.to_string())?;
+    decoder.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    return Ok(bytes);
+}
+
+// without zstd the runs of zeros are squeezed out, which is most of an
+// xor: a u16 count of zeros, a u16 count of the bytes after them, then
+// those bytes, over and over
+#[cfg(not(feature = "zstd"))]
+fn delta_squeeze(bytes: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+    let most = u16::max_value() as usize;
+    let mut at = 0;
+    while at < bytes.len() {
+        let zeros = bytes[at..].iter().take(most).take_while(|&&b| b == 0).count();
+        at += zeros;
+        let literal = bytes[at..].iter().take(most).take_while(|&&b| b != 0).count();
+        out.extend_from_slice(&(zeros as u16).to_le_bytes());
+        out.extend_from_slice(&(literal as u16).to_le_bytes());
+        out.extend_from_slice(&bytes[at..at + literal]);
+        at += literal;
+    }
+    return Ok(());
+}
+
+#[cfg(not(feature = "zstd"))]
+fn delta_expand(squeezed: &[u8]) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut at = 0;
+    while at < squeezed.len() {
+        if at + 4 > squeezed.len() {
+            return Err("a delta cut short".to_string());
+        }
+        let zeros = u16::from_le_bytes([squeezed[at], squeezed[at + 1]]) as usize;
+        let literal = u16::from_le_bytes([squeezed[at + 2], squeezed[at + 3]]) as usize;
+        at += 4;
+        if at + literal > squeezed.len() {
+            return Err("a delta cut short".to_string());
+        }
+        bytes.resize(bytes.len() + zeros, 0);
+        bytes.extend_from_slice(&squeezed[at..at + literal]);
+        at += literal;
+    }
+    return Ok(bytes);
+}
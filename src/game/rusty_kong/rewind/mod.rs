@@ -0,0 +1,229 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use super::savestate::{PackedParts, SaveState};
+
+mod delta;
+use self::delta::*;
+
+// dev mode keeps a savestate every REWIND_INTERVAL_TICKS, as many as fit
+// in settings.cfg's rewind_budget_kb, so a bug can be replayed the moment
+// after it happens
+pub const REWIND_INTERVAL_TICKS: u32 = 60;
+// how far back the hotkey goes, in snapshots
+pub const REWIND_STEP:           usize = 5;
+
+// the newest snapshot's packed parts are as they were saved; every older
+// one's are deltas against the snapshot after it
+struct Snapshot {
+    savestate: SaveState,
+    // its packed parts' bytes before the deltas
+    raw: usize,
+}
+
+struct Rewind {
+    enabled: bool,
+    // ticks since the newest snapshot
+    ticks: u32,
+    snapshots: VecDeque<Snapshot>,
+    // what the snapshots take up, and the most they ever have
+    bytes: usize,
+    peak: usize,
+}
+
+thread_local!(
+    static REWIND:RefCell<Rewind> = RefCell::new(Rewind {
+        enabled: false,
+        ticks: 0,
+        snapshots: VecDeque::new(),
+        bytes: 0,
+        peak: 0
+    });
+);
+
+pub struct RewindStats {
+    pub snapshots: usize,
+    pub bytes: usize,
+    pub peak: usize,
+    // what the same snapshots would take up without the deltas
+    pub raw: usize,
+    pub budget: usize,
+}
+
+fn rewind_budget() -> usize {
+    use super::settings::settings_read;
+    settings_read(|s| s.rewind_budget_kb as usize * 1024)
+}
+
+pub fn rewind_enable() {
+    REWIND.with(|cell| {cell.borrow_mut().enabled = true;});
+    info!("rewind on: a snapshot every {} ticks, {} bytes of them kept", REWIND_INTERVAL_TICKS, rewind_budget());
+}
+
+pub fn rewind_stats() -> RewindStats {
+    REWIND.with(|cell| {
+        let rewind = cell.borrow();
+        let boxed: usize = rewind.snapshots
+            .iter()
+            .map(|snapshot| snapshot.savestate.size() - packed_size(snapshot.savestate.packed()))
+            .sum();
+        RewindStats {
+            snapshots: rewind.snapshots.len(),
+            bytes: rewind.bytes,
+            peak: rewind.peak,
+            raw: boxed + rewind.snapshots.iter().map(|snapshot| snapshot.raw).sum::<usize>(),
+            budget: rewind_budget(),
+        }
+    })
+}
+
+fn packed_size(packed: &PackedParts) -> usize {
+    packed.iter().map(|&(_, ref bytes)| bytes.len()).sum()
+}
+
+fn packed_find<'a>(packed: &'a PackedParts, name: &str) -> Option<&'a [u8]> {
+    packed
+        .iter()
+        .find(|&&(existing, _)| existing == name)
+        .map(|&(_, ref bytes)| &bytes[..])
+}
+
+// after every logic tick; true when it took a snapshot
+pub fn rewind_record() -> bool {
+    use super::game_state_save;
+
+    let due = REWIND.with(|cell| {
+        let mut rewind = cell.borrow_mut();
+        if !rewind.enabled {
+            return false;
+        }
+        rewind.ticks += 1;
+        rewind.ticks >= REWIND_INTERVAL_TICKS || rewind.snapshots.is_empty()
+    });
+    if !due {
+        return false;
+    }
+
+    let savestate = game_state_save();
+    let budget = rewind_budget();
+    return REWIND.with(|cell| {
+        let rewind = &mut *cell.borrow_mut();
+        // what was the newest becomes a delta against this one; if it
+        // can't be squeezed this snapshot is skipped and the newest stays
+        // whole, the way one over budget would go
+        if let Some(newest) = rewind.snapshots.back_mut() {
+            let deltas: Result<PackedParts, _> = newest.savestate.packed()
+                .iter()
+                .map(|&(name, ref bytes)| delta_encode(bytes, packed_find(savestate.packed(), name)).map(|delta| (name, delta)))
+                .collect();
+            let deltas =
+                match deltas {
+                    Ok(d) => d,
+                    Err(e) => {
+                        warn!("rewind snapshot skipped: {}", e);
+                        rewind.ticks = 0;
+                        return false;
+                    },
+                };
+            let before = newest.savestate.size();
+            newest.savestate.swap_packed(deltas);
+            rewind.bytes = rewind.bytes - before + newest.savestate.size();
+        }
+
+        let raw = packed_size(savestate.packed());
+        rewind.bytes += savestate.size();
+        rewind.snapshots.push_back(Snapshot { savestate, raw });
+        // the oldest is only ever a delta against the one after it, so it
+        // goes without touching the rest
+        while rewind.bytes > budget && rewind.snapshots.len() > 1 {
+            if let Some(oldest) = rewind.snapshots.pop_front() {
+                rewind.bytes -= oldest.savestate.size();
+            }
+        }
+        rewind.peak = rewind.peak.max(rewind.bytes);
+        rewind.ticks = 0;
+        return true;
+    });
+}
+
+// the packed parts of snapshot index as they were saved, from the newest
+// back through the deltas in between
+fn rewind_rebuild(rewind: &Rewind, index: usize) -> Result<PackedParts, String> {
+    let newest =
+        match rewind.snapshots.back() {
+            Some(s) => s,
+            None => return Err("no snapshots".to_string()),
+        };
+    let mut full = newest.savestate.packed().clone();
+    for older in (index..rewind.snapshots.len() - 1).rev() {
+        let mut parts = Vec::new();
+        for &(name, ref delta) in rewind.snapshots[older].savestate.packed().iter() {
+            let bytes = delta_decode(delta, packed_find(&full, name)).map_err(|e| format!("snapshot {}'s {}: {}", older, name, e))?;
+            parts.push((name, bytes));
+        }
+        full = parts;
+    }
+    return Ok(full);
+}
+
+// puts snapshot index back, 0 being the oldest, and keeps all of them; for
+// checking every delta still decodes
+pub fn rewind_restore(index: usize) -> Result<(), String> {
+    use super::game_state_restore;
+
+    REWIND.with(|cell| {
+        let rewind = &mut *cell.borrow_mut();
+        if index >= rewind.snapshots.len() {
+            return Err(format!("no snapshot {}, there are {}", index, rewind.snapshots.len()));
+        }
+        let full = rewind_rebuild(rewind, index)?;
+        let savestate = &mut rewind.snapshots[index].savestate;
+        let deltas = savestate.swap_packed(full);
+        game_state_restore(savestate);
+        savestate.swap_packed(deltas);
+        return Ok(());
+    })
+}
+
+// goes back steps snapshots (or as far as there are) and drops everything
+// newer, so recording carries on from there
+pub fn rewind_back(steps: usize) {
+    use super::game_state_restore;
+
+    REWIND.with(|cell| {
+        let rewind = &mut *cell.borrow_mut();
+        if !rewind.enabled || rewind.snapshots.is_empty() {
+            return;
+        }
+        let keep = rewind.snapshots.len().saturating_sub(steps).max(1);
+        let full =
+            match rewind_rebuild(rewind, keep - 1) {
+                Ok(f) => f,
+                Err(e) => {
+                    error!("can't rewind: {}", e);
+                    return;
+                },
+            };
+        let count = rewind.snapshots.len();
+        rewind.snapshots.truncate(keep);
+        rewind.ticks = 0;
+        if let Some(newest) = rewind.snapshots.back_mut() {
+            newest.savestate.swap_packed(full);
+            game_state_restore(&newest.savestate);
+        }
+        rewind.bytes = rewind.snapshots.iter().map(|snapshot| snapshot.savestate.size()).sum();
+        info!("rewound to snapshot {} of {}", keep, count);
+    });
+}
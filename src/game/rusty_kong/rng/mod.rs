@@ -0,0 +1,161 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::Write;
+use std::path::Path;
+
+use super::hashing::StateHasher;
+use super::savestate::SaveState;
+
+// anything random in the game logic draws from here and nowhere else, so a
+// run is reproducible from the seed plus the input stream
+pub const RNG_DEFAULT_SEED: u32 = 0x2f6b_1c4d;
+
+#[derive(Clone, Hash)]
+struct Rng {
+    state: u32,
+}
+
+thread_local!(
+    static RNG:RefCell<Rng> = RefCell::new(Rng {
+        state: RNG_DEFAULT_SEED
+    });
+);
+
+// one draw, kept for difficulty tuning & determinism digging.  not part of
+// the hashed or saved state, tracing never changes a run.
+struct RngCall {
+    tick: u32,
+    site: &'static str,
+    bound: u32,
+    outcome: u32,
+}
+
+struct RngTrace {
+    enabled: bool,
+    // logic ticks since tracing was enabled
+    tick: u32,
+    calls: Vec<RngCall>,
+}
+
+thread_local!(
+    static TRACE:RefCell<RngTrace> = RefCell::new(RngTrace {
+        enabled: false,
+        tick: 0,
+        calls: Vec::new()
+    });
+);
+
+pub fn rng_trace_enable() {
+    TRACE.with(|cell| {
+        let mut trace = cell.borrow_mut();
+        trace.enabled = true;
+        trace.tick = 0;
+        trace.calls.clear();
+    });
+}
+
+// call once per logic tick
+pub fn rng_trace_tick() {
+    TRACE.with(|cell| {
+        let mut trace = cell.borrow_mut();
+        if trace.enabled {
+            trace.tick = trace.tick.wrapping_add(1);
+        }
+    });
+}
+
+fn rng_trace(site: &'static str, bound: u32, outcome: u32) {
+    TRACE.with(|cell| {
+        let mut trace = cell.borrow_mut();
+        if !trace.enabled {
+            return;
+        }
+        let tick = trace.tick;
+        trace!("rng {} at tick {}: {} of {}", site, tick, outcome, bound);
+        trace.calls.push(RngCall { tick: tick, site: site, bound: bound, outcome: outcome });
+    });
+}
+
+// tick,site,bound,outcome per draw.  returns the number of draws written.
+pub fn rng_trace_write_csv(path: &Path) -> Result<usize, String> {
+    let mut contents = String::from("tick,site,bound,outcome\n");
+    let count = TRACE.with(|cell| {
+        let trace = cell.borrow();
+        for call in trace.calls.iter() {
+            contents.push_str(&format!("{},{},{},{}\n", call.tick, call.site, call.bound, call.outcome));
+        }
+        trace.calls.len()
+    });
+
+    let mut file =
+        match File::create(path) {
+            Ok(f) => f,
+            Err(e) => return Err(format!("can't create {}: {}", path.display(), e)),
+        };
+    if let Err(e) = file.write_all(contents.as_bytes()) {
+        return Err(format!("can't write {}: {}", path.display(), e));
+    }
+    return Ok(count);
+}
+
+// xorshift32 never leaves 0, so 0 is swapped for the default
+pub fn rng_seed(seed: u32) {
+    let seed = if seed == 0 { RNG_DEFAULT_SEED } else { seed };
+    RNG.with(|cell| {cell.borrow_mut().state = seed;});
+    debug!("rng seeded with {:08x}", seed);
+}
+
+// where the sequence is now; rng_seed() with this carries on from here
+pub fn rng_state() -> u32 {
+    RNG.with(|cell| cell.borrow().state)
+}
+
+fn rng_step() -> u32 {
+    RNG.with(|cell| {
+        let mut rng = cell.borrow_mut();
+        let mut x = rng.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        rng.state = x;
+        x
+    })
+}
+
+// 0 up to but not including bound; site names the caller in the trace,
+// e.g. "smash_points"
+pub fn rng_below(site: &'static str, bound: u32) -> u32 {
+    if bound == 0 {
+        return 0;
+    }
+    let outcome = rng_step() % bound;
+    rng_trace(site, bound, outcome);
+    return outcome;
+}
+
+pub fn rng_hash(hasher: &mut StateHasher) {
+    RNG.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn rng_save(savestate: &mut SaveState) {
+    savestate.put("rng", RNG.with(|cell| cell.borrow().clone()));
+}
+
+pub fn rng_restore(savestate: &SaveState) {
+    if let Some(value) = savestate.get::<Rng>("rng") {
+        RNG.with(|cell| {*cell.borrow_mut() = value;});
+    }
+}
@@ -0,0 +1,96 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::any::Any;
+use std::mem;
+
+// a big, flat part kept as bytes instead of boxed, so rewind can store it
+// as the difference from the snapshot next to it
+pub trait Packed: Sized {
+    fn pack(self:&Self, out: &mut Vec<u8>);
+    // None when the bytes aren't one of these
+    fn unpack(bytes: &[u8]) -> Option<Self>;
+}
+
+pub type PackedParts = Vec<(&'static str, Vec<u8>)>;
+
+// a snapshot of every stateful subsystem, opaque outside the module that
+// wrote each part.  modules fill it from a *_save() and take their part
+// back in a *_restore(), next to their *_hash(); see game_state_save().
+pub struct SaveState {
+    // with each one's size, for rewind's budget
+    parts: Vec<(&'static str, usize, Box<dyn Any>)>,
+    packed: PackedParts,
+}
+
+impl SaveState {
+    pub fn new() -> SaveState {
+        SaveState {
+            parts: Vec::new(),
+            packed: Vec::new()
+        }
+    }
+
+    pub fn put<T: Any>(self:&mut SaveState, name: &'static str, part: T) {
+        self.parts.retain(|&(existing, _, _)| existing != name);
+        self.parts.push((name, mem::size_of::<T>(), Box::new(part)));
+    }
+
+    pub fn put_packed<T: Packed>(self:&mut SaveState, name: &'static str, part: &T) {
+        let mut bytes = Vec::new();
+        part.pack(&mut bytes);
+        self.packed.retain(|&(existing, _)| existing != name);
+        self.packed.push((name, bytes));
+    }
+
+    pub fn get_packed<T: Packed>(self:&SaveState, name: &'static str) -> Option<T> {
+        let part = self.packed
+            .iter()
+            .find(|&&(existing, _)| existing == name)
+            .and_then(|&(_, ref bytes)| T::unpack(bytes));
+        if part.is_none() {
+            warn!("savestate has no usable \"{}\"", name);
+        }
+        return part;
+    }
+
+    pub fn packed(self:&SaveState) -> &PackedParts {
+        &self.packed
+    }
+
+    // the packed parts go out and these come in, for rewind to keep them
+    // as it likes and put them back before a restore
+    pub fn swap_packed(self:&mut SaveState, packed: PackedParts) -> PackedParts {
+        mem::replace(&mut self.packed, packed)
+    }
+
+    // XXX: the boxed parts count at their own size; what a Vec in one
+    // holds on the heap is missed, they're all small
+    pub fn size(self:&SaveState) -> usize {
+        let boxed: usize = self.parts.iter().map(|&(_, size, _)| size).sum();
+        let packed: usize = self.packed.iter().map(|&(_, ref bytes)| bytes.len()).sum();
+        return boxed + packed;
+    }
+
+    // a part that's missing or of another type is logged and skipped, so
+    // the module keeps whatever it has now
+    pub fn get<T: Any + Clone>(self:&SaveState, name: &'static str) -> Option<T> {
+        let part = self.parts
+            .iter()
+            .find(|&&(existing, _, _)| existing == name)
+            .and_then(|&(_, _, ref part)| part.downcast_ref::<T>());
+        if part.is_none() {
+            warn!("savestate has no usable \"{}\"", name);
+        }
+        part.cloned()
+    }
+}
@@ -0,0 +1,319 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::io::Read;
+use std::path::Path;
+
+use super::events::GameEvent;
+use super::level::EnemyKind;
+use super::sound::SoundId;
+use super::state_machine::GameState;
+
+// mod behaviours as data: scripts/hooks.txt, one rule per line, a hook and
+// then what to do when it fires.  a mod ships its own under
+// mods/<name>/scripts/ like any other asset
+//
+//      enter game_play     sound roar
+//      spawn fireball      points 100
+//      smash               sprite 0 0x7b 16 16 47
+//      touch               hide 0
+//
+// XXX: no rhai or lua, neither's in the offline crate set; an interpreter
+// would be one more ScriptHooks, installed in place of the rules
+pub const SCRIPT_DIR:  &str = "scripts";
+pub const SCRIPT_FILE: &str = "scripts/hooks.txt";
+
+// the sprites a script may show, and only those
+pub const SCRIPT_SPRITE_SLOT: usize = 64;
+pub const SCRIPT_SPRITE_MAX:  usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Collision {
+    // an enemy went under the hammer
+    Smashed,
+    // an enemy got the player
+    Touched,
+}
+
+// everything a script can do to the game; the calls are queued while a
+// hook runs and made once it's returned
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScriptCall {
+    Sprite { index: usize, tile: u16, x: u16, y: u16, palette: u8 },
+    Hide(usize),
+    Sound(SoundId),
+    // onto the current player's score, when the game's one that counts
+    Points(u32),
+}
+
+// the hook points; each does nothing unless a script says otherwise
+pub trait ScriptHooks {
+    fn state_enter(&mut self, _state: GameState, _calls: &mut Vec<ScriptCall>) {}
+    fn state_leave(&mut self, _state: GameState, _calls: &mut Vec<ScriptCall>) {}
+    fn enemy_spawned(&mut self, _kind: EnemyKind, _calls: &mut Vec<ScriptCall>) {}
+    fn collided(&mut self, _collision: Collision, _calls: &mut Vec<ScriptCall>) {}
+}
+
+pub struct NoScripts;
+
+impl ScriptHooks for NoScripts {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Hook {
+    Enter(GameState),
+    Leave(GameState),
+    Spawn(EnemyKind),
+    Collided(Collision),
+}
+
+// a hooks.txt, its calls made in file order whenever their hook comes up
+struct Rules {
+    rules: Vec<(Hook, ScriptCall)>,
+}
+
+impl Rules {
+    fn fire(self:&Rules, hook: Hook, calls: &mut Vec<ScriptCall>) {
+        calls.extend(self.rules.iter().filter(|&&(h, _)| h == hook).map(|&(_, call)| call));
+    }
+}
+
+impl ScriptHooks for Rules {
+    fn state_enter(&mut self, state: GameState, calls: &mut Vec<ScriptCall>) {
+        self.fire(Hook::Enter(state), calls);
+    }
+
+    fn state_leave(&mut self, state: GameState, calls: &mut Vec<ScriptCall>) {
+        self.fire(Hook::Leave(state), calls);
+    }
+
+    fn enemy_spawned(&mut self, kind: EnemyKind, calls: &mut Vec<ScriptCall>) {
+        self.fire(Hook::Spawn(kind), calls);
+    }
+
+    fn collided(&mut self, collision: Collision, calls: &mut Vec<ScriptCall>) {
+        self.fire(Hook::Collided(collision), calls);
+    }
+}
+
+thread_local!(
+    static HOOKS:RefCell<Box<dyn ScriptHooks>> = RefCell::new(Box::new(NoScripts));
+);
+
+const ENEMY_KINDS: [EnemyKind; 7] = [
+    EnemyKind::Barrel,
+    EnemyKind::Fireball,
+    EnemyKind::Pie,
+    EnemyKind::Spring,
+    EnemyKind::Snapjaw,
+    EnemyKind::Nitpicker,
+    EnemyKind::Spark,
+];
+
+fn scripting_number<T: ::std::str::FromStr>(word: &str) -> Result<T, String> {
+    word.parse::<T>().map_err(|_| format!("expected a number, got \"{}\"", word))
+}
+
+// the hook's words off the front of the line, and how many there were
+fn scripting_parse_hook(words: &[&str]) -> Result<(Hook, usize), String> {
+    let named = || words.get(1).ok_or_else(|| format!("{} needs to know what for", words[0]));
+    let hook =
+        match words[0] {
+            "enter" => (Hook::Enter(named()?.parse()?), 2),
+            "leave" => (Hook::Leave(named()?.parse()?), 2),
+            "spawn" => {
+                let name = named()?;
+                match ENEMY_KINDS.iter().find(|kind| kind.name() == *name) {
+                    Some(kind) => (Hook::Spawn(*kind), 2),
+                    None => return Err(format!("unknown enemy \"{}\"", name)),
+                }
+            },
+            "smash" => (Hook::Collided(Collision::Smashed), 1),
+            "touch" => (Hook::Collided(Collision::Touched), 1),
+            _ => return Err(format!("unknown hook \"{}\"", words[0])),
+        };
+    return Ok(hook);
+}
+
+fn scripting_parse_call(words: &[&str]) -> Result<ScriptCall, String> {
+    use super::sound::ALL_SOUNDS;
+
+    if words.is_empty() {
+        return Err("a hook with nothing to do".to_string());
+    }
+    let arguments = |count: usize| {
+        if words.len() == count + 1 {
+            Ok(())
+        } else {
+            Err(format!("{} takes {} arguments, got {}", words[0], count, words.len() - 1))
+        }
+    };
+    let sprite = |word: &str| {
+        let index = scripting_number(word)?;
+        if index < SCRIPT_SPRITE_MAX {
+            Ok(index)
+        } else {
+            Err(format!("scripts have sprites 0 to {}, there's no {}", SCRIPT_SPRITE_MAX - 1, index))
+        }
+    };
+
+    let call =
+        match words[0] {
+            "sprite" => {
+                arguments(5)?;
+                let tile =
+                    if words[2].starts_with("0x") {
+                        u16::from_str_radix(&words[2][2..], 16).map_err(|_| format!("expected a tile number, got \"{}\"", words[2]))?
+                    } else {
+                        scripting_number(words[2])?
+                    };
+                ScriptCall::Sprite {
+                    index: sprite(words[1])?,
+                    tile: tile,
+                    x: scripting_number(words[3])?,
+                    y: scripting_number(words[4])?,
+                    palette: scripting_number(words[5])?,
+                }
+            },
+            "hide" => {
+                arguments(1)?;
+                ScriptCall::Hide(sprite(words[1])?)
+            },
+            "sound" => {
+                arguments(1)?;
+                match ALL_SOUNDS.iter().find(|id| id.name() == words[1]) {
+                    Some(id) => ScriptCall::Sound(*id),
+                    None => return Err(format!("unknown sound \"{}\"", words[1])),
+                }
+            },
+            "points" => {
+                arguments(1)?;
+                ScriptCall::Points(scripting_number(words[1])?)
+            },
+            _ => return Err(format!("unknown call \"{}\"", words[0])),
+        };
+    return Ok(call);
+}
+
+// the rules in text and one line per problem, named after where it came from
+fn scripting_parse(source: &str, text: &str) -> (Rules, Vec<String>) {
+    let mut rules = Vec::new();
+    let mut problems = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let rule = scripting_parse_hook(&words).and_then(|(hook, used)| scripting_parse_call(&words[used..]).map(|call| (hook, call)));
+        match rule {
+            Ok(rule) => rules.push(rule),
+            Err(e) => problems.push(format!("{}:{}: {}", source, number + 1, e)),
+        }
+    }
+    return (Rules { rules: rules }, problems);
+}
+
+fn scripting_read(path: &Path) -> Option<(Rules, Vec<String>)> {
+    use super::assets::asset_open;
+
+    let mut text = String::new();
+    match asset_open(path).map(|mut file| file.read_to_string(&mut text)) {
+        Some(Ok(_)) => Some(scripting_parse(&path.display().to_string(), &text)),
+        Some(Err(e)) => Some((Rules { rules: Vec::new() }, vec![format!("can't read {}: {}", path.display(), e)])),
+        None => None,
+    }
+}
+
+// every problem with a hooks file
+pub fn scripting_check(path: &Path) -> Vec<String> {
+    match scripting_read(path) {
+        Some((_, problems)) => problems,
+        None => vec![format!("can't open {}", path.display())],
+    }
+}
+
+// in place of whatever was hooked up before
+pub fn scripting_install(hooks: Box<dyn ScriptHooks>) {
+    HOOKS.with(|cell| {*cell.borrow_mut() = hooks;});
+}
+
+fn scripting_make(call: ScriptCall) {
+    use super::level::level_is_scoring;
+    use super::player::player_add_score;
+    use super::sound::sound_play;
+    use super::video::*;
+
+    match call {
+        ScriptCall::Sprite { index, tile, x, y, palette } => video_sprite_set(SCRIPT_SPRITE_SLOT + index, tile, x, y, palette),
+        ScriptCall::Hide(index) => video_sprite_hide(SCRIPT_SPRITE_SLOT + index),
+        ScriptCall::Sound(id) => sound_play(id),
+        ScriptCall::Points(points) => {
+            if level_is_scoring() {
+                player_add_score(points);
+            }
+        },
+    }
+}
+
+// the calls are made after the hooks are let go of, as they publish events
+// of their own
+fn scripting_hook<F: FnOnce(&mut dyn ScriptHooks, &mut Vec<ScriptCall>)>(hook: F) {
+    let mut calls = Vec::new();
+    HOOKS.with(|cell| hook(&mut **cell.borrow_mut(), &mut calls));
+    for call in calls {
+        scripting_make(call);
+    }
+}
+
+pub fn scripting_collided(collision: Collision) {
+    scripting_hook(|hooks, calls| hooks.collided(collision, calls));
+}
+
+fn scripting_on_event(event: &GameEvent) {
+    match event {
+        &GameEvent::StateChanged { from, to } => {
+            scripting_hook(|hooks, calls| {
+                hooks.state_leave(from, calls);
+                hooks.state_enter(to, calls);
+            });
+        },
+        &GameEvent::EnemySpawned { kind } => scripting_hook(|hooks, calls| hooks.enemy_spawned(kind, calls)),
+        _ => {},
+    }
+}
+
+// a file with anything wrong in it is passed over whole, the same as a
+// sequence; none at all is the usual case.  the headless modes never get
+// here, so a mod's script can't move a golden or a soak.
+//
+// XXX: a script awarding points makes a score replays won't reproduce
+pub fn scripting_init() {
+    use super::assets::asset_path;
+    use super::events::events_subscribe;
+
+    let path = asset_path(SCRIPT_FILE);
+    match scripting_read(&path) {
+        Some((rules, ref problems)) if problems.is_empty() => {
+            info!("{} script hooks loaded", rules.rules.len());
+            scripting_install(Box::new(rules));
+        },
+        Some((_, problems)) => {
+            for problem in problems.iter() {
+                warn!("{}", problem);
+            }
+            warn!("{} passed over, no script hooks", path.display());
+        },
+        None => {},
+    }
+    events_subscribe(scripting_on_event);
+}
@@ -0,0 +1,577 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::events::*;
+use super::hashing::StateHasher;
+use super::kong::*;
+use super::level::*;
+use super::savestate::SaveState;
+use super::sound::*;
+use super::state_machine::Easing;
+use super::state_machine::GameState;
+use super::state_machine::game_state_current;
+use super::state_machine::game_state_go;
+use super::video::*;
+
+// cutscenes as data: sequences/<name>.txt, one step per line
+pub const SEQUENCE_DIR: &str = "sequences";
+
+// the copies the game was built with, played when a file is missing or
+// has something wrong with it
+const BUILTIN: [(&str, &str); 2] = [
+    ("long_intro",      include_str!("../../../../assets/sequences/long_intro.txt")),
+    ("kong_retreats",   include_str!("../../../../assets/sequences/kong_retreats.txt")),
+];
+
+// a climbing move swaps kong's arms every CLIMB_STRIDE ticks
+const CLIMB_STRIDE: u32 = 8;
+
+// the events a sequence may publish; only the ones with nothing in them
+const EVENTS: [(&str, GameEvent); 3] = [
+    ("kong_stomped",    GameEvent::KongStomped),
+    ("enemy_smashed",   GameEvent::EnemySmashed),
+    ("player_died",     GameEvent::PlayerDied),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Actor {
+    Kong,
+    Pauline,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Pose {
+    Still(KongPose),
+    // hand over hand, the way he goes up a ladder
+    Climbing,
+}
+
+// stages are kept by name, they're looked up again when the step plays
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Step {
+    // blanks the background and hides every sprite
+    Clear,
+    Wait(u32),
+    Show { actor: Actor, pose: KongPose, x: i32, y: i32 },
+    // from one point to the other over ticks, straight unless eased
+    Move { actor: Actor, pose: Pose, from: (i32, i32), to: (i32, i32), ticks: u32, easing: Easing },
+    Hide(Actor),
+    // pauline rides along this far from kong's corner whenever he's drawn,
+    // until she's shown or hidden on her own
+    Carry { x: i32, y: i32 },
+    Sound(SoundId),
+    Music(MusicId),
+    Event(&'static str),
+    Tiles { col: u32, row: u32, width: u32, height: u32, tile: u16, palette: u8 },
+    EraseTiles { col: u32, row: u32, width: u32, height: u32 },
+    Girders { stage: &'static str, flat: bool },
+    // erased and drawn again, for bending one
+    Girder { stage: &'static str, index: usize, flat: bool },
+    Ladders { stage: &'static str },
+    Ladder { col: u32, top_row: u32, bottom_row: u32 },
+    EraseLadder { col: u32, top_row: u32, bottom_row: u32 },
+    NextStage,
+    Go(GameState),
+}
+
+impl Step {
+    // the ticks a step holds the sequence for; the others take none
+    fn ticks(&self) -> u32 {
+        match self {
+            &Step::Wait(ticks) => ticks,
+            &Step::Move { ticks, .. } => ticks,
+            _ => 0,
+        }
+    }
+}
+
+// one sequence playing for the state that started it; like its timers
+// it only moves on while that state updates, and goes when it's left
+#[derive(Clone, Hash)]
+struct Running {
+    owner: GameState,
+    name: &'static str,
+    steps: Vec<Step>,
+    // the step being played and how many ticks into it
+    step: usize,
+    ticks: u32,
+    carry: Option<(i32, i32)>,
+}
+
+thread_local!(
+    static SEQUENCES:RefCell<HashMap<&'static str, Vec<Step>>> = RefCell::new(HashMap::new());
+    static RUNNING:RefCell<Vec<Running>> = RefCell::new(Vec::new());
+);
+
+fn sequence_number<T: FromStr>(word: &str) -> Result<T, String> {
+    word.parse::<T>().map_err(|_| format!("expected a number, got \"{}\"", word))
+}
+
+fn sequence_flat(word: &str) -> Result<bool, String> {
+    match word {
+        "flat"      => Ok(true),
+        "sloped"    => Ok(false),
+        _           => Err(format!("expected flat or sloped, got \"{}\"", word)),
+    }
+}
+
+fn sequence_stage(word: &str) -> Result<&'static Stage, String> {
+    level_stage_named(word).ok_or_else(|| format!("unknown stage \"{}\"", word))
+}
+
+fn sequence_actor(word: &str) -> Result<Actor, String> {
+    match word {
+        "kong"      => Ok(Actor::Kong),
+        "pauline"   => Ok(Actor::Pauline),
+        _           => Err(format!("unknown actor \"{}\", expected kong or pauline", word)),
+    }
+}
+
+fn sequence_pose(word: &str) -> Result<KongPose, String> {
+    KongPose::from_name(word).ok_or_else(|| format!("unknown pose \"{}\"", word))
+}
+
+// kong's lines have a pose after the actor, pauline's don't
+fn sequence_parse_step(words: &[&str]) -> Result<Step, String> {
+    let arguments = |count: usize| {
+        if words.len() == count + 1 {
+            Ok(())
+        } else {
+            Err(format!("{} takes {} arguments, got {}", words[0], count, words.len() - 1))
+        }
+    };
+    let kong = words.get(1) == Some(&"kong");
+
+    let step =
+        match words[0] {
+            "clear" => {
+                arguments(0)?;
+                Step::Clear
+            },
+            "wait" => {
+                arguments(1)?;
+                Step::Wait(sequence_number(words[1])?)
+            },
+            "show" if kong => {
+                arguments(4)?;
+                Step::Show { actor: Actor::Kong, pose: sequence_pose(words[2])?, x: sequence_number(words[3])?, y: sequence_number(words[4])? }
+            },
+            "show" => {
+                arguments(3)?;
+                Step::Show { actor: sequence_actor(words[1])?, pose: KongPose::Standing, x: sequence_number(words[2])?, y: sequence_number(words[3])? }
+            },
+            "move" => {
+                // an easing may follow the ticks
+                let eased = |count: usize| if words.len() == count + 2 { Ok(()) } else { arguments(count) };
+                let (pose, rest) =
+                    if kong {
+                        eased(7)?;
+                        let pose = if words[2] == "climbing" { Pose::Climbing } else { Pose::Still(sequence_pose(words[2])?) };
+                        (pose, &words[3..])
+                    } else {
+                        eased(6)?;
+                        sequence_actor(words[1])?;
+                        (Pose::Still(KongPose::Standing), &words[2..])
+                    };
+                let ticks = sequence_number(rest[4])?;
+                if ticks == 0 {
+                    return Err("a move takes at least a tick".to_string());
+                }
+                let easing =
+                    match rest.get(5) {
+                        Some(word) => Easing::from_name(word).ok_or_else(|| format!("unknown easing \"{}\"", word))?,
+                        None => Easing::Linear,
+                    };
+                Step::Move {
+                    actor: sequence_actor(words[1])?,
+                    pose: pose,
+                    from: (sequence_number(rest[0])?, sequence_number(rest[1])?),
+                    to: (sequence_number(rest[2])?, sequence_number(rest[3])?),
+                    ticks: ticks,
+                    easing: easing,
+                }
+            },
+            "hide" => {
+                arguments(1)?;
+                Step::Hide(sequence_actor(words[1])?)
+            },
+            "carry" => {
+                arguments(2)?;
+                Step::Carry { x: sequence_number(words[1])?, y: sequence_number(words[2])? }
+            },
+            "sound" => {
+                arguments(1)?;
+                match ALL_SOUNDS.iter().find(|id| id.name() == words[1]) {
+                    Some(id) => Step::Sound(*id),
+                    None => return Err(format!("unknown sound \"{}\"", words[1])),
+                }
+            },
+            "music" => {
+                arguments(1)?;
+                match ALL_MUSIC.iter().find(|id| id.name() == words[1]) {
+                    Some(id) => Step::Music(*id),
+                    None => return Err(format!("unknown music \"{}\"", words[1])),
+                }
+            },
+            "event" => {
+                arguments(1)?;
+                match EVENTS.iter().find(|&&(name, _)| name == words[1]) {
+                    Some(&(name, _)) => Step::Event(name),
+                    None => return Err(format!("unknown event \"{}\"", words[1])),
+                }
+            },
+            "tiles" => {
+                arguments(6)?;
+                let tile =
+                    if words[5].starts_with("0x") {
+                        u16::from_str_radix(&words[5][2..], 16).map_err(|_| format!("expected a tile number, got \"{}\"", words[5]))?
+                    } else {
+                        sequence_number(words[5])?
+                    };
+                Step::Tiles {
+                    col: sequence_number(words[1])?,
+                    row: sequence_number(words[2])?,
+                    width: sequence_number(words[3])?,
+                    height: sequence_number(words[4])?,
+                    tile: tile,
+                    palette: sequence_number(words[6])?,
+                }
+            },
+            "erase_tiles" => {
+                arguments(4)?;
+                Step::EraseTiles {
+                    col: sequence_number(words[1])?,
+                    row: sequence_number(words[2])?,
+                    width: sequence_number(words[3])?,
+                    height: sequence_number(words[4])?,
+                }
+            },
+            "girders" => {
+                arguments(2)?;
+                Step::Girders { stage: sequence_stage(words[1])?.name, flat: sequence_flat(words[2])? }
+            },
+            "girder" => {
+                arguments(3)?;
+                let stage = sequence_stage(words[1])?;
+                let index = sequence_number(words[2])?;
+                if index >= stage.layout.girders.len() {
+                    return Err(format!("{} has {} girders, there's no {}", stage.name, stage.layout.girders.len(), index));
+                }
+                Step::Girder { stage: stage.name, index: index, flat: sequence_flat(words[3])? }
+            },
+            "ladders" => {
+                arguments(1)?;
+                Step::Ladders { stage: sequence_stage(words[1])?.name }
+            },
+            "ladder" | "erase_ladder" => {
+                arguments(3)?;
+                let (col, top_row, bottom_row) = (sequence_number(words[1])?, sequence_number(words[2])?, sequence_number(words[3])?);
+                if top_row > bottom_row {
+                    return Err(format!("{}'s top row is under its bottom one", words[0]));
+                }
+                if words[0] == "ladder" {
+                    Step::Ladder { col: col, top_row: top_row, bottom_row: bottom_row }
+                } else {
+                    Step::EraseLadder { col: col, top_row: top_row, bottom_row: bottom_row }
+                }
+            },
+            "next_stage" => {
+                arguments(0)?;
+                Step::NextStage
+            },
+            "go" => {
+                arguments(1)?;
+                Step::Go(words[1].parse()?)
+            },
+            _ => return Err(format!("unknown step \"{}\"", words[0])),
+        };
+    return Ok(step);
+}
+
+// the steps in text and one line per problem, named after where it came from
+fn sequence_parse(source: &str, text: &str) -> (Vec<Step>, Vec<String>) {
+    let mut steps = Vec::new();
+    let mut problems = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match sequence_parse_step(&words) {
+            Ok(step) => steps.push(step),
+            Err(e) => problems.push(format!("{}:{}: {}", source, number + 1, e)),
+        }
+    }
+    return (steps, problems);
+}
+
+fn sequence_read(path: &Path) -> Option<(Vec<Step>, Vec<String>)> {
+    use super::assets::asset_open;
+
+    let mut text = String::new();
+    match asset_open(path).map(|mut file| file.read_to_string(&mut text)) {
+        Some(Ok(_)) => Some(sequence_parse(&path.display().to_string(), &text)),
+        Some(Err(e)) => Some((Vec::new(), vec![format!("can't read {}: {}", path.display(), e)])),
+        None => None,
+    }
+}
+
+// every problem with a sequence file
+pub fn sequence_check(path: &Path) -> Vec<String> {
+    match sequence_read(path) {
+        Some((_, problems)) => problems,
+        None => vec![format!("can't open {}", path.display())],
+    }
+}
+
+// the names of the sequences the states play
+pub fn sequence_names() -> Vec<&'static str> {
+    BUILTIN.iter().map(|&(name, _)| name).collect()
+}
+
+// (sequence, state) for every go step in the loaded sequences, by name
+pub fn sequence_gos() -> Vec<(&'static str, GameState)> {
+    let mut gos: Vec<(&'static str, GameState)> = SEQUENCES.with(|cell| {
+        cell.borrow()
+            .iter()
+            .flat_map(|(&name, steps)| {
+                steps.iter().filter_map(move |step| match step {
+                    &Step::Go(state) => Some((name, state)),
+                    _ => None,
+                })
+            })
+            .collect()
+    });
+    gos.sort_by_key(|&(name, _)| name);
+    return gos;
+}
+
+// a file with anything wrong in it is passed over whole; half a cutscene
+// would leave its state stuck
+pub fn sequence_init() {
+    use super::assets::asset_path;
+
+    let mut sequences = HashMap::new();
+    for &(name, builtin) in BUILTIN.iter() {
+        let path = asset_path(&format!("{}/{}.txt", SEQUENCE_DIR, name));
+        let steps =
+            match sequence_read(&path) {
+                Some((steps, ref problems)) if problems.is_empty() => steps,
+                Some((_, problems)) => {
+                    for problem in problems.iter() {
+                        warn!("{}", problem);
+                    }
+                    warn!("using the built-in {} sequence", name);
+                    sequence_parse(name, builtin).0
+                },
+                None => {
+                    warn!("can't open {}, using the built-in {} sequence", path.display(), name);
+                    sequence_parse(name, builtin).0
+                },
+            };
+        sequences.insert(name, steps);
+    }
+    info!("{} sequences loaded", sequences.len());
+    SEQUENCES.with(|cell| {*cell.borrow_mut() = sequences;});
+}
+
+fn sequence_draw(actor: Actor, pose: KongPose, x: i32, y: i32, carry: Option<(i32, i32)>) {
+    match actor {
+        Actor::Kong => {
+            kong_draw(x, y, pose);
+            if let Some((dx, dy)) = carry {
+                pauline_draw(x + dx, y + dy);
+            }
+        },
+        Actor::Pauline => pauline_draw(x, y),
+    }
+}
+
+// ticks is how far into a timed step this is
+fn sequence_play(step: &Step, ticks: u32, carry: Option<(i32, i32)>) {
+    let layout = |stage: &str| level_stage_named(stage).map(|stage| stage.layout);
+    match *step {
+        Step::Clear => {
+            video_bg_clear();
+            video_sprite_hide_all();
+        },
+        Step::Wait(_) | Step::Carry { .. } => {},
+        Step::Show { actor, pose, x, y } => sequence_draw(actor, pose, x, y, carry),
+        Step::Move { actor, pose, from, to, ticks: duration, easing } => {
+            let x = easing.value(from.0, to.0, ticks, duration);
+            let y = easing.value(from.1, to.1, ticks, duration);
+            let pose =
+                match pose {
+                    Pose::Still(pose) => pose,
+                    Pose::Climbing if (ticks / CLIMB_STRIDE) % 2 == 0 => KongPose::ClimbLeft,
+                    Pose::Climbing => KongPose::ClimbRight,
+                };
+            sequence_draw(actor, pose, x, y, carry);
+        },
+        Step::Hide(Actor::Kong) => kong_hide(),
+        Step::Hide(Actor::Pauline) => pauline_hide(),
+        Step::Sound(id) => sound_play(id),
+        Step::Music(id) => music_play(id),
+        Step::Event(name) => {
+            if let Some(&(_, event)) = EVENTS.iter().find(|&&(known, _)| known == name) {
+                events_publish(event);
+            }
+        },
+        Step::Tiles { col, row, width, height, tile, palette } => {
+            for row in row..row + height {
+                for col in col..col + width {
+                    video_bg_put(col, row, tile, palette);
+                }
+            }
+        },
+        Step::EraseTiles { col, row, width, height } => {
+            for row in row..row + height {
+                for col in col..col + width {
+                    video_bg_erase(col, row);
+                }
+            }
+        },
+        Step::Girders { stage, flat } => {
+            for girder in layout(stage).map_or(&[][..], |layout| layout.girders).iter() {
+                layout_draw_girder(girder, flat);
+            }
+        },
+        Step::Girder { stage, index, flat } => {
+            if let Some(girder) = layout(stage).and_then(|layout| layout.girders.get(index)) {
+                layout_erase_girder(girder);
+                layout_draw_girder(girder, flat);
+            }
+        },
+        Step::Ladders { stage } => {
+            for ladder in layout(stage).map_or(&[][..], |layout| layout.ladders).iter() {
+                layout_draw_ladder(ladder);
+            }
+        },
+        Step::Ladder { col, top_row, bottom_row } => layout_draw_ladder(&LadderSpan { col: col, top_row: top_row, bottom_row: bottom_row }),
+        Step::EraseLadder { col, top_row, bottom_row } => layout_erase_ladder(&LadderSpan { col: col, top_row: top_row, bottom_row: bottom_row }),
+        Step::NextStage => level_advance(),
+        Step::Go(state) => game_state_go(state),
+    }
+}
+
+// plays owner's steps up to the next timed one, then a tick of that when
+// ticking; a sequence that runs out is dropped
+fn sequence_run(owner: GameState, ticking: bool) {
+    loop {
+        let next = RUNNING.with(|cell| {
+            let mut running = cell.borrow_mut();
+            let index = running.iter().position(|sequence| sequence.owner == owner)?;
+            let step =
+                match running[index].steps.get(running[index].step) {
+                    Some(step) => step.clone(),
+                    None => {
+                        debug!("sequence {} finished", running[index].name);
+                        running.remove(index);
+                        return None;
+                    },
+                };
+
+            let sequence = &mut running[index];
+            let ticks = sequence.ticks;
+            let duration = step.ticks();
+            if duration > 0 && !ticking {
+                return None;
+            }
+            sequence.ticks += 1;
+            if sequence.ticks >= duration {
+                sequence.step += 1;
+                sequence.ticks = 0;
+            }
+            match step {
+                Step::Carry { x, y } => sequence.carry = Some((x, y)),
+                Step::Show { actor: Actor::Pauline, .. } | Step::Hide(Actor::Pauline) => sequence.carry = None,
+                _ => {},
+            }
+            Some((step, ticks, sequence.carry, duration > 0))
+        });
+
+        match next {
+            Some((step, ticks, carry, timed)) => {
+                sequence_play(&step, ticks, carry);
+                if timed {
+                    return;
+                }
+            },
+            None => return,
+        }
+    }
+}
+
+// starts a sequence for the current state; called from its enter, the
+// steps before the first wait or move play straight away
+pub fn sequence_start(name: &str) {
+    let found = SEQUENCES.with(|cell| {
+        cell.borrow()
+            .iter()
+            .find(|&(&known, _)| known == name)
+            .map(|(&known, steps)| (known, steps.clone()))
+    });
+    let (name, steps) =
+        match found {
+            Some(found) => found,
+            None => {
+                warn!("no sequence called {}", name);
+                return;
+            },
+        };
+
+    let owner = game_state_current();
+    let sequence = Running {
+        owner: owner,
+        name: name,
+        steps: steps,
+        step: 0,
+        ticks: 0,
+        carry: None,
+    };
+    RUNNING.with(|cell| {
+        let mut running = cell.borrow_mut();
+        running.retain(|sequence| sequence.owner != owner);
+        running.push(sequence);
+    });
+    sequence_run(owner, false);
+}
+
+// called by the machine after the current state's update
+pub fn sequence_update(state: GameState) {
+    sequence_run(state, true);
+}
+
+pub fn sequence_cancel(state: GameState) {
+    RUNNING.with(|cell| cell.borrow_mut().retain(|sequence| sequence.owner != state));
+}
+
+pub fn sequence_hash(hasher: &mut StateHasher) {
+    RUNNING.with(|cell| cell.borrow().hash(hasher));
+}
+
+pub fn sequence_save(savestate: &mut SaveState) {
+    savestate.put("sequences", RUNNING.with(|cell| cell.borrow().clone()));
+}
+
+pub fn sequence_restore(savestate: &SaveState) {
+    if let Some(running) = savestate.get::<Vec<Running>>("sequences") {
+        RUNNING.with(|cell| {*cell.borrow_mut() = running;});
+    }
+}
@@ -0,0 +1,717 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+
+use super::video::PaletteSet;
+use super::video::Vsync;
+use super::video::WindowMode;
+use super::video::FRAMEBUFFER_SCALE_MAX;
+use super::locale::Language;
+use super::physics::PhysicsPreset;
+use super::level::Region;
+use super::clock::FAST_FORWARD_MAX;
+use super::persist::PersistJob;
+use super::persist::persist_run;
+
+pub const SETTINGS_FILE: &str = "settings.cfg";
+
+pub const GAME_SPEED_MIN:      u8 = 50;
+pub const GAME_SPEED_STANDARD: u8 = 100;
+
+// longest the audio can be held back to meet the picture
+pub const AUDIO_LATENCY_MAX: u32 = 500;
+
+// playfield pixels cropped off every edge at most
+pub const OVERSCAN_MAX: u32 = 16;
+
+pub const WATCHDOG_TICKS_DEFAULT: u32 = 30 * 60;
+pub const BOOT_TICKS_DEFAULT:     u32 = 2 * 60;
+// a minute on the main menu without touching anything
+pub const MENU_IDLE_TICKS_DEFAULT: u32 = 60 * 60;
+// more presses of one button than a hand manages in a second is a turbo
+// controller
+pub const TURBO_MAX_PRESSES_DEFAULT:  u32 = 15;
+pub const TURBO_WINDOW_TICKS_DEFAULT: u32 = 60;
+// title, high scores, demo, credits
+pub const ATTRACT_TICKS_DEFAULT: [u32; 4] = [5 * 60, 5 * 60, 15 * 60, 4 * 60];
+// 600hz
+pub const FAST_FORWARD_SPEED_DEFAULT: u32 = 10;
+// what --dev's rewind snapshots may take up between them
+pub const REWIND_BUDGET_KB_DEFAULT: u32 = 512;
+
+#[derive(Clone)]
+pub struct Settings {
+    pub palette_set: PaletteSet,
+    // percentage of logic ticks run per rendered frame; accessibility only,
+    // the internal difficulty progression is untouched
+    pub game_speed: u8,
+    pub narration: bool,
+    pub language: Language,
+    // enabled mod directories under mods/, later entries win
+    pub mods: Vec<String>,
+    // where the main menu's community page gets its list of packs from,
+    // empty for none
+    pub community_index: String,
+    pub speedrun_timer: bool,
+    // ticks a transient state may run before the watchdog complains; 0 is off
+    pub watchdog_ticks: u32,
+    // on a watchdog trip, force the machine back to attract
+    pub watchdog_recover: bool,
+    // how long the self-test screen stays up
+    pub boot_ticks: u32,
+    // ticks per attract screen, in rotation order; 0 drops a screen
+    pub attract_ticks: [u32; 4],
+    // ticks the main menu & settings sit without input before going back
+    // to attract; 0 is off
+    pub menu_idle_ticks: u32,
+    // cabinet behaviour: boot into attract and start straight from there,
+    // with no main menu
+    pub arcade: bool,
+    // which set of movement constants from physics.txt is used
+    pub physics: PhysicsPreset,
+    // which cabinet's board order & text the donkey kong campaign follows
+    pub region: Region,
+    // the harder take on the donkey kong stages: extra fireballs, a faster
+    // conveyor, a second spring dropper
+    pub remix: bool,
+    // drop sprite lines past the arcade's per-scanline limit, flickering
+    // like the cabinet does when things bunch up
+    pub sprite_flicker: bool,
+    // playfield pixels cropped off each edge when presenting, for CRTs &
+    // capture cards that eat the border
+    pub overscan: u32,
+    // framebuffer pixels per playfield pixel; more gives the rotation &
+    // shaders something finer to work from, logic never knows.  read when
+    // the window opens
+    pub internal_scale: u32,
+    // read when the window opens
+    pub vsync: Vsync,
+    // which display the window opens on, from 0, and whether it covers it;
+    // --display overrides the display
+    pub display: u32,
+    pub window: WindowMode,
+    // sleep after each frame until the next tick is due, so frames go out
+    // one per tick even without vsync
+    pub frame_pacing: bool,
+    // render at a few fps with the window in the background and at 30 in
+    // attract; the logic runs at its rate regardless.  _mute quiets the
+    // background too
+    pub energy_saver: bool,
+    pub energy_saver_mute: bool,
+    // append every gameplay event to event_logs/, one jsonl file a session
+    pub event_log: bool,
+    // outline the title-safe area over the picture
+    pub safe_area_guide: bool,
+    // the game window at exactly capture_height lines of 16:9, borderless,
+    // the picture at a whole multiple and no overscan crop or guide, for
+    // window capture in streaming software; capture_key toggles it
+    pub capture: bool,
+    pub capture_key: String,
+    pub capture_height: u32,
+    // the pads the two players claimed on the controllers page, see
+    // input::INPUT_PAD_KEYBOARD; player1_pad & player2_pad in the file
+    pub player_pads: [String; 2],
+    // ms to hold sounds back after the frame they belong to is shown; the
+    // display's latency minus the audio output's, when that's positive
+    pub audio_latency_ms: u32,
+    // crawled along the bottom of the attract screens; empty is off
+    pub attract_marquee: String,
+    // the operator reset back to boot, an SDL key name
+    pub reset_key: String,
+    // voids the game in progress and drops back to attract with the
+    // buzzer, for a cabinet being rocked or fed bad coins; an SDL key name
+    pub tilt_key: String,
+    // held down, the logic runs fast_forward_speed ticks to every one, for
+    // getting through a soak or a bot run; an SDL key name
+    pub fast_forward_key: String,
+    pub fast_forward_speed: u32,
+    // whether the reset also zeroes the scores of the game it interrupts
+    pub reset_clears_scores: bool,
+    // a recorded run with more than turbo_max_presses of one button inside
+    // any turbo_window_ticks is flagged before its score is posted
+    pub turbo_max_presses: u32,
+    pub turbo_window_ticks: u32,
+    // the latched inputs in the bottom right corner, toggled with F4 too
+    pub input_display: bool,
+    // pad rumble on deaths, smashes & kong's stomps, and how hard in percent
+    pub rumble: bool,
+    pub rumble_strength: u32,
+    // what's going on after the name in the window title
+    pub title_status: bool,
+    // the discord application rich presence goes to, in builds with the
+    // discord feature; empty is off
+    pub discord_client_id: String,
+    // the cocktail table dip switch: player 2's turns are shown flipped
+    pub cocktail: bool,
+    // --dev's rewind drops its oldest snapshots to stay under this
+    pub rewind_budget_kb: u32,
+}
+
+thread_local!(
+    static SETTINGS:RefCell<Settings> = RefCell::new(Settings {
+        palette_set: PaletteSet::Arcade,
+        game_speed:  GAME_SPEED_STANDARD,
+        narration:   false,
+        language:    Language::English,
+        mods:        Vec::new(),
+        community_index: String::new(),
+        speedrun_timer: false,
+        watchdog_ticks: WATCHDOG_TICKS_DEFAULT,
+        watchdog_recover: false,
+        boot_ticks: BOOT_TICKS_DEFAULT,
+        attract_ticks: ATTRACT_TICKS_DEFAULT,
+        menu_idle_ticks: MENU_IDLE_TICKS_DEFAULT,
+        arcade: false,
+        physics: PhysicsPreset::Arcade,
+        region: Region::Us,
+        remix: false,
+        sprite_flicker: false,
+        overscan: 0,
+        internal_scale: 1,
+        vsync: Vsync::On,
+        display: 0,
+        window: WindowMode::Windowed,
+        frame_pacing: false,
+        energy_saver: false,
+        energy_saver_mute: false,
+        event_log: false,
+        safe_area_guide: false,
+        capture: false,
+        capture_key: "F9".to_string(),
+        capture_height: 1080,
+        player_pads: [String::new(), String::new()],
+        audio_latency_ms: 0,
+        attract_marquee: String::new(),
+        reset_key: "F3".to_string(),
+        tilt_key: "F2".to_string(),
+        fast_forward_key: "F8".to_string(),
+        fast_forward_speed: FAST_FORWARD_SPEED_DEFAULT,
+        reset_clears_scores: true,
+        turbo_max_presses: TURBO_MAX_PRESSES_DEFAULT,
+        turbo_window_ticks: TURBO_WINDOW_TICKS_DEFAULT,
+        input_display: false,
+        rumble: true,
+        title_status: false,
+        discord_client_id: String::new(),
+        rumble_strength: 100,
+        cocktail: false,
+        rewind_budget_kb: REWIND_BUDGET_KB_DEFAULT,
+    });
+    // nothing is written to SETTINGS_FILE while held
+    static HELD:RefCell<bool> = RefCell::new(false);
+);
+
+pub fn settings_get() -> Settings {
+    SETTINGS.with(|cell| cell.borrow().clone())
+}
+
+// for per-frame lookups that shouldn't clone the whole struct
+pub fn settings_read<R, F: FnOnce(&Settings) -> R>(f: F) -> R {
+    SETTINGS.with(|cell| f(&cell.borrow()))
+}
+
+pub fn settings_set(settings: Settings) {
+    SETTINGS.with(|cell| {*cell.borrow_mut() = settings;});
+}
+
+// for playing a session back: its trips through the menu change the live
+// settings, and they aren't the player's to keep
+pub fn settings_hold(held: bool) {
+    HELD.with(|cell| {*cell.borrow_mut() = held;});
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "on"  | "true"  | "yes" => Ok(true),
+        "off" | "false" | "no"  => Ok(false),
+        _ => Err(format!("{} expects on or off, got \"{}\"", key, value)),
+    }
+}
+
+fn settings_apply(settings: &mut Settings, key: &str, value: &str) -> Result<(), String> {
+    match key {
+        "palette_set" => {
+            settings.palette_set = match value {
+                "arcade"        => PaletteSet::Arcade,
+                "deuteranopia"  => PaletteSet::Deuteranopia,
+                "protanopia"    => PaletteSet::Protanopia,
+                "tritanopia"    => PaletteSet::Tritanopia,
+                _               => return Err(format!("unknown palette set \"{}\"", value)),
+            };
+        },
+        "game_speed" => {
+            let speed =
+                match value.parse::<u8>() {
+                    Ok(n) => n,
+                    Err(_) => return Err(format!("game_speed expects a percentage, got \"{}\"", value)),
+                };
+            if speed < GAME_SPEED_MIN || speed > GAME_SPEED_STANDARD {
+                return Err(format!(
+                    "game_speed must be between {} and {}",
+                    GAME_SPEED_MIN,
+                    GAME_SPEED_STANDARD));
+            }
+            settings.game_speed = speed;
+        },
+        "narration" => {
+            settings.narration = parse_bool(key, value)?;
+        },
+        "language" => {
+            settings.language =
+                match Language::from_code(value) {
+                    Some(l) => l,
+                    None => return Err(format!("unknown language \"{}\"", value)),
+                };
+        },
+        "speedrun_timer" => {
+            settings.speedrun_timer = parse_bool(key, value)?;
+        },
+        "watchdog_ticks" => {
+            settings.watchdog_ticks =
+                match value.parse::<u32>() {
+                    Ok(n) => n,
+                    Err(_) => return Err(format!("watchdog_ticks expects a tick count, got \"{}\"", value)),
+                };
+        },
+        "menu_idle_ticks" => {
+            settings.menu_idle_ticks =
+                match value.parse::<u32>() {
+                    Ok(n) => n,
+                    Err(_) => return Err(format!("menu_idle_ticks expects a tick count, got \"{}\"", value)),
+                };
+        },
+        "boot_ticks" => {
+            settings.boot_ticks =
+                match value.parse::<u32>() {
+                    Ok(n) => n,
+                    Err(_) => return Err(format!("boot_ticks expects a tick count, got \"{}\"", value)),
+                };
+        },
+        "attract_ticks" => {
+            let ticks: Vec<u32> = value
+                .split(',')
+                .map(|n| n.trim().parse::<u32>())
+                .collect::<Result<Vec<u32>, _>>()
+                .map_err(|_| format!("attract_ticks expects tick counts, got \"{}\"", value))?;
+            if ticks.len() != settings.attract_ticks.len() {
+                return Err(format!(
+                    "attract_ticks expects {} tick counts (title, scores, demo, credits)",
+                    settings.attract_ticks.len()));
+            }
+            settings.attract_ticks.copy_from_slice(&ticks);
+        },
+        "arcade" => {
+            settings.arcade = parse_bool(key, value)?;
+        },
+        "physics" => {
+            settings.physics =
+                match PhysicsPreset::from_name(value) {
+                    Some(p) => p,
+                    None => return Err(format!("unknown physics preset \"{}\"", value)),
+                };
+        },
+        "region" => {
+            settings.region =
+                match Region::from_name(value) {
+                    Some(r) => r,
+                    None => return Err(format!("unknown region \"{}\", expected us or japan", value)),
+                };
+        },
+        "remix" => {
+            settings.remix = parse_bool(key, value)?;
+        },
+        "discord_client_id" => {
+            settings.discord_client_id = value.to_string();
+        },
+        "title_status" => {
+            settings.title_status = parse_bool(key, value)?;
+        },
+        "rumble" => {
+            settings.rumble = parse_bool(key, value)?;
+        },
+        "rumble_strength" => {
+            settings.rumble_strength =
+                match value.parse::<u32>() {
+                    Ok(n) if n <= 100 => n,
+                    _ => return Err(format!("rumble_strength expects a percentage, got \"{}\"", value)),
+                };
+        },
+        "input_display" => {
+            settings.input_display = parse_bool(key, value)?;
+        },
+        "sprite_flicker" => {
+            settings.sprite_flicker = parse_bool(key, value)?;
+        },
+        "overscan" => {
+            let overscan =
+                match value.parse::<u32>() {
+                    Ok(n) => n,
+                    Err(_) => return Err(format!("overscan expects a pixel count, got \"{}\"", value)),
+                };
+            if overscan > OVERSCAN_MAX {
+                return Err(format!("overscan must be {} or less", OVERSCAN_MAX));
+            }
+            settings.overscan = overscan;
+        },
+        "vsync" => {
+            settings.vsync =
+                match Vsync::from_name(value) {
+                    Some(v) => v,
+                    None => return Err(format!("unknown vsync \"{}\", expected on, off or adaptive", value)),
+                };
+        },
+        "display" => {
+            settings.display =
+                match value.parse::<u32>() {
+                    Ok(n) => n,
+                    Err(_) => return Err(format!("display expects a display number, got \"{}\"", value)),
+                };
+        },
+        "window" => {
+            settings.window =
+                match WindowMode::from_name(value) {
+                    Some(m) => m,
+                    None => return Err(format!("unknown window \"{}\", expected windowed or borderless", value)),
+                };
+        },
+        "frame_pacing" => {
+            settings.frame_pacing = parse_bool(key, value)?;
+        },
+        "energy_saver" => {
+            settings.energy_saver = parse_bool(key, value)?;
+        },
+        "energy_saver_mute" => {
+            settings.energy_saver_mute = parse_bool(key, value)?;
+        },
+        "event_log" => {
+            settings.event_log = parse_bool(key, value)?;
+        },
+        "internal_scale" => {
+            let scale =
+                match value.parse::<u32>() {
+                    Ok(n) => n,
+                    Err(_) => return Err(format!("internal_scale expects a whole number, got \"{}\"", value)),
+                };
+            if scale == 0 || scale > FRAMEBUFFER_SCALE_MAX {
+                return Err(format!("internal_scale must be 1 to {}", FRAMEBUFFER_SCALE_MAX));
+            }
+            settings.internal_scale = scale;
+        },
+        "audio_latency_ms" => {
+            let latency =
+                match value.parse::<u32>() {
+                    Ok(n) => n,
+                    Err(_) => return Err(format!("audio_latency_ms expects milliseconds, got \"{}\"", value)),
+                };
+            if latency > AUDIO_LATENCY_MAX {
+                return Err(format!("audio_latency_ms must be {} or less", AUDIO_LATENCY_MAX));
+            }
+            settings.audio_latency_ms = latency;
+        },
+        "safe_area_guide" => {
+            settings.safe_area_guide = parse_bool(key, value)?;
+        },
+        "capture" => {
+            settings.capture = parse_bool(key, value)?;
+        },
+        "capture_key" => {
+            settings.capture_key = value.to_string();
+        },
+        "capture_height" => {
+            settings.capture_height =
+                match value.parse::<u32>() {
+                    Ok(n) if n == 1080 || n == 1440 => n,
+                    _ => return Err(format!("capture_height expects 1080 or 1440, got \"{}\"", value)),
+                };
+        },
+        "player1_pad" => {
+            settings.player_pads[0] = value.to_string();
+        },
+        "player2_pad" => {
+            settings.player_pads[1] = value.to_string();
+        },
+        "watchdog_recover" => {
+            settings.watchdog_recover = parse_bool(key, value)?;
+        },
+        "attract_marquee" => {
+            settings.attract_marquee = value.to_string();
+        },
+        "fast_forward_key" => {
+            settings.fast_forward_key = value.to_string();
+        },
+        "fast_forward_speed" => {
+            settings.fast_forward_speed =
+                match value.parse::<u32>() {
+                    Ok(n) if n >= 1 && n <= FAST_FORWARD_MAX => n,
+                    _ => return Err(format!("fast_forward_speed expects 1 to {} ticks a tick, got \"{}\"", FAST_FORWARD_MAX, value)),
+                };
+        },
+        "reset_key" => {
+            settings.reset_key = value.to_string();
+        },
+        "tilt_key" => {
+            settings.tilt_key = value.to_string();
+        },
+        "cocktail" => {
+            settings.cocktail = parse_bool(key, value)?;
+        },
+        "reset_clears_scores" => {
+            settings.reset_clears_scores = parse_bool(key, value)?;
+        },
+        "turbo_max_presses" => {
+            settings.turbo_max_presses =
+                match value.parse::<u32>() {
+                    Ok(n) => n,
+                    Err(_) => return Err(format!("turbo_max_presses expects a press count, got \"{}\"", value)),
+                };
+        },
+        "rewind_budget_kb" => {
+            settings.rewind_budget_kb =
+                match value.parse::<u32>() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("rewind_budget_kb expects a size in kb above 0, got \"{}\"", value)),
+                };
+        },
+        "turbo_window_ticks" => {
+            settings.turbo_window_ticks =
+                match value.parse::<u32>() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("turbo_window_ticks expects a tick count above 0, got \"{}\"", value)),
+                };
+        },
+        "mods" => {
+            settings.mods = value
+                .split(',')
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+                .map(|name| name.to_string())
+                .collect();
+        },
+        "community_index" => {
+            settings.community_index = value.to_string();
+        },
+        _ => return Err(format!("unknown setting \"{}\"", key)),
+    }
+    return Ok(());
+}
+
+pub struct KeyValue {
+    pub line: usize,
+    pub key: String,
+    pub value: String,
+}
+
+// the shared "key = value" file format used by settings and locale files: one
+// pair per line, lines starting with '#' are comments.  returns None when the
+// file can't be opened; malformed lines are logged and skipped.
+pub fn read_key_value_file(path: &Path) -> Option<Vec<KeyValue>> {
+    use super::assets::asset_open;
+
+    let file =
+        match asset_open(path) {
+            Some(f) => f,
+            None => return None,
+        };
+    return Some(key_value_parse(BufReader::new(file), path));
+}
+
+// the same, from anything; path is only for the messages
+pub fn key_value_parse<R: BufRead>(source: R, path: &Path) -> Vec<KeyValue> {
+    let mut pairs = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let line =
+            match line {
+                Ok(l) => l,
+                Err(e) => {
+                    error!("can't read {}: {}", path.display(), e);
+                    break;
+                },
+            };
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next().unwrap().trim();
+        match parts.next() {
+            Some(value) => pairs.push(KeyValue {
+                line: number + 1,
+                key: key.to_string(),
+                value: value.trim().to_string()
+            }),
+            None => warn!("{}:{}: expected key = value", path.display(), number + 1),
+        }
+    }
+    return pairs;
+}
+
+// fuzz target: any bytes at all are a key = value file with or without
+// pairs in it
+pub fn key_value_fuzz(bytes: &[u8]) {
+    key_value_parse(bytes, Path::new("fuzz"));
+}
+
+pub fn settings_load(path: &Path) {
+    let pairs =
+        match read_key_value_file(path) {
+            Some(p) => p,
+            None => {
+                info!("no settings file at {}, using defaults", path.display());
+                return;
+            },
+        };
+
+    let mut settings = settings_get();
+    for pair in pairs.iter() {
+        if let Err(e) = settings_apply(&mut settings, &pair.key, &pair.value) {
+            warn!("{}:{}: {}", path.display(), pair.line, e);
+        }
+    }
+    settings_set(settings);
+}
+
+// rewrites the given keys in a settings file in place, leaving comments
+// and every other line alone; keys the file doesn't have yet are appended
+pub fn settings_save_keys(path: &Path, pairs: &[(&str, String)]) -> Result<(), String> {
+    use std::fs;
+
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let mut written = vec![false; pairs.len()];
+    let mut lines = Vec::new();
+    for line in existing.lines() {
+        let key = line.splitn(2, '=').next().unwrap_or("").trim();
+        match pairs.iter().position(|&(k, _)| k == key && !line.trim().starts_with('#')) {
+            Some(index) => {
+                lines.push(format!("{} = {}", pairs[index].0, pairs[index].1));
+                written[index] = true;
+            },
+            None => lines.push(line.to_string()),
+        }
+    }
+    for (index, &(key, ref value)) in pairs.iter().enumerate() {
+        if !written[index] {
+            lines.push(format!("{} = {}", key, value));
+        }
+    }
+
+    let mut file = File::create(path).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    for line in lines.iter() {
+        writeln!(file, "{}", line).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    }
+    return Ok(());
+}
+
+// everything the settings page changes is kept across runs
+pub fn settings_save_menu() {
+    if HELD.with(|cell| *cell.borrow()) {
+        return;
+    }
+    let settings = settings_get();
+    let pairs = [
+        ("language", settings.language.code().to_string()),
+        ("palette_set", settings.palette_set.name().to_string()),
+        ("game_speed", settings.game_speed.to_string()),
+        ("narration", on_off(settings.narration)),
+        ("speedrun_timer", on_off(settings.speedrun_timer)),
+        ("physics", settings.physics.name().to_string()),
+        ("remix", on_off(settings.remix)),
+        ("overscan", settings.overscan.to_string()),
+        ("safe_area_guide", on_off(settings.safe_area_guide)),
+        ("audio_latency_ms", settings.audio_latency_ms.to_string()),
+    ];
+    persist_run(PersistJob::Settings, move || {
+        settings_save_keys(Path::new(SETTINGS_FILE), &pairs).map(|_| format!("menu settings saved to {}", SETTINGS_FILE))
+    });
+}
+
+// the controllers page's claims, as soon as both players have one
+pub fn settings_save_pads() {
+    if HELD.with(|cell| *cell.borrow()) {
+        return;
+    }
+    let settings = settings_get();
+    let pairs = [
+        ("player1_pad", settings.player_pads[0].clone()),
+        ("player2_pad", settings.player_pads[1].clone()),
+    ];
+    persist_run(PersistJob::Settings, move || {
+        settings_save_keys(Path::new(SETTINGS_FILE), &pairs).map(|_| format!("pad claims saved to {}", SETTINGS_FILE))
+    });
+}
+
+// a community pack is enabled for good as soon as it's installed
+pub fn settings_save_mods() {
+    if HELD.with(|cell| *cell.borrow()) {
+        return;
+    }
+    let pairs = [("mods", settings_read(|s| s.mods.join(",")))];
+    persist_run(PersistJob::Settings, move || {
+        settings_save_keys(Path::new(SETTINGS_FILE), &pairs).map(|_| format!("mods saved to {}", SETTINGS_FILE))
+    });
+}
+
+// daily scores and personal bests only count at full speed
+pub fn settings_is_standard_speed() -> bool {
+    settings_read(|s| s.game_speed) == GAME_SPEED_STANDARD
+}
+
+fn on_off(value: bool) -> String {
+    (if value { "on" } else { "off" }).to_string()
+}
+
+// the settings the game logic reads, as they'd be written in settings.cfg;
+// two runs with these equal and the same input stream play out the same
+pub fn settings_logic_pairs() -> Vec<(&'static str, String)> {
+    let settings = settings_get();
+    let attract_ticks: Vec<String> = settings.attract_ticks.iter().map(|t| t.to_string()).collect();
+    vec![
+        ("language", settings.language.code().to_string()),
+        ("game_speed", settings.game_speed.to_string()),
+        ("physics", settings.physics.name().to_string()),
+        ("region", settings.region.name().to_string()),
+        ("remix", on_off(settings.remix)),
+        ("arcade", on_off(settings.arcade)),
+        ("cocktail", on_off(settings.cocktail)),
+        ("boot_ticks", settings.boot_ticks.to_string()),
+        ("attract_ticks", attract_ticks.join(",")),
+        ("attract_marquee", settings.attract_marquee.clone()),
+        ("menu_idle_ticks", settings.menu_idle_ticks.to_string()),
+        ("watchdog_ticks", settings.watchdog_ticks.to_string()),
+        ("watchdog_recover", on_off(settings.watchdog_recover)),
+        ("reset_clears_scores", on_off(settings.reset_clears_scores)),
+    ]
+}
+
+// applies key/value pairs over the live settings, stopping at the first bad one
+pub fn settings_apply_pairs(pairs: &[(String, String)]) -> Result<(), String> {
+    let mut settings = settings_get();
+    for &(ref key, ref value) in pairs.iter() {
+        settings_apply(&mut settings, key, value)?;
+    }
+    settings_set(settings);
+    return Ok(());
+}
+
+pub fn settings_init() {
+    settings_load(Path::new(SETTINGS_FILE));
+    if !settings_is_standard_speed() {
+        warn!("game speed is {}%, daily scores and personal bests won't be kept", settings_read(|s| s.game_speed));
+    }
+}
@@ -0,0 +1,243 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use super::clock::TICKS_PER_SECOND;
+use super::events::GameEvent;
+use super::state_machine::StateReport;
+
+// a state this long without a transition is stuck, whatever it is; the
+// watchdog only looks after the transient ones
+const SOAK_STUCK_TICKS: u32 = 10 * 60 * TICKS_PER_SECOND;
+// memory is sampled this often, and the first sample's taken this far in
+// so start-up's allocations aren't counted as growth
+const SOAK_SAMPLE_TICKS: u32 = 60 * TICKS_PER_SECOND;
+// more resident memory than this over the first sample is a leak
+const SOAK_GROWTH_MAX: usize = 32 * 1024 * 1024;
+// debug builds: more live heap than this over the second level loop's
+// start is a leak.  the first loop's loading is left out of it
+const SOAK_LOOP_GROWTH_MAX: usize = 1024 * 1024;
+// progress in the log every this many minutes of play
+const SOAK_PROGRESS_MINUTES: u32 = 10;
+
+#[derive(Default)]
+struct Tally {
+    games: u32,
+    stages: u32,
+    deaths: u32,
+}
+
+thread_local!(
+    static TALLY:RefCell<Tally> = RefCell::new(Tally::default());
+);
+
+fn soak_on_event(event: &GameEvent) {
+    TALLY.with(|cell| {
+        let mut tally = cell.borrow_mut();
+        match event {
+            &GameEvent::GameStarted => tally.games += 1,
+            &GameEvent::StageCleared { .. } => tally.stages += 1,
+            &GameEvent::PlayerDied => tally.deaths += 1,
+            _ => {},
+        }
+    });
+}
+
+// XXX: linux only, from /proc; elsewhere the growth check is skipped.
+//      assumes 4k pages
+fn soak_resident() -> Option<usize> {
+    use std::fs;
+
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+    return Some(pages * 4096);
+}
+
+struct Soak {
+    ticks: u32,
+    // the problems found, each with the tick it was noticed on
+    problems: Vec<String>,
+    states: BTreeSet<String>,
+    enemies_peak: usize,
+    // resident bytes at the first sample, the last and the most seen
+    memory: Option<(usize, usize, usize)>,
+    arena_buffers: (usize, usize),
+    // the level last seen, and the tagged heap's live bytes each time it
+    // changed, a game over's drop back to the first included
+    level: u32,
+    loops: Vec<usize>,
+}
+
+// one tick's checks: stuck states, enemies left over outside a game, the
+// arena pooling ever more buffers and the heap at each level loop
+fn soak_check(soak: &mut Soak, report: &StateReport) {
+    use super::arena::arena_stats;
+    use super::arena::memory_live;
+    use super::enemies::enemies_hitboxes;
+    use super::level::level_current;
+    use super::state_machine::game_state_in_game;
+
+    let tick = soak.ticks;
+    if report.transitioned {
+        soak.states.insert(report.to.to_string());
+    }
+    if report.ticks == SOAK_STUCK_TICKS {
+        soak.problems.push(format!("tick {}: stuck in {} for {} ticks", tick, report.to, SOAK_STUCK_TICKS));
+    }
+
+    let enemies = enemies_hitboxes().len();
+    soak.enemies_peak = soak.enemies_peak.max(enemies);
+    if enemies > 0 && !game_state_in_game() && report.transitioned {
+        soak.problems.push(format!("tick {}: {} enemies still about in {}", tick, enemies, report.to));
+    }
+
+    let level = level_current().number;
+    if level != soak.level {
+        soak.level = level;
+        soak.loops.push(memory_live());
+    }
+
+    if tick % SOAK_SAMPLE_TICKS == 0 {
+        let buffers = arena_stats().buffers;
+        if tick == SOAK_SAMPLE_TICKS {
+            soak.arena_buffers = (buffers, buffers);
+        } else {
+            soak.arena_buffers.1 = buffers;
+        }
+        if let Some(resident) = soak_resident() {
+            soak.memory =
+                match soak.memory {
+                    None => Some((resident, resident, resident)),
+                    Some((first, _, peak)) => Some((first, resident, peak.max(resident))),
+                };
+        }
+    }
+}
+
+fn soak_run(ticks: u32, seed: u32, progress: Arc<AtomicUsize>) -> Soak {
+    use super::arena::arena_frame_reset;
+    use super::bot::bot_update;
+    use super::events::events_subscribe;
+    use super::game_update;
+    use super::input::input_set;
+    use super::locale::locale_init;
+    use super::physics::physics_init;
+    use super::sequence::sequence_init;
+    use super::rng::rng_seed;
+    use super::state_machine::game_state_init;
+
+    locale_init();
+    physics_init();
+    sequence_init();
+    rng_seed(seed);
+    game_state_init();
+    events_subscribe(soak_on_event);
+
+    let mut soak = Soak {
+        ticks: 0,
+        problems: Vec::new(),
+        states: BTreeSet::new(),
+        enemies_peak: 0,
+        memory: None,
+        arena_buffers: (0, 0),
+        level: 0,
+        loops: Vec::new(),
+    };
+    let minute = 60 * TICKS_PER_SECOND;
+    while soak.ticks < ticks {
+        input_set(bot_update());
+        let report = game_update();
+        arena_frame_reset();
+        soak.ticks += 1;
+        progress.store(soak.ticks as usize, Ordering::Relaxed);
+        soak_check(&mut soak, &report);
+        if soak.ticks % (SOAK_PROGRESS_MINUTES * minute) == 0 {
+            info!("soak: {} minutes in, {} problems", soak.ticks / minute, soak.problems.len());
+        }
+    }
+    return soak;
+}
+
+// plays the bot for minutes of game time headless, as fast as it goes, on
+// its own thread so a panic is caught and reported with its tick; false
+// when anything turned up
+pub fn soak(minutes: u32, seed: u32) -> bool {
+    let ticks = minutes * 60 * TICKS_PER_SECOND;
+    let progress = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+    info!("soak: {} minutes of the bot, seed {:08x}", minutes, seed);
+
+    let run = {
+        let progress = progress.clone();
+        thread::spawn(move || {
+            let soak = soak_run(ticks, seed, progress);
+            let tally = TALLY.with(|cell| {
+                let tally = cell.borrow();
+                (tally.games, tally.stages, tally.deaths)
+            });
+            (soak, tally)
+        })
+    };
+    let (soak, (games, stages, deaths)) =
+        match run.join() {
+            Ok(result) => result,
+            Err(_) => {
+                error!("soak: panicked on tick {} of {} (seed {:08x})", progress.load(Ordering::Relaxed), ticks, seed);
+                return false;
+            },
+        };
+
+    let wall = started.elapsed();
+    let mut problems = soak.problems;
+    if let Some((first, _, peak)) = soak.memory {
+        if peak > first + SOAK_GROWTH_MAX {
+            problems.push(format!("resident memory grew {} bytes past the first minute's", peak - first));
+        }
+    }
+    // the tagging allocator's only in debug builds
+    if cfg!(debug_assertions) && soak.loops.len() > 2 {
+        let steady = soak.loops[1];
+        let peak = soak.loops.iter().skip(2).cloned().max().unwrap_or(steady);
+        if peak > steady + SOAK_LOOP_GROWTH_MAX {
+            problems.push(format!("the heap grew {} bytes over {} level loops", peak - steady, soak.loops.len() - 2));
+        }
+    }
+    if soak.arena_buffers.1 > soak.arena_buffers.0 * 2 + 16 {
+        problems.push(format!("the arena went from {} buffers to {}", soak.arena_buffers.0, soak.arena_buffers.1));
+    }
+
+    let faster = soak.ticks as u64 * 1000 / (wall.as_millis() as u64 * TICKS_PER_SECOND as u64).max(1);
+    info!("soak: {} ticks in {}s ({}x), seed {:08x}", soak.ticks, wall.as_secs(), faster, seed);
+    info!("soak: {} games, {} stages cleared, {} deaths; {} enemies at most", games, stages, deaths, soak.enemies_peak);
+    info!("soak: states visited: {}", soak.states.iter().cloned().collect::<Vec<String>>().join(", "));
+    match soak.memory {
+        Some((first, last, peak)) => info!("soak: resident {}k after a minute, {}k at the end, {}k at most", first / 1024, last / 1024, peak / 1024),
+        None => info!("soak: no resident memory figures on this platform"),
+    }
+    if cfg!(debug_assertions) {
+        let loops: Vec<String> = soak.loops.iter().map(|bytes| format!("{}k", bytes / 1024)).collect();
+        info!("soak: heap at each level loop: {}", loops.join(", "));
+    }
+    for problem in problems.iter() {
+        error!("soak: {}", problem);
+    }
+    if problems.is_empty() {
+        info!("soak: no problems");
+    }
+    return problems.is_empty();
+}
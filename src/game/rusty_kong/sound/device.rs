@@ -0,0 +1,342 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::sync::atomic::Ordering;
+
+use sdl2::Sdl;
+use sdl2::audio::AudioDevice;
+use sdl2::audio::AudioSpecDesired;
+use sdl2::audio::AudioStatus;
+use sdl2::audio::AudioSubsystem;
+
+use super::*;
+use super::mixer::*;
+use super::queue::CommandSender;
+use super::queue::command_queue;
+
+const DEVICE_FREQUENCY: i32 = 44_100;
+const DEVICE_CHANNELS:  u8 = 2;
+// frames a callback; small enough to keep up with audio_latency_ms = 0
+const DEVICE_SAMPLES:   u16 = 1024;
+// a few seconds of commands at any sane rate
+const DEVICE_QUEUE: usize = 256;
+// a callback's due every 23ms at these settings; this long without one and
+// the stream's taken as dead even if sdl hasn't said so
+const DEVICE_STALL_MILLIS: u64 = 1000;
+
+struct Open {
+    device: AudioDevice<Mixer>,
+    sender: CommandSender,
+    shared: Arc<MixerShared>,
+    // the callback count when it last moved, and the wall clock then
+    callbacks: usize,
+    heard: u64,
+}
+
+struct Device {
+    subsystem: Option<AudioSubsystem>,
+    open: Option<Open>,
+    // the track as far as the commands sent go, so a new device picks the
+    // music back up rather than coming up quiet
+    music: Option<MusicId>,
+    music_paused: bool,
+    // what the last device's mixer was playing when it went, for the next
+    // to carry on from
+    last_mixer: Option<Arc<MixerShared>>,
+    // commands the queue had no room for
+    dropped: u32,
+    // kept here too, so a device opened later mixes the same samples
+    bank: SampleBank,
+    decoding: Option<mpsc::Receiver<Decoded>>,
+}
+
+enum Decoded {
+    Sound(SoundId, Samples),
+    Music(MusicId, Samples),
+}
+
+thread_local!(
+    static DEVICE:RefCell<Device> = RefCell::new(Device {
+        subsystem: None,
+        open: None,
+        music: None,
+        music_paused: false,
+        last_mixer: None,
+        dropped: 0,
+        bank: SampleBank::default(),
+        decoding: None
+    });
+);
+
+fn device_track(device: &mut Device, command: &SoundCommand) {
+    match command {
+        &SoundCommand::PlayMusic(id)                    |
+        &SoundCommand::CrossfadeMusic { id, .. }        => {
+            device.music = Some(id);
+            device.music_paused = false;
+        },
+        &SoundCommand::StopMusic                        |
+        &SoundCommand::FadeMusic { volume: 0, .. }      => device.music = None,
+        &SoundCommand::PauseMusic                       => device.music_paused = true,
+        &SoundCommand::ResumeMusic                      => device.music_paused = false,
+        _ => {},
+    }
+}
+
+// the backend once there's an audio subsystem: commands go down the queue
+// to the mixer, or nowhere while there's no device to mix for
+fn device_backend(command: &SoundCommand) {
+    DEVICE.with(|cell| {
+        let mut device = cell.borrow_mut();
+        device_track(&mut device, command);
+        let sent =
+            match device.open {
+                Some(ref open) => open.sender.push(command),
+                None => true,
+            };
+        if !sent {
+            if device.dropped == 0 {
+                warn!("sound: the mixer is behind, dropping commands");
+            }
+            device.dropped += 1;
+        }
+    });
+}
+
+fn device_open() -> Result<(), String> {
+    use rusty_kong::clock::clock_wall_millis;
+
+    let subsystem =
+        match DEVICE.with(|cell| cell.borrow().subsystem.clone()) {
+            Some(s) => s,
+            None => return Err("no audio subsystem".to_string()),
+        };
+    let desired = AudioSpecDesired {
+        freq: Some(DEVICE_FREQUENCY),
+        channels: Some(DEVICE_CHANNELS),
+        samples: Some(DEVICE_SAMPLES),
+    };
+    let (sender, receiver) = command_queue(DEVICE_QUEUE);
+    let (bank, music, paused, last) = DEVICE.with(|cell| {
+        let device = cell.borrow();
+        (device.bank.clone(), device.music, device.music_paused, device.last_mixer.as_ref().map(|shared| shared.music()))
+    });
+    // a device that took over picks the track up where the last one's
+    // mixer got to.  XXX: a track change still in the old queue when the
+    // device went is lost if nothing was sounding, as a jingle that's run
+    // out looks the same
+    let resume =
+        match (music, last) {
+            (Some(id), Some(Some((playing, position)))) if playing == id => Some(position),
+            _ => None,
+        };
+    let shared = Arc::new(MixerShared::default());
+    let device = {
+        let shared = shared.clone();
+        subsystem.open_playback(None, &desired, move |spec| {
+            let mut mixer = Mixer::new(receiver, &spec, bank, shared);
+            if let (Some(id), Some(position)) = (music, resume) {
+                mixer.resume_music(id, position, paused);
+            }
+            mixer
+        })?
+    };
+    device.resume();
+
+    // never a device before, or it was on another track: from the top
+    let ran_out = last.map_or(false, |music| music.is_none());
+    if let (Some(id), None, false) = (music, resume, ran_out) {
+        sender.push(&SoundCommand::PlayMusic(id));
+        if paused {
+            sender.push(&SoundCommand::PauseMusic);
+        }
+    }
+    if let Some(position) = resume {
+        info!("sound: music picks up {}ms in", position as u64 * 1000 / (DEVICE_FREQUENCY as u64 * DEVICE_CHANNELS as u64));
+    }
+    DEVICE.with(|cell| {
+        cell.borrow_mut().open = Some(Open {
+            device: device,
+            sender: sender,
+            shared: shared,
+            callbacks: 0,
+            heard: clock_wall_millis(),
+        });
+    });
+    return Ok(());
+}
+
+// the open device goes, keeping what its mixer last said it was playing,
+// and the default device, whichever it is now, takes over
+fn device_reopen() {
+    let closed = DEVICE.with(|cell| cell.borrow_mut().open.take());
+    if let Some(open) = closed {
+        DEVICE.with(|cell| {cell.borrow_mut().last_mixer = Some(open.shared.clone());});
+        drop(open);
+    }
+    match device_open() {
+        Ok(()) => info!("sound: reopened on the default device"),
+        Err(e) => warn!("no other audio device: {}", e),
+    }
+}
+
+// no audio subsystem is an init failure; no device is only no sound, the
+// game carries on and a device turning up later is picked up by
+// sound_device_added()
+pub fn sound_device_init(context: &Sdl) -> Result<(), String> {
+    let subsystem = context.audio()?;
+    info!("audio driver {}", subsystem.current_audio_driver());
+    DEVICE.with(|cell| {cell.borrow_mut().subsystem = Some(subsystem);});
+    sound_set_backend(device_backend);
+    if let Err(e) = device_open() {
+        warn!("no audio device: {}", e);
+    }
+    return Ok(());
+}
+
+fn device_decode<F>(sender: &mpsc::Sender<Decoded>, dir: &str, name: &str, make: F)
+    where F: FnOnce(Samples) -> Decoded + Send + 'static {
+    use rusty_kong::assets::decode_spawn;
+
+    let relative =
+        match sound_asset(dir, name) {
+            Some(r) => r,
+            None => return,
+        };
+    if !relative.ends_with(".wav") {
+        info!("{} isn't mixed, only wav is", relative);
+        return;
+    }
+    let bytes =
+        match mixer_read(&relative) {
+            Ok(b) => b,
+            Err(e) => {
+                warn!("{}", e);
+                return;
+            },
+        };
+    let sender = sender.clone();
+    // the device is only ever opened as asked, so its format is known up
+    // front whether or not there's one open yet
+    decode_spawn(move || {
+        match mixer_decode(&relative, &bytes, DEVICE_CHANNELS, DEVICE_FREQUENCY) {
+            Ok(samples) => {
+                let _ = sender.send(make(Arc::new(samples)));
+            },
+            Err(e) => warn!("{}", e),
+        }
+    });
+}
+
+// boot: every sound & track goes to the decode pool, and
+// sound_decode_finish() hands what came back to the mixer.  without an
+// audio subsystem (golden runs, replays) there's nothing to decode them for
+pub fn sound_decode_start() {
+    if DEVICE.with(|cell| cell.borrow().subsystem.is_none()) {
+        return;
+    }
+    let (sender, receiver) = mpsc::channel();
+    for &id in ALL_SOUNDS.iter() {
+        device_decode(&sender, SOUND_DIR, id.name(), move |samples| Decoded::Sound(id, samples));
+    }
+    for &id in ALL_MUSIC.iter() {
+        device_decode(&sender, MUSIC_DIR, id.name(), move |samples| Decoded::Music(id, samples));
+    }
+    DEVICE.with(|cell| {cell.borrow_mut().decoding = Some(receiver);});
+}
+
+// boot, once the pool's through: the bank's swapped in under the device's
+// lock, the one moment the audio thread waits on the game
+pub fn sound_decode_finish() {
+    let receiver =
+        match DEVICE.with(|cell| cell.borrow_mut().decoding.take()) {
+            Some(r) => r,
+            None => return,
+        };
+    let mut bank = SampleBank::default();
+    for decoded in receiver.try_iter() {
+        match decoded {
+            Decoded::Sound(id, samples) => {bank.sounds.insert(id, samples);},
+            Decoded::Music(id, samples) => {bank.music.insert(id, samples);},
+        }
+    }
+    info!("mixing {} sounds & {} tracks at {}hz, {} channels", bank.sounds.len(), bank.music.len(), DEVICE_FREQUENCY, DEVICE_CHANNELS);
+    DEVICE.with(|cell| {
+        let mut device = cell.borrow_mut();
+        device.bank = bank.clone();
+        if let Some(ref mut open) = device.open {
+            open.device.lock().install(bank);
+        }
+    });
+}
+
+// an output went away (a headset unplugged, a usb dac switched off).  sdl
+// doesn't say which, but ours has stopped by the time this arrives if it
+// was ours; some backends move a default device stream over by themselves
+pub fn sound_device_removed() {
+    let stopped = DEVICE.with(|cell| {
+        cell.borrow().open.as_ref().map_or(false, |open| open.device.status() != AudioStatus::Playing)
+    });
+    if stopped {
+        warn!("audio device lost");
+        device_reopen();
+    }
+}
+
+// once a frame: a device that's stopped under us or whose callbacks have
+// dried up is as good as gone, event or no event
+pub fn sound_device_poll() {
+    use rusty_kong::clock::clock_wall_millis;
+
+    let now = clock_wall_millis();
+    let lost = DEVICE.with(|cell| {
+        let mut device = cell.borrow_mut();
+        let open =
+            match device.open {
+                Some(ref mut open) => open,
+                None => return None,
+            };
+        if open.device.status() != AudioStatus::Playing {
+            return Some("stopped");
+        }
+        let callbacks = open.shared.callbacks.load(Ordering::Relaxed);
+        if callbacks != open.callbacks {
+            open.callbacks = callbacks;
+            open.heard = now;
+            return None;
+        }
+        if now > open.heard + DEVICE_STALL_MILLIS {
+            return Some("stalled");
+        }
+        return None;
+    });
+    if let Some(why) = lost {
+        warn!("audio device {}", why);
+        device_reopen();
+    }
+}
+
+pub fn sound_device_added() {
+    let subsystem = DEVICE.with(|cell| {
+        let device = cell.borrow();
+        device.subsystem.is_some() && device.open.is_none()
+    });
+    if !subsystem {
+        return;
+    }
+    match device_open() {
+        Ok(()) => info!("audio device back"),
+        Err(e) => warn!("audio device: {}", e),
+    }
+}
@@ -0,0 +1,288 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use sdl2::audio::AudioCVT;
+use sdl2::audio::AudioCallback;
+use sdl2::audio::AudioFormat;
+use sdl2::audio::AudioSpec;
+use sdl2::audio::AudioSpecWAV;
+use sdl2::rwops::RWops;
+
+use super::*;
+use super::queue::CommandReceiver;
+
+// XXX: which tracks go round until something else plays, by ear from the
+// cabinet; the rest are jingles that play once
+const MUSIC_LOOPS: [MusicId; 2] = [MusicId::Stage, MusicId::HammerTime];
+// effects sounding at once; one more takes the oldest's place
+const MIXER_VOICES: usize = 8;
+// fades are asked for in logic ticks
+const MIXER_TICKS_PER_SECOND: f32 = 60.0;
+
+pub type Samples = Arc<Vec<i16>>;
+
+// everything the mixer can play, decoded for the device's format
+#[derive(Clone, Default)]
+pub struct SampleBank {
+    pub sounds: HashMap<SoundId, Samples>,
+    pub music: HashMap<MusicId, Samples>,
+}
+
+// main thread: the pack and the mod lookups aren't shared with workers, so
+// the bytes are read here and only the decode is handed off
+pub fn mixer_read(relative: &str) -> Result<Vec<u8>, String> {
+    use rusty_kong::assets::asset_open;
+    use rusty_kong::assets::asset_path;
+
+    let mut bytes = Vec::new();
+    asset_open(&asset_path(relative))
+        .ok_or_else(|| format!("can't open {}", relative))?
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("can't read {}: {}", relative, e))?;
+    return Ok(bytes);
+}
+
+// XXX: only wav is mixed; an ogg would need a decoder this build doesn't have
+pub fn mixer_decode(relative: &str, bytes: &[u8], channels: u8, freq: i32) -> Result<Vec<i16>, String> {
+    let mut source = RWops::from_bytes(bytes)?;
+    let wav = AudioSpecWAV::load_wav_rw(&mut source).map_err(|e| format!("{}: {}", relative, e))?;
+    let cvt = AudioCVT::new(wav.format, wav.channels, wav.freq, AudioFormat::s16_sys(), channels, freq)
+        .map_err(|e| format!("{}: {}", relative, e))?;
+    let converted = cvt.convert(wav.buffer().to_vec());
+    return Ok(converted
+        .chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| i16::from_ne_bytes([pair[0], pair[1]]))
+        .collect());
+}
+
+// what the game can see of the audio thread: that callbacks are still
+// coming, and the track sounding and how far in, so a device opened in its
+// place picks it up where it was
+#[derive(Default)]
+pub struct MixerShared {
+    pub callbacks: AtomicUsize,
+    // the track's index in ALL_MUSIC + 1, 0 for none
+    pub music: AtomicUsize,
+    pub position: AtomicUsize,
+}
+
+impl MixerShared {
+    pub fn music(self:&MixerShared) -> Option<(MusicId, usize)> {
+        let index = self.music.load(Ordering::Relaxed);
+        if index == 0 {
+            return None;
+        }
+        return ALL_MUSIC.get(index - 1).map(|&id| (id, self.position.load(Ordering::Relaxed)));
+    }
+}
+
+struct Track {
+    id: MusicId,
+    samples: Samples,
+    position: usize,
+    looping: bool,
+    // 0 to 1, moving by step a frame until it's at target
+    volume: f32,
+    target: f32,
+    step: f32,
+}
+
+impl Track {
+    // one sample's worth; None once a jingle's over or a fade's taken it to 0
+    fn next(self:&mut Track, channels: usize) -> Option<i32> {
+        if self.position >= self.samples.len() {
+            if !self.looping || self.samples.is_empty() {
+                return None;
+            }
+            self.position = 0;
+        }
+        if self.position % channels == 0 && self.volume != self.target {
+            let distance = self.target - self.volume;
+            self.volume = if distance.abs() <= self.step { self.target } else { self.volume + self.step * distance.signum() };
+            if self.volume == 0.0 && self.target == 0.0 {
+                return None;
+            }
+        }
+        let sample = self.samples[self.position] as f32 * self.volume;
+        self.position += 1;
+        return Some(sample as i32);
+    }
+}
+
+// runs on sdl's audio thread, never the game's: everything it plays was
+// decoded elsewhere and installed with the device locked, and all it hears
+// from the game is what comes down the command queue, so a hitch in the
+// logic can't starve it
+pub struct Mixer {
+    commands: CommandReceiver,
+    sounds: HashMap<SoundId, Samples>,
+    music: HashMap<MusicId, Samples>,
+    channels: usize,
+    frames_per_tick: f32,
+    // (samples, position) per effect sounding
+    voices: Vec<(Samples, usize)>,
+    track: Option<Track>,
+    // the one a crossfade is taking out
+    outgoing: Option<Track>,
+    paused: bool,
+    shared: Arc<MixerShared>,
+}
+
+impl Mixer {
+    pub fn new(commands: CommandReceiver, spec: &AudioSpec, bank: SampleBank, shared: Arc<MixerShared>) -> Mixer {
+        return Mixer {
+            commands: commands,
+            sounds: bank.sounds,
+            music: bank.music,
+            channels: spec.channels.max(1) as usize,
+            frames_per_tick: spec.freq as f32 / MIXER_TICKS_PER_SECOND,
+            voices: Vec::new(),
+            track: None,
+            outgoing: None,
+            paused: false,
+            shared: shared,
+        };
+    }
+
+    // a device taking over from one that went away: the track carries on
+    // from where the last mixer got to.  positions are into the samples,
+    // which are decoded the same whatever the device
+    pub fn resume_music(self:&mut Mixer, id: MusicId, position: usize, paused: bool) {
+        let channels = self.channels;
+        self.track = self.track(id, 1.0).map(|mut track| {
+            track.position = (position - position % channels).min(track.samples.len());
+            track
+        });
+        self.paused = paused;
+    }
+
+    fn publish(self:&Mixer) {
+        let (music, position) =
+            match self.track {
+                Some(ref track) => (ALL_MUSIC.iter().position(|&id| id == track.id).map_or(0, |index| index + 1), track.position),
+                None => (0, 0),
+            };
+        self.shared.position.store(position, Ordering::Relaxed);
+        self.shared.music.store(music, Ordering::Relaxed);
+        self.shared.callbacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // boot's decodes landing; whatever's sounding carries on with the
+    // samples it already holds
+    pub fn install(self:&mut Mixer, bank: SampleBank) {
+        self.sounds = bank.sounds;
+        self.music = bank.music;
+    }
+
+    fn step(self:&Mixer, from: f32, to: f32, ticks: u32) -> f32 {
+        (to - from).abs() / (ticks as f32 * self.frames_per_tick).max(1.0)
+    }
+
+    fn track(self:&Mixer, id: MusicId, volume: f32) -> Option<Track> {
+        self.music.get(&id).map(|samples| Track {
+            id: id,
+            samples: samples.clone(),
+            position: 0,
+            looping: MUSIC_LOOPS.contains(&id),
+            volume: volume,
+            target: volume,
+            step: 0.0,
+        })
+    }
+
+    fn apply(self:&mut Mixer, command: SoundCommand) {
+        match command {
+            SoundCommand::Play(id) => {
+                if let Some(samples) = self.sounds.get(&id).cloned() {
+                    if self.voices.len() >= MIXER_VOICES {
+                        self.voices.remove(0);
+                    }
+                    self.voices.push((samples, 0));
+                }
+            },
+            SoundCommand::PlayMusic(id) => {
+                self.outgoing = None;
+                self.track = self.track(id, 1.0);
+                self.paused = false;
+            },
+            SoundCommand::StopMusic => {
+                self.track = None;
+                self.outgoing = None;
+            },
+            SoundCommand::PauseMusic => self.paused = true,
+            SoundCommand::ResumeMusic => self.paused = false,
+            SoundCommand::FadeMusic { volume, ticks } => {
+                let target = volume.min(100) as f32 / 100.0;
+                let step = self.track.as_ref().map_or(0.0, |track| self.step(track.volume, target, ticks));
+                if let Some(ref mut track) = self.track {
+                    track.target = target;
+                    track.step = step;
+                }
+            },
+            SoundCommand::CrossfadeMusic { id, ticks } => {
+                let step = self.step(0.0, 1.0, ticks);
+                self.outgoing = self.track.take().map(|mut track| {
+                    track.target = 0.0;
+                    track.step = step;
+                    track
+                });
+                self.track = self.track(id, 0.0).map(|mut track| {
+                    track.target = 1.0;
+                    track.step = step;
+                    track
+                });
+                self.paused = false;
+            },
+        }
+    }
+}
+
+fn mixer_track_next(track: &mut Option<Track>, channels: usize) -> i32 {
+    let sample = track.as_mut().and_then(|track| track.next(channels));
+    if sample.is_none() {
+        *track = None;
+    }
+    return sample.unwrap_or(0);
+}
+
+impl AudioCallback for Mixer {
+    type Channel = i16;
+
+    fn callback(&mut self, out: &mut [i16]) {
+        while let Some(command) = self.commands.pop() {
+            self.apply(command);
+        }
+        let channels = self.channels;
+        for sample in out.iter_mut() {
+            let mut mixed: i32 = 0;
+            for voice in self.voices.iter_mut() {
+                if let Some(&value) = voice.0.get(voice.1) {
+                    mixed += value as i32;
+                    voice.1 += 1;
+                }
+            }
+            if !self.paused {
+                mixed += mixer_track_next(&mut self.track, channels);
+                mixed += mixer_track_next(&mut self.outgoing, channels);
+            }
+            *sample = mixed.max(i16::min_value() as i32).min(i16::max_value() as i32) as i16;
+        }
+        self.voices.retain(|voice| voice.1 < voice.0.len());
+        self.publish();
+    }
+}
@@ -9,3 +9,371 @@
 // with this source file to learn more.
 //
 // --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use super::events::GameEvent;
+use super::state_machine::GameState;
+
+mod queue;
+mod mixer;
+mod device;
+pub use self::device::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SoundId {
+    Jump,
+    Walk,
+    Death,
+    Bonus,
+    Smash,
+    Stomp,
+    Roar,
+    Spring,
+    // the cabinet tilting
+    Buzzer,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MusicId {
+    Introduction,
+    HowHigh,
+    Stage,
+    HammerTime,
+    StageClear,
+    KongRetreats,
+}
+
+pub const ALL_SOUNDS: [SoundId; 9] = [
+    SoundId::Jump,
+    SoundId::Walk,
+    SoundId::Death,
+    SoundId::Bonus,
+    SoundId::Smash,
+    SoundId::Stomp,
+    SoundId::Roar,
+    SoundId::Spring,
+    SoundId::Buzzer,
+];
+
+pub const ALL_MUSIC: [MusicId; 6] = [
+    MusicId::Introduction,
+    MusicId::HowHigh,
+    MusicId::Stage,
+    MusicId::HammerTime,
+    MusicId::StageClear,
+    MusicId::KongRetreats,
+];
+
+// where the samples will be looked for, as <dir>/<name>.<extension>
+pub const SOUND_DIR: &str = "sounds";
+pub const MUSIC_DIR: &str = "music";
+pub const SOUND_EXTENSIONS: [&str; 2] = ["wav", "ogg"];
+
+impl SoundId {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &SoundId::Jump      => "jump",
+            &SoundId::Walk      => "walk",
+            &SoundId::Death     => "death",
+            &SoundId::Bonus     => "bonus",
+            &SoundId::Smash     => "smash",
+            &SoundId::Stomp     => "stomp",
+            &SoundId::Roar      => "roar",
+            &SoundId::Spring    => "spring",
+            &SoundId::Buzzer    => "buzzer",
+        }
+    }
+}
+
+impl MusicId {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &MusicId::Introduction  => "introduction",
+            &MusicId::HowHigh       => "how_high",
+            &MusicId::Stage         => "stage",
+            &MusicId::HammerTime    => "hammer_time",
+            &MusicId::StageClear    => "stage_clear",
+            &MusicId::KongRetreats  => "kong_retreats",
+        }
+    }
+}
+
+// the sample a sound or track would be mixed from, through mods and the
+// pack like any other asset; None when there isn't one
+pub fn sound_asset(dir: &str, name: &str) -> Option<String> {
+    use super::assets::asset_exists;
+
+    SOUND_EXTENSIONS
+        .iter()
+        .map(|extension| format!("{}/{}.{}", dir, name, extension))
+        .find(|relative| asset_exists(relative))
+}
+
+// music always starts at full volume; volumes are percentages and ramps
+// are in logic ticks, which the backend is left to turn into time
+#[derive(Clone, Copy, Debug)]
+pub enum SoundCommand {
+    Play(SoundId),
+    PlayMusic(MusicId),
+    StopMusic,
+    PauseMusic,
+    ResumeMusic,
+    // ramps the current track to volume; reaching 0 stops it
+    FadeMusic { volume: u8, ticks: u32 },
+    // brings id in over ticks while the current track fades out under it
+    CrossfadeMusic { id: MusicId, ticks: u32 },
+}
+
+// how a state transition treats the music its enter asks for
+#[derive(Clone, Copy, Debug)]
+enum MusicBlend {
+    // a stop becomes a fade down to volume, so the old theme carries on
+    // under whatever the new state plays
+    Duck { volume: u8, ticks: u32 },
+    // a new track is faded in over the old one instead of cutting
+    Crossfade { ticks: u32 },
+}
+
+// anything not listed hard-cuts like the cabinet
+const MUSIC_BLENDS: [(GameState, GameState, MusicBlend); 4] = [
+    (GameState::GamePlay,   GameState::PlayerDies,  MusicBlend::Duck { volume: 25, ticks: 20 }),
+    (GameState::PlayerDies, GameState::GameOver,    MusicBlend::Duck { volume: 0, ticks: 60 }),
+    (GameState::GamePlay,   GameState::PlayerWins,  MusicBlend::Crossfade { ticks: 30 }),
+    (GameState::HowHigh,    GameState::GamePlay,    MusicBlend::Crossfade { ticks: 20 }),
+];
+
+// the logic side only queues commands; a backend turns them into noise.
+// headless the backend just logs; with a window, sound_device_init() puts
+// the mixer on sdl's audio thread behind device.rs's backend.
+pub type SoundBackend = fn(&SoundCommand);
+
+fn log_backend(command: &SoundCommand) {
+    debug!("sound: {:?}", command);
+}
+
+struct SoundQueue {
+    backend: SoundBackend,
+    // made during the current tick
+    pending: Vec<SoundCommand>,
+    // effects are dropped while muted; music is paused separately
+    muted: bool,
+    // from ticks whose frame hasn't been presented yet
+    unpresented: Vec<SoundCommand>,
+    // (due, command) in due order, due in ms on the sound_present() clock
+    scheduled: VecDeque<(u64, SoundCommand)>,
+    // set by the first sound_present(); until then, and always headless,
+    // commands go straight to the backend at the end of their tick
+    presenting: bool,
+    // the window's in the background and quiet, or the game's being
+    // fast-forwarded: either way effects don't reach the backend and the
+    // music's held, whatever the logic asks for
+    background: bool,
+    fast_forward: bool,
+    // the logic's own pause of the music, which coming back mustn't undo
+    music_paused: bool,
+}
+
+thread_local!(
+    static SOUND:RefCell<SoundQueue> = RefCell::new(SoundQueue {
+        backend: log_backend,
+        pending: Vec::new(),
+        muted: false,
+        unpresented: Vec::new(),
+        scheduled: VecDeque::new(),
+        presenting: false,
+        background: false,
+        fast_forward: false,
+        music_paused: false
+    });
+);
+
+fn sound_queue(command: SoundCommand) {
+    SOUND.with(|cell| {cell.borrow_mut().pending.push(command);});
+}
+
+pub fn sound_play(id: SoundId) {
+    if SOUND.with(|cell| cell.borrow().muted) {
+        return;
+    }
+    sound_queue(SoundCommand::Play(id));
+}
+
+pub fn sound_set_muted(muted: bool) {
+    SOUND.with(|cell| {cell.borrow_mut().muted = muted;});
+}
+
+pub fn music_play(id: MusicId) {
+    sound_queue(SoundCommand::PlayMusic(id));
+}
+
+pub fn music_stop() {
+    sound_queue(SoundCommand::StopMusic);
+}
+
+pub fn music_pause() {
+    sound_queue(SoundCommand::PauseMusic);
+}
+
+pub fn music_resume() {
+    sound_queue(SoundCommand::ResumeMusic);
+}
+
+pub fn sound_set_backend(backend: SoundBackend) {
+    SOUND.with(|cell| {cell.borrow_mut().backend = backend;});
+}
+
+// drops everything not yet played and stops the music straight away,
+// rather than on a later frame
+pub fn sound_reset() {
+    let backend = SOUND.with(|cell| {
+        let mut sound = cell.borrow_mut();
+        sound.pending.clear();
+        sound.unpresented.clear();
+        sound.scheduled.clear();
+        sound.music_paused = false;
+        sound.backend
+    });
+    backend(&SoundCommand::StopMusic);
+}
+
+// the way out to the backend, past the background hold
+fn sound_send(backend: SoundBackend, commands: &[SoundCommand]) {
+    for command in commands.iter() {
+        let background = SOUND.with(|cell| {
+            let mut sound = cell.borrow_mut();
+            match command {
+                &SoundCommand::PauseMusic  => sound.music_paused = true,
+                &SoundCommand::Play(_)     |
+                &SoundCommand::FadeMusic { .. } => {},
+                _ => sound.music_paused = false,
+            }
+            sound.background || sound.fast_forward
+        });
+        if !background {
+            backend(command);
+            continue;
+        }
+        match command {
+            &SoundCommand::Play(_) | &SoundCommand::PauseMusic | &SoundCommand::ResumeMusic => {},
+            // a new track starts where it would, held
+            &SoundCommand::PlayMusic(_) | &SoundCommand::CrossfadeMusic { .. } => {
+                backend(command);
+                backend(&SoundCommand::PauseMusic);
+            },
+            _ => backend(command),
+        }
+    }
+}
+
+fn sound_hold<F: FnOnce(&mut SoundQueue)>(f: F) {
+    let (backend, was, held, music_paused) = SOUND.with(|cell| {
+        let mut sound = cell.borrow_mut();
+        let was = sound.background || sound.fast_forward;
+        f(&mut sound);
+        (sound.backend, was, sound.background || sound.fast_forward, sound.music_paused)
+    });
+    if was != held && !music_paused {
+        backend(if held { &SoundCommand::PauseMusic } else { &SoundCommand::ResumeMusic });
+    }
+}
+
+// energy saver: quiet while the window's in the background, the logic's
+// sounds carrying on unheard
+pub fn sound_set_background(background: bool) {
+    sound_hold(|sound| sound.background = background);
+}
+
+// ten ticks' effects a frame is noise, so fast-forward is quiet the same
+// way; the music picks up where it was held, behind the game
+pub fn sound_set_fast_forward(fast_forward: bool) {
+    sound_hold(|sound| sound.fast_forward = fast_forward);
+}
+
+// the transition's event comes after the new state's enter, so its music
+// commands are still pending this tick and can be rewritten in place
+fn sound_blend_music(blend: MusicBlend) {
+    SOUND.with(|cell| {
+        let mut sound = cell.borrow_mut();
+        match blend {
+            MusicBlend::Duck { volume, ticks } => {
+                let fade = SoundCommand::FadeMusic { volume: volume, ticks: ticks };
+                let stop = sound.pending.iter().position(|command| match command {
+                    &SoundCommand::StopMusic => true,
+                    _ => false,
+                });
+                match stop {
+                    Some(index) => sound.pending[index] = fade,
+                    None => sound.pending.push(fade),
+                }
+            },
+            MusicBlend::Crossfade { ticks } => {
+                for command in sound.pending.iter_mut() {
+                    if let SoundCommand::PlayMusic(id) = *command {
+                        *command = SoundCommand::CrossfadeMusic { id: id, ticks: ticks };
+                    }
+                }
+            },
+        }
+    });
+}
+
+fn sound_on_event(event: &GameEvent) {
+    match event {
+        &GameEvent::StateChanged { from, to } => {
+            let blend = MUSIC_BLENDS.iter().find(|&&(f, t, _)| f == from && t == to);
+            if let Some(&(_, _, blend)) = blend {
+                debug!("music {:?} for {} -> {}", blend, from, to);
+                sound_blend_music(blend);
+            }
+        },
+        _ => {},
+    }
+}
+
+pub fn sound_init() {
+    use super::events::events_subscribe;
+    events_subscribe(sound_on_event);
+}
+
+// closes off this tick's commands.  once frames are being presented they
+// wait for the frame this tick ends up on; otherwise they go to the backend
+// now, in the order they were made.
+pub fn sound_update() {
+    let (backend, pending) = SOUND.with(|cell| {
+        let mut sound = cell.borrow_mut();
+        let pending = sound.pending.drain(..).collect::<Vec<SoundCommand>>();
+        if sound.presenting {
+            sound.unpresented.extend(pending);
+            return (sound.backend, Vec::new());
+        }
+        (sound.backend, pending)
+    });
+    sound_send(backend, &pending);
+}
+
+// call right after a frame is presented, with a millisecond clock.  every
+// tick since the last frame belongs to this one, so their commands are due
+// audio_latency_ms from now -- enough to let slow outputs (bluetooth, big
+// mixer buffers) line up with the picture -- and anything due goes out.
+pub fn sound_present(now: u64) {
+    use super::settings::settings_read;
+
+    let latency = settings_read(|s| s.audio_latency_ms) as u64;
+    let (backend, due) = SOUND.with(|cell| {
+        let mut sound = cell.borrow_mut();
+        sound.presenting = true;
+        let unpresented = sound.unpresented.drain(..).collect::<Vec<SoundCommand>>();
+        for command in unpresented {
+            sound.scheduled.push_back((now + latency, command));
+        }
+
+        let mut due = Vec::new();
+        while sound.scheduled.front().map_or(false, |&(at, _)| at <= now) {
+            due.push(sound.scheduled.pop_front().unwrap().1);
+        }
+        (sound.backend, due)
+    });
+    sound_send(backend, &due);
+}
@@ -0,0 +1,107 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use super::*;
+
+// one producer, the game thread, and one consumer, the audio callback;
+// neither ever waits on the other.  a command packs into a u64 so the
+// slots can be plain atomics:
+//
+//      bits 0-7    which command
+//      bits 8-15   the sound or track, or FadeMusic's volume
+//      bits 32-63  ticks for a fade or crossfade
+struct Ring {
+    slots: Vec<AtomicU64>,
+    // counted up forever, a slot is the count modulo the length: the next
+    // one to read, and the next one to write
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+pub struct CommandSender {
+    ring: Arc<Ring>,
+}
+
+pub struct CommandReceiver {
+    ring: Arc<Ring>,
+}
+
+// a power of two, so the counts wrapping round don't skip a slot
+pub fn command_queue(capacity: usize) -> (CommandSender, CommandReceiver) {
+    let capacity = capacity.next_power_of_two();
+    let ring = Arc::new(Ring {
+        slots: (0..capacity).map(|_| AtomicU64::new(0)).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    return (CommandSender { ring: ring.clone() }, CommandReceiver { ring: ring });
+}
+
+fn command_pack(command: &SoundCommand) -> u64 {
+    let sound = |id: SoundId| ALL_SOUNDS.iter().position(|&s| s == id).unwrap_or(0) as u64;
+    let music = |id: MusicId| ALL_MUSIC.iter().position(|&m| m == id).unwrap_or(0) as u64;
+    match command {
+        &SoundCommand::Play(id)                         => 0 | sound(id) << 8,
+        &SoundCommand::PlayMusic(id)                    => 1 | music(id) << 8,
+        &SoundCommand::StopMusic                        => 2,
+        &SoundCommand::PauseMusic                       => 3,
+        &SoundCommand::ResumeMusic                      => 4,
+        &SoundCommand::FadeMusic { volume, ticks }      => 5 | (volume as u64) << 8 | (ticks as u64) << 32,
+        &SoundCommand::CrossfadeMusic { id, ticks }     => 6 | music(id) << 8 | (ticks as u64) << 32,
+    }
+}
+
+fn command_unpack(packed: u64) -> Option<SoundCommand> {
+    let index = ((packed >> 8) & 0xff) as usize;
+    let ticks = (packed >> 32) as u32;
+    match packed & 0xff {
+        0 => ALL_SOUNDS.get(index).map(|&id| SoundCommand::Play(id)),
+        1 => ALL_MUSIC.get(index).map(|&id| SoundCommand::PlayMusic(id)),
+        2 => Some(SoundCommand::StopMusic),
+        3 => Some(SoundCommand::PauseMusic),
+        4 => Some(SoundCommand::ResumeMusic),
+        5 => Some(SoundCommand::FadeMusic { volume: index as u8, ticks: ticks }),
+        6 => ALL_MUSIC.get(index).map(|&id| SoundCommand::CrossfadeMusic { id: id, ticks: ticks }),
+        _ => None,
+    }
+}
+
+impl CommandSender {
+    // false when the audio thread is that far behind; the command is dropped
+    pub fn push(self:&CommandSender, command: &SoundCommand) -> bool {
+        let ring = &self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(ring.head.load(Ordering::Acquire)) >= ring.slots.len() {
+            return false;
+        }
+        ring.slots[tail % ring.slots.len()].store(command_pack(command), Ordering::Relaxed);
+        ring.tail.store(tail.wrapping_add(1), Ordering::Release);
+        return true;
+    }
+}
+
+impl CommandReceiver {
+    pub fn pop(self:&CommandReceiver) -> Option<SoundCommand> {
+        let ring = &self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        if head == ring.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let packed = ring.slots[head % ring.slots.len()].load(Ordering::Relaxed);
+        ring.head.store(head.wrapping_add(1), Ordering::Release);
+        return command_unpack(packed);
+    }
+}
@@ -0,0 +1,316 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::clock::*;
+use super::events::GameEvent;
+use super::level::Level;
+use super::level::Stage;
+use super::persist::PersistJob;
+use super::persist::persist_run;
+use super::state_machine::GameState;
+use super::video::FrameBuffer;
+
+// personal bests and LiveSplit exports live here, one pair per campaign
+pub const SPLITS_DIR: &str = "splits";
+
+// times are cumulative from the start of the run
+#[derive(Clone)]
+struct Split {
+    name: String,
+    frames: u32,
+    real: Duration,
+}
+
+// nothing in here feeds back into the game, so it stays out of
+// game_state_hash(); real time is wall clock and would never match anyway
+struct Run {
+    // clock_wall() and clock_ticks() when the run began
+    started: Option<Duration>,
+    started_tick: u32,
+    splits: Vec<Split>,
+    personal_best: Vec<Split>,
+}
+
+thread_local!(
+    static RUN:RefCell<Run> = RefCell::new(Run {
+        started: None,
+        started_tick: 0,
+        splits: Vec::new(),
+        personal_best: Vec::new()
+    });
+);
+
+fn split_name(level: &Level, stage: &Stage) -> String {
+    format!("{}-{} {}m {}", level.number, level.round + 1, stage.height, stage.name)
+}
+
+fn splits_path(extension: &str) -> PathBuf {
+    use super::level::level_campaign;
+    Path::new(SPLITS_DIR).join(format!("{}.{}", level_campaign().name.replace(' ', "_"), extension))
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1_000_000
+}
+
+fn frames_duration(frames: u32) -> Duration {
+    let seconds = frames / TICKS_PER_SECOND;
+    let nanos = (frames % TICKS_PER_SECOND) as u64 * 1_000_000_000 / TICKS_PER_SECOND as u64;
+    Duration::new(seconds as u64, nanos as u32)
+}
+
+// "split = frames, millis, name" per line, in run order
+fn speedrun_load_pb() -> Vec<Split> {
+    use super::settings::read_key_value_file;
+
+    let path = splits_path("pb");
+    let pairs =
+        match read_key_value_file(&path) {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+
+    let mut splits = Vec::new();
+    for pair in pairs.iter() {
+        let fields: Vec<&str> = pair.value.splitn(3, ',').map(|f| f.trim()).collect();
+        let parsed =
+            match (pair.key.as_ref(), fields.len()) {
+                ("split", 3) => fields[0].parse::<u32>().ok().and_then(|frames| {
+                    fields[1].parse::<u64>().ok().map(|millis| (frames, millis))
+                }),
+                _ => None,
+            };
+        match parsed {
+            Some((frames, millis)) => splits.push(Split {
+                name: fields[2].to_string(),
+                frames: frames,
+                real: Duration::from_millis(millis),
+            }),
+            None => {
+                warn!("{}:{}: expected split = frames, millis, name", path.display(), pair.line);
+                return Vec::new();
+            },
+        }
+    }
+    return splits;
+}
+
+// on the io worker, so a personal best at the end of a run doesn't hitch
+// the game over screen
+fn speedrun_write(job: PersistJob, path: PathBuf, contents: String) {
+    persist_run(job, move || {
+        fs::create_dir_all(SPLITS_DIR)
+            .and_then(|_| File::create(&path).and_then(|mut file| file.write_all(contents.as_bytes())))
+            .map(|_| format!("{} written to {}", job.name(), path.display()))
+            .map_err(|e| format!("can't write {}: {}", path.display(), e))
+    });
+}
+
+fn speedrun_save_pb(splits: &[Split]) {
+    let mut contents = String::from("# personal best, written by the speedrun timer\n");
+    for split in splits.iter() {
+        contents.push_str(&format!("split = {}, {}, {}\n", split.frames, duration_millis(split.real), split.name));
+    }
+    speedrun_write(PersistJob::PersonalBest, splits_path("pb"), contents);
+}
+
+// LiveSplit wants hh:mm:ss.fffffff
+fn lss_time(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{:02}:{:02}:{:02}.{:07}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        duration.subsec_nanos() / 100)
+}
+
+fn lss_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// a minimal LiveSplit splits file: the personal best as the only comparison
+fn speedrun_export_lss(splits: &[Split]) {
+    use super::level::level_campaign;
+
+    let mut contents = String::new();
+    contents.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    contents.push_str("<Run version=\"1.7.0\">\n");
+    contents.push_str("  <GameIcon />\n");
+    contents.push_str("  <GameName>Rusty Kong</GameName>\n");
+    contents.push_str(&format!("  <CategoryName>{}</CategoryName>\n", lss_escape(level_campaign().name)));
+    contents.push_str("  <Offset>00:00:00</Offset>\n");
+    contents.push_str("  <AttemptCount>0</AttemptCount>\n");
+    contents.push_str("  <AttemptHistory />\n");
+    contents.push_str("  <Segments>\n");
+    for split in splits.iter() {
+        contents.push_str("    <Segment>\n");
+        contents.push_str(&format!("      <Name>{}</Name>\n", lss_escape(&split.name)));
+        contents.push_str("      <Icon />\n");
+        contents.push_str("      <SplitTimes>\n");
+        contents.push_str("        <SplitTime name=\"Personal Best\">\n");
+        contents.push_str(&format!("          <RealTime>{}</RealTime>\n", lss_time(split.real)));
+        contents.push_str(&format!("          <GameTime>{}</GameTime>\n", lss_time(frames_duration(split.frames))));
+        contents.push_str("        </SplitTime>\n");
+        contents.push_str("      </SplitTimes>\n");
+        contents.push_str("      <BestSegmentTime />\n");
+        contents.push_str("      <SegmentHistory />\n");
+        contents.push_str("    </Segment>\n");
+    }
+    contents.push_str("  </Segments>\n");
+    contents.push_str("  <AutoSplitterSettings />\n");
+    contents.push_str("</Run>\n");
+    speedrun_write(PersistJob::LiveSplit, splits_path("lss"), contents);
+}
+
+// further is better; equally far is decided on the final real time
+fn run_is_better(run: &[Split], personal_best: &[Split]) -> bool {
+    if run.len() != personal_best.len() {
+        return run.len() > personal_best.len();
+    }
+    match (run.last(), personal_best.last()) {
+        (Some(r), Some(pb)) => r.real < pb.real,
+        _ => false,
+    }
+}
+
+fn speedrun_start() {
+    let personal_best = speedrun_load_pb();
+    RUN.with(|cell| {
+        let mut run = cell.borrow_mut();
+        run.started = Some(clock_wall());
+        run.started_tick = clock_ticks();
+        run.splits.clear();
+        run.personal_best = personal_best;
+    });
+}
+
+fn speedrun_split(level: &Level, stage: &Stage) {
+    RUN.with(|cell| {
+        let mut run = cell.borrow_mut();
+        let real =
+            match run.started {
+                Some(started) => clock_wall() - started,
+                None => return,
+            };
+        let frames = clock_ticks().saturating_sub(run.started_tick);
+        let split = Split { name: split_name(level, stage), frames: frames, real: real };
+        info!("split {}: {} frames, {}ms", split.name, split.frames, duration_millis(split.real));
+        run.splits.push(split);
+    });
+}
+
+fn speedrun_finish() {
+    use super::settings::*;
+
+    let finished = RUN.with(|cell| {
+        let mut run = cell.borrow_mut();
+        match run.started.take() {
+            Some(_) => Some((run.splits.clone(), run.personal_best.clone())),
+            None => None,
+        }
+    });
+
+    if let Some((splits, personal_best)) = finished {
+        if !settings_is_standard_speed() {
+            info!("run at {}% speed doesn't count towards the personal best", settings_read(|s| s.game_speed));
+            return;
+        }
+        if !splits.is_empty() && run_is_better(&splits, &personal_best) {
+            info!("new personal best over {} splits", splits.len());
+            speedrun_save_pb(&splits);
+            speedrun_export_lss(&splits);
+        }
+    }
+}
+
+// a reset run never counts towards the personal best
+fn speedrun_abandon() {
+    RUN.with(|cell| {
+        let mut run = cell.borrow_mut();
+        if run.started.take().is_some() {
+            info!("run abandoned after {} frames", clock_ticks().saturating_sub(run.started_tick));
+        }
+    });
+}
+
+// a run is only timed when the timer was on as it started; the settings
+// page can turn it on and off between runs
+fn speedrun_on_event(event: &GameEvent) {
+    use super::settings::settings_read;
+
+    match event {
+        &GameEvent::GameStarted if settings_read(|s| s.speedrun_timer) => speedrun_start(),
+        &GameEvent::Reset => speedrun_abandon(),
+        &GameEvent::StageCleared { level, stage, .. } => speedrun_split(&level, stage),
+        &GameEvent::StateChanged { to: GameState::Attract, .. } |
+        &GameEvent::StateChanged { to: GameState::MainMenu, .. } => speedrun_finish(),
+        _ => {},
+    }
+}
+
+fn clock_text(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{:02}.{:02}.{:02}", seconds / 60, seconds % 60, duration.subsec_nanos() / 10_000_000)
+}
+
+fn speedrun_overlay(framebuffer: &mut FrameBuffer) {
+    use super::video::*;
+
+    let (real, frames, last) = RUN.with(|cell| {
+        let run = cell.borrow();
+        let last = run.splits.last().map(|split| {
+            let index = run.splits.len() - 1;
+            let delta = run.personal_best
+                .get(index)
+                .map(|pb| duration_millis(split.real) as i64 - duration_millis(pb.real) as i64);
+            (split.name.clone(), delta)
+        });
+        (run.started.map(|started| clock_wall() - started), clock_ticks().saturating_sub(run.started_tick), last)
+    });
+
+    let real =
+        match real {
+            Some(r) => r,
+            None => return,
+        };
+
+    let bottom = SCREEN_HEIGHT - TILE_HEIGHT;
+    text_draw(framebuffer, 0, bottom - TILE_HEIGHT, &format!("{} {:>7}F", clock_text(real), frames), TEXT_PALETTE_WHITE);
+
+    if let Some((name, delta)) = last {
+        // there's no plus glyph in the character set, so ahead/behind is
+        // carried by the color and the minus sign alone
+        let (text, palette) =
+            match delta {
+                Some(d) if d < 0 => (format!("-{}.{:02} {}", -d / 1000, -d % 1000 / 10, name), TEXT_PALETTE_GREEN),
+                Some(d)          => (format!("{}.{:02} {}", d / 1000, d % 1000 / 10, name), TEXT_PALETTE_RED),
+                None             => (name, TEXT_PALETTE_WHITE),
+            };
+        text_draw(framebuffer, 0, bottom, &text, palette);
+    }
+}
+
+pub fn speedrun_init() {
+    use super::events::events_subscribe;
+    use super::video::video_add_overlay;
+
+    events_subscribe(speedrun_on_event);
+    video_add_overlay(speedrun_overlay);
+}
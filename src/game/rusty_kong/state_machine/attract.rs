@@ -10,11 +10,249 @@
 //
 // --------------------------------------------------------------------------
 
+use std::cell::RefCell;
+
+use rusty_kong::input::*;
+use rusty_kong::level::*;
+use rusty_kong::video::*;
+use super::game_state_go;
+use super::GameState;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AttractScreen {
+    Title,
+    HighScores,
+    Demo,
+    Credits,
+}
+
+pub const ATTRACT_ROTATION: [AttractScreen; 4] = [
+    AttractScreen::Title,
+    AttractScreen::HighScores,
+    AttractScreen::Demo,
+    AttractScreen::Credits,
+];
+
+// pure bookkeeping, no drawing or input reads, so the rotation can be
+// driven tick by tick from anywhere
+pub struct AttractScheduler {
+    // ticks per screen in ATTRACT_ROTATION order; 0 leaves a screen out
+    durations: [u32; 4],
+    index: usize,
+    ticks: u32,
+}
+
+impl AttractScheduler {
+    pub fn new(durations: [u32; 4]) -> AttractScheduler {
+        let mut scheduler = AttractScheduler {
+            durations: durations,
+            index: 0,
+            ticks: 0,
+        };
+        if scheduler.durations[0] == 0 {
+            scheduler.next();
+        }
+        return scheduler;
+    }
+
+    pub fn screen(&self) -> AttractScreen {
+        ATTRACT_ROTATION[self.index]
+    }
+
+    // moves on to the next screen with a non-zero duration; with every
+    // duration at 0 it stays put
+    fn next(&mut self) {
+        for _ in 0..ATTRACT_ROTATION.len() {
+            self.index = (self.index + 1) % ATTRACT_ROTATION.len();
+            if self.durations[self.index] != 0 {
+                break;
+            }
+        }
+        self.ticks = 0;
+    }
+
+    // one attract tick; skip cuts the current screen short.  returns the
+    // screen just switched to, if any.
+    pub fn tick(&mut self, skip: bool) -> Option<AttractScreen> {
+        self.ticks = self.ticks.saturating_add(1);
+        let expired = self.ticks >= self.durations[self.index] && self.durations[self.index] != 0;
+        if !skip && !expired {
+            return None;
+        }
+        let before = self.index;
+        self.next();
+        if self.index == before {
+            return None;
+        }
+        return Some(self.screen());
+    }
+}
+
+// the text along the bottom of every screen but the demo, which needs the
+// whole playfield
+const PROMPT_ROW:    u32 = 26;
+const COPYRIGHT_ROW: u32 = 29;
+const MARQUEE_ROW:   u32 = 31;
+
+// XXX: by eye from cabinet footage
+const PROMPT_ON_TICKS:  u32 = 32;
+const PROMPT_OFF_TICKS: u32 = 16;
+
+// ticks per marquee column
+const MARQUEE_STEP_TICKS: u32 = 8;
+
+const COPYRIGHT: &str = "- 2018 JEFF PANICI -";
+
+struct AttractText {
+    prompt: Blink,
+    marquee: String,
+    marquee_ticks: u32,
+}
+
+thread_local!(
+    static SCHEDULER:RefCell<Option<AttractScheduler>> = RefCell::new(None);
+    static TEXT:RefCell<AttractText> = RefCell::new(AttractText {
+        prompt: Blink::new(PROMPT_ON_TICKS, PROMPT_OFF_TICKS),
+        marquee: String::new(),
+        marquee_ticks: 0
+    });
+);
+
+fn centered_col(text: &str) -> u32 {
+    TEXT_COL_COUNT.saturating_sub(text.chars().count() as u32) / 2
+}
+
+fn attract_draw_prompt(visible: bool) {
+    use rusty_kong::locale::*;
+
+    let prompt = locale_text(StringId::InsertCoin);
+    if visible {
+        video_text(centered_col(&prompt), PROMPT_ROW, &prompt, TEXT_PALETTE_RED);
+    } else {
+        video_text_clear_row(PROMPT_ROW);
+    }
+}
+
+// the footer for one tick: the prompt only when it blinks, the marquee
+// only when it steps
+fn attract_draw_text(redraw: bool) {
+    let (blinked, visible, marquee) = TEXT.with(|cell| {
+        let mut text = cell.borrow_mut();
+        let blinked = text.prompt.tick();
+        let stepped = text.marquee_ticks % MARQUEE_STEP_TICKS == 0;
+        let marquee =
+            if !text.marquee.is_empty() && (stepped || redraw) {
+                Some(text_marquee(&text.marquee, TEXT_COL_COUNT, text.marquee_ticks / MARQUEE_STEP_TICKS))
+            } else {
+                None
+            };
+        text.marquee_ticks = text.marquee_ticks.wrapping_add(1);
+        (blinked, text.prompt.visible(), marquee)
+    });
+
+    if blinked || redraw {
+        attract_draw_prompt(visible);
+    }
+    if redraw {
+        video_text(centered_col(COPYRIGHT), COPYRIGHT_ROW, COPYRIGHT, TEXT_PALETTE_WHITE);
+    }
+    if let Some(marquee) = marquee {
+        video_text(0, MARQUEE_ROW, &marquee, TEXT_PALETTE_BLUE);
+    }
+}
+
+fn attract_draw(screen: AttractScreen) {
+    use rusty_kong::kong::*;
+
+    video_bg_clear();
+    video_sprite_hide_all();
+
+    match screen {
+        AttractScreen::Title => {
+            video_text(9, 10, "RUSTY KONG", TEXT_PALETTE_RED);
+            video_text(4, 14, &level_campaign().name.to_uppercase(), TEXT_PALETTE_WHITE);
+            video_text(4, 24, "PUSH START", TEXT_PALETTE_BLUE);
+        },
+        AttractScreen::HighScores => {
+            // XXX: there's no high score table yet, only the heading
+            video_text(8, 8, "HIGH SCORES", TEXT_PALETTE_RED);
+            video_text(5, 12, "RANK  SCORE  NAME", TEXT_PALETTE_WHITE);
+        },
+        AttractScreen::Demo => {
+            // XXX: a still of the first stage until entities can play a
+            // recorded or bot-driven demo
+            let stage = &level_campaign().stages[0];
+            layout_draw(stage.layout);
+            kong_draw(KONG_BARRELS_X, KONG_BARRELS_Y, KongPose::Standing);
+            video_text(10, 1, "DEMO", TEXT_PALETTE_WHITE);
+        },
+        AttractScreen::Credits => {
+            video_text(5, 10, "BY JEFF PANICI", TEXT_PALETTE_WHITE);
+            video_text(5, 12, "MIT LICENSE", TEXT_PALETTE_BLUE);
+        },
+    }
+    if screen != AttractScreen::Demo {
+        attract_draw_text(true);
+    }
+}
+
 pub fn attract_enter() {
+    use rusty_kong::settings::settings_read;
+
+    let scheduler = AttractScheduler::new(settings_read(|s| s.attract_ticks));
+    TEXT.with(|cell| {
+        let mut text = cell.borrow_mut();
+        text.prompt = Blink::new(PROMPT_ON_TICKS, PROMPT_OFF_TICKS);
+        text.marquee = settings_read(|s| s.attract_marquee.clone());
+        text.marquee_ticks = 0;
+    });
+    attract_draw(scheduler.screen());
+    SCHEDULER.with(|cell| {*cell.borrow_mut() = Some(scheduler);});
 }
 
+// left/right cycles the campaign, start plays it -- straight away on a
+// cabinet, through the main menu otherwise; jump and up/down skip to the
+// next screen
 pub fn attract_update() {
+    let count = level_campaign_count();
+    if input_is_pressed(F_INPUT_LEFT) {
+        level_select_campaign(level_campaign_index() + count - 1);
+    }
+    if input_is_pressed(F_INPUT_RIGHT) {
+        level_select_campaign(level_campaign_index() + 1);
+    }
+    if input_is_pressed(F_INPUT_START) {
+        use rusty_kong::player::player_start_game;
+        use rusty_kong::settings::settings_read;
+
+        if settings_read(|s| s.arcade) {
+            player_start_game(1);
+            level_start_game();
+            game_state_go(level_campaign().first_state);
+        } else {
+            game_state_go(GameState::MainMenu);
+        }
+        return;
+    }
+
+    let skip = input_is_pressed(F_INPUT_JUMP | F_INPUT_UP | F_INPUT_DOWN);
+    let (switched, screen) = SCHEDULER.with(|cell| {
+        let mut scheduler = cell.borrow_mut();
+        match scheduler.as_mut() {
+            Some(scheduler) => (scheduler.tick(skip), scheduler.screen()),
+            None => (None, AttractScreen::Title),
+        }
+    });
+    if let Some(screen) = switched {
+        debug!("attract: {:?}", screen);
+        attract_draw(screen);
+    } else if screen != AttractScreen::Demo {
+        attract_draw_text(false);
+    }
 }
 
 pub fn attract_leave() {
+    SCHEDULER.with(|cell| {*cell.borrow_mut() = None;});
+    video_bg_clear();
+    video_sprite_hide_all();
 }
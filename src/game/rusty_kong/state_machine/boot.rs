@@ -10,11 +10,225 @@
 //
 // --------------------------------------------------------------------------
 
+use std::cell::RefCell;
+use std::path::Path;
+
+use rusty_kong::assets::*;
+use rusty_kong::video::*;
+use super::game_state_go;
+use super::game_state_home;
+use super::get_state_ticks;
+
+const BOOT_COL: u32 = 6;
+const BOOT_ROW: u32 = 10;
+
+// one line of the self-test screen comes up every BOOT_LINE_TICKS
+const BOOT_LINE_TICKS: u32 = 15;
+
+// the pack's progress bar, while there's one to unpack
+const PACK_BAR_WIDTH: u32 = 16;
+// the decodes' bar goes under the self-test lines
+const DECODE_ROW: u32 = BOOT_ROW + 14;
+
+thread_local!(
+    static ASSET_PROBLEMS:RefCell<Vec<String>> = RefCell::new(Vec::new());
+    static UNPACKING:RefCell<bool> = RefCell::new(false);
+    // the self-test waits on the pack, and counts its lines from here
+    static SELF_TEST_START:RefCell<u32> = RefCell::new(0);
+    // the samples & sprites are decoded once a run, like the pack's loaded
+    static DECODE_STARTED:RefCell<bool> = RefCell::new(false);
+);
+
+// video, sound and input were brought up by game_init() before the machine
+// started, so by the time boot runs they're known good
+fn boot_lines(roms_ok: bool) -> [(&'static str, bool); 5] {
+    [
+        ("RAM",   true),
+        ("ROM",   roms_ok),
+        ("VIDEO", true),
+        ("SOUND", true),
+        ("INPUT", true),
+    ]
+}
+
 pub fn boot_enter() {
+    video_bg_clear();
+    video_sprite_hide_all();
+    ASSET_PROBLEMS.with(|cell| cell.borrow_mut().clear());
+    UNPACKING.with(|cell| {*cell.borrow_mut() = false;});
+    SELF_TEST_START.with(|cell| {*cell.borrow_mut() = 0;});
+
+    // the pack streams in once per run; a reset finds it already loaded
+    if Path::new(ASSETS_PACK).is_file() && !pack_is_loaded() {
+        match pack_open(Path::new(ASSETS_PACK)) {
+            Ok(()) => {
+                UNPACKING.with(|cell| {*cell.borrow_mut() = true;});
+                return;
+            },
+            Err(e) => {
+                error!("{}", e);
+                ASSET_PROBLEMS.with(|cell| cell.borrow_mut().push(e));
+            },
+        }
+    }
+    boot_verify();
+    boot_decode_start();
+}
+
+fn boot_verify() {
+    let problems = assets_verify();
+    ASSET_PROBLEMS.with(|cell| cell.borrow_mut().extend(problems));
+}
+
+// the pack's in (or there isn't one), so whatever the mixer and the atlas
+// read now is what they'd read for good
+fn boot_decode_start() {
+    use rusty_kong::sound::sound_decode_start;
+
+    if DECODE_STARTED.with(|cell| cell.replace(true)) {
+        return;
+    }
+    sound_decode_start();
+}
+
+// both take what they queued, so this is harmless more than once
+fn boot_decode_finish() {
+    use rusty_kong::sound::sound_decode_finish;
+
+    sound_decode_finish();
+    video_atlas_finish();
+    decode_pool_stop();
+}
+
+// for a run that starts past boot: everything's decoded up front instead
+pub fn boot_decode_wait() {
+    use std::thread;
+    use std::time::Duration;
+
+    boot_decode_start();
+    while decode_is_busy() {
+        thread::sleep(Duration::from_millis(1));
+    }
+    boot_decode_finish();
+}
+
+// (the bar, how much of it is filled)
+fn boot_progress_bar(read: u64, total: u64) -> (String, u32) {
+    let filled = if total == 0 { PACK_BAR_WIDTH } else { (read * PACK_BAR_WIDTH as u64 / total) as u32 };
+    let bar: String = (0..PACK_BAR_WIDTH).map(|col| if col < filled { '-' } else { '.' }).collect();
+    return (bar, filled);
+}
+
+fn boot_draw_progress(read: u64, total: u64) {
+    let (bar, filled) = boot_progress_bar(read, total);
+    video_text(BOOT_COL, BOOT_ROW, "LOADING", TEXT_PALETTE_WHITE);
+    video_text(BOOT_COL, BOOT_ROW + 2, &bar, TEXT_PALETTE_BLUE);
+    video_text(BOOT_COL + PACK_BAR_WIDTH + 1, BOOT_ROW + 2, &format!("{:3}", filled * 100 / PACK_BAR_WIDTH), TEXT_PALETTE_WHITE);
+}
+
+// the decodes run alongside the self-test rather than holding it up, and
+// how long they take isn't the same from one run to the next, so their
+// bar is drawn over the screen instead of on the tilemaps a journal hashes
+fn boot_overlay(framebuffer: &mut FrameBuffer) {
+    use super::GameState;
+    use super::game_state_current;
+
+    let (done, queued) = decode_progress();
+    if game_state_current() != GameState::Boot || done >= queued {
+        return;
+    }
+    let (bar, filled) = boot_progress_bar(done as u64, queued as u64);
+    let (x, y) = (BOOT_COL * TILE_WIDTH, DECODE_ROW * TILE_HEIGHT);
+    text_draw(framebuffer, x, y, "DECODING", TEXT_PALETTE_WHITE);
+    text_draw(framebuffer, x, y + 2 * TILE_HEIGHT, &bar, TEXT_PALETTE_BLUE);
+    text_draw(framebuffer, x + (PACK_BAR_WIDTH + 1) * TILE_WIDTH, y + 2 * TILE_HEIGHT, &format!("{:3}", filled * 100 / PACK_BAR_WIDTH), TEXT_PALETTE_WHITE);
+}
+
+pub fn boot_init() {
+    video_add_overlay(boot_overlay);
+}
+
+// true while the pack is still coming in; whatever was loaded from files
+// before it arrived is loaded again from it
+fn boot_unpack() -> bool {
+    use rusty_kong::locale::locale_init;
+    use rusty_kong::physics::physics_init;
+    use rusty_kong::sequence::sequence_init;
+
+    if !UNPACKING.with(|cell| *cell.borrow()) {
+        return false;
+    }
+    match pack_load_step(PACK_BYTES_PER_TICK) {
+        Ok(PackLoad::Loading { read, total }) => {
+            boot_draw_progress(read, total);
+            return true;
+        },
+        Ok(_) => {
+            locale_init();
+            physics_init();
+            sequence_init();
+            boot_verify();
+        },
+        Err(e) => {
+            error!("{}", e);
+            ASSET_PROBLEMS.with(|cell| cell.borrow_mut().push(e));
+        },
+    }
+    boot_decode_start();
+    video_bg_clear();
+    UNPACKING.with(|cell| {*cell.borrow_mut() = false;});
+    SELF_TEST_START.with(|cell| {*cell.borrow_mut() = get_state_ticks();});
+    return false;
 }
 
 pub fn boot_update() {
+    use rusty_kong::settings::settings_read;
+
+    if boot_unpack() {
+        return;
+    }
+    let ticks = get_state_ticks() - SELF_TEST_START.with(|cell| *cell.borrow());
+    let problems = ASSET_PROBLEMS.with(|cell| cell.borrow().clone());
+    let lines = boot_lines(problems.is_empty());
+
+    if ticks % BOOT_LINE_TICKS == 0 {
+        let index = (ticks / BOOT_LINE_TICKS) as usize;
+        if index < lines.len() {
+            let (name, ok) = lines[index];
+            let (status, palette) = if ok { ("OK", TEXT_PALETTE_WHITE) } else { ("NG", TEXT_PALETTE_RED) };
+            let row = BOOT_ROW + index as u32 * 2;
+            video_text(BOOT_COL, row, name, TEXT_PALETTE_WHITE);
+            video_text(BOOT_COL + 8, row, status, palette);
+        }
+    }
+
+    if !decode_is_busy() {
+        boot_decode_finish();
+    }
+    let done = ticks >= settings_read(|s| s.boot_ticks) && ticks >= lines.len() as u32 * BOOT_LINE_TICKS;
+    if !done {
+        return;
+    }
+    // XXX: the self-test's usually long enough to cover the decodes; when
+    // it isn't, this frame waits rather than boot running long, so a
+    // recording's ticks line up with a playback that decodes nothing
+    if decode_is_busy() {
+        warn!("boot: the self-test finished before the decodes did");
+        boot_decode_wait();
+    }
+
+    if problems.is_empty() {
+        game_state_go(game_state_home());
+    } else {
+        use super::game_state_fail;
+        let mut message = format!("ROM NG. {} asset problems.", problems.len());
+        for problem in problems.iter().take(3) {
+            message.push_str(&format!(" {}.", problem));
+        }
+        game_state_fail(&message);
+    }
 }
 
 pub fn boot_leave() {
+    video_bg_clear();
 }
@@ -0,0 +1,94 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+
+use rusty_kong::input::*;
+use rusty_kong::video::*;
+use super::game_state_go;
+use super::game_state_request_quit;
+use super::GameState;
+
+const ERROR_COL: u32 = 1;
+const ERROR_ROW: u32 = 8;
+const ERROR_WIDTH: usize = TEXT_COL_COUNT as usize - 2;
+const ERROR_MAX_LINES: usize = 12;
+
+thread_local!(
+    static FAILURE:RefCell<String> = RefCell::new(String::new());
+);
+
+pub fn error_screen_set_message(message: &str) {
+    FAILURE.with(|cell| {*cell.borrow_mut() = message.to_string();});
+}
+
+// greedy word wrap; words longer than a line are cut
+fn error_screen_wrap(message: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in message.split_whitespace() {
+        let mut word = word.to_string();
+        while word.len() > ERROR_WIDTH {
+            let rest = word.split_off(ERROR_WIDTH);
+            if !line.is_empty() {
+                lines.push(line);
+                line = String::new();
+            }
+            lines.push(word);
+            word = rest;
+        }
+        if !line.is_empty() && line.len() + 1 + word.len() > ERROR_WIDTH {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    return lines;
+}
+
+pub fn error_screen_enter() {
+    let message = FAILURE.with(|cell| cell.borrow().clone());
+    error!("error screen: {}", message);
+
+    video_bg_clear();
+    video_sprite_hide_all();
+    video_text(ERROR_COL, ERROR_ROW - 3, "ERROR", TEXT_PALETTE_RED);
+
+    // the character set only has upper case, digits, '.' and '-'
+    let lines = error_screen_wrap(&message.to_uppercase());
+    for (index, line) in lines.iter().take(ERROR_MAX_LINES).enumerate() {
+        video_text(ERROR_COL, ERROR_ROW + index as u32, line, TEXT_PALETTE_WHITE);
+    }
+
+    let row = ERROR_ROW + ERROR_MAX_LINES as u32 + 2;
+    video_text(ERROR_COL, row, "START  RETRY", TEXT_PALETTE_BLUE);
+    video_text(ERROR_COL, row + 2, "JUMP   QUIT", TEXT_PALETTE_BLUE);
+}
+
+pub fn error_screen_update() {
+    // retrying goes back through the self-test, which checks the assets again
+    if input_is_pressed(F_INPUT_START) {
+        game_state_go(GameState::Boot);
+    } else if input_is_pressed(F_INPUT_JUMP) {
+        game_state_request_quit();
+    }
+}
+
+pub fn error_screen_leave() {
+    video_bg_clear();
+}
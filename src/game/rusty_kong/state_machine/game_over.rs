@@ -0,0 +1,47 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use rusty_kong::player::*;
+use rusty_kong::video::*;
+use super::game_state_go;
+use super::game_state_home;
+use super::schedule_after;
+use super::GameState;
+
+const GAME_OVER_TICKS: u32 = 3 * 60;
+const GAME_OVER_ROW:   u32 = 18;
+
+pub fn game_over_enter() {
+    video_text_clear_row(GAME_OVER_ROW);
+    video_text_clear_row(GAME_OVER_ROW + 2);
+    if player_count() > 1 {
+        video_text(8, GAME_OVER_ROW, &format!("PLAYER {}", player_current() + 1), TEXT_PALETTE_WHITE);
+    }
+    video_text(9, GAME_OVER_ROW + 2, "GAME OVER", TEXT_PALETTE_RED);
+    schedule_after(GAME_OVER_TICKS, game_over_done);
+}
+
+// the other player carries on if they still have lives
+fn game_over_done() {
+    match player_next_with_lives() {
+        Some(index) => {
+            player_switch_to(index);
+            game_state_go(GameState::HowHigh);
+        },
+        None => game_state_go(game_state_home()),
+    }
+}
+
+pub fn game_over_leave() {
+    video_bg_clear();
+    video_sprite_hide_all();
+}
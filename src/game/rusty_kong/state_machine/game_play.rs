@@ -9,12 +9,88 @@
 // with this source file to learn more.
 //
 // --------------------------------------------------------------------------
-pub fn game_play_enter() {
 
+use rusty_kong::clock::clock_game_tick;
+use rusty_kong::enemies::*;
+use rusty_kong::events::*;
+use rusty_kong::input::*;
+use rusty_kong::kong::*;
+use rusty_kong::level::*;
+use rusty_kong::player::*;
+use rusty_kong::replay::ghost_update;
+use rusty_kong::scripting::*;
+use rusty_kong::sound::*;
+use rusty_kong::video::*;
+use super::game_state_go;
+use super::game_state_push;
+use super::GameState;
+
+const PRACTICE_TIMER_COL: u32 = 17;
+const PRACTICE_TIMER_ROW: u32 = 1;
+
+// ticks as seconds and hundredths, at 60 ticks a second
+fn practice_timer_text(ticks: u32) -> String {
+    format!("TIME {:3}.{:02}", ticks / 60, (ticks % 60) * 100 / 60)
+}
+
+pub fn game_play_enter() {
+    let stage = level_current_stage();
+    video_bg_clear();
+    layout_draw(stage.layout);
+    // built now rather than on the first tick's lookups
+    layout_grid(stage.layout);
+    kong_stage_start(stage);
+    enemies_stage_start(stage);
+    player_stage_start(stage);
+    hammers_stage_start(stage);
+    music_play(MusicId::Stage);
 }
 
 pub fn game_play_update() {
+    // pausing is only offered here, never over a death or win sequence
+    if input_is_pressed(F_INPUT_START) {
+        game_state_push(GameState::Paused);
+        return;
+    }
+
+    clock_game_tick();
+    for ladder in level_current_stage().layout.retracting.iter() {
+        layout_draw_retracting(ladder, level_stage_ticks());
+    }
+    player_update();
+    hammers_update();
+    enemies_update();
+
+    if let Some(hammer) = hammer_hitbox() {
+        for (_, _, points) in enemies_smash(&hammer) {
+            sound_play(SoundId::Smash);
+            events_publish(GameEvent::EnemySmashed);
+            scripting_collided(Collision::Smashed);
+            if level_is_scoring() {
+                player_add_score(points);
+            }
+        }
+    }
+
+    if player_fell() {
+        game_state_go(GameState::PlayerDies);
+        return;
+    }
+    if enemies_touching(&player_hitbox()) {
+        scripting_collided(Collision::Touched);
+        game_state_go(GameState::PlayerDies);
+        return;
+    }
 
+    if level_is_practice() {
+        if input_is_pressed(F_INPUT_RESTART) {
+            level_restart_stage();
+            game_state_go(GameState::GamePlay);
+            return;
+        }
+        video_text(PRACTICE_TIMER_COL, PRACTICE_TIMER_ROW, &practice_timer_text(level_stage_ticks()), TEXT_PALETTE_WHITE);
+        ghost_update();
+    }
 }
 
 pub fn game_play_leave() {
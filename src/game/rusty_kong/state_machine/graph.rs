@@ -0,0 +1,140 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use super::*;
+
+// every hand-over the states make in code, from and to with what causes
+// it.  the campaigns, stages and sequences add theirs from their data in
+// state_graph_edges(); keep this in step when a state learns a new go.
+const TRANSITIONS: [(GameState, GameState, &str); 19] = [
+    (GameState::None,       GameState::Boot,        "start"),
+    (GameState::Boot,       GameState::Attract,     "home, arcade"),
+    (GameState::Boot,       GameState::MainMenu,    "home"),
+    (GameState::Boot,       GameState::Error,       "asset problems"),
+    (GameState::Error,      GameState::Boot,        "retry"),
+    (GameState::Attract,    GameState::MainMenu,    "start"),
+    (GameState::MainMenu,   GameState::Attract,     "idle"),
+    (GameState::MainMenu,   GameState::GamePlay,    "practice"),
+    (GameState::HowHigh,    GameState::GamePlay,    ""),
+    (GameState::GamePlay,   GameState::Paused,      "push: start"),
+    (GameState::Paused,     GameState::GamePlay,    "pop: start"),
+    (GameState::GamePlay,   GameState::PlayerDies,  "hit or fell"),
+    (GameState::GamePlay,   GameState::GamePlay,    "practice restart"),
+    (GameState::PlayerDies, GameState::GamePlay,    "restart"),
+    (GameState::PlayerDies, GameState::HowHigh,     "other player's turn"),
+    (GameState::PlayerDies, GameState::GameOver,    "out of lives"),
+    (GameState::GameOver,   GameState::HowHigh,     "other player's turn"),
+    (GameState::GameOver,   GameState::Attract,     "home, arcade"),
+    (GameState::GameOver,   GameState::MainMenu,    "home"),
+];
+
+thread_local!(
+    // every from -> to the machine has made this run
+    static OBSERVED:RefCell<BTreeSet<(GameState, GameState)>> = RefCell::new(BTreeSet::new());
+);
+
+pub fn state_graph_observe(from: GameState, to: GameState) {
+    OBSERVED.with(|cell| {cell.borrow_mut().insert((from, to));});
+}
+
+// the table plus what the data says, one edge per pair with every reason
+// for it
+fn state_graph_edges() -> Vec<(GameState, GameState, Vec<String>)> {
+    use rusty_kong::level::*;
+    use rusty_kong::sequence::sequence_gos;
+
+    let mut found: Vec<(GameState, GameState, String)> = TRANSITIONS
+        .iter()
+        .map(|&(from, to, why)| (from, to, why.to_string()))
+        .collect();
+    for index in 0..level_campaign_count() {
+        let campaign = level_campaign_at(index);
+        found.push((GameState::Attract, campaign.first_state, format!("coin, {}", campaign.name)));
+        found.push((GameState::MainMenu, campaign.first_state, format!("play, {}", campaign.name)));
+        for stage in campaign.stages.iter() {
+            found.push((GameState::PlayerWins, stage.after_win, format!("after {}", stage.name)));
+        }
+    }
+    for (name, to) in sequence_gos() {
+        match name.parse::<GameState>() {
+            Ok(from) => found.push((from, to, format!("sequences/{}.txt", name))),
+            Err(_) => warn!("state graph: sequence {} isn't named after a state, its go is left out", name),
+        }
+    }
+
+    let mut edges: Vec<(GameState, GameState, Vec<String>)> = Vec::new();
+    for (from, to, why) in found {
+        match edges.iter().position(|&(f, t, _)| f == from && t == to) {
+            Some(index) => {
+                if !why.is_empty() && !edges[index].2.contains(&why) {
+                    edges[index].2.push(why);
+                }
+            },
+            None => edges.push((from, to, if why.is_empty() { Vec::new() } else { vec![why] })),
+        }
+    }
+    return edges;
+}
+
+// the states & transitions as graphviz, with the ones this run went
+// through drawn bold and any the table doesn't know about in red
+fn state_graph_dot() -> (String, usize) {
+    let edges = state_graph_edges();
+    let observed = OBSERVED.with(|cell| cell.borrow().clone());
+
+    let mut dot = String::new();
+    dot.push_str("// written by --state-graph; render with: dot -Tsvg -o states.svg <this file>\n");
+    dot.push_str("// ellipses move on by themselves, boxes wait on the player; bold was seen this run, red isn't in the table\n");
+    dot.push_str("digraph states {\n");
+    dot.push_str("    node [fontname=\"monospace\"];\n");
+    dot.push_str("    edge [fontname=\"monospace\", fontsize=10];\n");
+    for state in ::std::iter::once(&GameState::None).chain(GameState::all()) {
+        let shape = if state_is_transient(*state) { "ellipse" } else { "box" };
+        dot.push_str(&format!("    {} [shape={}];\n", state, shape));
+    }
+    dot.push_str("    anywhere [shape=point];\n");
+
+    for &(from, to, ref why) in edges.iter() {
+        let style = if observed.contains(&(from, to)) { ", style=bold" } else { "" };
+        dot.push_str(&format!("    {} -> {} [label=\"{}\"{}];\n", from, to, why.join("\\n"), style));
+    }
+    // the ones that can happen from anywhere
+    dot.push_str("    anywhere -> boot [label=\"reset\", style=dashed];\n");
+    dot.push_str("    anywhere -> attract [label=\"tilt\", style=dashed];\n");
+    dot.push_str("    anywhere -> error [label=\"game_state_fail()\", style=dashed];\n");
+    for state in GameState::all().filter(|state| state_is_transient(**state)) {
+        dot.push_str(&format!("    {} -> attract [label=\"watchdog\", style=dotted];\n", state));
+    }
+
+    let unlisted: Vec<&(GameState, GameState)> = observed
+        .iter()
+        .filter(|&&(from, to)| !edges.iter().any(|&(f, t, _)| f == from && t == to))
+        .collect();
+    for &&(from, to) in unlisted.iter() {
+        dot.push_str(&format!("    {} -> {} [label=\"not in the table\", style=bold, color=red, fontcolor=red];\n", from, to));
+    }
+    dot.push_str("}\n");
+    return (dot, unlisted.len());
+}
+
+// returns how many observed transitions weren't in the table
+pub fn state_graph_write(path: &Path) -> Result<usize, String> {
+    let (dot, unlisted) = state_graph_dot();
+    let mut file = File::create(path).map_err(|e| format!("can't create {}: {}", path.display(), e))?;
+    file.write_all(dot.as_bytes()).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    return Ok(unlisted);
+}
@@ -10,14 +10,66 @@
 //
 // --------------------------------------------------------------------------
 
-pub fn how_high_enter() {
+use rusty_kong::kong::*;
+use rusty_kong::level::*;
+use rusty_kong::locale::*;
+use rusty_kong::sound::*;
+use rusty_kong::video::*;
+use super::game_state_go;
+use super::schedule_after;
+use super::GameState;
+
+// one kong per 25m, stacked up from the bottom with the height beside each
+const STACK_METERS:     u32 = 25;
+const STACK_MAX:        u32 = 6;
+const STACK_X:          i32 = 88;
+const STACK_BOTTOM:     i32 = 26 * TILE_HEIGHT as i32;
+const STACK_LABEL_COL:  u32 = 16;
+// slots clear of the kong & pauline sprites
+const STACK_SPRITE_SLOT:usize = 8;
+
+const BANNER_ROW: u32 = 29;
+
+// the banner stays up longer the higher the player has got
+const HOW_HIGH_TICKS:       u32 = 2 * 60;
+const HOW_HIGH_TICKS_ICON:  u32 = 20;
 
+fn how_high_icons() -> u32 {
+    (level_current_stage().height / STACK_METERS).max(1).min(STACK_MAX)
 }
 
-pub fn how_high_update() {
+fn how_high_ticks() -> u32 {
+    HOW_HIGH_TICKS + (how_high_icons() - 1) * HOW_HIGH_TICKS_ICON
+}
+
+pub fn how_high_enter() {
+    video_bg_clear();
+    video_sprite_hide_all();
 
+    for index in 0..how_high_icons() {
+        let y = STACK_BOTTOM - ((index + 1) * KONG_HEIGHT) as i32;
+        kong_draw_at(STACK_SPRITE_SLOT + index as usize * KONG_SPRITE_COUNT, STACK_X, y, KongPose::Standing);
+        let row = (y / TILE_HEIGHT as i32) as u32 + 2;
+        video_text(STACK_LABEL_COL, row, &format!("{}M", (index + 1) * STACK_METERS), TEXT_PALETTE_WHITE);
+    }
+
+    let banner = locale_text(level_region().how_high);
+    video_text(4, BANNER_ROW, &banner, TEXT_PALETTE_WHITE);
+
+    let level = level_current();
+    video_text(2, 1, &format!("L-{:02}", level.number), TEXT_PALETTE_BLUE);
+
+    music_play(MusicId::HowHigh);
+    schedule_after(how_high_ticks(), how_high_done);
 }
 
-pub fn how_high_leave() {
+fn how_high_done() {
+    game_state_go(GameState::GamePlay);
+}
 
+pub fn how_high_leave() {
+    for index in 0..STACK_MAX as usize {
+        kong_hide_at(STACK_SPRITE_SLOT + index * KONG_SPRITE_COUNT);
+    }
+    video_bg_clear();
 }
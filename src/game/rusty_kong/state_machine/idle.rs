@@ -0,0 +1,41 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use rusty_kong::input::*;
+
+// ticks without any input latched.  a state that should give up on a
+// player who's walked away keeps one, resets it on enter and asks it every
+// update; what happens on the timeout is up to the state.
+#[derive(Clone, Copy, Hash)]
+pub struct IdleTimer {
+    ticks: u32,
+}
+
+impl IdleTimer {
+    pub fn new() -> IdleTimer {
+        IdleTimer { ticks: 0 }
+    }
+
+    pub fn reset(self:&mut IdleTimer) {
+        self.ticks = 0;
+    }
+
+    // call once per update; true on the tick limit is reached, and never
+    // with a limit of 0.  anything held counts as input.
+    pub fn update(self:&mut IdleTimer, limit: u32) -> bool {
+        if input_get() != F_INPUT_NONE {
+            self.ticks = 0;
+            return false;
+        }
+        self.ticks = self.ticks.saturating_add(1);
+        return limit != 0 && self.ticks == limit;
+    }
+}
@@ -10,14 +10,16 @@
 //
 // --------------------------------------------------------------------------
 
-pub fn kong_retreats_enter() {
-
-}
-
-pub fn kong_retreats_update() {
+use rusty_kong::kong::*;
+use rusty_kong::sequence::sequence_start;
 
+// kong climbs off the top with pauline and the level moves on, played out
+// of sequences/kong_retreats.txt
+pub fn kong_retreats_enter() {
+    sequence_start("kong_retreats");
 }
 
 pub fn kong_retreats_leave() {
-
-}
\ No newline at end of file
+    kong_hide();
+    pauline_hide();
+}
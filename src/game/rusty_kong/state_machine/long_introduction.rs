@@ -10,17 +10,16 @@
 //
 // --------------------------------------------------------------------------
 
-pub fn long_intro_enter() {
-    use super::super::video::TileMaps;
-    use super::super::video::video_set_bg;
-
-    video_set_bg(TileMaps::LongIntroduction);
-}
-
-pub fn long_intro_update() {
+use rusty_kong::kong::*;
+use rusty_kong::sequence::sequence_start;
 
+// kong carries pauline up, stomps the girders crooked and roars; the whole
+// thing is sequences/long_intro.txt, this state only plays it
+pub fn long_intro_enter() {
+    sequence_start("long_intro");
 }
 
 pub fn long_intro_leave() {
-
+    kong_hide();
+    pauline_hide();
 }
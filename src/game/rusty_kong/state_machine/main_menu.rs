@@ -0,0 +1,863 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+
+use rusty_kong::community::*;
+use rusty_kong::daily::*;
+use rusty_kong::events::*;
+use rusty_kong::input::*;
+use rusty_kong::level::*;
+use rusty_kong::locale::*;
+use rusty_kong::physics::*;
+use rusty_kong::player::player_start_game;
+use rusty_kong::settings::*;
+use rusty_kong::sound::*;
+use rusty_kong::video::*;
+use super::game_state_go;
+use super::game_state_request_quit;
+use super::GameState;
+use super::IdleTimer;
+
+const MENU_COL: u32 = 8;
+const MENU_ROW: u32 = 10;
+// the settings list is longer, so it starts higher up
+const SETTINGS_ROW: u32 = 5;
+
+#[derive(Clone, Copy, PartialEq)]
+enum MenuEntry {
+    Play,
+    TwoPlayers,
+    Practice,
+    Daily,
+    Community,
+    Settings,
+    Quit,
+}
+
+const MENU_ENTRIES: [MenuEntry; 7] = [
+    MenuEntry::Play,
+    MenuEntry::TwoPlayers,
+    MenuEntry::Practice,
+    MenuEntry::Daily,
+    MenuEntry::Community,
+    MenuEntry::Settings,
+    MenuEntry::Quit,
+];
+
+impl MenuEntry {
+    fn label(&self) -> &'static str {
+        match self {
+            &MenuEntry::Play        => "PLAY",
+            &MenuEntry::TwoPlayers  => "2 PLAYERS",
+            &MenuEntry::Practice    => "PRACTICE",
+            &MenuEntry::Daily       => "DAILY",
+            &MenuEntry::Community   => "COMMUNITY",
+            &MenuEntry::Settings    => "SETTINGS",
+            &MenuEntry::Quit        => "QUIT",
+        }
+    }
+}
+
+// the settings page edits the live settings, and every change is written
+// back to settings.cfg
+#[derive(Clone, Copy, PartialEq)]
+enum SettingsEntry {
+    Language,
+    Palette,
+    Speed,
+    Narration,
+    Timer,
+    Physics,
+    Remix,
+    Overscan,
+    SafeArea,
+    AudioLatency,
+    SoundTest,
+    InputTest,
+    Back,
+}
+
+const SETTINGS_ENTRIES: [SettingsEntry; 13] = [
+    SettingsEntry::Language,
+    SettingsEntry::Palette,
+    SettingsEntry::Speed,
+    SettingsEntry::Narration,
+    SettingsEntry::Timer,
+    SettingsEntry::Physics,
+    SettingsEntry::Remix,
+    SettingsEntry::Overscan,
+    SettingsEntry::SafeArea,
+    SettingsEntry::AudioLatency,
+    SettingsEntry::SoundTest,
+    SettingsEntry::InputTest,
+    SettingsEntry::Back,
+];
+
+const PALETTE_SETS: [PaletteSet; 4] = [
+    PaletteSet::Arcade,
+    PaletteSet::Deuteranopia,
+    PaletteSet::Protanopia,
+    PaletteSet::Tritanopia,
+];
+
+const LANGUAGES: [Language; 4] = [
+    Language::English,
+    Language::French,
+    Language::German,
+    Language::Spanish,
+];
+
+const SPEED_STEP: u8 = 10;
+const OVERSCAN_STEP: u32 = 2;
+const AUDIO_LATENCY_STEP: u32 = 10;
+
+fn palette_set_label(palette_set: PaletteSet) -> &'static str {
+    match palette_set {
+        PaletteSet::Arcade          => "ARCADE",
+        PaletteSet::Deuteranopia    => "DEUTERANOPIA",
+        PaletteSet::Protanopia      => "PROTANOPIA",
+        PaletteSet::Tritanopia      => "TRITANOPIA",
+    }
+}
+
+// in the language itself, less the accents the font doesn't have
+fn language_label(language: Language) -> &'static str {
+    match language {
+        Language::English   => "ENGLISH",
+        Language::French    => "FRANCAIS",
+        Language::German    => "DEUTSCH",
+        Language::Spanish   => "ESPANOL",
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value { "ON" } else { "OFF" }
+}
+
+impl SettingsEntry {
+    fn label(&self, settings: &Settings) -> String {
+        match self {
+            &SettingsEntry::Language    => format!("LANG   {}", language_label(settings.language)),
+            &SettingsEntry::Palette     => format!("COLORS {}", palette_set_label(settings.palette_set)),
+            &SettingsEntry::Speed       => format!("SPEED  {}", settings.game_speed),
+            &SettingsEntry::Narration   => format!("VOICE  {}", on_off(settings.narration)),
+            &SettingsEntry::Timer       => format!("TIMER  {}", on_off(settings.speedrun_timer)),
+            &SettingsEntry::Physics     => format!("PHYSICS {}", settings.physics.name().to_uppercase()),
+            &SettingsEntry::Remix       => format!("REMIX  {}", on_off(settings.remix)),
+            &SettingsEntry::Overscan    => format!("CROP   {}", settings.overscan),
+            &SettingsEntry::SafeArea    => format!("SAFE   {}", on_off(settings.safe_area_guide)),
+            &SettingsEntry::AudioLatency => format!("SOUND  {}MS", settings.audio_latency_ms),
+            &SettingsEntry::SoundTest   => "SOUND TEST".to_string(),
+            &SettingsEntry::InputTest   => "INPUT TEST".to_string(),
+            &SettingsEntry::Back        => "BACK".to_string(),
+        }
+    }
+
+    // the rows that open a page or go back have nothing to change
+    fn has_value(&self) -> bool {
+        match self {
+            &SettingsEntry::SoundTest | &SettingsEntry::InputTest | &SettingsEntry::Back => false,
+            _ => true,
+        }
+    }
+
+    // left is -1, right is +1
+    fn change(&self, settings: &mut Settings, direction: i32) {
+        match self {
+            &SettingsEntry::Language => {
+                let count = LANGUAGES.len() as i32;
+                let index = LANGUAGES.iter().position(|&l| l == settings.language).unwrap_or(0) as i32;
+                settings.language = LANGUAGES[((index + direction + count) % count) as usize];
+                locale_load(settings.language);
+            },
+            &SettingsEntry::Palette => {
+                let count = PALETTE_SETS.len() as i32;
+                let index = PALETTE_SETS.iter().position(|&p| p == settings.palette_set).unwrap_or(0) as i32;
+                settings.palette_set = PALETTE_SETS[((index + direction + count) % count) as usize];
+            },
+            &SettingsEntry::Speed => {
+                let speed = settings.game_speed as i32 + direction * SPEED_STEP as i32;
+                settings.game_speed = speed.max(GAME_SPEED_MIN as i32).min(GAME_SPEED_STANDARD as i32) as u8;
+            },
+            &SettingsEntry::Narration   => settings.narration = !settings.narration,
+            &SettingsEntry::Timer       => settings.speedrun_timer = !settings.speedrun_timer,
+            &SettingsEntry::Physics     => {
+                // only two presets, either direction flips it; takes effect
+                // from the next stage on
+                settings.physics =
+                    match settings.physics {
+                        PhysicsPreset::Arcade     => PhysicsPreset::Modernized,
+                        PhysicsPreset::Modernized => PhysicsPreset::Arcade,
+                    };
+                physics_load(settings.physics);
+            },
+            &SettingsEntry::Remix       => settings.remix = !settings.remix,
+            &SettingsEntry::Overscan    => {
+                let overscan = settings.overscan as i32 + direction * OVERSCAN_STEP as i32;
+                settings.overscan = overscan.max(0).min(OVERSCAN_MAX as i32) as u32;
+            },
+            &SettingsEntry::SafeArea    => settings.safe_area_guide = !settings.safe_area_guide,
+            &SettingsEntry::AudioLatency => {
+                let latency = settings.audio_latency_ms as i32 + direction * AUDIO_LATENCY_STEP as i32;
+                settings.audio_latency_ms = latency.max(0).min(AUDIO_LATENCY_MAX as i32) as u32;
+            },
+            &SettingsEntry::SoundTest   => {},
+            &SettingsEntry::InputTest   => {},
+            &SettingsEntry::Back        => {},
+        }
+    }
+}
+
+// the sound test plays whatever's picked, like a cabinet's service mode.
+// entries without a sample are still sent to the backend, and flagged.
+#[derive(Clone, Copy, PartialEq)]
+enum SoundTestEntry {
+    Sound(SoundId),
+    Music(MusicId),
+    StopMusic,
+    Back,
+}
+
+fn sound_test_entries() -> Vec<SoundTestEntry> {
+    let mut entries: Vec<SoundTestEntry> = ALL_SOUNDS.iter().map(|&id| SoundTestEntry::Sound(id)).collect();
+    entries.extend(ALL_MUSIC.iter().map(|&id| SoundTestEntry::Music(id)));
+    entries.push(SoundTestEntry::StopMusic);
+    entries.push(SoundTestEntry::Back);
+    return entries;
+}
+
+impl SoundTestEntry {
+    // the list's row, with a blank line before each group
+    fn row(&self, index: usize) -> u32 {
+        let gaps =
+            match self {
+                &SoundTestEntry::Sound(_)   => 0,
+                &SoundTestEntry::Music(_)   => 1,
+                _                           => 2,
+            };
+        SOUND_TEST_ROW + index as u32 + gaps
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            &SoundTestEntry::Sound(id)  => id.name(),
+            &SoundTestEntry::Music(id)  => id.name(),
+            &SoundTestEntry::StopMusic  => "STOP MUSIC",
+            &SoundTestEntry::Back       => "BACK",
+        }
+    }
+
+    fn asset(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            &SoundTestEntry::Sound(id)  => Some((SOUND_DIR, id.name())),
+            &SoundTestEntry::Music(id)  => Some((MUSIC_DIR, id.name())),
+            _                           => None,
+        }
+    }
+}
+
+const SOUND_TEST_ROW: u32 = 6;
+
+// every button is live on the input test, so leaving it takes holding
+// start this long
+const INPUT_TEST_EXIT_TICKS: u32 = 2 * 60;
+const INPUT_TEST_ROW: u32 = 6;
+const DAILY_ROW: u32 = 6;
+const CONTROLLERS_ROW: u32 = 8;
+// a pad's name is cut to fit beside its player
+const CONTROLLERS_NAME_MAX: usize = 16;
+const DAILY_SCORES_SHOWN: usize = 5;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Page {
+    Main,
+    Settings,
+    SoundTest,
+    InputTest,
+    Daily,
+    Community,
+    Controllers,
+}
+
+struct Menu {
+    page: Page,
+    selected: usize,
+    practice_stage: usize,
+    settings_selected: usize,
+    sound_test_selected: usize,
+    // the entries with no sample on disk or in the pack, looked up once
+    // when the page opens
+    sound_test_missing: Vec<bool>,
+    // ticks start has been held on the input test
+    input_test_held: u32,
+    // the day the daily page was opened on and its scores, best first,
+    // each with whether it was flagged for turbo
+    daily_day: u32,
+    daily_scores: Vec<(u32, bool)>,
+    daily_selected: usize,
+    // a slot in the community list, or COMMUNITY_SHOWN for BACK
+    community_selected: usize,
+    // the controllers page's claims so far, as settings.cfg keeps them,
+    // and the player the next start claims for
+    pad_claims: [String; 2],
+    pad_claiming: usize,
+    // back to attract after settings.menu_idle_ticks of nothing, on any page
+    idle: IdleTimer,
+}
+
+fn menu_fresh() -> Menu {
+    Menu {
+        page: Page::Main,
+        selected: 0,
+        practice_stage: 0,
+        settings_selected: 0,
+        sound_test_selected: 0,
+        sound_test_missing: Vec::new(),
+        input_test_held: 0,
+        daily_day: 0,
+        daily_scores: Vec::new(),
+        daily_selected: 0,
+        community_selected: 0,
+        pad_claims: [String::new(), String::new()],
+        pad_claiming: 0,
+        idle: IdleTimer::new()
+    }
+}
+
+thread_local!(
+    static MENU:RefCell<Menu> = RefCell::new(menu_fresh());
+);
+
+// back to how a fresh launch finds it: the cursors aren't part of any
+// savestate, but where they start decides what a journal plays back as
+pub fn main_menu_reset() {
+    MENU.with(|cell| {*cell.borrow_mut() = menu_fresh();});
+}
+
+// the entries are up rather than one of the pages they lead to
+pub fn main_menu_on_main_page() -> bool {
+    MENU.with(|cell| cell.borrow().page == Page::Main)
+}
+
+// the community page's cursor, for the list drawn over it
+pub fn main_menu_community_slot() -> Option<usize> {
+    MENU.with(|cell| {
+        let menu = cell.borrow();
+        if menu.page == Page::Community { Some(menu.community_selected) } else { None }
+    })
+}
+
+fn menu_palette(selected: bool) -> u8 {
+    if selected { TEXT_PALETTE_WHITE } else { TEXT_PALETTE_BLUE }
+}
+
+fn main_menu_draw_main(selected: usize, practice_stage: usize) {
+    for (index, entry) in MENU_ENTRIES.iter().enumerate() {
+        let row = MENU_ROW + index as u32 * 2;
+        video_text(MENU_COL, row, entry.label(), menu_palette(index == selected));
+    }
+
+    // the stage practice will load, picked with left/right
+    let row = MENU_ROW + MENU_ENTRIES.len() as u32 * 2;
+    let stage = &level_campaign().stages[practice_stage];
+    if MENU_ENTRIES[selected] == MenuEntry::Practice {
+        video_text(MENU_COL, row, &format!("{}M {}", stage.height, stage.name), TEXT_PALETTE_RED);
+    }
+}
+
+fn main_menu_draw_settings(selected: usize) {
+    let settings = settings_get();
+    video_text(MENU_COL, SETTINGS_ROW - 2, "SETTINGS", TEXT_PALETTE_RED);
+    for (index, entry) in SETTINGS_ENTRIES.iter().enumerate() {
+        let row = SETTINGS_ROW + index as u32 * 2;
+        video_text(MENU_COL - 4, row, &entry.label(&settings), menu_palette(index == selected));
+    }
+}
+
+fn main_menu_draw_sound_test() {
+    let (selected, missing) = MENU.with(|cell| {
+        let menu = cell.borrow();
+        (menu.sound_test_selected, menu.sound_test_missing.clone())
+    });
+    video_text(MENU_COL, SOUND_TEST_ROW - 2, "SOUND TEST", TEXT_PALETTE_RED);
+    for (index, entry) in sound_test_entries().iter().enumerate() {
+        let row = entry.row(index);
+        video_text(MENU_COL - 4, row, entry.label(), menu_palette(index == selected));
+        if missing.get(index).cloned().unwrap_or(false) {
+            video_text(MENU_COL + 10, row, "NO FILE", TEXT_PALETTE_RED);
+        }
+    }
+}
+
+// one row per action: its name lit while the latched flag is down, then
+// the key and the pad button bound to it, each lit while held
+fn main_menu_draw_input_test() {
+    let draw_lit = |col: u32, row: u32, text: &str, lit: bool| {
+        video_text(col, row, text, if lit { TEXT_PALETTE_WHITE } else { TEXT_PALETTE_BLUE });
+    };
+    let (keys, buttons, (stick_x, stick_y)) = input_raw();
+    let flags = input_get();
+
+    video_text(MENU_COL - 2, INPUT_TEST_ROW - 2, "INPUT TEST", TEXT_PALETTE_RED);
+    video_text(1, INPUT_TEST_ROW, "ACTION  KEY    PAD", TEXT_PALETTE_RED);
+    for (index, binding) in INPUT_BINDINGS.iter().enumerate() {
+        let row = INPUT_TEST_ROW + 1 + index as u32;
+        draw_lit(1, row, binding.name, flags & binding.flag != 0);
+        draw_lit(9, row, binding.key_label, keys & binding.flag != 0);
+        draw_lit(16, row, binding.button_label, buttons & binding.flag != 0);
+    }
+
+    let row = INPUT_TEST_ROW + 2 + INPUT_BINDINGS.len() as u32;
+    video_text(1, row, &format!("STICK {} {}", stick_x, stick_y), TEXT_PALETTE_WHITE);
+    video_text(1, row + 1, "NOT BOUND", TEXT_PALETTE_BLUE);
+
+    let devices = input_devices();
+    video_text(1, row + 3, "DEVICES", TEXT_PALETTE_RED);
+    if devices.is_empty() {
+        video_text(1, row + 4, "NONE - KEYBOARD ONLY", TEXT_PALETTE_WHITE);
+    }
+    for (index, device) in devices.iter().take(4).enumerate() {
+        video_text(1, row + 4 + index as u32, device, TEXT_PALETTE_WHITE);
+    }
+
+    video_text(1, SCREEN_HEIGHT / TILE_HEIGHT - 2, "HOLD START TO EXIT", TEXT_PALETTE_RED);
+}
+
+// the day, its stage order and the best local scores posted to it
+fn main_menu_draw_daily() {
+    let (day, scores, selected) = MENU.with(|cell| {
+        let menu = cell.borrow();
+        (menu.daily_day, menu.daily_scores.clone(), menu.daily_selected)
+    });
+    let campaign = level_campaign_at(0);
+    let order: Vec<String> = daily_order(day)
+        .iter()
+        .map(|&stage| format!("{}M", campaign.stages[stage].height))
+        .collect();
+
+    video_text(MENU_COL - 2, DAILY_ROW - 2, "DAILY CHALLENGE", TEXT_PALETTE_RED);
+    video_text(MENU_COL, DAILY_ROW, &daily_date(day), TEXT_PALETTE_WHITE);
+    video_text(MENU_COL - 4, DAILY_ROW + 2, &order.join(" "), TEXT_PALETTE_WHITE);
+
+    video_text(MENU_COL - 1, DAILY_ROW + 5, "RANK  SCORE", TEXT_PALETTE_RED);
+    if scores.is_empty() {
+        video_text(MENU_COL - 1, DAILY_ROW + 7, "NO SCORES YET", TEXT_PALETTE_BLUE);
+    }
+    for (index, &(score, turbo)) in scores.iter().take(DAILY_SCORES_SHOWN).enumerate() {
+        let row = DAILY_ROW + 7 + index as u32;
+        video_text(MENU_COL - 1, row, &format!("{:>4}  {:>6}", index + 1, score), TEXT_PALETTE_WHITE);
+        if turbo {
+            video_text(MENU_COL + 12, row, "TURBO", TEXT_PALETTE_RED);
+        }
+    }
+
+    let row = DAILY_ROW + 8 + DAILY_SCORES_SHOWN as u32;
+    video_text(MENU_COL, row, "START", menu_palette(selected == 0));
+    video_text(MENU_COL, row + 2, "BACK", menu_palette(selected == 1));
+}
+
+// the heading & BACK; the list itself comes and goes with the network, so
+// the community module draws it over the top
+fn main_menu_draw_community() {
+    let selected = MENU.with(|cell| cell.borrow().community_selected);
+    video_text(MENU_COL, COMMUNITY_ROW - 4, "COMMUNITY", TEXT_PALETTE_RED);
+    let row = COMMUNITY_ROW + COMMUNITY_SHOWN as u32 * 2;
+    video_text(MENU_COL, row, "BACK", menu_palette(selected == COMMUNITY_SHOWN));
+}
+
+fn pad_claim_label(claim: &str) -> String {
+    if claim.is_empty() {
+        return "ANY PAD".to_string();
+    }
+    if claim == INPUT_PAD_KEYBOARD {
+        return "KEYBOARD".to_string();
+    }
+    match input_pad_label(claim) {
+        Some(label) => label.to_uppercase().chars().take(CONTROLLERS_NAME_MAX).collect(),
+        None => "PAD NOT FOUND".to_string(),
+    }
+}
+
+// each player and the device they've claimed, the one to claim next lit
+fn main_menu_draw_controllers() {
+    let (claims, claiming) = MENU.with(|cell| {
+        let menu = cell.borrow();
+        (menu.pad_claims.clone(), menu.pad_claiming)
+    });
+    video_text(MENU_COL - 2, CONTROLLERS_ROW - 4, "CONTROLLERS", TEXT_PALETTE_RED);
+    video_text(1, CONTROLLERS_ROW - 2, "PRESS START ON YOURS", TEXT_PALETTE_WHITE);
+    for (index, claim) in claims.iter().enumerate() {
+        let row = CONTROLLERS_ROW + index as u32 * 2;
+        video_text(1, row, &format!("PLAYER {}", index + 1), menu_palette(index == claiming));
+        video_text(10, row, &pad_claim_label(claim), menu_palette(index == claiming));
+    }
+    let devices = input_devices();
+    let row = CONTROLLERS_ROW + 6;
+    video_text(1, row, "DEVICES", TEXT_PALETTE_RED);
+    video_text(1, row + 1, "KEYBOARD - BOTH PLAYERS", TEXT_PALETTE_BLUE);
+    for (index, device) in devices.iter().take(4).enumerate() {
+        video_text(1, row + 2 + index as u32, device, TEXT_PALETTE_BLUE);
+    }
+    video_text(1, SCREEN_HEIGHT / TILE_HEIGHT - 2, "JUMP TO PLAY AS THEY ARE", TEXT_PALETTE_RED);
+}
+
+fn main_menu_draw() {
+    let (page, selected, practice_stage, settings_selected) = MENU.with(|cell| {
+        let menu = cell.borrow();
+        (menu.page, menu.selected, menu.practice_stage, menu.settings_selected)
+    });
+
+    video_bg_clear();
+    match page {
+        Page::Main          => main_menu_draw_main(selected, practice_stage),
+        Page::Settings      => main_menu_draw_settings(settings_selected),
+        Page::SoundTest     => main_menu_draw_sound_test(),
+        Page::InputTest     => main_menu_draw_input_test(),
+        Page::Daily         => main_menu_draw_daily(),
+        Page::Community     => main_menu_draw_community(),
+        Page::Controllers   => main_menu_draw_controllers(),
+    }
+}
+
+// the row under the cursor as it's drawn, for narration; None on the
+// pages without one
+pub fn main_menu_selected_label() -> Option<String> {
+    let (page, selected, settings_selected, sound_test_selected, daily_selected, community_selected) = MENU.with(|cell| {
+        let menu = cell.borrow();
+        (menu.page, menu.selected, menu.settings_selected, menu.sound_test_selected, menu.daily_selected, menu.community_selected)
+    });
+    match page {
+        Page::Main          => Some(MENU_ENTRIES[selected].label().to_string()),
+        Page::Settings      => Some(SETTINGS_ENTRIES[settings_selected].label(&settings_get())),
+        Page::SoundTest     => sound_test_entries().get(sound_test_selected).map(|entry| entry.label().to_string()),
+        Page::Daily         => Some(if daily_selected == 0 { "START" } else { "BACK" }.to_string()),
+        Page::Community if community_selected == COMMUNITY_SHOWN => Some("BACK".to_string()),
+        _                   => None,
+    }
+}
+
+pub fn main_menu_enter() {
+    MENU.with(|cell| {
+        let mut menu = cell.borrow_mut();
+        menu.page = Page::Main;
+        menu.idle.reset();
+    });
+    main_menu_draw();
+    events_publish(GameEvent::MenuMoved);
+}
+
+fn main_menu_confirm(entry: MenuEntry, practice_stage: usize) {
+    match entry {
+        MenuEntry::Play => {
+            player_start_game(1);
+            level_start_game();
+            game_state_go(level_campaign().first_state);
+        },
+        MenuEntry::TwoPlayers => main_menu_open_controllers(),
+        MenuEntry::Practice => {
+            player_start_game(1);
+            level_start_practice(practice_stage);
+            game_state_go(GameState::GamePlay);
+        },
+        MenuEntry::Daily => main_menu_open_daily(),
+        MenuEntry::Community => {
+            MENU.with(|cell| {
+                let mut menu = cell.borrow_mut();
+                menu.page = Page::Community;
+                menu.community_selected = 0;
+            });
+            community_refresh();
+        },
+        MenuEntry::Settings => {
+            MENU.with(|cell| {
+                let mut menu = cell.borrow_mut();
+                menu.page = Page::Settings;
+                menu.settings_selected = 0;
+            });
+        },
+        MenuEntry::Quit => game_state_request_quit(),
+    }
+}
+
+fn main_menu_update_main() {
+    let entry_count = MENU_ENTRIES.len();
+    let stage_count = level_campaign().stages.len();
+
+    MENU.with(|cell| {
+        let mut menu = cell.borrow_mut();
+        if input_is_pressed(F_INPUT_UP) {
+            menu.selected = (menu.selected + entry_count - 1) % entry_count;
+        }
+        if input_is_pressed(F_INPUT_DOWN) {
+            menu.selected = (menu.selected + 1) % entry_count;
+        }
+        if MENU_ENTRIES[menu.selected] == MenuEntry::Practice {
+            if input_is_pressed(F_INPUT_LEFT) {
+                menu.practice_stage = (menu.practice_stage + stage_count - 1) % stage_count;
+            }
+            if input_is_pressed(F_INPUT_RIGHT) {
+                menu.practice_stage = (menu.practice_stage + 1) % stage_count;
+            }
+        }
+        // the campaign may have changed since the last visit
+        menu.practice_stage %= stage_count;
+    });
+
+    if input_is_pressed(F_INPUT_START) || input_is_pressed(F_INPUT_JUMP) {
+        let (selected, practice_stage) = MENU.with(|cell| {
+            let menu = cell.borrow();
+            (menu.selected, menu.practice_stage)
+        });
+        main_menu_confirm(MENU_ENTRIES[selected], practice_stage);
+    }
+}
+
+fn main_menu_update_settings() {
+    let entry_count = SETTINGS_ENTRIES.len();
+    let selected = MENU.with(|cell| {
+        let mut menu = cell.borrow_mut();
+        if input_is_pressed(F_INPUT_UP) {
+            menu.settings_selected = (menu.settings_selected + entry_count - 1) % entry_count;
+        }
+        if input_is_pressed(F_INPUT_DOWN) {
+            menu.settings_selected = (menu.settings_selected + 1) % entry_count;
+        }
+        menu.settings_selected
+    });
+
+    let entry = SETTINGS_ENTRIES[selected];
+    let direction =
+        if input_is_pressed(F_INPUT_LEFT) {
+            -1
+        } else if input_is_pressed(F_INPUT_RIGHT) || input_is_pressed(F_INPUT_JUMP) {
+            1
+        } else {
+            0
+        };
+    // a row's label shows its value, so the same label is nothing changed
+    // (a clamped step has nowhere to go) and nothing to save
+    if direction != 0 && entry.has_value() {
+        let mut settings = settings_get();
+        let before = entry.label(&settings);
+        entry.change(&mut settings, direction);
+        if entry.label(&settings) != before {
+            settings_set(settings);
+            settings_save_menu();
+        }
+    }
+
+    if entry == SettingsEntry::Back && (input_is_pressed(F_INPUT_START) || input_is_pressed(F_INPUT_JUMP)) {
+        MENU.with(|cell| {cell.borrow_mut().page = Page::Main;});
+    }
+    if entry == SettingsEntry::SoundTest && (input_is_pressed(F_INPUT_START) || input_is_pressed(F_INPUT_JUMP)) {
+        main_menu_open_sound_test();
+    }
+    if entry == SettingsEntry::InputTest && (input_is_pressed(F_INPUT_START) || input_is_pressed(F_INPUT_JUMP)) {
+        MENU.with(|cell| {
+            let mut menu = cell.borrow_mut();
+            menu.page = Page::InputTest;
+            menu.input_test_held = 0;
+        });
+    }
+}
+
+fn main_menu_update_input_test() {
+    MENU.with(|cell| {
+        let mut menu = cell.borrow_mut();
+        menu.input_test_held = if input_is_down(F_INPUT_START) { menu.input_test_held + 1 } else { 0 };
+        if menu.input_test_held >= INPUT_TEST_EXIT_TICKS {
+            menu.page = Page::Settings;
+        }
+    });
+}
+
+fn main_menu_open_daily() {
+    let day = daily_today();
+    let scores: Vec<(u32, bool)> = daily_scores(day).iter().map(|entry| (entry.score, entry.turbo)).collect();
+    MENU.with(|cell| {
+        let mut menu = cell.borrow_mut();
+        menu.page = Page::Daily;
+        menu.daily_day = day;
+        menu.daily_scores = scores;
+        menu.daily_selected = 0;
+    });
+}
+
+fn main_menu_update_daily() {
+    let (day, selected) = MENU.with(|cell| {
+        let mut menu = cell.borrow_mut();
+        if input_is_pressed(F_INPUT_UP) || input_is_pressed(F_INPUT_DOWN) {
+            menu.daily_selected = 1 - menu.daily_selected;
+        }
+        (menu.daily_day, menu.daily_selected)
+    });
+    if input_is_pressed(F_INPUT_START) || input_is_pressed(F_INPUT_JUMP) {
+        if selected == 0 {
+            daily_play(day);
+        } else {
+            MENU.with(|cell| {cell.borrow_mut().page = Page::Main;});
+        }
+    }
+}
+
+// the cursor covers every slot whether or not there's a pack in it, so
+// the page plays out the same with or without a network
+fn main_menu_update_community() {
+    let count = COMMUNITY_SHOWN + 1;
+    let selected = MENU.with(|cell| {
+        let mut menu = cell.borrow_mut();
+        if input_is_pressed(F_INPUT_UP) {
+            menu.community_selected = (menu.community_selected + count - 1) % count;
+        }
+        if input_is_pressed(F_INPUT_DOWN) {
+            menu.community_selected = (menu.community_selected + 1) % count;
+        }
+        menu.community_selected
+    });
+    if input_is_pressed(F_INPUT_START) || input_is_pressed(F_INPUT_JUMP) {
+        if selected == COMMUNITY_SHOWN {
+            MENU.with(|cell| {cell.borrow_mut().page = Page::Main;});
+        } else {
+            community_get(selected);
+        }
+    }
+}
+
+fn main_menu_open_sound_test() {
+    let missing: Vec<bool> = sound_test_entries()
+        .iter()
+        .map(|entry| match entry.asset() {
+            Some((dir, name)) => sound_asset(dir, name).is_none(),
+            None => false,
+        })
+        .collect();
+    info!("sound test: {} of {} sounds & tracks have no sample", missing.iter().filter(|&&m| m).count(), ALL_SOUNDS.len() + ALL_MUSIC.len());
+    MENU.with(|cell| {
+        let mut menu = cell.borrow_mut();
+        menu.page = Page::SoundTest;
+        menu.sound_test_selected = 0;
+        menu.sound_test_missing = missing;
+    });
+}
+
+fn main_menu_update_sound_test() {
+    let entries = sound_test_entries();
+    let entry_count = entries.len();
+    let selected = MENU.with(|cell| {
+        let mut menu = cell.borrow_mut();
+        if input_is_pressed(F_INPUT_UP) {
+            menu.sound_test_selected = (menu.sound_test_selected + entry_count - 1) % entry_count;
+        }
+        if input_is_pressed(F_INPUT_DOWN) {
+            menu.sound_test_selected = (menu.sound_test_selected + 1) % entry_count;
+        }
+        menu.sound_test_selected
+    });
+
+    if !(input_is_pressed(F_INPUT_START) || input_is_pressed(F_INPUT_JUMP)) {
+        return;
+    }
+    match entries[selected] {
+        SoundTestEntry::Sound(id) => {
+            info!("sound test: {}", id.name());
+            sound_play(id);
+        },
+        SoundTestEntry::Music(id) => {
+            info!("sound test: {}", id.name());
+            music_play(id);
+        },
+        SoundTestEntry::StopMusic => music_stop(),
+        SoundTestEntry::Back => {
+            music_stop();
+            MENU.with(|cell| {cell.borrow_mut().page = Page::Settings;});
+        },
+    }
+}
+
+// 2 players goes by here first: the claims start as settings.cfg has them
+fn main_menu_open_controllers() {
+    let claims = settings_read(|s| s.player_pads.clone());
+    MENU.with(|cell| {
+        let mut menu = cell.borrow_mut();
+        menu.page = Page::Controllers;
+        menu.pad_claims = claims;
+        menu.pad_claiming = 0;
+    });
+}
+
+// start claims whichever device it was pressed on for the next player, and
+// the game's on once both have one, or straight away on jump.  a pad the
+// other player has already is refused; the keyboard's shared.  what's
+// claimed is only ever the input layer's business: the page moves on the
+// latched presses alone, so a playback without the pads goes the same way
+fn main_menu_update_controllers() {
+    let done =
+        if input_is_pressed(F_INPUT_START) {
+            let device = input_start_held_by().unwrap_or_default();
+            MENU.with(|cell| {
+                let mut menu = cell.borrow_mut();
+                let claiming = menu.pad_claiming;
+                let taken = device != INPUT_PAD_KEYBOARD && !device.is_empty() && menu.pad_claims[..claiming].contains(&device);
+                if taken {
+                    info!("controllers: {} is already player 1's", pad_claim_label(&device));
+                    return false;
+                }
+                info!("controllers: player {} claims {}", claiming + 1, pad_claim_label(&device));
+                menu.pad_claims[claiming] = device;
+                menu.pad_claiming += 1;
+                menu.pad_claiming == menu.pad_claims.len()
+            })
+        } else {
+            input_is_pressed(F_INPUT_JUMP)
+        };
+    if !done {
+        return;
+    }
+
+    let mut settings = settings_get();
+    settings.player_pads = MENU.with(|cell| cell.borrow().pad_claims.clone());
+    settings_set(settings);
+    settings_save_pads();
+    player_start_game(2);
+    level_start_game();
+    game_state_go(level_campaign().first_state);
+}
+
+pub fn main_menu_update() {
+    let limit = settings_read(|s| s.menu_idle_ticks);
+    if MENU.with(|cell| cell.borrow_mut().idle.update(limit)) {
+        info!("main menu: no input for {} ticks, back to attract", limit);
+        music_stop();
+        game_state_go(GameState::Attract);
+        return;
+    }
+    let before = main_menu_selected_label();
+    match MENU.with(|cell| cell.borrow().page) {
+        Page::Main          => main_menu_update_main(),
+        Page::Settings      => main_menu_update_settings(),
+        Page::SoundTest     => main_menu_update_sound_test(),
+        Page::InputTest     => main_menu_update_input_test(),
+        Page::Daily         => main_menu_update_daily(),
+        Page::Community     => main_menu_update_community(),
+        Page::Controllers   => main_menu_update_controllers(),
+    }
+    // a new row, a row's value changed or a new page
+    if main_menu_selected_label() != before {
+        events_publish(GameEvent::MenuMoved);
+    }
+    main_menu_draw();
+}
+
+pub fn main_menu_leave() {
+    video_bg_clear();
+}
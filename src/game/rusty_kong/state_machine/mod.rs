@@ -11,10 +11,20 @@
 // --------------------------------------------------------------------------
 
 use std::cell::RefCell;
-use std::marker::Sync;
 use std::fmt::Error;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::convert::TryFrom;
+use std::slice::Iter;
+
+use super::hashing::StateHasher;
+use super::savestate::SaveState;
+use super::events::GameEvent;
+use super::events::events_publish;
+use super::sequence::sequence_cancel;
+use super::sequence::sequence_update;
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub enum GameState {
@@ -27,6 +37,10 @@ pub enum GameState {
     PlayerDies,
     PlayerWins,
     KongRetreats,
+    MainMenu,
+    Error,
+    GameOver,
+    Paused,
 }
 
 impl Display for GameState {
@@ -40,37 +54,122 @@ impl Display for GameState {
             &GameState::GamePlay            => write!(f, "game_play"),
             &GameState::PlayerDies          => write!(f, "player_dies"),
             &GameState::PlayerWins          => write!(f, "player_wins"),
-            &GameState::KongRetreats        => write!(f, "kong_retreats")
+            &GameState::KongRetreats        => write!(f, "kong_retreats"),
+            &GameState::MainMenu            => write!(f, "main_menu"),
+            &GameState::Error               => write!(f, "error"),
+            &GameState::GameOver            => write!(f, "game_over"),
+            &GameState::Paused              => write!(f, "paused")
         }
     }
 }
 
+// what one game_state_update() did.  without a transition from and to are
+// both the current state; ticks is how long the current state has run.
+#[derive(Clone, Copy)]
+pub struct StateReport {
+    pub transitioned: bool,
+    pub from: GameState,
+    pub to: GameState,
+    pub ticks: u32,
+}
+
+// every state that can be asked for by name; None is the machine's resting
+// value, not somewhere to go
+const ALL_STATES: [GameState; 12] = [
+    GameState::Boot,
+    GameState::Attract,
+    GameState::LongIntroduction,
+    GameState::HowHigh,
+    GameState::GamePlay,
+    GameState::PlayerDies,
+    GameState::PlayerWins,
+    GameState::KongRetreats,
+    GameState::MainMenu,
+    GameState::Error,
+    GameState::GameOver,
+    GameState::Paused,
+];
+
+impl GameState {
+    pub fn all() -> Iter<'static, GameState> {
+        ALL_STATES.iter()
+    }
+}
+
+// accepts the same names Display produces
+impl FromStr for GameState {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<GameState, String> {
+        match GameState::all().find(|state| state.to_string() == name) {
+            Some(state) => Ok(*state),
+            None => {
+                let names: Vec<String> = GameState::all().map(|state| state.to_string()).collect();
+                Err(format!("unknown state \"{}\", expected one of: {}", name, names.join(", ")))
+            },
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a str> for GameState {
+    type Error = String;
+
+    fn try_from(name: &'a str) -> Result<GameState, String> {
+        name.parse()
+    }
+}
+
 struct StateHandlers {
     enter: fn(),
     update: fn(),
     leave: fn(),
-    first_update: RefCell<bool>
-}
-
-unsafe impl Sync for StateHandlers {
 }
 
+#[derive(Clone, Hash)]
 struct States {
     previous: GameState,
     current: GameState,
     next: GameState,
+    // update ticks since current was entered
+    ticks: u32,
+    // states suspended under current by game_state_push(), with their ticks
+    stack: Vec<(GameState, u32)>,
+    push: GameState,
+    pop: bool,
 }
 
 thread_local!(
     static STATE:RefCell<States> = RefCell::new(States {
         previous: GameState::None,
         current:  GameState::None,
-        next:     GameState::None
+        next:     GameState::None,
+        ticks:    0,
+        stack:    Vec::new(),
+        push:     GameState::None,
+        pop:      false
     });
+    // states whose first update has been logged since they were entered;
+    // only for the log, so it's kept out of the hash and the savestate
+    static FIRST_UPDATES:RefCell<Vec<GameState>> = RefCell::new(Vec::new());
 );
 
+mod idle;
+use self::idle::*;
+
+mod schedule;
+pub use self::schedule::*;
+
+mod tween;
+pub use self::tween::*;
+
+mod graph;
+pub use self::graph::state_graph_write;
+use self::graph::state_graph_observe;
+
 mod boot;
 use self::boot::*;
+pub use self::boot::boot_init;
+pub use self::boot::boot_decode_wait;
 
 mod attract;
 use self::attract::*;
@@ -93,76 +192,104 @@ use self::player_wins::*;
 mod kong_retreats;
 use self::kong_retreats::*;
 
+mod main_menu;
+use self::main_menu::*;
+pub use self::main_menu::main_menu_reset;
+pub use self::main_menu::main_menu_on_main_page;
+pub use self::main_menu::main_menu_community_slot;
+pub use self::main_menu::main_menu_selected_label;
+
+mod game_over;
+use self::game_over::*;
+
+mod paused;
+use self::paused::*;
+
+mod error_screen;
+use self::error_screen::*;
+
 mod state_nop;
 use self::state_nop::*;
 
-#[allow(dead_code)]
-lazy_static! {
-    static ref STATE_HANDLERS:Vec<StateHandlers> = vec!(
-        StateHandlers {
-            enter: state_nop,
-            update: state_nop,
-            leave: state_nop,
-            first_update: RefCell::new(true)
-        },
-
-        StateHandlers {
-            enter: boot_enter,
-            update: boot_update,
-            leave: boot_leave,
-            first_update: RefCell::new(true)
-        },
-
-        StateHandlers {
-            enter: attract_enter,
-            update: attract_update,
-            leave: attract_leave,
-            first_update: RefCell::new(true)
-        },
-
-        StateHandlers {
-            enter: long_intro_enter,
-            update: long_intro_update,
-            leave: long_intro_leave,
-            first_update: RefCell::new(true)
-        },
-
-        StateHandlers {
-            enter: how_high_enter,
-            update: how_high_update,
-            leave: how_high_leave,
-            first_update: RefCell::new(true)
-        },
-
-        StateHandlers {
-            enter: game_play_enter,
-            update: game_play_update,
-            leave: game_play_leave,
-            first_update: RefCell::new(true)
-        },
-
-        StateHandlers {
-            enter: player_dies_enter,
-            update: player_dies_update,
-            leave: player_dies_leave,
-            first_update: RefCell::new(true)
-        },
-
-        StateHandlers {
-            enter: player_wins_enter,
-            update: player_wins_update,
-            leave: player_wins_leave,
-            first_update: RefCell::new(true)
-        },
-
-        StateHandlers {
-            enter: kong_retreats_enter,
-            update: kong_retreats_update,
-            leave: kong_retreats_leave,
-            first_update: RefCell::new(true)
-        },
-    );
-}
+// one static per state, looked up through an exhaustive match, so a new
+// GameState can't compile until it has handlers
+static STATE_NOP_HANDLERS:StateHandlers = StateHandlers {
+    enter: state_nop,
+    update: state_nop,
+    leave: state_nop
+};
+
+static BOOT_HANDLERS:StateHandlers = StateHandlers {
+    enter: boot_enter,
+    update: boot_update,
+    leave: boot_leave
+};
+
+static ATTRACT_HANDLERS:StateHandlers = StateHandlers {
+    enter: attract_enter,
+    update: attract_update,
+    leave: attract_leave
+};
+
+static LONG_INTRO_HANDLERS:StateHandlers = StateHandlers {
+    enter: long_intro_enter,
+    update: state_nop,
+    leave: long_intro_leave
+};
+
+static HOW_HIGH_HANDLERS:StateHandlers = StateHandlers {
+    enter: how_high_enter,
+    update: state_nop,
+    leave: how_high_leave
+};
+
+static GAME_PLAY_HANDLERS:StateHandlers = StateHandlers {
+    enter: game_play_enter,
+    update: game_play_update,
+    leave: game_play_leave
+};
+
+static PLAYER_DIES_HANDLERS:StateHandlers = StateHandlers {
+    enter: player_dies_enter,
+    update: player_dies_update,
+    leave: player_dies_leave
+};
+
+static PLAYER_WINS_HANDLERS:StateHandlers = StateHandlers {
+    enter: player_wins_enter,
+    update: player_wins_update,
+    leave: player_wins_leave
+};
+
+static KONG_RETREATS_HANDLERS:StateHandlers = StateHandlers {
+    enter: kong_retreats_enter,
+    update: state_nop,
+    leave: kong_retreats_leave
+};
+
+static MAIN_MENU_HANDLERS:StateHandlers = StateHandlers {
+    enter: main_menu_enter,
+    update: main_menu_update,
+    leave: main_menu_leave
+};
+
+static ERROR_HANDLERS:StateHandlers = StateHandlers {
+    enter: error_screen_enter,
+    update: error_screen_update,
+    leave: error_screen_leave
+};
+
+static GAME_OVER_HANDLERS:StateHandlers = StateHandlers {
+    enter: game_over_enter,
+    update: state_nop,
+    leave: game_over_leave
+};
+
+static PAUSED_HANDLERS:StateHandlers = StateHandlers {
+    enter: paused_enter,
+    update: paused_update,
+    leave: paused_leave
+};
 
 fn get_previous_state() -> GameState {
     STATE.with(|cell| cell.borrow().previous)
@@ -188,43 +315,347 @@ fn set_current_state(state:GameState) {
     STATE.with(|cell| {cell.borrow_mut().current = state;});
 }
 
+// update ticks since the current state was entered; 0 on the first update
+pub fn get_state_ticks() -> u32 {
+    STATE.with(|cell| cell.borrow().ticks)
+}
+
+pub fn game_state_current() -> GameState {
+    get_current_state()
+}
+
 pub fn game_state_go(state:GameState) {
     STATE.with(|cell| {cell.borrow_mut().next = state;});
 }
 
+// runs state on top of the current one without leaving it; the current
+// state's update stops until game_state_pop(), and it carries on from the
+// same tick without its enter being called again
+pub fn game_state_push(state:GameState) {
+    STATE.with(|cell| {cell.borrow_mut().push = state;});
+}
+
+pub fn game_state_pop() {
+    STATE.with(|cell| {cell.borrow_mut().pop = true;});
+}
+
+// shows message on the error screen, which offers retry (back to boot) or quit
+pub fn game_state_fail(message: &str) {
+    error_screen_set_message(message);
+    game_state_go(GameState::Error);
+}
+
+thread_local!(
+    static QUIT:RefCell<bool> = RefCell::new(false);
+);
+
+// quit from the error screen or the main menu; the main loop exits once it
+// sees game_state_quit_requested()
+pub fn game_state_request_quit() {
+    info!("quit requested from {}", get_current_state());
+    QUIT.with(|cell| {*cell.borrow_mut() = true;});
+}
+
+pub fn game_state_quit_requested() -> bool {
+    QUIT.with(|cell| *cell.borrow())
+}
+
+pub fn state_machine_hash(hasher: &mut StateHasher) {
+    STATE.with(|cell| cell.borrow().hash(hasher));
+    schedule_hash(hasher);
+    tween_hash(hasher);
+}
+
+// only the machine itself; a load never runs enter or leave, the state
+// simply carries on from where the snapshot was taken
+pub fn state_machine_save(savestate: &mut SaveState) {
+    savestate.put("state_machine", STATE.with(|cell| cell.borrow().clone()));
+    schedule_save(savestate);
+    tween_save(savestate);
+}
+
+pub fn state_machine_restore(savestate: &SaveState) {
+    if let Some(states) = savestate.get::<States>("state_machine") {
+        STATE.with(|cell| {*cell.borrow_mut() = states;});
+    }
+    schedule_restore(savestate);
+    tween_restore(savestate);
+}
+
+// states that play out and move on by themselves; anything else waits on
+// the player and may legitimately sit forever
+fn state_is_transient(state: GameState) -> bool {
+    match state {
+        GameState::Boot             |
+        GameState::LongIntroduction |
+        GameState::HowHigh          |
+        GameState::PlayerDies       |
+        GameState::PlayerWins       |
+        GameState::KongRetreats     |
+        GameState::GameOver         => true,
+        _                           => false,
+    }
+}
+
+fn game_state_watchdog() {
+    use rusty_kong::settings::settings_read;
+    use rusty_kong::game_state_hash;
+
+    let (limit, recover) = settings_read(|s| (s.watchdog_ticks, s.watchdog_recover));
+    let (previous, current, next, ticks) = STATE.with(|cell| {
+        let states = cell.borrow();
+        (states.previous, states.current, states.next, states.ticks)
+    });
+
+    // fires once per visit, on the tick the limit is reached
+    if limit == 0 || ticks != limit || !state_is_transient(current) {
+        return;
+    }
+
+    error!("watchdog: {} has run {} ticks without a transition (previous: {}, next: {}, state hash: {:016x})",
+        current,
+        ticks,
+        previous,
+        next,
+        game_state_hash());
+    if recover {
+        warn!("watchdog: forcing {} -> {}", current, GameState::Attract);
+        game_state_go(GameState::Attract);
+    }
+}
+
+// a game is under way: anywhere from its intro to its game over, paused
+// included
+pub fn game_state_in_game() -> bool {
+    match get_current_state() {
+        GameState::None         |
+        GameState::Boot         |
+        GameState::Attract      |
+        GameState::MainMenu     |
+        GameState::Error        => false,
+        _                       => true,
+    }
+}
+
+// cocktail tables: player 2 sits across from player 1, so their turns are
+// shown upside down with the controls to match.  call once per tick, after
+// the update; it only touches presentation and the next tick's input.
+pub fn game_state_cocktail() {
+    use rusty_kong::input::input_set_mirrored;
+    use rusty_kong::player::*;
+    use rusty_kong::playfield::*;
+    use rusty_kong::settings::settings_read;
+
+    let flipped = settings_read(|s| s.cocktail) && game_state_in_game() && player_count() > 1 && player_current() == 1;
+
+    let mut camera = playfield_camera();
+    let rotation = if flipped { Rotation::Flipped } else { Rotation::Upright };
+    if camera.rotation != rotation {
+        debug!("cocktail: {:?}", rotation);
+        camera.rotation = rotation;
+        playfield_set_camera(camera);
+        input_set_mirrored(flipped);
+    }
+}
+
+// where boot and game over land: attract on a cabinet, the main menu on the
+// home build
+pub fn game_state_home() -> GameState {
+    use rusty_kong::settings::settings_read;
+
+    if settings_read(|s| s.arcade) { GameState::Attract } else { GameState::MainMenu }
+}
+
+// drops the game in progress for a reset or a tilt.  the leave handlers of
+// the current state and anything pushed under it run on the next update as
+// for any go; everything that outlives a state is torn down here.
+fn game_state_abandon() {
+    use rusty_kong::enemies::enemies_clear;
+    use rusty_kong::player::*;
+    use rusty_kong::sound::sound_reset;
+    use rusty_kong::video::*;
+
+    events_publish(GameEvent::Reset);
+    STATE.with(|cell| {
+        let mut states = cell.borrow_mut();
+        states.push = GameState::None;
+        states.pop = false;
+    });
+
+    enemies_clear();
+    hammers_clear();
+    video_sprite_hide_all();
+    sound_reset();
+    video_caption_clear();
+    video_dim(false);
+}
+
+// the operator reset: the game in progress is dropped and it's back to boot
+pub fn game_state_reset() {
+    use rusty_kong::player::player_start_game;
+    use rusty_kong::settings::settings_read;
+
+    warn!("reset from {}", get_current_state());
+    game_state_abandon();
+    if settings_read(|s| s.reset_clears_scores) {
+        player_start_game(1);
+    }
+    game_state_go(GameState::Boot);
+}
+
+// the cabinet's tilt: the game in progress is void, its scores gone
+// whatever reset_clears_scores says, the buzzer goes and it's straight to
+// attract without the boot
+pub fn game_state_tilt() {
+    use rusty_kong::player::player_start_game;
+    use rusty_kong::sound::*;
+
+    warn!("tilt in {}", get_current_state());
+    game_state_abandon();
+    events_publish(GameEvent::Tilted);
+    player_start_game(1);
+    sound_play(SoundId::Buzzer);
+    game_state_go(GameState::Attract);
+}
+
 pub fn game_state_init() {
     game_state_go(GameState::Boot);
 }
 
-fn get_state_handlers<'a>(state: GameState) -> &'a StateHandlers {
-    &STATE_HANDLERS[state as usize]
+fn get_state_handlers(state: GameState) -> &'static StateHandlers {
+    match state {
+        GameState::None             => &STATE_NOP_HANDLERS,
+        GameState::Boot             => &BOOT_HANDLERS,
+        GameState::Attract          => &ATTRACT_HANDLERS,
+        GameState::LongIntroduction => &LONG_INTRO_HANDLERS,
+        GameState::HowHigh          => &HOW_HIGH_HANDLERS,
+        GameState::GamePlay         => &GAME_PLAY_HANDLERS,
+        GameState::PlayerDies       => &PLAYER_DIES_HANDLERS,
+        GameState::PlayerWins       => &PLAYER_WINS_HANDLERS,
+        GameState::KongRetreats     => &KONG_RETREATS_HANDLERS,
+        GameState::MainMenu         => &MAIN_MENU_HANDLERS,
+        GameState::Error            => &ERROR_HANDLERS,
+        GameState::GameOver         => &GAME_OVER_HANDLERS,
+        GameState::Paused           => &PAUSED_HANDLERS,
+    }
+}
+
+fn game_state_transition_report() -> StateReport {
+    state_graph_observe(get_previous_state(), get_current_state());
+    events_publish(GameEvent::StateChanged {
+        from: get_previous_state(),
+        to: get_current_state()
+    });
+
+    return StateReport {
+        transitioned: true,
+        from: get_previous_state(),
+        to: get_current_state(),
+        ticks: get_state_ticks()
+    };
+}
+
+fn game_state_leave(state: GameState) {
+    let handlers = get_state_handlers(state);
+    debug!("calling {}_leave().", state);
+    (handlers.leave)();
+    sequence_cancel(state);
+    tween_cancel(state);
+    schedule_cancel(state);
+    FIRST_UPDATES.with(|cell| cell.borrow_mut().retain(|&logged| logged != state));
 }
 
-pub fn game_state_update() {
+pub fn game_state_update() -> StateReport {
+    let (pop, push) = STATE.with(|cell| {
+        let mut states = cell.borrow_mut();
+        let pending = (states.pop, states.push);
+        states.pop = false;
+        states.push = GameState::None;
+        pending
+    });
+
+    if pop && get_next_state() == GameState::None {
+        let resumed = STATE.with(|cell| cell.borrow_mut().stack.pop());
+        if let Some((state, ticks)) = resumed {
+            set_previous_state(get_current_state());
+            debug!("pop from: {}.", get_previous_state());
+            game_state_leave(get_previous_state());
+            set_current_state(state);
+            STATE.with(|cell| {cell.borrow_mut().ticks = ticks;});
+            debug!("resuming: {}.", get_current_state());
+            return game_state_transition_report();
+        }
+        warn!("game_state_pop() with nothing pushed under {}", get_current_state());
+    }
+
+    if push != GameState::None && get_next_state() == GameState::None {
+        let ticks = get_state_ticks();
+        let suspended = get_current_state();
+        STATE.with(|cell| {
+            let mut states = cell.borrow_mut();
+            states.stack.push((suspended, ticks));
+            states.ticks = 0;
+        });
+        set_previous_state(suspended);
+        set_current_state(push);
+        debug!("push over {}: {}.", suspended, push);
+        let handlers = get_state_handlers(push);
+        debug!("calling {}_enter.", push);
+        (handlers.enter)();
+        return game_state_transition_report();
+    }
+
     if get_next_state() != GameState::None {
         set_previous_state(get_current_state());
         debug!("transition from: {}.", get_previous_state());
-        let previous_handlers = get_state_handlers(get_previous_state());
-        debug!("calling {}_leave().", get_previous_state());
-        (previous_handlers.leave)();
-        let mut first_update = previous_handlers.first_update.borrow_mut();
-        *first_update = true;
+        game_state_leave(get_previous_state());
+
+        // a go from a pushed state leaves everything under it too
+        let suspended = STATE.with(|cell| cell.borrow_mut().stack.drain(..).rev().collect::<Vec<(GameState, u32)>>());
+        for &(state, _) in suspended.iter() {
+            game_state_leave(state);
+        }
 
         set_current_state(get_next_state());
         debug!("transition to: {}.", get_current_state());
         set_next_state(GameState::None);
+        STATE.with(|cell| {cell.borrow_mut().ticks = 0;});
 
         let current_handlers = get_state_handlers(get_current_state());
         debug!("calling {}_enter.", get_current_state());
         (current_handlers.enter)();
+
+        return game_state_transition_report();
     } else {
         let handlers = get_state_handlers(get_current_state());
-        let mut first_update = handlers.first_update.borrow_mut();
-        if *first_update {
+        let first_update = FIRST_UPDATES.with(|cell| {
+            let mut logged = cell.borrow_mut();
+            let first = !logged.contains(&get_current_state());
+            if first {
+                logged.push(get_current_state());
+            }
+            first
+        });
+        if first_update {
             debug!("calling {}_update.", get_current_state());
             debug!("NOTE: only the first call is logged to avoid noise.");
-            *first_update = false;
         }
         (handlers.update)();
+        sequence_update(get_current_state());
+        tween_update(get_current_state());
+        schedule_update(get_current_state(), get_state_ticks());
+        STATE.with(|cell| {
+            let mut states = cell.borrow_mut();
+            states.ticks = states.ticks.wrapping_add(1);
+        });
+        game_state_watchdog();
+
+        let ticks = STATE.with(|cell| cell.borrow().ticks);
+        return StateReport {
+            transitioned: false,
+            from: get_current_state(),
+            to: get_current_state(),
+            ticks: ticks
+        };
     }
-}
\ No newline at end of file
+}
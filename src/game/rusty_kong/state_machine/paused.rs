@@ -0,0 +1,56 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use rusty_kong::input::*;
+use rusty_kong::sound::*;
+use rusty_kong::video::*;
+use super::game_state_pop;
+use super::schedule_after;
+
+const PAUSE_TEXT: &str = "PAUSE";
+// XXX: by eye, a slower take on the attract prompt
+const PAUSE_ON_TICKS:  u32 = 48;
+const PAUSE_OFF_TICKS: u32 = 24;
+
+fn paused_show() {
+    let x = (SCREEN_WIDTH - PAUSE_TEXT.len() as u32 * TILE_WIDTH) / 2;
+    let y = (SCREEN_HEIGHT - TILE_HEIGHT) / 2;
+    video_caption_add(x, y, PAUSE_TEXT, TEXT_PALETTE_WHITE);
+    schedule_after(PAUSE_ON_TICKS, paused_hide);
+}
+
+fn paused_hide() {
+    video_caption_clear();
+    schedule_after(PAUSE_OFF_TICKS, paused_show);
+}
+
+// only ever pushed over GamePlay, so the playfield and its tick count are
+// still there underneath when this pops
+pub fn paused_enter() {
+    video_dim(true);
+    paused_show();
+    music_pause();
+    sound_set_muted(true);
+}
+
+pub fn paused_update() {
+    if input_is_pressed(F_INPUT_START) {
+        game_state_pop();
+    }
+}
+
+pub fn paused_leave() {
+    video_dim(false);
+    video_caption_clear();
+    sound_set_muted(false);
+    music_resume();
+}
@@ -10,14 +10,115 @@
 //
 // --------------------------------------------------------------------------
 
-pub fn player_dies_enter() {
+use rusty_kong::events::*;
+use rusty_kong::level::*;
+use rusty_kong::player::*;
+use rusty_kong::sound::*;
+use super::game_state_go;
+use super::get_state_ticks;
+use super::GameState;
+
+// XXX: death frames are guesses at the ripper output; the spin is faked by
+//      flipping the standing frame until the real ones are identified
+const MARIO_STAND: u16 = 0x00;
+const MARIO_DEAD:  u16 = 0x0f;
+const HALO:        u16 = 0x13;
+
+// (tile, horizontal flip, vertical flip), a quarter turn each
+const SPIN_FRAMES: [(u16, bool, bool); 4] = [
+    (MARIO_STAND, false, false),
+    (MARIO_STAND, true,  false),
+    (MARIO_STAND, true,  true),
+    (MARIO_STAND, false, true),
+];
+
+// everything freezes, mario spins, then lies under the halo
+const FREEZE_TICKS:    u32 = 60;
+const SPIN_FRAME_TICKS:u32 = 8;
+const SPIN_TURNS:      u32 = 4;
+const SPIN_TICKS:      u32 = SPIN_FRAME_TICKS * SPIN_FRAMES.len() as u32 * SPIN_TURNS;
+const HALO_TICKS:      u32 = 90;
+const DEATH_TICKS:     u32 = FREEZE_TICKS + SPIN_TICKS + HALO_TICKS;
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DeathRoute {
+    // same player, same stage
+    Restart,
+    // two player: hand over to the other player
+    SwitchPlayer(usize),
+    // out of lives; game over hands over to anyone left
+    GameOver,
+}
+
+// every "what happens after a death" decision lives here.  practice never
+// costs a life; with two players the turn passes on every death, like the
+// cabinet.
+pub fn player_dies_route(practice: bool, lives_left: u32, next_player: Option<usize>) -> DeathRoute {
+    if practice {
+        return DeathRoute::Restart;
+    }
+    if lives_left == 0 {
+        return DeathRoute::GameOver;
+    }
+    match next_player {
+        Some(index) => DeathRoute::SwitchPlayer(index),
+        None => DeathRoute::Restart,
+    }
+}
+
+// enemies only move in game_play_update(), so they're frozen for as long as
+// this state runs without anything else having to know
+pub fn player_dies_enter() {
+    music_stop();
+    hammer_drop();
+    player_draw_frame(MARIO_STAND, false, false);
 }
 
 pub fn player_dies_update() {
+    let ticks = get_state_ticks();
+
+    if ticks == FREEZE_TICKS {
+        sound_play(SoundId::Death);
+        events_publish(GameEvent::PlayerDied);
+    }
+
+    if ticks >= FREEZE_TICKS && ticks < FREEZE_TICKS + SPIN_TICKS {
+        let frame = ((ticks - FREEZE_TICKS) / SPIN_FRAME_TICKS) as usize % SPIN_FRAMES.len();
+        let (tile, horizontal, vertical) = SPIN_FRAMES[frame];
+        player_draw_frame(tile, horizontal, vertical);
+        return;
+    }
+
+    if ticks == FREEZE_TICKS + SPIN_TICKS {
+        player_draw_frame(MARIO_DEAD, false, false);
+        player_draw_halo(HALO);
+        return;
+    }
 
+    if ticks < DEATH_TICKS {
+        return;
+    }
+
+    let practice = level_is_practice();
+    let lives_left = if practice { player_lives() } else { player_lose_life() };
+    let route = player_dies_route(practice, lives_left, player_next_with_lives());
+    debug!("player {} died: {:?}", player_current() + 1, route);
+
+    match route {
+        DeathRoute::Restart => {
+            level_restart_stage();
+            game_state_go(GameState::GamePlay);
+        },
+        DeathRoute::SwitchPlayer(index) => {
+            player_switch_to(index);
+            game_state_go(GameState::HowHigh);
+        },
+        DeathRoute::GameOver => {
+            game_state_go(GameState::GameOver);
+        },
+    }
 }
 
 pub fn player_dies_leave() {
-
+    player_hide();
 }
@@ -10,14 +10,118 @@
 //
 // --------------------------------------------------------------------------
 
+use std::cell::RefCell;
+
+use rusty_kong::kong::*;
+use rusty_kong::level::*;
+use rusty_kong::player::*;
+use rusty_kong::sound::*;
+use rusty_kong::video::*;
+use super::game_state_go;
+use super::get_state_ticks;
+use super::GameState;
+
+// the heart goes up, kong snatches pauline and the heart breaks, then
+// whatever bonus is left is counted into the score
+const HEART_TICKS:      u32 = 60;
+const HEART_RISE_TICKS: u32 = 24;
+const BREAK_TICKS:      u32 = 90;
+const TALLY_TICKS:      u32 = 120;
+// points moved from bonus to score per tick of the tally
+const TALLY_STEP:       u32 = 100;
+const TALLY_SOUND_TICKS:u32 = 8;
+const HOLD_TICKS:       u32 = 60;
+
+const BONUS_COL: u32 = 17;
+const BONUS_ROW: u32 = 3;
+
+struct Tally {
+    // bonus still to be counted into the score
+    bonus: u32,
+    // the tick the count finished on
+    done_at: Option<u32>,
+}
+
+thread_local!(
+    static TALLY:RefCell<Tally> = RefCell::new(Tally {
+        bonus: 0,
+        done_at: None
+    });
+);
+
+// only stages kong retreats from have him on a perch to grab pauline from
+fn player_wins_kong_grabs() -> bool {
+    level_current_stage().after_win == GameState::KongRetreats
+}
+
+fn player_wins_draw_bonus(bonus: u32) {
+    video_text(BONUS_COL, BONUS_ROW, &format!("BONUS {:4}", bonus), TEXT_PALETTE_BLUE);
+}
+
+// enemies only move in game_play_update(), so they stay frozen here
 pub fn player_wins_enter() {
+    // non-scoring runs (practice) count nothing in
+    let bonus = if level_is_scoring() { level_bonus() } else { 0 };
+    TALLY.with(|cell| {*cell.borrow_mut() = Tally { bonus: bonus, done_at: None };});
 
+    music_play(MusicId::StageClear);
+    if player_wins_kong_grabs() {
+        kong_draw(KONG_PERCH_X, KONG_PERCH_Y, KongPose::Standing);
+        pauline_draw(PAULINE_PERCH_X, PAULINE_PERCH_Y);
+    }
+    player_wins_draw_bonus(bonus);
 }
 
 pub fn player_wins_update() {
+    let ticks = get_state_ticks();
+
+    if ticks == HEART_TICKS {
+        heart_rise(HEART_RISE_TICKS);
+    }
+
+    if ticks == BREAK_TICKS && player_wins_kong_grabs() {
+        pauline_hide();
+        kong_draw(KONG_PERCH_X, KONG_PERCH_Y, KongPose::ClimbLeft);
+        heart_draw(true);
+    }
+
+    if ticks < TALLY_TICKS {
+        return;
+    }
+
+    let (left, done_at) = TALLY.with(|cell| {
+        let mut tally = cell.borrow_mut();
+        let step = tally.bonus.min(TALLY_STEP);
+        tally.bonus -= step;
+        if step > 0 {
+            player_add_score(step);
+        } else if tally.done_at.is_none() {
+            tally.done_at = Some(ticks);
+        }
+        (tally.bonus, tally.done_at)
+    });
+    player_wins_draw_bonus(left);
 
+    if left > 0 && (ticks - TALLY_TICKS) % TALLY_SOUND_TICKS == 0 {
+        sound_play(SoundId::Bonus);
+    }
+
+    match done_at {
+        Some(done) if ticks >= done + HOLD_TICKS => {},
+        _ => return,
+    }
+
+    let after_win = level_current_stage().after_win;
+    debug!("player {} score {}", player_current() + 1, player_score());
+
+    // KongRetreats advances the level itself once kong is gone
+    if after_win != GameState::KongRetreats {
+        level_advance();
+    }
+    game_state_go(after_win);
 }
 
 pub fn player_wins_leave() {
-
+    heart_hide();
+    video_text_clear_row(BONUS_ROW);
 }
@@ -0,0 +1,93 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+use rusty_kong::hashing::StateHasher;
+use rusty_kong::savestate::SaveState;
+use super::*;
+
+pub type TimerCallback = fn();
+
+// a callback waiting on its owner's update count.  owners are states, so a
+// pushed state's timers sit still while it's covered and everything a state
+// scheduled goes when it's left.
+#[derive(Clone)]
+struct Timer {
+    owner: GameState,
+    // get_state_ticks() of the update it runs after
+    due: u32,
+    callback: TimerCallback,
+}
+
+thread_local!(
+    static TIMERS:RefCell<Vec<Timer>> = RefCell::new(Vec::new());
+);
+
+// runs callback after frames more updates of the current state: scheduled
+// from an enter, 0 is the state's first update.  gone if the state is left
+// first.
+pub fn schedule_after(frames: u32, callback: TimerCallback) {
+    let timer = Timer {
+        owner: get_current_state(),
+        due: get_state_ticks().saturating_add(frames),
+        callback: callback,
+    };
+    TIMERS.with(|cell| {cell.borrow_mut().push(timer);});
+}
+
+// called by the machine right after the current state's update, before its
+// tick count moves on; callbacks may schedule more, which run this pass if
+// they're already due
+pub fn schedule_update(state: GameState, ticks: u32) {
+    loop {
+        let due = TIMERS.with(|cell| {
+            let mut timers = cell.borrow_mut();
+            match timers.iter().position(|timer| timer.owner == state && timer.due <= ticks) {
+                Some(index) => Some(timers.remove(index).callback),
+                None => None,
+            }
+        });
+        match due {
+            Some(callback) => callback(),
+            None => break,
+        }
+    }
+}
+
+pub fn schedule_cancel(state: GameState) {
+    TIMERS.with(|cell| cell.borrow_mut().retain(|timer| timer.owner != state));
+}
+
+// callbacks are code addresses, which differ from one process to the
+// next, so only who's waiting and until when goes in
+pub fn schedule_hash(hasher: &mut StateHasher) {
+    TIMERS.with(|cell| {
+        let timers = cell.borrow();
+        hasher.write_usize(timers.len());
+        for timer in timers.iter() {
+            timer.owner.hash(hasher);
+            timer.due.hash(hasher);
+        }
+    });
+}
+
+pub fn schedule_save(savestate: &mut SaveState) {
+    savestate.put("schedule", TIMERS.with(|cell| cell.borrow().clone()));
+}
+
+pub fn schedule_restore(savestate: &SaveState) {
+    if let Some(timers) = savestate.get::<Vec<Timer>>("schedule") {
+        TIMERS.with(|cell| {*cell.borrow_mut() = timers;});
+    }
+}
@@ -0,0 +1,215 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use rusty_kong::hashing::StateHasher;
+use rusty_kong::savestate::SaveState;
+use rusty_kong::video::*;
+use super::*;
+
+// progress is worked in fixed point, TWEEN_ONE being all the way, so an
+// eased field lands on the same values on every machine
+const TWEEN_ONE: i64 = 10_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Easing {
+    Linear,
+    // quadratic: slow off the mark, quick at the end, and the other way
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    // overshoots into a few shrinking bounces at the end, penner's
+    // bounce-out
+    Bounce,
+}
+
+impl Easing {
+    pub fn from_name(name: &str) -> Option<Easing> {
+        match name {
+            "linear"        => Some(Easing::Linear),
+            "ease_in"       => Some(Easing::EaseIn),
+            "ease_out"      => Some(Easing::EaseOut),
+            "ease_in_out"   => Some(Easing::EaseInOut),
+            "bounce"        => Some(Easing::Bounce),
+            _               => None,
+        }
+    }
+
+    // progress 0 to TWEEN_ONE in, eased progress out; only Bounce ever
+    // comes back below where it was
+    fn apply(self, progress: i64) -> i64 {
+        let t = progress.max(0).min(TWEEN_ONE);
+        match self {
+            Easing::Linear      => t,
+            Easing::EaseIn      => t * t / TWEEN_ONE,
+            Easing::EaseOut     => TWEEN_ONE - (TWEEN_ONE - t) * (TWEEN_ONE - t) / TWEEN_ONE,
+            Easing::EaseInOut   => {
+                if t < TWEEN_ONE / 2 {
+                    2 * t * t / TWEEN_ONE
+                } else {
+                    TWEEN_ONE - 2 * (TWEEN_ONE - t) * (TWEEN_ONE - t) / TWEEN_ONE
+                }
+            },
+            Easing::Bounce      => {
+                // 7.5625 t^2 on four arcs, breaking at 1/2.75, 2/2.75 & 2.5/2.75
+                let arc = |t: i64, centre: i64, top: i64| 121 * (t - centre) * (t - centre) / (16 * TWEEN_ONE) + top;
+                if t >= TWEEN_ONE {
+                    TWEEN_ONE
+                } else if t < 3636 {
+                    arc(t, 0, 0)
+                } else if t < 7273 {
+                    arc(t, 5455, 7500)
+                } else if t < 9091 {
+                    arc(t, 8182, 9375)
+                } else {
+                    arc(t, 9545, 9844)
+                }
+            },
+        }
+    }
+
+    // from to to, tick ticks into a tween of duration ticks
+    pub fn value(self, from: i32, to: i32, tick: u32, duration: u32) -> i32 {
+        if duration == 0 {
+            return to;
+        }
+        if self == Easing::Linear {
+            // skips the fixed point so it steps exactly as a plain lerp
+            return from + (to - from) * tick as i32 / duration as i32;
+        }
+        let eased = self.apply(tick as i64 * TWEEN_ONE / duration as i64);
+        return from + ((to - from) as i64 * eased / TWEEN_ONE) as i32;
+    }
+}
+
+// what a tween moves; the sequences ease their moves with Easing on their
+// own
+#[derive(Clone, Copy, PartialEq, Hash, Debug)]
+pub enum TweenTarget {
+    // a sprite slot's position
+    Sprite { slot: usize },
+}
+
+// a sprite's x & y
+#[derive(Clone)]
+struct Tween {
+    owner: GameState,
+    target: TweenTarget,
+    easing: Easing,
+    from: [i32; 2],
+    to: [i32; 2],
+    ticks: u32,
+    duration: u32,
+    // runs as the tween lands, the way a scheduled callback would
+    then: Option<TimerCallback>,
+}
+
+thread_local!(
+    static TWEENS:RefCell<Vec<Tween>> = RefCell::new(Vec::new());
+);
+
+fn tween_start(target: TweenTarget, from: [i32; 2], to: [i32; 2], frames: u32, easing: Easing, then: Option<TimerCallback>) {
+    let tween = Tween {
+        owner: get_current_state(),
+        target: target,
+        easing: easing,
+        from: from,
+        to: to,
+        ticks: 0,
+        duration: frames,
+        then: then,
+    };
+    TWEENS.with(|cell| {cell.borrow_mut().push(tween);});
+}
+
+// a hidden slot has nowhere to start from and is left alone
+pub fn tween_sprite(slot: usize, to: (u16, u16), frames: u32, easing: Easing, then: Option<TimerCallback>) {
+    match video_sprite_frame(slot) {
+        Some(frame) => {
+            let from = [frame.x as i32, frame.y as i32];
+            tween_start(TweenTarget::Sprite { slot: slot }, from, [to.0 as i32, to.1 as i32], frames, easing, then);
+        },
+        None => warn!("tween: sprite {} is hidden", slot),
+    }
+}
+
+fn tween_apply(target: TweenTarget, values: [i32; 2]) -> Result<(), String> {
+    match target {
+        TweenTarget::Sprite { slot } => {
+            match video_sprite_frame(slot) {
+                Some(frame) => {
+                    video_sprite_set(slot, frame.tile, values[0].max(0) as u16, values[1].max(0) as u16, frame.palette);
+                    return Ok(());
+                },
+                None => Err(format!("sprite {} was hidden", slot)),
+            }
+        },
+    }
+}
+
+// called by the machine after the current state's update, just before its
+// timers; a target that's gone away drops its tween
+pub fn tween_update(state: GameState) {
+    let stepped: Vec<(TweenTarget, [i32; 2], bool, Option<TimerCallback>)> = TWEENS.with(|cell| {
+        let mut tweens = cell.borrow_mut();
+        let mut stepped = Vec::new();
+        for tween in tweens.iter_mut().filter(|tween| tween.owner == state) {
+            tween.ticks = (tween.ticks + 1).min(tween.duration);
+            let mut values = [0; 2];
+            for channel in 0..2 {
+                values[channel] = tween.easing.value(tween.from[channel], tween.to[channel], tween.ticks, tween.duration);
+            }
+            stepped.push((tween.target, values, tween.ticks == tween.duration, tween.then));
+        }
+        tweens.retain(|tween| tween.owner != state || tween.ticks < tween.duration);
+        stepped
+    });
+
+    for (target, values, landed, then) in stepped {
+        if let Err(e) = tween_apply(target, values) {
+            warn!("tween: {}", e);
+            TWEENS.with(|cell| cell.borrow_mut().retain(|tween| tween.target != target));
+            continue;
+        }
+        if landed {
+            if let Some(then) = then {
+                then();
+            }
+        }
+    }
+}
+
+// a tween cut short stays wherever it had got to
+pub fn tween_cancel(state: GameState) {
+    TWEENS.with(|cell| cell.borrow_mut().retain(|tween| tween.owner != state));
+}
+
+pub fn tween_hash(hasher: &mut StateHasher) {
+    TWEENS.with(|cell| {
+        let tweens = cell.borrow();
+        tweens.len().hash(hasher);
+        for tween in tweens.iter() {
+            (tween.owner, tween.target, tween.easing, tween.from, tween.to, tween.ticks, tween.duration).hash(hasher);
+        }
+    });
+}
+
+pub fn tween_save(savestate: &mut SaveState) {
+    savestate.put("tweens", TWEENS.with(|cell| cell.borrow().clone()));
+}
+
+pub fn tween_restore(savestate: &SaveState) {
+    if let Some(tweens) = savestate.get::<Vec<Tween>>("tweens") {
+        TWEENS.with(|cell| {*cell.borrow_mut() = tweens;});
+    }
+}
@@ -0,0 +1,77 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+
+use discord_rpc_client::Client;
+
+use super::*;
+use rusty_kong::clock::clock_unix_secs;
+
+thread_local!(
+    static CLIENT:RefCell<Option<Client>> = RefCell::new(None);
+    // the session's start, for discord's elapsed time
+    static STARTED:RefCell<u64> = RefCell::new(0);
+);
+
+// the top line is what's being played, the bottom one how it's going
+fn discord_lines(status: &GameStatus) -> (String, String) {
+    match status.stage {
+        Some(_) if status.practice => (format!("practice - {}", status.campaign), status_text(status)),
+        Some(_) => (status.campaign.to_string(), format!("{} - {} lives", status_text(status), status.lives)),
+        None => ("not playing".to_string(), status_text(status)),
+    }
+}
+
+fn discord_on_status(status: &GameStatus) {
+    let (details, state) = discord_lines(status);
+    let started = STARTED.with(|cell| *cell.borrow());
+    CLIENT.with(|cell| {
+        if let Some(ref mut client) = *cell.borrow_mut() {
+            let result = client.set_activity(|activity| activity
+                .details(details)
+                .state(state)
+                .timestamps(|timestamps| timestamps.start(started)));
+            if let Err(e) = result {
+                debug!("discord: {}", e);
+            }
+        }
+    });
+}
+
+// connects when discord_client_id is set; discord not running just means
+// nothing shows up, the client keeps retrying in the background
+pub fn discord_init() {
+    use rusty_kong::settings::settings_read;
+
+    let id = settings_read(|s| s.discord_client_id.clone());
+    if id.is_empty() {
+        return;
+    }
+    let id =
+        match id.parse::<u64>() {
+            Ok(n) => n,
+            Err(_) => {
+                warn!("discord_client_id \"{}\" isn't an application id", id);
+                return;
+            },
+        };
+
+    let mut client = Client::new(id);
+    client.start();
+    CLIENT.with(|cell| {*cell.borrow_mut() = Some(client);});
+    STARTED.with(|cell| {
+        *cell.borrow_mut() = clock_unix_secs();
+    });
+    status_subscribe(discord_on_status);
+    info!("discord rich presence on for application {}", id);
+}
@@ -0,0 +1,141 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+
+use super::state_machine::GameState;
+
+#[cfg(feature = "discord")]
+mod discord;
+
+// what the game is up to, in one place for anything outside it that wants
+// to show that: the window title today, rich presence and the like later.
+// read-only and off the logic's path; it's polled once per rendered frame.
+#[derive(Clone, PartialEq)]
+pub struct GameStatus {
+    pub state: GameState,
+    pub campaign: &'static str,
+    // the stage and where it sits in the run, while a game is going
+    pub stage: Option<&'static str>,
+    pub height: u32,
+    pub level: u32,
+    pub round: usize,
+    pub practice: bool,
+    pub players: usize,
+    // 0-based, like player_current()
+    pub player: usize,
+    pub score: u32,
+    pub lives: u32,
+    // percent of the asset pack streamed in, while boot is at it
+    pub loading: Option<u32>,
+}
+
+pub type StatusListener = fn(&GameStatus);
+
+thread_local!(
+    static LISTENERS:RefCell<Vec<StatusListener>> = RefCell::new(Vec::new());
+    static LAST:RefCell<Option<GameStatus>> = RefCell::new(None);
+);
+
+fn status_in_game(state: GameState) -> bool {
+    match state {
+        GameState::LongIntroduction |
+        GameState::HowHigh |
+        GameState::GamePlay |
+        GameState::PlayerDies |
+        GameState::PlayerWins |
+        GameState::KongRetreats |
+        GameState::GameOver |
+        GameState::Paused => true,
+        _ => false,
+    }
+}
+
+pub fn status_current() -> GameStatus {
+    use super::assets::pack_progress;
+    use super::level::*;
+    use super::player::*;
+    use super::state_machine::game_state_current;
+
+    let state = game_state_current();
+    let stage = if status_in_game(state) { Some(level_current_stage()) } else { None };
+    let level = level_current();
+    GameStatus {
+        state: state,
+        campaign: level_campaign().name,
+        stage: stage.map(|s| s.name),
+        height: stage.map_or(0, |s| s.height),
+        level: level.number,
+        round: level.round,
+        practice: level_is_practice(),
+        players: player_count(),
+        player: player_current(),
+        score: player_score(),
+        lives: player_lives(),
+        loading: pack_progress().map(|(read, total)| (read * 100 / total.max(1)) as u32),
+    }
+}
+
+// a line for a window title or a status message
+pub fn status_text(status: &GameStatus) -> String {
+    if let Some(percent) = status.loading {
+        return format!("loading {}%", percent);
+    }
+    match status.stage {
+        Some(stage) if status.practice => format!("practicing {}m {}", status.height, stage),
+        Some(stage) => format!("{}m {} - level {} - {}up {}",
+            status.height,
+            stage,
+            status.level,
+            status.player + 1,
+            status.score),
+        None => status.state.to_string().replace('_', " "),
+    }
+}
+
+// the integrations built in, each of which decides from the settings
+// whether it's wanted
+pub fn status_init() {
+    #[cfg(feature = "discord")]
+    {
+        use self::discord::discord_init;
+        discord_init();
+    }
+}
+
+// called once for every change, from the frame it's noticed on; the
+// discord presence is the only listener so far, so it's only built with it
+#[cfg(feature = "discord")]
+pub fn status_subscribe(listener: StatusListener) {
+    LISTENERS.with(|cell| {cell.borrow_mut().push(listener);});
+}
+
+// call once per rendered frame; the new status when it's changed since the
+// last call, after the listeners have seen it
+pub fn status_poll() -> Option<GameStatus> {
+    let status = status_current();
+    let changed = LAST.with(|cell| {
+        let mut last = cell.borrow_mut();
+        if last.as_ref() == Some(&status) {
+            return false;
+        }
+        *last = Some(status.clone());
+        true
+    });
+    if !changed {
+        return None;
+    }
+    let listeners = LISTENERS.with(|cell| cell.borrow().clone());
+    for listener in listeners.iter() {
+        listener(&status);
+    }
+    return Some(status);
+}
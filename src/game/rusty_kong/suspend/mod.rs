@@ -0,0 +1,191 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+
+use super::replay::ReplayResumed;
+use super::savestate::SaveState;
+use super::video::FrameBuffer;
+
+// a home game closed mid-play carries on at the next launch.  a savestate
+// can't be written out as it is -- boxed values, fn pointers & &'static
+// stages -- so what goes to disk is the session's journal, a replay from
+// boot recorded all along, and the next launch plays it back headless to
+// get the savestate, checking every hash on the way.
+pub const SUSPEND_FILE: &str = "suspend.replay";
+
+// under the main menu's entries, while they're up
+const SUSPEND_OFFER_COL: u32 = 3;
+const SUSPEND_OFFER_ROW: u32 = 27;
+
+struct Suspended {
+    savestate: SaveState,
+    resumed: ReplayResumed,
+}
+
+thread_local!(
+    static SUSPENDED:RefCell<Option<Suspended>> = RefCell::new(None);
+);
+
+fn suspend_remove() {
+    if let Err(e) = fs::remove_file(SUSPEND_FILE) {
+        warn!("can't remove {}: {}", SUSPEND_FILE, e);
+    }
+}
+
+// call before game_init(): the playback can't have anything subscribed to
+// events, or it'd post daily scores & split times all over again.  every
+// subsystem is put back the way it was, so the launch itself goes on as if
+// the file wasn't there and the journal it starts plays back the same.
+pub fn suspend_load() {
+    use super::game_state_restore;
+    use super::game_state_save;
+    use super::daily::daily_forget;
+    use super::input::F_INPUT_NONE;
+    use super::input::input_set;
+    use super::replay::REPLAY_HASH_INTERVAL;
+    use super::replay::replay_record_discard;
+    use super::replay::replay_resume;
+    use super::settings::settings_get;
+    use super::settings::settings_hold;
+    use super::settings::settings_set;
+    use super::sound::sound_reset;
+    use super::state_machine::main_menu_reset;
+
+    if !Path::new(SUSPEND_FILE).exists() {
+        return;
+    }
+
+    let fresh = game_state_save();
+    let settings = settings_get();
+    settings_hold(true);
+    let resumed = replay_resume(SUSPEND_FILE, REPLAY_HASH_INTERVAL);
+    let savestate = game_state_save();
+    settings_hold(false);
+
+    game_state_restore(&fresh);
+    settings_set(settings);
+    sound_reset();
+    main_menu_reset();
+    daily_forget();
+    replay_record_discard();
+    input_set(F_INPUT_NONE);
+
+    match resumed {
+        Ok(resumed) => {
+            info!("{}: a suspended game, {} ticks in", SUSPEND_FILE, resumed.ticks());
+            SUSPENDED.with(|cell| {
+                *cell.borrow_mut() = Some(Suspended {
+                    savestate: savestate,
+                    resumed: resumed,
+                });
+            });
+        },
+        Err(e) => {
+            warn!("can't resume the suspended game, dropping it: {}", e);
+            suspend_remove();
+        },
+    }
+}
+
+pub fn suspend_offered() -> bool {
+    SUSPENDED.with(|cell| cell.borrow().is_some())
+}
+
+// the journal goes with the window: kept when a game was under way, thrown
+// away otherwise, leaving any earlier suspended game for the next launch
+pub fn suspend_close() {
+    use super::replay::replay_journal_discard;
+    use super::replay::replay_journal_finish;
+    use super::state_machine::game_state_in_game;
+
+    if !game_state_in_game() {
+        replay_journal_discard();
+        return;
+    }
+    match replay_journal_finish() {
+        Ok(ticks) => info!("game suspended to {}, {} ticks in", SUSPEND_FILE, ticks),
+        Err(e) => error!("can't suspend the game: {}", e),
+    }
+}
+
+// picks the suspended game up where it was closed, under the settings it
+// was played with, and journals on from there
+pub fn suspend_continue() {
+    use super::game_state_restore;
+    use super::locale::locale_init;
+    use super::replay::replay_journal_resume;
+    use super::settings::settings_apply_pairs;
+    use super::sound::sound_reset;
+    use super::state_machine::GameState;
+    use super::state_machine::game_state_current;
+
+    if game_state_current() != GameState::MainMenu {
+        return;
+    }
+    let suspended =
+        match SUSPENDED.with(|cell| cell.borrow_mut().take()) {
+            Some(s) => s,
+            None => return,
+        };
+    if let Err(e) = settings_apply_pairs(&suspended.resumed.settings()) {
+        warn!("{}: {}", SUSPEND_FILE, e);
+    }
+    locale_init();
+    sound_reset();
+    game_state_restore(&suspended.savestate);
+    info!("continuing the suspended game, {} ticks in", suspended.resumed.ticks());
+    replay_journal_resume(suspended.resumed);
+    suspend_remove();
+}
+
+// call every frame: starting a new game instead gives up the suspended one
+pub fn suspend_poll() {
+    use super::state_machine::game_state_in_game;
+
+    if suspend_offered() && game_state_in_game() {
+        SUSPENDED.with(|cell| {cell.borrow_mut().take();});
+        info!("a new game started, the suspended one is gone");
+        suspend_remove();
+    }
+}
+
+// drawn over the menu rather than being one of its entries: what the menu
+// shows is part of the state the journal hashes, and a launch with nothing
+// suspended has to play back the same as one with
+fn suspend_overlay(framebuffer: &mut FrameBuffer) {
+    use super::state_machine::GameState;
+    use super::state_machine::game_state_current;
+    use super::state_machine::main_menu_on_main_page;
+    use super::video::*;
+
+    if !suspend_offered() || game_state_current() != GameState::MainMenu || !main_menu_on_main_page() {
+        return;
+    }
+    let (x, y) = (SUSPEND_OFFER_COL * TILE_WIDTH, SUSPEND_OFFER_ROW * TILE_HEIGHT);
+    text_draw(framebuffer, x, y, "CONTINUE PREVIOUS GAME?", TEXT_PALETTE_RED);
+    text_draw(framebuffer, x, y + TILE_HEIGHT * 2, "F5 CONTINUE", TEXT_PALETTE_WHITE);
+}
+
+pub fn suspend_init() {
+    use super::video::video_add_overlay;
+    video_add_overlay(suspend_overlay);
+}
+
+// call before the first tick, after --state & friends have had their say
+pub fn suspend_journal_start() {
+    use super::replay::REPLAY_HASH_INTERVAL;
+    use super::replay::replay_journal_start;
+    replay_journal_start(SUSPEND_FILE, REPLAY_HASH_INTERVAL);
+}
@@ -0,0 +1,165 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::assets::*;
+use super::sound::*;
+
+// containers and formats the sound mixer is built to read
+const SOUND_DIRS: [&str; 2] = [SOUND_DIR, MUSIC_DIR];
+
+// art & fonts are compiled in from the ripper output; the files ship for
+// editing, the game never opens them.  the sheets' pngs aren't listed, as
+// the window icon is a png that is opened.
+const SOURCE_EXTENSIONS: [&str; 3] = ["xcf", "ttf", "md"];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+struct Report {
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+fn extension(relative: &str) -> String {
+    Path::new(relative)
+        .extension()
+        .map_or(String::new(), |e| e.to_string_lossy().to_lowercase())
+}
+
+fn validate_file(dir: &Path, relative: &str, report: &mut Report) {
+    use super::locale::*;
+    use super::physics::*;
+    use super::scripting::*;
+    use super::sequence::*;
+    use super::video::SPRITE_DIR;
+    use super::video::WINDOW_ICON;
+    use super::video::atlas_tile_from_name;
+
+    let path = dir.join(relative);
+    let mut parts = relative.splitn(2, '/');
+    let (top, rest) = (parts.next().unwrap(), parts.next());
+
+    if relative == PHYSICS_FILE {
+        report.errors.extend(physics_check(&path));
+    } else if relative == WINDOW_ICON {
+        let mut signature = [0u8; 8];
+        let read = File::open(&path).and_then(|mut file| file.read_exact(&mut signature));
+        if read.is_err() || signature != PNG_SIGNATURE {
+            report.errors.push(format!("{}: isn't a png, the window won't get an icon", relative));
+        }
+    } else if top == LOCALE_DIR && rest.is_some() {
+        let code = Path::new(rest.unwrap()).file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned());
+        if Language::from_code(&code).is_none() || extension(relative) != "txt" {
+            report.warnings.push(format!("{} isn't a language the game has, it'll never be loaded", relative));
+        } else {
+            report.errors.extend(locale_check(&path));
+        }
+    } else if top == SEQUENCE_DIR && rest.is_some() {
+        let name = Path::new(rest.unwrap()).file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned());
+        if !sequence_names().contains(&name.as_str()) || extension(relative) != "txt" {
+            report.warnings.push(format!("{} isn't a sequence the game plays, it'll never be loaded", relative));
+        } else {
+            report.errors.extend(sequence_check(&path));
+        }
+    } else if top == SCRIPT_DIR && rest.is_some() {
+        if relative != SCRIPT_FILE {
+            report.warnings.push(format!("{} isn't where the script hooks are read from, that's {}", relative, SCRIPT_FILE));
+        } else {
+            report.errors.extend(scripting_check(&path));
+        }
+    } else if SOUND_DIRS.contains(&top) && rest.is_some() {
+        if !SOUND_EXTENSIONS.contains(&extension(relative).as_str()) {
+            report.errors.push(format!("{}: only .wav and .ogg files can be mixed, convert it to one of those", relative));
+        }
+    } else if top == SPRITE_DIR && rest.is_some() {
+        if atlas_tile_from_name(rest.unwrap()).is_none() {
+            report.errors.push(format!("{}: loose sprites are named after their tile, like {}/37.png", relative, SPRITE_DIR));
+        } else {
+            report.warnings.push(format!("{}: loose sprites are only packed in with --dev", relative));
+        }
+    } else if SOURCE_EXTENSIONS.contains(&extension(relative).as_str()) {
+        report.warnings.push(format!("{}: art & fonts are compiled in, changing this file won't change the game", relative));
+    } else {
+        report.warnings.push(format!("{} isn't a file the game reads, it won't override anything", relative));
+    }
+}
+
+// an .rkpak is unpacked under the temp directory and checked from there
+fn validate_pack(target: &str) -> bool {
+    use std::env;
+    use std::process;
+
+    let mut bytes = Vec::new();
+    if let Err(e) = File::open(target).and_then(|mut file| file.read_to_end(&mut bytes)) {
+        error!("can't read {}: {}", target, e);
+        return false;
+    }
+    let dir = env::temp_dir().join(format!("rusty-kong-validate-{}", process::id()));
+    let passed =
+        match pack_unpack(&bytes, &dir) {
+            Ok(_) => validate_dir(target, &dir),
+            Err(e) => {
+                error!("{}: {}", target, e);
+                false
+            },
+        };
+    if let Err(e) = fs::remove_dir_all(&dir) {
+        warn!("can't remove {}: {}", dir.display(), e);
+    }
+    return passed;
+}
+
+// checks the directory a mod or the base pack lives in, or a pack written
+// from one, then the built-in stage data every pack plays on.  prints what
+// it finds; true when there are no errors.
+pub fn validate_assets(target: &str) -> bool {
+    let dir = Path::new(target);
+    if dir.is_file() {
+        if extension(target) != "rkpak" {
+            error!("{} isn't a pack; point this at a mod, a pack, or the asset directory a pack is written from", target);
+            return false;
+        }
+        return validate_pack(target);
+    }
+    if !dir.is_dir() {
+        error!("{} doesn't exist", target);
+        return false;
+    }
+    return validate_dir(target, dir);
+}
+
+fn validate_dir(target: &str, dir: &Path) -> bool {
+    use super::level::level_check;
+
+    let mut report = Report { errors: Vec::new(), warnings: Vec::new() };
+    let files = assets_files(dir);
+    for relative in files.iter() {
+        validate_file(dir, relative, &mut report);
+    }
+    if dir.join(ASSET_CHECKSUMS).is_file() {
+        report.errors.extend(assets_verify_dir(dir).into_iter().map(|p| format!("{}: {}", ASSET_CHECKSUMS, p)));
+    }
+    report.errors.extend(level_check());
+
+    for warning in report.warnings.iter() {
+        warn!("{}", warning);
+    }
+    for error in report.errors.iter() {
+        error!("{}", error);
+    }
+    info!("{}: {} files, {} errors, {} warnings", target, files.len(), report.errors.len(), report.warnings.len());
+    return report.errors.is_empty();
+}
@@ -0,0 +1,275 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use std::sync::mpsc;
+
+use sdl2::image::ImageRWops;
+use sdl2::image::Sdl2ImageContext;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rwops::RWops;
+
+use super::common::*;
+
+// loose sprite art, one png per sprite named after the tile it replaces
+// ("37.png" or "37-held-barrel.png"), under assets/ or an enabled mod
+pub const SPRITE_DIR: &str = "sprites";
+
+// the atlas grows downward in shelves this wide
+const ATLAS_WIDTH: u32 = 256;
+
+// the colors the ripper keys sprite art with, in palette entry order;
+// anything transparent is entry 0 too
+const SPRITE_KEY_COLORS: [(u8, u8, u8); 4] = [
+    (0x00, 0x00, 0x00),
+    (0xff, 0x9d, 0x9e),
+    (0xff, 0xf3, 0x14),
+    (0x0a, 0x07, 0xe8),
+];
+
+// where a packed sprite sits in the atlas
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+struct Atlas {
+    // palette entries, ATLAS_WIDTH a row
+    pixels: Vec<u8>,
+    regions: HashMap<u16, AtlasRegion>,
+}
+
+// the pngs out with the decode pool; the image context has to outlive them
+struct AtlasDecode {
+    _image: Sdl2ImageContext,
+    queued: usize,
+    // (load order, path, what came of it)
+    decoded: mpsc::Receiver<(usize, PathBuf, Result<LooseSprite, String>)>,
+}
+
+thread_local!(
+    static ATLAS:RefCell<Option<Atlas>> = RefCell::new(None);
+    static DECODING:RefCell<Option<AtlasDecode>> = RefCell::new(None);
+);
+
+struct LooseSprite {
+    tile: u16,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+// "37-held-barrel.png" -> 37
+pub fn atlas_tile_from_name(name: &str) -> Option<u16> {
+    let path = Path::new(name);
+    if path.extension().map_or(true, |e| e.to_string_lossy().to_lowercase() != "png") {
+        return None;
+    }
+    let stem = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned());
+    match stem.split('-').next().map(|n| n.parse::<u16>()) {
+        Some(Ok(tile)) if (tile as u32) < SPRITE_MAX => Some(tile),
+        _ => None,
+    }
+}
+
+fn key_color_entry(r: u8, g: u8, b: u8) -> (u8, bool) {
+    let distance = |&(kr, kg, kb): &(u8, u8, u8)| {
+        let (dr, dg, db) = (kr as i32 - r as i32, kg as i32 - g as i32, kb as i32 - b as i32);
+        dr * dr + dg * dg + db * db
+    };
+    let (entry, nearest) = SPRITE_KEY_COLORS
+        .iter()
+        .enumerate()
+        .min_by_key(|&(_, key)| distance(key))
+        .unwrap();
+    (entry as u8, distance(nearest) == 0)
+}
+
+// on a decode worker: the surface never leaves it, only the entries do
+fn atlas_read(path: &Path, tile: u16, bytes: &[u8]) -> Result<LooseSprite, String> {
+    let surface = RWops::from_bytes(bytes)?.load_png()?.convert_format(PixelFormatEnum::RGBA32)?;
+    let (width, height, pitch) = (surface.width(), surface.height(), surface.pitch());
+    if width == 0 || height == 0 || width > ATLAS_WIDTH {
+        return Err(format!("{}x{} doesn't fit a {} wide atlas", width, height, ATLAS_WIDTH));
+    }
+    let bytes =
+        match surface.without_lock() {
+            Some(b) => b,
+            None => return Err("can't read the pixels".to_string()),
+        };
+
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    let mut off_key = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let offset = (y * pitch + x * 4) as usize;
+            let (r, g, b, a) = (bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]);
+            if a == 0 {
+                pixels.push(0);
+                continue;
+            }
+            let (entry, exact) = key_color_entry(r, g, b);
+            if !exact {
+                off_key += 1;
+            }
+            pixels.push(entry);
+        }
+    }
+    if off_key > 0 {
+        warn!("{}: {} pixels aren't sprite key colors, used the nearest", path.display(), off_key);
+    }
+    return Ok(LooseSprite { tile: tile, width: width, height: height, pixels: pixels });
+}
+
+// the base pack's sprites, then each enabled mod's over them
+fn atlas_dirs() -> Vec<PathBuf> {
+    use rusty_kong::assets::*;
+    use rusty_kong::settings::settings_read;
+
+    let mut dirs = vec![Path::new(ASSETS_DIR).join(SPRITE_DIR)];
+    dirs.extend(settings_read(|s| s.mods.clone())
+        .iter()
+        .map(|name| Path::new(MODS_DIR).join(name).join(SPRITE_DIR)));
+    return dirs;
+}
+
+// shelf packing: tallest first, left to right, a new shelf when a row fills
+fn atlas_pack(mut sprites: Vec<LooseSprite>) -> Atlas {
+    sprites.sort_by(|a, b| b.height.cmp(&a.height).then(a.tile.cmp(&b.tile)));
+
+    let mut regions = HashMap::new();
+    let (mut x, mut y, mut shelf) = (0, 0, 0);
+    for sprite in sprites.iter() {
+        if x + sprite.width > ATLAS_WIDTH {
+            x = 0;
+            y += shelf;
+            shelf = 0;
+        }
+        regions.insert(sprite.tile, AtlasRegion { x: x, y: y, width: sprite.width, height: sprite.height });
+        x += sprite.width;
+        shelf = shelf.max(sprite.height);
+    }
+
+    let mut pixels = vec![0u8; (ATLAS_WIDTH * (y + shelf)) as usize];
+    for sprite in sprites.iter() {
+        let region = regions[&sprite.tile];
+        for sy in 0..sprite.height {
+            let row = ((region.y + sy) * ATLAS_WIDTH + region.x) as usize;
+            let source = (sy * sprite.width) as usize;
+            pixels[row..row + sprite.width as usize].copy_from_slice(&sprite.pixels[source..source + sprite.width as usize]);
+        }
+    }
+    return Atlas { pixels: pixels, regions: regions };
+}
+
+// dev mode only: every loose sprite png goes to the decode pool, and once
+// video_atlas_finish() packs them into one atlas those tiles draw from it
+// instead of the compiled in bitmaps
+pub fn video_atlas_load() -> Result<usize, String> {
+    use rusty_kong::assets::decode_spawn;
+
+    let image = sdl2::image::init(sdl2::image::INIT_PNG)?;
+    let (sender, receiver) = mpsc::channel();
+    let mut queued = 0;
+    for dir in atlas_dirs().iter() {
+        let entries =
+            match fs::read_dir(dir) {
+                Ok(e) => e,
+                Err(_) => continue,
+            };
+        let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        paths.sort();
+        for path in paths.into_iter().filter(|p| p.is_file()) {
+            let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+            let tile =
+                match atlas_tile_from_name(&name) {
+                    Some(t) => t,
+                    None => {
+                        warn!("{}: not named after a sprite tile, skipped", path.display());
+                        continue;
+                    },
+                };
+            let bytes =
+                match fs::read(&path) {
+                    Ok(b) => b,
+                    Err(e) => {
+                        warn!("{}: {}", path.display(), e);
+                        continue;
+                    },
+                };
+            let (order, sender) = (queued, sender.clone());
+            decode_spawn(move || {
+                let sprite = atlas_read(&path, tile, &bytes);
+                let _ = sender.send((order, path, sprite));
+            });
+            queued += 1;
+        }
+    }
+    DECODING.with(|cell| {*cell.borrow_mut() = Some(AtlasDecode { _image: image, queued: queued, decoded: receiver });});
+    return Ok(queued);
+}
+
+// boot, once the pool's through: later dirs' sprites win, as they were listed
+pub fn video_atlas_finish() {
+    let decode =
+        match DECODING.with(|cell| cell.borrow_mut().take()) {
+            Some(d) => d,
+            None => return,
+        };
+    let mut decoded: Vec<_> = decode.decoded.try_iter().collect();
+    if decoded.len() < decode.queued {
+        warn!("{} loose sprites never came back from decoding", decode.queued - decoded.len());
+    }
+    decoded.sort_by_key(|&(order, _, _)| order);
+
+    let mut loose: HashMap<u16, LooseSprite> = HashMap::new();
+    for (_, path, sprite) in decoded {
+        match sprite {
+            Ok(sprite) => {
+                let tile = sprite.tile;
+                if loose.insert(tile, sprite).is_some() {
+                    debug!("sprite {} overridden by {}", tile, path.display());
+                }
+            },
+            Err(e) => warn!("{}: {}", path.display(), e),
+        }
+    }
+
+    if loose.is_empty() {
+        ATLAS.with(|cell| {*cell.borrow_mut() = None;});
+        return;
+    }
+    let count = loose.len();
+    let atlas = atlas_pack(loose.into_iter().map(|(_, sprite)| sprite).collect());
+    info!("packed {} loose sprites into a {}x{} atlas", count, ATLAS_WIDTH, atlas.pixels.len() as u32 / ATLAS_WIDTH);
+    ATLAS.with(|cell| {*cell.borrow_mut() = Some(atlas);});
+}
+
+// hands the packed region for a tile and the atlas pixels to f, or none
+// when the tile isn't in the atlas
+pub fn atlas_lookup<T, F>(tile: u16, f: F) -> Option<T>
+    where F: FnOnce(&AtlasRegion, &[u8], u32) -> T {
+    ATLAS.with(|cell| {
+        match *cell.borrow() {
+            Some(ref atlas) => atlas.regions.get(&tile).map(|region| f(region, &atlas.pixels, ATLAS_WIDTH)),
+            None => None,
+        }
+    })
+}
@@ -0,0 +1,261 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::common::*;
+use super::framebuffer::FrameBuffer;
+
+// the tile bank & sprite sheet, each tile converted through its palette
+// the first time it's drawn with it and resident from then on.  a layer is
+// one pass copying out of these, where it used to be a call per tile or
+// sprite looking every pixel's color up again
+pub const RESIDENT_TILES:   usize = 1024;
+pub const RESIDENT_SPRITES: usize = 512;
+
+// a tile as drawn: which, which way round, in which colors, at what scale,
+// hatched or not.  keying on the colors themselves means a palette edit or
+// a different palette set is just a miss, nothing has to be told to let go
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct SheetKey {
+    tile: u16,
+    hflip: bool,
+    vflip: bool,
+    scale: u32,
+    colors: [u8; 16],
+    hatched: bool,
+}
+
+impl SheetKey {
+    fn new(tile: u16, hflip: bool, vflip: bool, scale: u32, palette: &Palette, hatched: bool) -> SheetKey {
+        let mut colors = [0u8; 16];
+        for (entry, color) in palette.entries.iter().enumerate() {
+            colors[entry * 4..entry * 4 + 4].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+        }
+        return SheetKey { tile: tile, hflip: hflip, vflip: vflip, scale: scale, colors: colors, hatched: hatched };
+    }
+}
+
+// every fourth diagonal of a hatched sprite, counted on screen so flipping
+// doesn't turn the stripes round
+const HATCH_SPACING: u32 = 4;
+
+// fixed slots; once they're all taken the one drawn longest ago goes
+struct Sheet<T> {
+    capacity: usize,
+    slots: Vec<(SheetKey, u64, T)>,
+    index: HashMap<SheetKey, usize>,
+}
+
+impl<T> Sheet<T> {
+    fn new(capacity: usize) -> Sheet<T> {
+        return Sheet { capacity: capacity, slots: Vec::new(), index: HashMap::new() };
+    }
+
+    // the slot for key, converting it with make on a miss
+    fn resident<F>(self:&mut Sheet<T>, key: SheetKey, frame: u64, stats: &mut DrawStats, make: F) -> usize
+        where F: FnOnce() -> T {
+        if let Some(&slot) = self.index.get(&key) {
+            self.slots[slot].1 = frame;
+            return slot;
+        }
+        stats.uploads += 1;
+        let slot =
+            if self.slots.len() < self.capacity {
+                self.slots.push((key, frame, make()));
+                self.slots.len() - 1
+            } else {
+                let oldest = (0..self.slots.len()).min_by_key(|&slot| self.slots[slot].1).unwrap();
+                self.index.remove(&self.slots[oldest].0);
+                self.slots[oldest] = (key, frame, make());
+                stats.evictions += 1;
+                oldest
+            };
+        self.index.insert(key, slot);
+        return slot;
+    }
+}
+
+// the last compose's, for the profiler panel
+#[derive(Clone, Copy, Default)]
+pub struct DrawStats {
+    pub bg_calls: u32,
+    pub bg_tiles: u32,
+    pub fg_calls: u32,
+    pub fg_sprites: u32,
+    // tiles converted into a sheet this frame, and ones pushed out for them
+    pub uploads: u32,
+    pub evictions: u32,
+    pub resident_tiles: usize,
+    pub resident_sprites: usize,
+}
+
+struct Sheets {
+    frame: u64,
+    // RGBA rows at the framebuffer's scale, ready to copy
+    tiles: Sheet<Vec<u8>>,
+    // flips applied, none for transparent
+    sprites: Sheet<Vec<Option<PaletteEntry>>>,
+    stats: DrawStats,
+    last: DrawStats,
+}
+
+thread_local!(
+    static SHEETS:RefCell<Sheets> = RefCell::new(Sheets {
+        frame: 0,
+        tiles: Sheet::new(RESIDENT_TILES),
+        sprites: Sheet::new(RESIDENT_SPRITES),
+        stats: DrawStats::default(),
+        last: DrawStats::default()
+    });
+);
+
+pub fn draw_stats() -> DrawStats {
+    SHEETS.with(|cell| cell.borrow().last)
+}
+
+pub fn batch_frame_start() {
+    SHEETS.with(|cell| {
+        let mut sheets = cell.borrow_mut();
+        sheets.frame += 1;
+        sheets.stats = DrawStats::default();
+    });
+}
+
+pub fn batch_frame_end() {
+    SHEETS.with(|cell| {
+        let mut sheets = cell.borrow_mut();
+        sheets.stats.resident_tiles = sheets.tiles.slots.len();
+        sheets.stats.resident_sprites = sheets.sprites.slots.len();
+        sheets.last = sheets.stats;
+    });
+}
+
+fn batch_convert_tile(tile: u16, hflip: bool, vflip: bool, scale: u32, palette: &Palette) -> Vec<u8> {
+    use super::tiles::get_tile_bitmap;
+
+    let bitmap = get_tile_bitmap(tile as u8);
+    let width = TILE_WIDTH * scale;
+    let mut rgba = Vec::with_capacity((width * TILE_HEIGHT * scale * 4) as usize);
+    for y in 0..TILE_HEIGHT * scale {
+        let ty = y / scale;
+        let sy = if vflip { TILE_HEIGHT - 1 - ty } else { ty };
+        for x in 0..width {
+            let tx = x / scale;
+            let sx = if hflip { TILE_WIDTH - 1 - tx } else { tx };
+            let entry = palette.entries[bitmap[(sy * TILE_WIDTH + sx) as usize] as usize];
+            rgba.extend_from_slice(&[entry.r, entry.g, entry.b, entry.a]);
+        }
+    }
+    return rgba;
+}
+
+fn batch_convert_sprite(tile: u16, hflip: bool, vflip: bool, palette: &Palette, hatched: bool) -> Vec<Option<PaletteEntry>> {
+    use super::sprites::get_sprite_bitmap;
+
+    let bitmap = get_sprite_bitmap(tile as u8);
+    let mut pixels = Vec::with_capacity((SPRITE_WIDTH * SPRITE_HEIGHT) as usize);
+    for py in 0..SPRITE_HEIGHT {
+        let sy = if vflip { SPRITE_HEIGHT - 1 - py } else { py };
+        for px in 0..SPRITE_WIDTH {
+            let sx = if hflip { SPRITE_WIDTH - 1 - px } else { px };
+            // color zero is transparent for sprites
+            let color = bitmap[(sy * SPRITE_WIDTH + sx) as usize];
+            // the hatching is in entry zero, black in every set
+            let stripe = hatched && (px + py) % HATCH_SPACING == 0;
+            pixels.push(
+                match color {
+                    0 => None,
+                    _ if stripe => Some(palette.entries[0]),
+                    _ => Some(palette.entries[color as usize]),
+                });
+        }
+    }
+    return pixels;
+}
+
+// the background layer, every enabled tile a row copy out of the sheet;
+// the columns past the screen's edge are skipped, as plot() clipped them
+pub fn batch_bg(framebuffer: &mut FrameBuffer, table: &[BackgroundControlBlock]) {
+    use super::palettes::get_palette;
+
+    let scale = framebuffer.scale();
+    let pitch = framebuffer.pitch();
+    let row_bytes = (TILE_WIDTH * scale * 4) as usize;
+    SHEETS.with(|cell| {
+        let sheets = &mut *cell.borrow_mut();
+        let frame = sheets.frame;
+        sheets.stats.bg_calls += 1;
+        for (index, bg_cntl) in table.iter().enumerate() {
+            let (col, row) = (index as u32 % TILE_COL_COUNT, index as u32 / TILE_COL_COUNT);
+            if !bg_cntl.is_enabled() || (col + 1) * TILE_WIDTH > SCREEN_WIDTH || (row + 1) * TILE_HEIGHT > SCREEN_HEIGHT {
+                continue;
+            }
+            let (tile, hflip, vflip) = (bg_cntl.get_tile(), bg_cntl.is_horizontally_flipped(), bg_cntl.is_vertically_flipped());
+            let palette = get_palette(bg_cntl.get_palette());
+            let key = SheetKey::new(tile, hflip, vflip, scale, &palette, false);
+            let slot = sheets.tiles.resident(key, frame, &mut sheets.stats, || batch_convert_tile(tile, hflip, vflip, scale, &palette));
+            let rgba = &sheets.tiles.slots[slot].2;
+            let left = (col * TILE_WIDTH * scale * 4) as usize;
+            for y in 0..(TILE_HEIGHT * scale) as usize {
+                let start = ((row * TILE_HEIGHT * scale) as usize + y) * pitch + left;
+                framebuffer.pixels[start..start + row_bytes].copy_from_slice(&rgba[y * row_bytes..(y + 1) * row_bytes]);
+            }
+            sheets.stats.bg_tiles += 1;
+        }
+    });
+}
+
+// the sprite layer in table order.  dropped, when the scanline limit's on,
+// is per sprite the lines it lost.  loose art from the dev atlas isn't in
+// the sheet and still goes through its own call
+pub fn batch_fg(framebuffer: &mut FrameBuffer, table: &[SpriteControlBlock], dropped: Option<&[u16]>) {
+    use super::atlas::atlas_lookup;
+    use super::palettes::get_palette;
+    use super::palettes::palette_is_hatched;
+
+    SHEETS.with(|cell| {
+        let sheets = &mut *cell.borrow_mut();
+        let frame = sheets.frame;
+        sheets.stats.fg_calls += 1;
+        for (index, fg_cntl) in table.iter().enumerate() {
+            if !fg_cntl.is_enabled() {
+                continue;
+            }
+            let lines = dropped.map_or(0, |d| d[index]);
+            sheets.stats.fg_sprites += 1;
+            let tile = fg_cntl.get_tile();
+            if atlas_lookup(tile, |_, _, _| ()).is_some() {
+                fg_cntl.update_masked(framebuffer, lines);
+                sheets.stats.fg_calls += 1;
+                continue;
+            }
+            let (hflip, vflip) = (fg_cntl.is_horizontally_flipped(), fg_cntl.is_vertically_flipped());
+            let palette = get_palette(fg_cntl.get_palette());
+            let hatched = palette_is_hatched(fg_cntl.get_palette());
+            let key = SheetKey::new(tile, hflip, vflip, 1, &palette, hatched);
+            let slot = sheets.sprites.resident(key, frame, &mut sheets.stats, || batch_convert_sprite(tile, hflip, vflip, &palette, hatched));
+            let pixels = &sheets.sprites.slots[slot].2;
+            let (left, top) = fg_cntl.get_position();
+            for py in 0..SPRITE_HEIGHT {
+                if lines & (1 << py) != 0 {
+                    continue;
+                }
+                for px in 0..SPRITE_WIDTH {
+                    if let Some(entry) = pixels[(py * SPRITE_WIDTH + px) as usize] {
+                        framebuffer.plot(left as u32 + px, top as u32 + py, entry);
+                    }
+                }
+            }
+        }
+    });
+}
@@ -10,6 +10,8 @@
 //
 // --------------------------------------------------------------------------
 
+use super::framebuffer::FrameBuffer;
+
 pub const SCREEN_WIDTH:  u32 = 224;
 pub const SCREEN_HEIGHT: u32 = 256;
 
@@ -41,6 +43,26 @@ pub enum TileMaps {
     Level1,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PaletteSet {
+    Arcade,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+impl PaletteSet {
+    // as settings.cfg's palette_set has it
+    pub fn name(&self) -> &'static str {
+        match self {
+            &PaletteSet::Arcade         => "arcade",
+            &PaletteSet::Deuteranopia   => "deuteranopia",
+            &PaletteSet::Protanopia     => "protanopia",
+            &PaletteSet::Tritanopia     => "tritanopia",
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Palette {
     pub entries: [PaletteEntry; 4]
@@ -66,7 +88,7 @@ pub struct TileMap {
     pub entries: [TileMapEntry; (TILE_ROW_COUNT * TILE_COL_COUNT) as usize]
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Hash)]
 pub struct SpriteControlBlock {
     y: u16,
     x: u16,
@@ -96,16 +118,77 @@ impl SpriteControlBlock {
         }
     }
 
-    pub fn update(self:&SpriteControlBlock) {
+    // bit n of dropped set skips the sprite's nth line, the way a scanline
+    // that ran out of sprite slots would
+    pub fn update_masked(self:&SpriteControlBlock, framebuffer:&mut FrameBuffer, dropped:u16) {
+        self.draw(framebuffer, dropped, false);
+    }
+
+    // blended over what's there instead of covering it
+    pub fn update_translucent(self:&SpriteControlBlock, framebuffer:&mut FrameBuffer) {
+        self.draw(framebuffer, 0, true);
+    }
+
+    fn draw(self:&SpriteControlBlock, framebuffer:&mut FrameBuffer, dropped:u16, translucent:bool) {
+        use super::atlas::atlas_lookup;
         use super::palettes::get_palette;
         use super::sprites::get_sprite_bitmap;
 
+        if !self.is_enabled() {
+            return;
+        }
+
+        let palette = get_palette(self.palette);
+        // loose art packed in dev mode wins over the compiled in bitmap
+        let packed = atlas_lookup(self.tile, |region, pixels, pitch| {
+            self.plot_bitmap(framebuffer, &palette, dropped, translucent, region.width, region.height, |sx, sy| {
+                pixels[((region.y + sy) * pitch + region.x + sx) as usize]
+            });
+        });
+        if packed.is_none() {
+            let bitmap = get_sprite_bitmap(self.tile as u8);
+            self.plot_bitmap(framebuffer, &palette, dropped, translucent, SPRITE_WIDTH, SPRITE_HEIGHT, |sx, sy| {
+                bitmap[(sy * SPRITE_WIDTH + sx) as usize]
+            });
+        }
+    }
+
+    fn plot_bitmap<F>(self:&SpriteControlBlock, framebuffer:&mut FrameBuffer, palette:&Palette, dropped:u16, translucent:bool, width:u32, height:u32, color_at:F)
+        where F: Fn(u32, u32) -> u8 {
+        for py in 0..height {
+            if py < SPRITE_HEIGHT && dropped & (1 << py) != 0 {
+                continue;
+            }
+            for px in 0..width {
+                let sx = if self.is_horizontally_flipped() { width - 1 - px } else { px };
+                let sy = if self.is_vertically_flipped() { height - 1 - py } else { py };
+                let color = color_at(sx, sy);
+                // color zero is transparent for sprites
+                if color == 0 {
+                    continue;
+                }
+                let (x, y, entry) = (self.x as u32 + px, self.y as u32 + py, palette.entries[color as usize]);
+                if translucent {
+                    framebuffer.blend(x, y, entry);
+                } else {
+                    framebuffer.plot(x, y, entry);
+                }
+            }
+        }
     }
 
     pub fn tile(self:&mut SpriteControlBlock, number:u16) {
         self.tile = number;
     }
 
+    pub fn get_tile(self:&SpriteControlBlock) -> u16 {
+        self.tile
+    }
+
+    pub fn get_palette(self:&SpriteControlBlock) -> u8 {
+        self.palette
+    }
+
     pub fn is_changed(self:&SpriteControlBlock) -> bool {
         self.flags & F_SPR_CHANGED != 0
     }
@@ -178,9 +261,35 @@ impl SpriteControlBlock {
     pub fn is_horizontally_flipped(self:&SpriteControlBlock) -> bool {
         self.flags & F_SPR_HFLIP != 0
     }
+
+    // for savestates: every field little endian, in declaration order
+    pub const PACKED_SIZE: usize = 16;
+
+    pub fn pack(self:&SpriteControlBlock, out:&mut Vec<u8>) {
+        out.extend_from_slice(&self.y.to_le_bytes());
+        out.extend_from_slice(&self.x.to_le_bytes());
+        out.push(self.flags);
+        out.extend_from_slice(&self.tile.to_le_bytes());
+        out.push(self.palette);
+        out.extend_from_slice(&self.user_data1.to_le_bytes());
+        out.extend_from_slice(&self.user_data2.to_le_bytes());
+    }
+
+    // bytes is PACKED_SIZE long
+    pub fn unpack(bytes:&[u8]) -> SpriteControlBlock {
+        SpriteControlBlock {
+            y: u16::from_le_bytes([bytes[0], bytes[1]]),
+            x: u16::from_le_bytes([bytes[2], bytes[3]]),
+            flags: bytes[4],
+            tile: u16::from_le_bytes([bytes[5], bytes[6]]),
+            palette: bytes[7],
+            user_data1: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            user_data2: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        }
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Hash)]
 pub struct BackgroundControlBlock {
     tile: u16,
     flags: u8,
@@ -203,10 +312,12 @@ impl BackgroundControlBlock {
             user_data2: 0}
     }
 
-    pub fn update(self:&BackgroundControlBlock) {
-        use super::palettes::get_palette;
-        use super::tiles::get_tile_bitmap;
+    pub fn get_tile(self:&BackgroundControlBlock) -> u16 {
+        self.tile
+    }
 
+    pub fn get_palette(self:&BackgroundControlBlock) -> u8 {
+        self.palette
     }
 
     pub fn is_changed(self:&BackgroundControlBlock) -> bool {
@@ -264,4 +375,26 @@ impl BackgroundControlBlock {
     pub fn is_horizontally_flipped(self:&BackgroundControlBlock) -> bool {
         self.flags & F_BG_HFLIP != 0
     }
+
+    // for savestates: every field little endian, in declaration order
+    pub const PACKED_SIZE: usize = 12;
+
+    pub fn pack(self:&BackgroundControlBlock, out:&mut Vec<u8>) {
+        out.extend_from_slice(&self.tile.to_le_bytes());
+        out.push(self.flags);
+        out.push(self.palette);
+        out.extend_from_slice(&self.user_data1.to_le_bytes());
+        out.extend_from_slice(&self.user_data2.to_le_bytes());
+    }
+
+    // bytes is PACKED_SIZE long
+    pub fn unpack(bytes:&[u8]) -> BackgroundControlBlock {
+        BackgroundControlBlock {
+            tile: u16::from_le_bytes([bytes[0], bytes[1]]),
+            flags: bytes[2],
+            palette: bytes[3],
+            user_data1: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            user_data2: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+        }
+    }
 }
\ No newline at end of file
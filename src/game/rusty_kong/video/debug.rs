@@ -0,0 +1,419 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::path::Path;
+
+use sdl2::keyboard::Keycode;
+
+use rusty_kong::inspector::*;
+use super::common::*;
+use super::framebuffer::FrameBuffer;
+use super::palettes::*;
+use super::text::*;
+
+// what the dev mode debug window shows; tab in that window moves on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DebugPanel {
+    Inspector,
+    Entities,
+    Tiles,
+    Profiler,
+    Palettes,
+}
+
+const DEBUG_PANELS: [DebugPanel; 5] = [
+    DebugPanel::Inspector,
+    DebugPanel::Entities,
+    DebugPanel::Tiles,
+    DebugPanel::Profiler,
+    DebugPanel::Palettes,
+];
+
+impl DebugPanel {
+    fn title(&self) -> &'static str {
+        match self {
+            &DebugPanel::Inspector => "INSPECTOR",
+            &DebugPanel::Entities => "SPRITES",
+            &DebugPanel::Tiles    => "TILES",
+            &DebugPanel::Profiler => "PROFILER",
+            &DebugPanel::Palettes => "PALETTES",
+        }
+    }
+}
+
+// one sample a pixel column across the graph
+const FRAME_TIME_HISTORY: usize = SCREEN_WIDTH as usize;
+// a frame at 60hz, in microseconds
+const FRAME_BUDGET: u32 = 16_667;
+// graph pixels per millisecond
+const GRAPH_SCALE: u32 = 8;
+const GRAPH_BOTTOM: u32 = SCREEN_HEIGHT - 1;
+
+struct Debug {
+    panel: usize,
+    frame_times: VecDeque<u32>,
+    // the inspector's cursor, counted over editable fields only
+    selected: usize,
+    // the palette editor's cursor, palette * 4 + entry
+    swatch: usize,
+}
+
+thread_local!(
+    static DEBUG:RefCell<Debug> = RefCell::new(Debug {
+        panel: 0,
+        frame_times: VecDeque::new(),
+        selected: 0,
+        swatch: 0
+    });
+);
+
+// page up/down nudge by this much, left/right by one
+const INSPECTOR_BIG_STEP: i32 = 10;
+
+// (kind, index, field, value) for every field the inspector can edit, in
+// the order they're listed
+fn inspector_editable(entities: &[InspectEntity]) -> Vec<(&'static str, usize, &'static str, i32)> {
+    let mut editable = Vec::new();
+    for entity in entities.iter() {
+        for field in entity.fields.iter() {
+            if let InspectValue::Number(value) = field.value {
+                editable.push((entity.kind, entity.index, field.name, value));
+            }
+        }
+    }
+    return editable;
+}
+
+// the editor's grid: 8 palettes a row, each 4 swatches side by side
+const SWATCH_WIDTH:  u32 = 6;
+const SWATCH_HEIGHT: u32 = 12;
+const SWATCH_GAP:    u32 = 4;
+const SWATCH_TOP:    u32 = 3 * TILE_HEIGHT;
+const PALETTES_PER_ROW: usize = 8;
+// q/a, w/s & e/d nudge red, green & blue by this much
+const SWATCH_STEP: i32 = 4;
+// where x writes the edited set, next to wherever the game was started
+const PALETTE_EXPORT_PATH: &str = "palettes-edited.png";
+
+fn palette_nudge(value: u8, step: i32) -> u8 {
+    (value as i32 + step).max(0).min(0xff) as u8
+}
+
+// arrows move the cursor, q/a w/s e/d change the color under it,
+// backspace puts the original back and x exports
+fn video_debug_palettes_key(keycode: Keycode) {
+    let swatch = DEBUG.with(|cell| cell.borrow().swatch);
+    let last = PALETTE_MAX * 4 - 1;
+    let row = PALETTES_PER_ROW * 4;
+    let moved =
+        match keycode {
+            Keycode::Left   => Some(swatch.saturating_sub(1)),
+            Keycode::Right  => Some((swatch + 1).min(last)),
+            Keycode::Up     => Some(if swatch >= row { swatch - row } else { swatch }),
+            Keycode::Down   => Some(if swatch + row <= last { swatch + row } else { swatch }),
+            _ => None,
+        };
+    if let Some(moved) = moved {
+        DEBUG.with(|cell| {cell.borrow_mut().swatch = moved;});
+        return;
+    }
+
+    let (number, entry) = ((swatch / 4) as u8, swatch % 4);
+    let mut color = palette_edited(number).entries[entry];
+    match keycode {
+        Keycode::Q => color.r = palette_nudge(color.r, SWATCH_STEP),
+        Keycode::A => color.r = palette_nudge(color.r, -SWATCH_STEP),
+        Keycode::W => color.g = palette_nudge(color.g, SWATCH_STEP),
+        Keycode::S => color.g = palette_nudge(color.g, -SWATCH_STEP),
+        Keycode::E => color.b = palette_nudge(color.b, SWATCH_STEP),
+        Keycode::D => color.b = palette_nudge(color.b, -SWATCH_STEP),
+        Keycode::Backspace => {
+            palette_revert(number, entry);
+            return;
+        },
+        Keycode::X => {
+            match palette_export(Path::new(PALETTE_EXPORT_PATH)) {
+                Ok(()) => info!("palettes: {} edits exported to {}", palette_edit_count(), PALETTE_EXPORT_PATH),
+                Err(e) => warn!("palettes: {}", e),
+            }
+            return;
+        },
+        _ => return,
+    }
+    palette_edit(number, entry, color);
+}
+
+// keys pressed with the debug window focused; the inspector and the
+// palette editor take them
+pub fn video_debug_key(keycode: Keycode) {
+    let (panel, selected) = DEBUG.with(|cell| {
+        let debug = cell.borrow();
+        (DEBUG_PANELS[debug.panel], debug.selected)
+    });
+    if panel == DebugPanel::Palettes {
+        video_debug_palettes_key(keycode);
+        return;
+    }
+    if panel != DebugPanel::Inspector {
+        return;
+    }
+
+    let editable = inspector_editable(&inspector_entities());
+    if editable.is_empty() {
+        return;
+    }
+    let selected = selected.min(editable.len() - 1);
+    let step =
+        match keycode {
+            Keycode::Up => {
+                DEBUG.with(|cell| {cell.borrow_mut().selected = selected.saturating_sub(1);});
+                return;
+            },
+            Keycode::Down => {
+                DEBUG.with(|cell| {cell.borrow_mut().selected = (selected + 1).min(editable.len() - 1);});
+                return;
+            },
+            Keycode::Left       => -1,
+            Keycode::Right      => 1,
+            Keycode::PageDown   => -INSPECTOR_BIG_STEP,
+            Keycode::PageUp     => INSPECTOR_BIG_STEP,
+            _ => return,
+        };
+    let (kind, index, field, value) = editable[selected];
+    if let Err(e) = inspector_set(kind, index, field, value + step) {
+        warn!("inspector: {}", e);
+    }
+}
+
+// entity headings in blue, fields under them; the cursor's field is red
+fn video_debug_inspector(framebuffer: &mut FrameBuffer) {
+    let entities = inspector_entities();
+    let selected = DEBUG.with(|cell| cell.borrow().selected);
+    let last_row = SCREEN_HEIGHT / TILE_HEIGHT - 1;
+
+    let mut row = 2;
+    let mut editable = 0;
+    'entities: for entity in entities.iter() {
+        if row > last_row {
+            break;
+        }
+        text_draw(framebuffer, 0, row * TILE_HEIGHT, &format!("{} {}", entity.kind, entity.index), TEXT_PALETTE_BLUE);
+        row += 1;
+        for field in entity.fields.iter() {
+            if row > last_row {
+                break 'entities;
+            }
+            let (text, palette) =
+                match field.value {
+                    InspectValue::Number(value) => {
+                        let palette = if editable == selected { TEXT_PALETTE_RED } else { TEXT_PALETTE_WHITE };
+                        editable += 1;
+                        (format!(" {:10} {}", field.name, value), palette)
+                    },
+                    InspectValue::Label(label) => (format!(" {:10} {}", field.name, label), TEXT_PALETTE_GREEN),
+                };
+            text_draw(framebuffer, 0, row * TILE_HEIGHT, &text, palette);
+            row += 1;
+        }
+    }
+    if entities.is_empty() {
+        text_draw(framebuffer, 0, 2 * TILE_HEIGHT, "NOTHING LIVE", TEXT_PALETTE_WHITE);
+    }
+}
+
+pub fn video_debug_next_panel() {
+    DEBUG.with(|cell| {
+        let mut debug = cell.borrow_mut();
+        debug.panel = (debug.panel + 1) % DEBUG_PANELS.len();
+    });
+}
+
+// wall clock time of the last frame, logic and render together
+pub fn video_debug_frame_time(micros: u32) {
+    DEBUG.with(|cell| {
+        let mut debug = cell.borrow_mut();
+        if debug.frame_times.len() == FRAME_TIME_HISTORY {
+            debug.frame_times.pop_front();
+        }
+        debug.frame_times.push_back(micros);
+    });
+}
+
+fn video_debug_entities(framebuffer: &mut FrameBuffer) {
+    use super::SPR_CNTL;
+
+    let rows = SCREEN_HEIGHT / TILE_HEIGHT - 3;
+    text_draw(framebuffer, 0, 2 * TILE_HEIGHT, "SLOT TILE   X   Y PAL", TEXT_PALETTE_BLUE);
+    SPR_CNTL.with(|cell| {
+        let table = cell.borrow();
+        let enabled: Vec<(usize, &SpriteControlBlock)> = table
+            .iter()
+            .enumerate()
+            .filter(|&(_, sprite)| sprite.is_enabled())
+            .collect();
+        for (row, &(slot, sprite)) in enabled.iter().take(rows as usize).enumerate() {
+            let (x, y) = sprite.get_position();
+            let line = format!("{:4}   {:02X} {:3} {:3} {:3}", slot, sprite.get_tile(), x, y, sprite.get_palette());
+            text_draw(framebuffer, 0, (row as u32 + 3) * TILE_HEIGHT, &line, TEXT_PALETTE_WHITE);
+        }
+        if enabled.len() > rows as usize {
+            let more = format!("{} MORE", enabled.len() - rows as usize);
+            text_draw(framebuffer, 0, SCREEN_HEIGHT - TILE_HEIGHT, &more, TEXT_PALETTE_RED);
+        }
+    });
+}
+
+// the whole character set in a 16 x 16 grid, drawn with the text palette
+fn video_debug_tiles(framebuffer: &mut FrameBuffer) {
+    use super::palettes::get_palette;
+    use super::tiles::get_tile_bitmap;
+
+    let left = (SCREEN_WIDTH - 16 * TILE_WIDTH) / 2;
+    let top = 3 * TILE_HEIGHT;
+    let palette = get_palette(TEXT_PALETTE_WHITE);
+    for number in 0..TILE_MAX {
+        let bitmap = get_tile_bitmap(number as u8);
+        let x = left + (number % 16) * TILE_WIDTH;
+        let y = top + (number / 16) * TILE_HEIGHT;
+        for ty in 0..TILE_HEIGHT {
+            for tx in 0..TILE_WIDTH {
+                let color = bitmap[(ty * TILE_WIDTH + tx) as usize];
+                framebuffer.plot(x + tx, y + ty, palette.entries[color as usize]);
+            }
+        }
+    }
+    for col in 0..16 {
+        text_draw(framebuffer, left + col * TILE_WIDTH, top - TILE_HEIGHT, &format!("{:X}", col), TEXT_PALETTE_BLUE);
+    }
+}
+
+// every arcade palette with edits applied, whichever set the game is
+// showing; the cursor's swatch gets a white frame & its values below
+fn video_debug_palettes(framebuffer: &mut FrameBuffer) {
+    let frame = PaletteEntry { r: 0xff, g: 0xff, b: 0xff, a: 0xff };
+    let swatch = DEBUG.with(|cell| cell.borrow().swatch);
+    let group_width = 4 * SWATCH_WIDTH + SWATCH_GAP;
+    let group_height = SWATCH_HEIGHT + SWATCH_GAP;
+    let left = (SCREEN_WIDTH - PALETTES_PER_ROW as u32 * group_width + SWATCH_GAP) / 2;
+
+    for number in 0..PALETTE_MAX {
+        let palette = palette_edited(number as u8);
+        let x = left + (number % PALETTES_PER_ROW) as u32 * group_width;
+        let y = SWATCH_TOP + (number / PALETTES_PER_ROW) as u32 * group_height;
+        for entry in 0..4 {
+            let sx = x + entry as u32 * SWATCH_WIDTH;
+            let selected = number * 4 + entry == swatch;
+            for py in 0..SWATCH_HEIGHT {
+                for px in 0..SWATCH_WIDTH {
+                    let edge = px == 0 || py == 0 || px == SWATCH_WIDTH - 1 || py == SWATCH_HEIGHT - 1;
+                    let color = if selected && edge { frame } else { palette.entries[entry] };
+                    framebuffer.plot(sx + px, y + py, color);
+                }
+            }
+        }
+    }
+
+    let (number, entry) = (swatch / 4, swatch % 4);
+    let color = palette_edited(number as u8).entries[entry];
+    let text_row = (SWATCH_TOP + (PALETTE_MAX / PALETTES_PER_ROW) as u32 * group_height) / TILE_HEIGHT + 1;
+    let lines = [
+        (format!("PALETTE {} ENTRY {}", number, entry), TEXT_PALETTE_WHITE),
+        (format!("R {:02X}  G {:02X}  B {:02X}", color.r, color.g, color.b), TEXT_PALETTE_WHITE),
+        (format!("{} EDITED", palette_edit_count()), TEXT_PALETTE_GREEN),
+        ("X EXPORTS".to_string(), TEXT_PALETTE_BLUE),
+    ];
+    for (offset, &(ref text, palette)) in lines.iter().enumerate() {
+        text_draw(framebuffer, 0, (text_row + offset as u32) * TILE_HEIGHT, text, palette);
+    }
+}
+
+fn millis_text(micros: u32) -> String {
+    format!("{}.{}", micros / 1000, micros % 1000 / 100)
+}
+
+// frame times as bars, oldest on the left; red ones blew the 60hz budget.
+// under the times, what the last compose drew and how many calls it took,
+// the scratch arena, and under that the heap
+fn video_debug_profiler(framebuffer: &mut FrameBuffer) {
+    use rusty_kong::arena::arena_stats;
+    use super::batch::*;
+
+    let ok   = PaletteEntry { r: 0x00, g: 0xc0, b: 0x00, a: 0xff };
+    let slow = PaletteEntry { r: 0xe0, g: 0x00, b: 0x00, a: 0xff };
+    let line = PaletteEntry { r: 0x80, g: 0x80, b: 0x80, a: 0xff };
+
+    let times: Vec<u32> = DEBUG.with(|cell| cell.borrow().frame_times.iter().cloned().collect());
+    for (x, &micros) in times.iter().enumerate() {
+        let height = (micros * GRAPH_SCALE / 1000).min(GRAPH_BOTTOM - 4 * TILE_HEIGHT);
+        let color = if micros > FRAME_BUDGET { slow } else { ok };
+        for y in 0..height {
+            framebuffer.plot(x as u32, GRAPH_BOTTOM - y, color);
+        }
+    }
+    let budget = GRAPH_BOTTOM - FRAME_BUDGET * GRAPH_SCALE / 1000;
+    for x in (0..SCREEN_WIDTH).filter(|x| x % 4 == 0) {
+        framebuffer.plot(x, budget, line);
+    }
+
+    let last = times.last().cloned().unwrap_or(0);
+    let worst = times.iter().cloned().max().unwrap_or(0);
+    let average = if times.is_empty() { 0 } else { times.iter().sum::<u32>() / times.len() as u32 };
+    text_draw(framebuffer, 0, 2 * TILE_HEIGHT, &format!("LAST {} MS", millis_text(last)), TEXT_PALETTE_WHITE);
+    text_draw(framebuffer, 0, 3 * TILE_HEIGHT, &format!("AVG  {} MS", millis_text(average)), TEXT_PALETTE_WHITE);
+    text_draw(framebuffer, 0, 4 * TILE_HEIGHT, &format!("MAX  {} MS", millis_text(worst)), TEXT_PALETTE_WHITE);
+
+    let stats = draw_stats();
+    let arena = arena_stats();
+    let lines = [
+        format!("BG {:2} CALLS {:4} TILES", stats.bg_calls, stats.bg_tiles),
+        format!("FG {:2} CALLS {:4} SPRITES", stats.fg_calls, stats.fg_sprites),
+        format!("UPLOADS {} EVICTED {}", stats.uploads, stats.evictions),
+        format!("RESIDENT {}/{} {}/{}", stats.resident_tiles, RESIDENT_TILES, stats.resident_sprites, RESIDENT_SPRITES),
+        format!("ARENA {} BUFS {}K PEAK {}/{}", arena.buffers, arena.bytes / 1024, arena.frame_peak, arena.peak),
+    ];
+    for (offset, text) in lines.iter().enumerate() {
+        text_draw(framebuffer, 0, (6 + offset as u32) * TILE_HEIGHT, text, TEXT_PALETTE_BLUE);
+    }
+
+    video_debug_memory(framebuffer, 11);
+}
+
+// the live heap by subsystem, from the debug build's tagging allocator
+fn video_debug_memory(framebuffer: &mut FrameBuffer, row: u32) {
+    use rusty_kong::arena::memory_live;
+    use rusty_kong::arena::memory_usage;
+
+    if !cfg!(debug_assertions) {
+        text_draw(framebuffer, 0, row * TILE_HEIGHT, "HEAP NOT TRACKED IN RELEASE", TEXT_PALETTE_WHITE);
+        return;
+    }
+    text_draw(framebuffer, 0, row * TILE_HEIGHT, &format!("HEAP {}K", memory_live() / 1024), TEXT_PALETTE_WHITE);
+    for (offset, &(tag, bytes)) in memory_usage().iter().enumerate() {
+        let text = format!("{:6} {:6}K", tag.name().to_uppercase(), bytes / 1024);
+        text_draw(framebuffer, 0, (row + 1 + offset as u32) * TILE_HEIGHT, &text, TEXT_PALETTE_BLUE);
+    }
+}
+
+pub fn video_debug_compose(framebuffer: &mut FrameBuffer) {
+    let panel = DEBUG_PANELS[DEBUG.with(|cell| cell.borrow().panel)];
+    framebuffer.clear();
+    text_draw(framebuffer, 0, 0, panel.title(), TEXT_PALETTE_RED);
+    match panel {
+        DebugPanel::Inspector => video_debug_inspector(framebuffer),
+        DebugPanel::Entities => video_debug_entities(framebuffer),
+        DebugPanel::Tiles    => video_debug_tiles(framebuffer),
+        DebugPanel::Profiler => video_debug_profiler(framebuffer),
+        DebugPanel::Palettes => video_debug_palettes(framebuffer),
+    }
+}
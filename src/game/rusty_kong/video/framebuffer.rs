@@ -0,0 +1,154 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use super::common::*;
+
+// internal pixels per playfield pixel at most
+pub const FRAMEBUFFER_SCALE_MAX: u32 = 4;
+
+// RGBA, one byte per channel, SCREEN_WIDTH x SCREEN_HEIGHT times the scale.
+// the control tables are composed into this every frame; the window (or the
+// headless golden runner) only ever sees the finished buffer.  everything
+// draws in playfield pixels, scaled buffers fill a square per one.
+pub struct FrameBuffer {
+    pub pixels: Vec<u8>,
+    scale: u32,
+}
+
+impl FrameBuffer {
+    pub fn new() -> FrameBuffer {
+        return FrameBuffer::scaled(1);
+    }
+
+    pub fn scaled(scale: u32) -> FrameBuffer {
+        let scale = scale.max(1).min(FRAMEBUFFER_SCALE_MAX);
+        let mut framebuffer = FrameBuffer {
+            pixels: vec![0; (SCREEN_WIDTH * scale * 4 * SCREEN_HEIGHT * scale) as usize],
+            scale: scale,
+        };
+        framebuffer.clear();
+        return framebuffer;
+    }
+
+    pub fn scale(self:&FrameBuffer) -> u32 {
+        return self.scale;
+    }
+
+    pub fn width(self:&FrameBuffer) -> u32 {
+        return SCREEN_WIDTH * self.scale;
+    }
+
+    pub fn height(self:&FrameBuffer) -> u32 {
+        return SCREEN_HEIGHT * self.scale;
+    }
+
+    // bytes per row
+    pub fn pitch(self:&FrameBuffer) -> usize {
+        return self.width() as usize * 4;
+    }
+
+    pub fn clear(self:&mut FrameBuffer) {
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel[0] = 0x00;
+            pixel[1] = 0x00;
+            pixel[2] = 0x00;
+            pixel[3] = 0xff;
+        }
+    }
+
+    // quarter brightness, for pausing and the like
+    pub fn dim(self:&mut FrameBuffer) {
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel[0] >>= 2;
+            pixel[1] >>= 2;
+            pixel[2] >>= 2;
+        }
+    }
+
+    // a bottom up 24 bit .bmp, which needs no encoder
+    pub fn to_bmp(self:&FrameBuffer) -> Vec<u8> {
+        let (width, height, pitch) = (self.width(), self.height(), self.pitch());
+        let row = (width * 3 + 3) as usize & !3;
+        let size = 54 + row * height as usize;
+        let mut bmp = Vec::with_capacity(size);
+        let put = |bmp: &mut Vec<u8>, value: u32, bytes: usize| {
+            for i in 0..bytes {
+                bmp.push((value >> (i * 8)) as u8);
+            }
+        };
+        bmp.extend_from_slice(b"BM");
+        put(&mut bmp, size as u32, 4);
+        put(&mut bmp, 0, 4);
+        put(&mut bmp, 54, 4);
+        put(&mut bmp, 40, 4);
+        put(&mut bmp, width, 4);
+        put(&mut bmp, height, 4);
+        put(&mut bmp, 1, 2);
+        put(&mut bmp, 24, 2);
+        put(&mut bmp, 0, 4);
+        put(&mut bmp, (row * height as usize) as u32, 4);
+        put(&mut bmp, 2835, 4);
+        put(&mut bmp, 2835, 4);
+        put(&mut bmp, 0, 4);
+        put(&mut bmp, 0, 4);
+        for y in (0..height as usize).rev() {
+            let line = &self.pixels[y * pitch..(y + 1) * pitch];
+            for pixel in line.chunks(4) {
+                bmp.push(pixel[2]);
+                bmp.push(pixel[1]);
+                bmp.push(pixel[0]);
+            }
+            for _ in width as usize * 3..row {
+                bmp.push(0);
+            }
+        }
+        return bmp;
+    }
+
+    // the internal pixels a playfield pixel covers, a row at a time
+    fn rows(self:&FrameBuffer, x:u32, y:u32) -> Option<(usize, usize, usize)> {
+        if x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT {
+            return None;
+        }
+        let scale = self.scale as usize;
+        let offset = y as usize * scale * self.pitch() + x as usize * scale * 4;
+        return Some((offset, scale, self.pitch()));
+    }
+
+    pub fn plot(self:&mut FrameBuffer, x:u32, y:u32, entry:PaletteEntry) {
+        if let Some((offset, scale, pitch)) = self.rows(x, y) {
+            for row in 0..scale {
+                let start = offset + row * pitch;
+                for pixel in self.pixels[start..start + scale * 4].chunks_mut(4) {
+                    pixel[0] = entry.r;
+                    pixel[1] = entry.g;
+                    pixel[2] = entry.b;
+                    pixel[3] = entry.a;
+                }
+            }
+        }
+    }
+
+    // half and half with what's already there
+    pub fn blend(self:&mut FrameBuffer, x:u32, y:u32, entry:PaletteEntry) {
+        if let Some((offset, scale, pitch)) = self.rows(x, y) {
+            for row in 0..scale {
+                let start = offset + row * pitch;
+                for pixel in self.pixels[start..start + scale * 4].chunks_mut(4) {
+                    pixel[0] = ((pixel[0] as u16 + entry.r as u16) / 2) as u8;
+                    pixel[1] = ((pixel[1] as u16 + entry.g as u16) / 2) as u8;
+                    pixel[2] = ((pixel[2] as u16 + entry.b as u16) / 2) as u8;
+                }
+            }
+        }
+    }
+}
@@ -13,71 +13,899 @@
 mod common;
 use self::common::*;
 pub use self::common::TileMaps;
+pub use self::common::PaletteSet;
+pub use self::common::PaletteEntry;
+pub use self::common::SCREEN_WIDTH;
+pub use self::common::SCREEN_HEIGHT;
+pub use self::common::TILE_WIDTH;
+pub use self::common::TILE_HEIGHT;
+pub use self::common::TILE_COL_COUNT;
+pub use self::common::TILE_ROW_COUNT;
 
 mod palettes;
 use self::palettes::get_palette;
+pub use self::palettes::BLUE_BARREL_PALETTE;
 
 mod sprites;
 use self::sprites::get_sprite_bitmap;
 
+mod atlas;
+pub use self::atlas::video_atlas_finish;
+pub use self::atlas::video_atlas_load;
+pub use self::atlas::atlas_tile_from_name;
+pub use self::atlas::SPRITE_DIR;
+
 mod tiles;
 use self::tiles::get_tile_bitmap;
+pub use self::tiles::tile_is_ladder;
 
 mod tile_maps;
 
+mod batch;
+use self::batch::*;
+
+mod text;
+pub use self::text::*;
+
+mod debug;
+pub use self::debug::*;
+
+mod window;
+pub use self::window::WindowMode;
+use self::window::*;
+
+mod framebuffer;
+pub use self::framebuffer::FrameBuffer;
+pub use self::framebuffer::FRAMEBUFFER_SCALE_MAX;
+
+use std::cell::RefCell;
+use std::hash::Hash;
+
 use sdl2::Sdl;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::Texture;
+use sdl2::render::TextureCreator;
 use sdl2::render::WindowCanvas;
+use sdl2::video::VideoSubsystem;
+use sdl2::video::WindowContext;
+
+use super::hashing::StateHasher;
+use super::savestate::{Packed, SaveState};
+use super::playfield::*;
+use super::status::*;
+
+thread_local!(
+    static SPR_CNTL:RefCell<[SpriteControlBlock; SPRITE_MAX as usize]> = RefCell::new(SpriteControlBlock::new_control_table());
+    static BG1_CNTL:RefCell<[BackgroundControlBlock; (TILE_ROW_COUNT * TILE_COL_COUNT) as usize]> = RefCell::new(BackgroundControlBlock::new_control_table());
+);
+
+// drawn straight into the framebuffer after the control tables, on the
+// window only -- overlays never reach video_compose() or the golden hashes
+pub type Overlay = fn(&mut FrameBuffer);
+
+thread_local!(
+    static OVERLAYS:RefCell<Vec<Overlay>> = RefCell::new(Vec::new());
+);
+
+// whole-screen effects applied in video_compose() after the control tables:
+// dimming, then captions drawn at full brightness over the top
+#[derive(Clone, Hash)]
+struct Caption {
+    x: u32,
+    y: u32,
+    text: String,
+    palette: u8,
+}
+
+#[derive(Clone, Hash)]
+struct PostProcess {
+    dim: bool,
+    captions: Vec<Caption>,
+}
+
+thread_local!(
+    static POST_PROCESS:RefCell<PostProcess> = RefCell::new(PostProcess {
+        dim: false,
+        captions: Vec::new()
+    });
+);
+
+// how presenting waits for the display.  adaptive waits like on unless a
+// frame is already late, then tears rather than drop to half rate; drivers
+// without it get plain vsync
+#[derive(Clone, Copy, PartialEq)]
+pub enum Vsync {
+    Off,
+    On,
+    Adaptive,
+}
+
+impl Vsync {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Vsync::Off         => "off",
+            &Vsync::On          => "on",
+            &Vsync::Adaptive    => "adaptive",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Vsync> {
+        [Vsync::Off, Vsync::On, Vsync::Adaptive].iter().find(|vsync| vsync.name() == name).cloned()
+    }
+}
+
+// a window and the framebuffer that gets presented to it
+struct SwapTarget {
+    // the framebuffer's texture, made once and streamed into every frame
+    // rather than made & thrown away each time; remade if the size changes
+    texture: Option<Texture<'static>>,
+    // leaked on purpose, one a window, so the texture can sit next to the
+    // canvas it belongs to
+    texture_creator: &'static TextureCreator<WindowContext>,
+    canvas: WindowCanvas,
+    framebuffer: FrameBuffer,
+    // whole multiples only, black round it; the game window is, whatever
+    // size it's dragged to, the debug window's stretched
+    whole_scale: bool,
+    // the last present's framebuffer rect, where in the drawable it went
+    // and whether it went upside down; what window_to_framebuffer() undoes
+    shown: Option<(Rect, Rect, bool)>,
+}
+
+impl SwapTarget {
+    // scale is the framebuffer's, the window's size is its own
+    fn open(video_subsystem: &VideoSubsystem, title: &str, placement: &WindowPlacement, scale: u32, vsync: Vsync) -> Result<SwapTarget, String> {
+        use sdl2::video::SwapInterval;
+
+        let mut builder = video_subsystem.window(title, placement.width, placement.height);
+        match placement.position {
+            Some((x, y)) => builder.position(x, y),
+            None => builder.position_centered(),
+        };
+        if placement.borderless {
+            builder.borderless();
+        } else {
+            builder.resizable();
+        }
+        let window = builder
+            .opengl()
+            .allow_highdpi()
+            .build()
+            .map_err(|e| format!("can't open the {} window: {}", title, e))?;
+        let builder = window.into_canvas();
+        let builder = if vsync == Vsync::Off { builder } else { builder.present_vsync() };
+        let canvas = builder
+            .build()
+            .map_err(|e| format!("can't create a renderer: {}", e))?;
+        // the renderer's gl context is current once it's built
+        if vsync == Vsync::Adaptive && !video_subsystem.gl_set_swap_interval(SwapInterval::LateSwapTearing) {
+            warn!("no adaptive vsync on this driver, it's plain vsync");
+        }
+        let texture_creator: &'static TextureCreator<WindowContext> = Box::leak(Box::new(canvas.texture_creator()));
+        return Ok(SwapTarget {
+            texture: None,
+            texture_creator: texture_creator,
+            canvas: canvas,
+            framebuffer: FrameBuffer::scaled(scale),
+            whole_scale: false,
+            shown: None,
+        });
+    }
+
+    fn window_id(self:&SwapTarget) -> u32 {
+        self.canvas.window().id()
+    }
+
+    // a point in the window, in screen coordinates as mouse events have
+    // them, to the framebuffer pixel drawn there: up to drawable pixels,
+    // into the letterbox, down by the multiple or the stretch, and back
+    // round if it was flipped.  None over the bars or before a present
+    fn window_to_framebuffer(self:&SwapTarget, x: i32, y: i32) -> Option<(u32, u32)> {
+        let (src, dst, flipped) = self.shown?;
+        let window = self.canvas.window();
+        let (size, drawable) = (window.size(), window.drawable_size());
+        if size.0 == 0 || size.1 == 0 {
+            return None;
+        }
+        let px = x as i64 * drawable.0 as i64 / size.0 as i64;
+        let py = y as i64 * drawable.1 as i64 / size.1 as i64;
+        let (dx, dy) = (px - dst.x() as i64, py - dst.y() as i64);
+        if dx < 0 || dy < 0 || dx >= dst.width() as i64 || dy >= dst.height() as i64 {
+            return None;
+        }
+        let fx = (dx * src.width() as i64 / dst.width() as i64) as u32;
+        let fy = (dy * src.height() as i64 / dst.height() as i64) as u32;
+        let (fx, fy) = if flipped { (src.width() - 1 - fx, src.height() - 1 - fy) } else { (fx, fy) };
+        return Some((src.x() as u32 + fx, src.y() as u32 + fy));
+    }
+
+    // src is the part of the framebuffer to show, stretched to the window
+    // or at a whole multiple of the window's actual pixels -- the drawable
+    // size, which follows the display's dpi as it moves and the window as
+    // it's resized
+    fn present(self:&mut SwapTarget, src: Option<Rect>, flipped: bool) {
+        use sdl2::pixels::Color;
+        let (width, height) = (self.framebuffer.width(), self.framebuffer.height());
+        let stale = self.texture.as_ref().map_or(true, |t| {
+            let query = t.query();
+            query.width != width || query.height != height
+        });
+        if stale {
+            // ABGR8888 is R, G, B, A in memory on little-endian machines,
+            // which is the layout FrameBuffer uses
+            self.texture = Some(self.texture_creator
+                .create_texture_streaming(PixelFormatEnum::ABGR8888, width, height)
+                .unwrap());
+        }
+        let texture = self.texture.as_mut().unwrap();
+        texture
+            .update(None, &self.framebuffer.pixels, self.framebuffer.pitch())
+            .unwrap();
+        let dst =
+            if self.whole_scale {
+                let scale = self.framebuffer.scale();
+                let (width, height) = src.map_or((self.framebuffer.width(), self.framebuffer.height()), |r| (r.width(), r.height()));
+                let output = self.canvas.output_size().unwrap_or((width, height));
+                self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+                self.canvas.clear();
+                window_whole_rect(output, (width / scale, height / scale))
+            } else {
+                None
+            };
+        self.canvas
+            .copy_ex(texture, src, dst, 0.0, None, flipped, flipped)
+            .expect("Render failed");
+        let output = self.canvas.output_size().unwrap_or((width, height));
+        self.shown = Some((
+            src.unwrap_or_else(|| Rect::new(0, 0, width, height)),
+            dst.unwrap_or_else(|| Rect::new(0, 0, output.0, output.1)),
+            flipped));
+
+        self.canvas.present();
+    }
+}
+
+// the game window, plus the debug panels window in dev mode; each is its
+// own swap target presented once per rendered frame
+pub struct VideoSystem {
+    game: SwapTarget,
+    debug: Option<SwapTarget>,
+    // the icon may only turn up once the asset pack is in
+    icon_set: bool,
+    // window = borderless, so there's no border to put back after capture
+    borderless: bool,
+    // in capture mode: the window's size & position from before, to go
+    // back to
+    capture: Option<((u32, u32), (i32, i32))>,
+}
+
+// capture mode's output is 16:9 at capture_height lines
+const CAPTURE_ASPECT: (u32, u32) = (16, 9);
+
+const WINDOW_TITLE: &str = "Rusty Kong";
+// relative to the base pack, so a mod can ship its own
+pub const WINDOW_ICON: &str = "icon.png";
+
+fn video_load_icon(video: &mut VideoSystem) -> Result<(), String> {
+    use std::io::Read;
+    use sdl2::image::ImageRWops;
+    use sdl2::rwops::RWops;
+    use super::assets::*;
+
+    let path = asset_path(WINDOW_ICON);
+    let mut bytes = Vec::new();
+    match asset_open(&path) {
+        Some(mut file) => file.read_to_end(&mut bytes).map_err(|e| format!("can't read {}: {}", path.display(), e))?,
+        None => return Err(format!("no {}", path.display())),
+    };
+    let icon = RWops::from_bytes(&bytes)?.load_png()?;
+    video.game.canvas.window_mut().set_icon(icon);
+    video.icon_set = true;
+    return Ok(());
+}
+
+// keeps the game window's title (and icon, until it's found) in step with
+// the status; call when it changes
+pub fn video_window_status(video: &mut VideoSystem, status: &GameStatus) {
+    use super::settings::settings_read;
+
+    if !video.icon_set && status.loading.is_none() {
+        if let Err(e) = video_load_icon(video) {
+            debug!("window icon: {}", e);
+        }
+    }
+    let title =
+        if settings_read(|s| s.title_status) {
+            format!("{} - {}", WINDOW_TITLE, status_text(status))
+        } else {
+            WINDOW_TITLE.to_string()
+        };
+    if let Err(e) = video.game.canvas.window_mut().set_title(&title) {
+        warn!("can't set the window title: {}", e);
+    }
+}
+
+// the game window's last frame, overlays and all, as a .bmp
+pub fn video_screenshot(video: &VideoSystem) -> Vec<u8> {
+    video.game.framebuffer.to_bmp()
+}
+
+// debug window pixels per framebuffer pixel
+const DEBUG_WINDOW_SCALE: u32 = 2;
+
+fn video_bg(framebuffer: &mut FrameBuffer) {
+    BG1_CNTL.with(|cell| batch_bg(framebuffer, &cell.borrow()[..]));
+}
+
+// how many sprites the arcade board can fetch for one scanline
+// XXX: from memory of the schematics, not measured
+pub const SPRITES_PER_LINE: u8 = 16;
+
+thread_local!(
+    // which sprite gets first claim on each line this frame
+    static FLICKER_START:RefCell<usize> = RefCell::new(0);
+);
+
+// per sprite, the lines over the scanline limit.  sprites claim lines in
+// index order starting from a different one each frame, so the ones that
+// lose out take turns -- that's the flicker.  drawing order is unchanged.
+fn video_fg_dropped_lines(table: &[SpriteControlBlock], dropped: &mut Vec<u16>) {
+    let start = FLICKER_START.with(|cell| {
+        let mut start = cell.borrow_mut();
+        *start = (*start + 1) % table.len();
+        *start
+    });
+
+    let mut counts = [0u8; SCREEN_HEIGHT as usize];
+    dropped.resize(table.len(), 0);
+    for n in 0..table.len() {
+        let index = (start + n) % table.len();
+        let sprite = &table[index];
+        if !sprite.is_enabled() {
+            continue;
+        }
+        let (_, top) = sprite.get_position();
+        for py in 0..SPRITE_HEIGHT {
+            let y = (top as u32 + py) as usize;
+            if y >= counts.len() {
+                break;
+            }
+            if counts[y] >= SPRITES_PER_LINE {
+                dropped[index] |= 1 << py;
+            } else {
+                counts[y] += 1;
+            }
+        }
+    }
+}
+
+fn video_fg(framebuffer: &mut FrameBuffer) {
+    use super::arena::arena_vec;
+    use super::settings::settings_read;
+
+    let flicker = settings_read(|s| s.sprite_flicker);
+    SPR_CNTL.with(|cell| {
+        let table = cell.borrow();
+        if !flicker {
+            batch_fg(framebuffer, &table[..], None);
+            return;
+        }
+
+        arena_vec(|dropped| {
+            video_fg_dropped_lines(&table[..], dropped);
+            batch_fg(framebuffer, &table[..], Some(&dropped[..]));
+        });
+    });
+}
+
+pub fn video_hash(hasher: &mut StateHasher) {
+    SPR_CNTL.with(|cell| cell.borrow().hash(hasher));
+    BG1_CNTL.with(|cell| cell.borrow().hash(hasher));
+    POST_PROCESS.with(|cell| cell.borrow().hash(hasher));
+}
+
+type SpriteTable = [SpriteControlBlock; SPRITE_MAX as usize];
+type BackgroundTable = [BackgroundControlBlock; (TILE_ROW_COUNT * TILE_COL_COUNT) as usize];
+
+// the two tables are most of a savestate, and most of them is the same
+// from one rewind snapshot to the next
+impl Packed for SpriteTable {
+    fn pack(self:&SpriteTable, out: &mut Vec<u8>) {
+        for sprite in self.iter() {
+            sprite.pack(out);
+        }
+    }
+
+    fn unpack(bytes: &[u8]) -> Option<SpriteTable> {
+        if bytes.len() != SpriteControlBlock::PACKED_SIZE * SPRITE_MAX as usize {
+            return None;
+        }
+        let mut table = SpriteControlBlock::new_control_table();
+        for (sprite, chunk) in table.iter_mut().zip(bytes.chunks(SpriteControlBlock::PACKED_SIZE)) {
+            *sprite = SpriteControlBlock::unpack(chunk);
+        }
+        return Some(table);
+    }
+}
+
+impl Packed for BackgroundTable {
+    fn pack(self:&BackgroundTable, out: &mut Vec<u8>) {
+        for cell in self.iter() {
+            cell.pack(out);
+        }
+    }
+
+    fn unpack(bytes: &[u8]) -> Option<BackgroundTable> {
+        if bytes.len() != BackgroundControlBlock::PACKED_SIZE * (TILE_ROW_COUNT * TILE_COL_COUNT) as usize {
+            return None;
+        }
+        let mut table = BackgroundControlBlock::new_control_table();
+        for (cell, chunk) in table.iter_mut().zip(bytes.chunks(BackgroundControlBlock::PACKED_SIZE)) {
+            *cell = BackgroundControlBlock::unpack(chunk);
+        }
+        return Some(table);
+    }
+}
+
+pub fn video_save(savestate: &mut SaveState) {
+    SPR_CNTL.with(|cell| savestate.put_packed("sprites", &*cell.borrow()));
+    BG1_CNTL.with(|cell| savestate.put_packed("background", &*cell.borrow()));
+    savestate.put("post_process", POST_PROCESS.with(|cell| cell.borrow().clone()));
+}
+
+pub fn video_restore(savestate: &SaveState) {
+    if let Some(sprites) = savestate.get_packed::<SpriteTable>("sprites") {
+        SPR_CNTL.with(|cell| {*cell.borrow_mut() = sprites;});
+    }
+    if let Some(background) = savestate.get_packed::<BackgroundTable>("background") {
+        BG1_CNTL.with(|cell| {*cell.borrow_mut() = background;});
+    }
+    if let Some(post_process) = savestate.get::<PostProcess>("post_process") {
+        POST_PROCESS.with(|cell| {*cell.borrow_mut() = post_process;});
+    }
+}
+
+// tile number at a background cell, or None when the cell is blank
+pub fn video_bg_tile(col: u32, row: u32) -> Option<u16> {
+    if col >= TILE_COL_COUNT || row >= TILE_ROW_COUNT {
+        return None;
+    }
+    BG1_CNTL.with(|cell| {
+        let bg_cntl = &cell.borrow()[(row * TILE_COL_COUNT + col) as usize];
+        if bg_cntl.is_enabled() {
+            Some(bg_cntl.get_tile())
+        } else {
+            None
+        }
+    })
+}
+
+// sets a background cell; out of range cells are ignored
+pub fn video_bg_put(col: u32, row: u32, tile: u16, palette: u8) {
+    if col >= TILE_COL_COUNT || row >= TILE_ROW_COUNT {
+        return;
+    }
+    BG1_CNTL.with(|cell| {
+        let bg_cntl = &mut cell.borrow_mut()[(row * TILE_COL_COUNT + col) as usize];
+        bg_cntl.tile(tile);
+        bg_cntl.palette(palette);
+        bg_cntl.enable(true);
+        bg_cntl.changed(true);
+    });
+}
+
+pub fn video_bg_erase(col: u32, row: u32) {
+    if col >= TILE_COL_COUNT || row >= TILE_ROW_COUNT {
+        return;
+    }
+    BG1_CNTL.with(|cell| {
+        let bg_cntl = &mut cell.borrow_mut()[(row * TILE_COL_COUNT + col) as usize];
+        bg_cntl.enable(false);
+        bg_cntl.changed(true);
+    });
+}
 
-lazy_static! {
-    static ref SPR_CNTL:[SpriteControlBlock; SPRITE_MAX as usize] = SpriteControlBlock::new_control_table();
-    static ref BG1_CNTL:[BackgroundControlBlock; (TILE_ROW_COUNT * TILE_COL_COUNT) as usize] = BackgroundControlBlock::new_control_table();
+pub fn video_bg_clear() {
+    BG1_CNTL.with(|cell| {*cell.borrow_mut() = BackgroundControlBlock::new_control_table();});
 }
 
-fn video_bg(canvas: &mut WindowCanvas) {
-    // XXX: need Surface that is the background buffer
-    //      only changed tiles get rendered into this buffer
-    //      copy this into canvas
-    for bg_cntl in BG1_CNTL.iter() {
-        bg_cntl.update();
+pub fn video_sprite_set(index: usize, tile: u16, x: u16, y: u16, palette: u8) {
+    if index >= SPRITE_MAX as usize {
+        return;
     }
+    SPR_CNTL.with(|cell| {
+        let spr_cntl = &mut cell.borrow_mut()[index];
+        spr_cntl.tile(tile);
+        spr_cntl.position(x, y);
+        spr_cntl.palette(palette);
+        spr_cntl.enable(true);
+        spr_cntl.changed(true);
+    });
+}
+
+pub fn video_sprite_flip(index: usize, horizontal: bool, vertical: bool) {
+    if index >= SPRITE_MAX as usize {
+        return;
+    }
+    SPR_CNTL.with(|cell| {
+        let spr_cntl = &mut cell.borrow_mut()[index];
+        spr_cntl.horizontal_flip(horizontal);
+        spr_cntl.vertical_flip(vertical);
+        spr_cntl.changed(true);
+    });
+}
+
+pub fn video_sprite_hide(index: usize) {
+    if index >= SPRITE_MAX as usize {
+        return;
+    }
+    SPR_CNTL.with(|cell| {
+        let spr_cntl = &mut cell.borrow_mut()[index];
+        spr_cntl.enable(false);
+        spr_cntl.changed(true);
+    });
+}
+
+pub fn video_sprite_hide_all() {
+    SPR_CNTL.with(|cell| {*cell.borrow_mut() = SpriteControlBlock::new_control_table();});
+}
+
+// what a sprite slot shows, copied out of the control table
+#[derive(Clone, Copy, PartialEq)]
+pub struct SpriteFrame {
+    pub tile: u16,
+    pub x: u16,
+    pub y: u16,
+    pub palette: u8,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
 }
 
-// XXX: structure passed in here should hold WindowCanvas, background buffer, and any other state
-fn video_fg(canvas: &mut WindowCanvas) {
-    for fg_cntl in SPR_CNTL.iter() {
-        // XXX: update internal surface
-        //      copy to canvas
-        fg_cntl.update();
+// None while the slot is hidden
+pub fn video_sprite_frame(index: usize) -> Option<SpriteFrame> {
+    if index >= SPRITE_MAX as usize {
+        return None;
     }
+    SPR_CNTL.with(|cell| {
+        let spr_cntl = &cell.borrow()[index];
+        if !spr_cntl.is_enabled() {
+            return None;
+        }
+        let (x, y) = spr_cntl.get_position();
+        Some(SpriteFrame {
+            tile: spr_cntl.get_tile(),
+            x: x,
+            y: y,
+            palette: spr_cntl.get_palette(),
+            flip_horizontal: spr_cntl.is_horizontally_flipped(),
+            flip_vertical: spr_cntl.is_vertically_flipped(),
+        })
+    })
+}
+
+// a frame from outside the control table, half see-through; for overlays
+pub fn video_draw_translucent(framebuffer: &mut FrameBuffer, frame: &SpriteFrame) {
+    let mut spr_cntl = SpriteControlBlock::new_empty();
+    spr_cntl.tile(frame.tile);
+    spr_cntl.position(frame.x, frame.y);
+    spr_cntl.palette(frame.palette);
+    spr_cntl.horizontal_flip(frame.flip_horizontal);
+    spr_cntl.vertical_flip(frame.flip_vertical);
+    spr_cntl.enable(true);
+    spr_cntl.update_translucent(framebuffer);
+}
+
+pub fn video_dim(dim: bool) {
+    POST_PROCESS.with(|cell| {cell.borrow_mut().dim = dim;});
+}
+
+// x/y in pixels, like text_draw()
+pub fn video_caption_add(x: u32, y: u32, text: &str, palette: u8) {
+    POST_PROCESS.with(|cell| {
+        cell.borrow_mut().captions.push(Caption {
+            x: x,
+            y: y,
+            text: text.to_string(),
+            palette: palette
+        });
+    });
+}
+
+pub fn video_caption_clear() {
+    POST_PROCESS.with(|cell| {cell.borrow_mut().captions.clear();});
+}
+
+fn video_post_process(framebuffer: &mut FrameBuffer) {
+    POST_PROCESS.with(|cell| {
+        let post_process = cell.borrow();
+        if post_process.dim {
+            framebuffer.dim();
+        }
+        for caption in post_process.captions.iter() {
+            text_draw(framebuffer, caption.x, caption.y, &caption.text, caption.palette);
+        }
+    });
 }
 
 pub fn video_set_bg(map: TileMaps) {
 
 }
 
-// XXX: structure passed in here should hold WindowCanvas, background buffer, and any other state
-pub fn video_update(canvas: &mut WindowCanvas) {
-//    canvas.set_draw_color(Color::RGB(0, 0, 0));
-//    canvas.clear();
-    video_bg(canvas);
-    //canvas.copy(background_surface, .....);
+// renders the control tables without touching SDL; video_update() and the
+// headless golden runner both go through here so they can't drift apart
+pub fn video_compose(framebuffer: &mut FrameBuffer) {
+    framebuffer.clear();
+    batch_frame_start();
+    video_bg(framebuffer);
+    video_fg(framebuffer);
+    batch_frame_end();
+    video_post_process(framebuffer);
+}
+
+// the part of the framebuffer that reaches the window, in its own pixels
+fn video_overscan_rect(scale: u32) -> Option<Rect> {
+    use super::settings::settings_read;
+
+    let overscan = settings_read(|s| s.overscan);
+    if overscan == 0 {
+        return None;
+    }
+    return Some(Rect::new(
+        (overscan * scale) as i32,
+        (overscan * scale) as i32,
+        (SCREEN_WIDTH - overscan * 2) * scale,
+        (SCREEN_HEIGHT - overscan * 2) * scale));
+}
 
-    video_fg(canvas);
+// title-safe is this far inside whatever survives the crop
+const SAFE_AREA_INSET: u32 = 8;
 
-    canvas.present();
+// a dotted outline of the title-safe area, drawn after the overlays so it
+// sits over everything; never part of video_compose()
+fn video_safe_area_guide(framebuffer: &mut FrameBuffer) {
+    use super::settings::settings_read;
+
+    let (enabled, overscan) = settings_read(|s| (s.safe_area_guide, s.overscan));
+    if !enabled {
+        return;
+    }
+    let guide = PaletteEntry { r: 0xff, g: 0x00, b: 0xff, a: 0xff };
+    let inset = overscan + SAFE_AREA_INSET;
+    let (left, top) = (inset, inset);
+    let (right, bottom) = (SCREEN_WIDTH - 1 - inset, SCREEN_HEIGHT - 1 - inset);
+    for x in (left..right + 1).filter(|x| x % 2 == 0) {
+        framebuffer.plot(x, top, guide);
+        framebuffer.plot(x, bottom, guide);
+    }
+    for y in (top..bottom + 1).filter(|y| y % 2 == 0) {
+        framebuffer.plot(left, y, guide);
+        framebuffer.plot(right, y, guide);
+    }
 }
 
-pub fn video_init(sdl_context: &Sdl) -> WindowCanvas {
-    // XXX: set up the background buffer and package it with WindowCanvas
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem.window("Rusty Kong", SCREEN_WIDTH * 4, SCREEN_HEIGHT * 4)
-        .position_centered()
-        .opengl()
-        .build()
-        .unwrap();
-    return window
-        .into_canvas()
-        .present_vsync()
-        .build()
-        .unwrap();
+pub fn video_add_overlay(overlay: Overlay) {
+    OVERLAYS.with(|cell| {cell.borrow_mut().push(overlay);});
+}
+
+pub fn video_update(video: &mut VideoSystem) {
+    let game = &mut video.game;
+    video_compose(&mut game.framebuffer);
+    // by index rather than from a copy: nothing adds one mid frame
+    let overlays = OVERLAYS.with(|cell| cell.borrow().len());
+    for index in 0..overlays {
+        if let Some(overlay) = OVERLAYS.with(|cell| cell.borrow().get(index).cloned()) {
+            overlay(&mut game.framebuffer);
+        }
+    }
+    // capture shows the whole picture, nothing drawn over it for a crt
+    let capture = video.capture.is_some();
+    if !capture {
+        video_safe_area_guide(&mut game.framebuffer);
+    }
+
+    // the canvas stretches what's left after the overscan crop to the
+    // window; the camera only decides which way up it goes
+    let flipped = playfield_camera().rotation == Rotation::Flipped;
+    let src = if capture { None } else { video_overscan_rect(game.framebuffer.scale()) };
+    game.present(src, flipped);
+
+    if let Some(ref mut debug) = video.debug {
+        video_debug_compose(&mut debug.framebuffer);
+        debug.present(None, false);
+    }
+}
+
+pub fn video_init(sdl_context: &Sdl) -> Result<VideoSystem, String> {
+    // without it windows scales the whole window up on a high dpi display,
+    // blurring every pixel; it has to be in before the video subsystem is
+    sdl2::hint::set("SDL_WINDOWS_DPI_AWARENESS", "permonitorv2");
+    let video_subsystem = sdl_context.video()?;
+    use super::settings::settings_read;
+
+    let (display, mode) = settings_read(|s| (s.display, s.window));
+    let display = window_display(&video_subsystem, display);
+    let placement = window_place(&video_subsystem, display, mode);
+
+    let scale = settings_read(|s| s.internal_scale);
+    let vsync = settings_read(|s| s.vsync);
+    let mut game = SwapTarget::open(&video_subsystem, WINDOW_TITLE, &placement, scale, vsync)?;
+    game.whole_scale = true;
+    // smaller than 1x and there'd be no whole multiple to letterbox
+    if let Err(e) = game.canvas.window_mut().set_minimum_size(PLAYFIELD.width as u32, PLAYFIELD.height as u32) {
+        warn!("can't set the window's minimum size: {}", e);
+    }
+    if scale > 1 {
+        info!("internal framebuffer {}x{}", SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale);
+    }
+    let mut video = VideoSystem {
+        game: game,
+        debug: None,
+        icon_set: false,
+        borderless: placement.borderless,
+        capture: None,
+    };
+    // a missing icon is noticed when the status first comes through
+    let _ = video_load_icon(&mut video);
+    video_window_fit(&video);
+    if settings_read(|s| s.capture) {
+        video_capture_toggle(&mut video);
+    }
+    return Ok(video);
+}
+
+// capture mode on or off: on, the game window goes borderless at the top
+// left of its display, sized so its drawable is exactly capture_height
+// lines of 16:9 whatever the dpi; off, it goes back where it was
+pub fn video_capture_toggle(video: &mut VideoSystem) {
+    use sdl2::video::WindowPos;
+    use super::settings::settings_read;
+
+    let borderless = video.borderless;
+    let window = video.game.canvas.window_mut();
+    match video.capture.take() {
+        Some((size, position)) => {
+            window.set_bordered(!borderless);
+            if let Err(e) = window.set_size(size.0, size.1) {
+                warn!("capture: can't put the window back to {}x{}: {}", size.0, size.1, e);
+            }
+            window.set_position(WindowPos::Positioned(position.0), WindowPos::Positioned(position.1));
+            info!("capture off");
+        },
+        None => {
+            let height = settings_read(|s| s.capture_height);
+            let output = (height * CAPTURE_ASPECT.0 / CAPTURE_ASPECT.1, height);
+            let (size, drawable) = (window.size(), window.drawable_size());
+            let screen = (
+                output.0 * size.0 / drawable.0.max(1),
+                output.1 * size.1 / drawable.1.max(1));
+            video.capture = Some((size, window.position()));
+
+            let display = window.display_index().unwrap_or(0);
+            let bounds = window.subsystem().display_bounds(display);
+            window.set_bordered(false);
+            if let Err(e) = window.set_size(screen.0, screen.1) {
+                warn!("capture: can't size the window to {}x{}: {}", screen.0, screen.1, e);
+            }
+            match bounds {
+                Ok(bounds) => {
+                    window.set_position(WindowPos::Positioned(bounds.x()), WindowPos::Positioned(bounds.y()));
+                    if bounds.width() < screen.0 || bounds.height() < screen.1 {
+                        warn!("capture: {}x{} is bigger than display {}, part of it is off screen", output.0, output.1, display);
+                    }
+                },
+                Err(e) => warn!("capture: can't get display {}'s bounds: {}", display, e),
+            }
+            // XXX: a window manager can refuse the size or round it
+            let drawable = window.drawable_size();
+            if drawable != output {
+                warn!("capture: asked for {}x{}, the window's {}x{}", output.0, output.1, drawable.0, drawable.1);
+            }
+            info!("capture on: {}x{}", output.0, output.1);
+        },
+    }
+    video_window_fit(video);
+}
+
+// the camera follows the game window's size: the picture's rescaled and
+// letterboxed by present() as it's drawn, this keeps the playfield mapping
+// to match
+fn video_window_fit(video: &VideoSystem) {
+    let size = video.game.canvas.window().size();
+    let (scale, origin) = window_fit(size);
+    let mut camera = playfield_camera();
+    if camera.scale != scale || camera.origin != origin {
+        info!("window {}x{}, the playfield at {}x", size.0, size.1, scale);
+    }
+    camera.scale = scale;
+    camera.origin = origin;
+    playfield_set_camera(camera);
+}
+
+// a playfield pixel and the tile cell it's in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PlayfieldPoint {
+    pub x: i32,
+    pub y: i32,
+    pub col: u32,
+    pub row: u32,
+}
+
+// what's under the mouse, for the editor & debug tools: a game window
+// point to the playfield pixel shown there, through the dpi, letterbox,
+// overscan crop, internal scale and cocktail flip as last presented
+pub fn video_window_to_playfield(video: &VideoSystem, x: i32, y: i32) -> Option<PlayfieldPoint> {
+    let (fx, fy) = video.game.window_to_framebuffer(x, y)?;
+    let scale = video.game.framebuffer.scale();
+    let (x, y) = ((fx / scale) as i32, (fy / scale) as i32);
+    if !PLAYFIELD.contains(x, y) {
+        return None;
+    }
+    return Some(PlayfieldPoint {
+        x: x,
+        y: y,
+        col: playfield_col(x),
+        row: playfield_row(y),
+    });
+}
+
+// dev mode: a click on the game window logs what's under it
+pub fn video_debug_click(video: &VideoSystem, window_id: u32, x: i32, y: i32) {
+    if video.game.window_id() != window_id {
+        return;
+    }
+    match video_window_to_playfield(video, x, y) {
+        Some(point) => {
+            let tile =
+                match video_bg_tile(point.col, point.row) {
+                    Some(tile) => format!("tile {:#04x}", tile),
+                    None => "blank".to_string(),
+                };
+            info!("playfield {},{}: col {} row {}, {}", point.x, point.y, point.col, point.row, tile);
+        },
+        None => info!("window {},{} is off the playfield", x, y),
+    }
+}
+
+// a window's been resized, by hand or by the window manager
+pub fn video_window_resized(video: &mut VideoSystem, window_id: u32) {
+    if video.game.window_id() == window_id {
+        video_window_fit(video);
+    }
+}
+
+// dev mode only; the game window stays exactly as it would be without it
+pub fn video_debug_open(sdl_context: &Sdl, video: &mut VideoSystem) -> Result<(), String> {
+    let video_subsystem = sdl_context.video()?;
+    let placement = WindowPlacement {
+        position: None,
+        width: SCREEN_WIDTH * DEBUG_WINDOW_SCALE,
+        height: SCREEN_HEIGHT * DEBUG_WINDOW_SCALE,
+        borderless: false,
+    };
+    let debug = SwapTarget::open(
+        &video_subsystem,
+        "Rusty Kong debug",
+        &placement,
+        1,
+        // one window waiting on the display is enough, two halve the rate
+        Vsync::Off)?;
+    info!("debug window open, tab cycles panels");
+    video.debug = Some(debug);
+    return Ok(());
+}
+
+pub fn video_is_debug_window(video: &VideoSystem, window_id: u32) -> bool {
+    video.debug.as_ref().map_or(false, |debug| debug.window_id() == window_id)
+}
+
+// closing the debug window just drops it; returns false for any other
+// window, which the caller treats as quitting
+pub fn video_close_window(video: &mut VideoSystem, window_id: u32) -> bool {
+    if video_is_debug_window(video, window_id) {
+        video.debug = None;
+        info!("debug window closed");
+        return true;
+    }
+    return false;
 }
@@ -10,10 +10,191 @@
 //
 // --------------------------------------------------------------------------
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
 use super::common::*;
 
+pub const PALETTE_MAX: usize = 64;
+
+// XXX: by eye, like the other sprite palettes; the arcade tells the blue
+//      barrel from the brown ones and the fireballs by color alone
+pub const BLUE_BARREL_PALETTE: u8 = 45;
+
+// live changes from the palette editor, (palette, entry) -> color, laid
+// over the arcade set only; the daltonized sets are worked out once at
+// start up and don't follow
+thread_local!(
+    static EDITS:RefCell<HashMap<(u8, usize), PaletteEntry>> = RefCell::new(HashMap::new());
+);
+
+fn arcade_palette(number: u8) -> Palette {
+    let mut palette = PAL_CNTL[number as usize];
+    EDITS.with(|cell| {
+        for (&(edited, entry), &color) in cell.borrow().iter() {
+            if edited == number {
+                palette.entries[entry] = color;
+            }
+        }
+    });
+    return palette;
+}
+
 pub fn get_palette(number:u8) -> Palette {
-    PAL_CNTL[number as usize]
+    use super::super::settings::settings_read;
+
+    match settings_read(|s| s.palette_set) {
+        PaletteSet::Arcade          => arcade_palette(number),
+        PaletteSet::Deuteranopia    => DEUTERANOPIA_PAL_CNTL[number as usize],
+        PaletteSet::Protanopia      => PROTANOPIA_PAL_CNTL[number as usize],
+        PaletteSet::Tritanopia      => TRITANOPIA_PAL_CNTL[number as usize],
+    }
+}
+
+// sprites in these colors get hatched over in the colorblind sets, so the
+// blue barrel reads by its pattern as well
+pub fn palette_is_hatched(number: u8) -> bool {
+    use super::super::settings::settings_read;
+
+    number == BLUE_BARREL_PALETTE && settings_read(|s| s.palette_set) != PaletteSet::Arcade
+}
+
+// the arcade colors with any edits, whatever set is being shown
+pub fn palette_edited(number: u8) -> Palette {
+    arcade_palette(number)
+}
+
+pub fn palette_edit(number: u8, entry: usize, color: PaletteEntry) {
+    EDITS.with(|cell| {cell.borrow_mut().insert((number, entry), color);});
+}
+
+pub fn palette_revert(number: u8, entry: usize) {
+    EDITS.with(|cell| {cell.borrow_mut().remove(&(number, entry));});
+}
+
+pub fn palette_edit_count() -> usize {
+    EDITS.with(|cell| cell.borrow().len())
+}
+
+// the ripper reads a palette sheet as 16 x 16 cells of 50 x 38 pixels,
+// sampling each at 2,2 from its corner; four cells in reading order make a
+// palette
+const SHEET_WIDTH:  u32 = 800;
+const SHEET_HEIGHT: u32 = 600;
+const SHEET_CELL_WIDTH:  u32 = 50;
+const SHEET_CELL_HEIGHT: u32 = 38;
+
+// writes the edited arcade set out as a sheet the ripper can read back in
+// place of donkey-kong-palettes.png
+pub fn palette_export(path: &Path) -> Result<(), String> {
+    use sdl2::image::SaveSurface;
+    use sdl2::pixels::PixelFormatEnum;
+    use sdl2::surface::Surface;
+
+    let pitch = SHEET_WIDTH * 3;
+    let mut pixels = vec![0u8; (pitch * SHEET_HEIGHT) as usize];
+    for y in 0..SHEET_HEIGHT {
+        for x in 0..SHEET_WIDTH {
+            let cell = (y / SHEET_CELL_HEIGHT) * (SHEET_WIDTH / SHEET_CELL_WIDTH) + x / SHEET_CELL_WIDTH;
+            let (number, entry) = (cell as usize / 4, cell as usize % 4);
+            if number >= PALETTE_MAX {
+                continue;
+            }
+            let color = arcade_palette(number as u8).entries[entry];
+            let offset = (y * pitch + x * 3) as usize;
+            pixels[offset]     = color.r;
+            pixels[offset + 1] = color.g;
+            pixels[offset + 2] = color.b;
+        }
+    }
+
+    let surface = Surface::from_data(&mut pixels, SHEET_WIDTH, SHEET_HEIGHT, pitch, PixelFormatEnum::RGB24)?;
+    surface.save(path).map_err(|e| format!("can't write {}: {}", path.display(), e))
+}
+
+// the alternate sets are derived from PAL_CNTL by daltonizing (Fidaner et al.):
+// simulate the deficiency in LMS space, then push the colour information that
+// was lost into channels that are still visible.  because tiles and sprites both
+// go through get_palette, every colour-coded distinction (blue vs. brown barrels,
+// fireballs) survives the swap; the blue barrel is hatched on top.
+const RGB_TO_LMS: [[f32; 3]; 3] = [
+    [17.8824,    43.5161,  4.11935],
+    [ 3.45565,   27.1554,  3.86714],
+    [ 0.0299566,  0.184309, 1.46709],
+];
+
+const LMS_TO_RGB: [[f32; 3]; 3] = [
+    [ 0.0809444479,   -0.130504409,   0.116721066],
+    [-0.0102485335,    0.0540193266, -0.113614708],
+    [-0.000365296938, -0.00412161469, 0.693511405],
+];
+
+const PROTANOPIA_SIM: [[f32; 3]; 3] = [
+    [0.0, 2.02344, -2.52581],
+    [0.0, 1.0,      0.0],
+    [0.0, 0.0,      1.0],
+];
+
+const DEUTERANOPIA_SIM: [[f32; 3]; 3] = [
+    [1.0,      0.0, 0.0],
+    [0.494207, 0.0, 1.24827],
+    [0.0,      0.0, 1.0],
+];
+
+const TRITANOPIA_SIM: [[f32; 3]; 3] = [
+    [ 1.0,      0.0,      0.0],
+    [ 0.0,      1.0,      0.0],
+    [-0.395913, 0.801109, 0.0],
+];
+
+// how the lost information is redistributed into the remaining channels
+const ERROR_SHIFT: [[f32; 3]; 3] = [
+    [0.0, 0.0, 0.0],
+    [0.7, 1.0, 0.0],
+    [0.7, 0.0, 1.0],
+];
+
+fn matrix_apply(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+fn daltonize_entry(sim: &[[f32; 3]; 3], entry: PaletteEntry) -> PaletteEntry {
+    let rgb = [entry.r as f32, entry.g as f32, entry.b as f32];
+    let simulated = matrix_apply(&LMS_TO_RGB, matrix_apply(sim, matrix_apply(&RGB_TO_LMS, rgb)));
+    let error = [rgb[0] - simulated[0], rgb[1] - simulated[1], rgb[2] - simulated[2]];
+    let shift = matrix_apply(&ERROR_SHIFT, error);
+    let channel = |i: usize| (rgb[i] + shift[i]).max(0.0).min(255.0).round() as u8;
+
+    PaletteEntry {
+        r: channel(0),
+        g: channel(1),
+        b: channel(2),
+        a: entry.a
+    }
+}
+
+fn daltonize_palettes(sim: &[[f32; 3]; 3]) -> Vec<Palette> {
+    PAL_CNTL
+        .iter()
+        .map(|palette| {
+            let mut adjusted = *palette;
+            for entry in adjusted.entries.iter_mut() {
+                *entry = daltonize_entry(sim, *entry);
+            }
+            adjusted
+        })
+        .collect()
+}
+
+lazy_static! {
+    static ref DEUTERANOPIA_PAL_CNTL:Vec<Palette> = daltonize_palettes(&DEUTERANOPIA_SIM);
+    static ref PROTANOPIA_PAL_CNTL:Vec<Palette> = daltonize_palettes(&PROTANOPIA_SIM);
+    static ref TRITANOPIA_PAL_CNTL:Vec<Palette> = daltonize_palettes(&TRITANOPIA_SIM);
 }
 
 lazy_static! {
@@ -0,0 +1,125 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+
+use super::common::*;
+use super::framebuffer::FrameBuffer;
+
+// palettes whose color 3 (the glyph ink) reads well on black
+pub const TEXT_PALETTE_BLUE:  u8 = 0;
+pub const TEXT_PALETTE_WHITE: u8 = 1;
+pub const TEXT_PALETTE_RED:   u8 = 2;
+pub const TEXT_PALETTE_GREEN: u8 = 3;
+
+// the visible width of the screen in tiles; the tile map is wider
+pub const TEXT_COL_COUNT: u32 = SCREEN_WIDTH / TILE_WIDTH;
+
+// maps a character onto the arcade character set.  lower case folds to
+// upper, anything the ROM can't draw comes out as a space; it has no
+// accented letters.
+pub fn text_tile(ch: char) -> u16 {
+    let ch = ch.to_ascii_uppercase();
+    match ch {
+        '0'..='9' => 0x00 + (ch as u16 - '0' as u16),
+        'A'..='Z' => 0x11 + (ch as u16 - 'A' as u16),
+        '.'       => 0x2b,
+        '-'       => 0x2c,
+        '\''      => 0x3a,
+        '?'       => 0xfb,
+        _         => 0x10,
+    }
+}
+
+// writes text into the background starting at col/row, clipped at the
+// right edge of the screen
+pub fn video_text(col: u32, row: u32, text: &str, palette: u8) {
+    use super::video_bg_put;
+
+    for (offset, ch) in text.chars().enumerate() {
+        let col = col + offset as u32;
+        if col >= TEXT_COL_COUNT {
+            break;
+        }
+        video_bg_put(col, row, text_tile(ch), palette);
+    }
+}
+
+// the width-column window onto a message crawling right to left, offset
+// columns in: it enters from the right edge, runs off the left and comes
+// round again
+pub fn text_marquee(text: &str, width: u32, offset: u32) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let period = width as usize + chars.len();
+    let start = offset as usize % period;
+    return (0..width as usize)
+        .map(|i| {
+            let index = (start + i) % period;
+            if index < width as usize { ' ' } else { chars[index - width as usize] }
+        })
+        .collect();
+}
+
+// an on/off square wave for prompts, starting on
+#[derive(Clone, Copy)]
+pub struct Blink {
+    on_ticks: u32,
+    off_ticks: u32,
+    ticks: u32,
+}
+
+impl Blink {
+    pub fn new(on_ticks: u32, off_ticks: u32) -> Blink {
+        Blink {
+            on_ticks: on_ticks,
+            off_ticks: off_ticks,
+            ticks: 0,
+        }
+    }
+
+    pub fn visible(&self) -> bool {
+        self.ticks % (self.on_ticks + self.off_ticks).max(1) < self.on_ticks
+    }
+
+    // returns true when the tick flipped visibility
+    pub fn tick(&mut self) -> bool {
+        let before = self.visible();
+        self.ticks = self.ticks.wrapping_add(1);
+        return self.visible() != before;
+    }
+}
+
+// blanks a whole row of the visible screen with spaces
+pub fn video_text_clear_row(row: u32) {
+    use super::video_bg_put;
+
+    for col in 0..TEXT_COL_COUNT {
+        video_bg_put(col, row, text_tile(' '), TEXT_PALETTE_WHITE);
+    }
+}
+
+// draws text directly into a framebuffer at a pixel position, bypassing the
+// background table; for overlays that sit above whatever the game shows
+pub fn text_draw(framebuffer: &mut FrameBuffer, x: u32, y: u32, text: &str, palette: u8) {
+    use super::palettes::get_palette;
+    use super::tiles::get_tile_bitmap;
+
+    let palette = get_palette(palette);
+    for (offset, ch) in text.chars().enumerate() {
+        let bitmap = get_tile_bitmap(text_tile(ch) as u8);
+        let left = x + offset as u32 * TILE_WIDTH;
+        for ty in 0..TILE_HEIGHT {
+            for tx in 0..TILE_WIDTH {
+                let color = bitmap[(ty * TILE_WIDTH + tx) as usize];
+                framebuffer.plot(left + tx, y + ty, palette.entries[color as usize]);
+            }
+        }
+    }
+}
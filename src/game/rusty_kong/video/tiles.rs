@@ -16,6 +16,12 @@ pub fn get_tile_bitmap<'a>(number:u8) -> &'a [u8; (TILE_WIDTH*TILE_HEIGHT) as us
     &TILE_BITMAPS[number as usize]
 }
 
+// 0xc0 is a bare ladder section, 0xc1-0xc7 and 0xd1-0xd7 are the ladder
+// crossing a girder at each of its slope offsets
+pub fn tile_is_ladder(number:u16) -> bool {
+    (number >= 0xc0 && number <= 0xc7) || (number >= 0xd1 && number <= 0xd7)
+}
+
 static TILE_BITMAPS:[[u8; (TILE_WIDTH*TILE_HEIGHT) as usize]; TILE_MAX as usize] = [
     // tile #0
     [0x00,0x00,0x00,0x03,0x03,0x03,0x00,0x00,
@@ -0,0 +1,141 @@
+// --------------------------------------------------------------------------
+//
+// Rusty Kong
+// Copyright (C) 2018 Jeff Panici
+// All rights reserved.
+//
+// This software source file is licensed according to the
+// MIT License.  Refer to the LICENSE file distributed along
+// with this source file to learn more.
+//
+// --------------------------------------------------------------------------
+use sdl2::rect::Rect;
+use sdl2::video::VideoSubsystem;
+
+use rusty_kong::playfield::*;
+
+// windowed is a window the size of a whole multiple of the playfield;
+// borderless covers the whole display, the picture at the biggest whole
+// multiple that fits and black round it
+#[derive(Clone, Copy, PartialEq)]
+pub enum WindowMode {
+    Windowed,
+    Borderless,
+}
+
+impl WindowMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &WindowMode::Windowed   => "windowed",
+            &WindowMode::Borderless => "borderless",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WindowMode> {
+        [WindowMode::Windowed, WindowMode::Borderless].iter().find(|mode| mode.name() == name).cloned()
+    }
+}
+
+// room left round a window for the taskbar, title bar & the like
+const WINDOW_MARGIN: u32 = 64;
+
+// where a window goes, in screen coordinates; None centers it
+pub struct WindowPlacement {
+    pub position: Option<(i32, i32)>,
+    pub width: u32,
+    pub height: u32,
+    pub borderless: bool,
+}
+
+// the display asked for, or the first when there's no such display
+pub fn window_display(video_subsystem: &VideoSubsystem, wanted: u32) -> i32 {
+    let count = video_subsystem.num_video_displays().unwrap_or(1).max(1);
+    let display =
+        if (wanted as i32) < count {
+            wanted as i32
+        } else {
+            warn!("there's no display {}, {} found; using display 0", wanted, count);
+            0
+        };
+    let name = video_subsystem.display_name(display).unwrap_or_default();
+    match video_subsystem.display_dpi(display) {
+        Ok((diagonal, _, _)) => info!("display {} of {}: \"{}\", {:.0} dpi", display, count, name, diagonal),
+        Err(_) => info!("display {} of {}: \"{}\"", display, count, name),
+    }
+    return display;
+}
+
+// places the game window on a display.  sizes are in screen coordinates,
+// which on a high dpi display are fewer than its pixels; present() sorts
+// the pixels out from the drawable size.  the camera's fitted to whatever
+// size the window ends up, see window_fit().
+pub fn window_place(video_subsystem: &VideoSubsystem, display: i32, mode: WindowMode) -> WindowPlacement {
+    let (width, height) = (PLAYFIELD.width as u32, PLAYFIELD.height as u32);
+    let bounds =
+        match video_subsystem.display_bounds(display) {
+            Ok(b) => b,
+            Err(e) => {
+                warn!("can't get display {}'s bounds: {}", display, e);
+                return WindowPlacement {
+                    position: None,
+                    width: width * CAMERA_SCALE_DEFAULT,
+                    height: height * CAMERA_SCALE_DEFAULT,
+                    borderless: false,
+                };
+            },
+        };
+
+    match mode {
+        WindowMode::Borderless => {
+            return WindowPlacement {
+                position: Some((bounds.x(), bounds.y())),
+                width: bounds.width(),
+                height: bounds.height(),
+                borderless: true,
+            };
+        },
+        WindowMode::Windowed => {
+            let room = (bounds.width().saturating_sub(WINDOW_MARGIN), bounds.height().saturating_sub(WINDOW_MARGIN));
+            let scale = (room.0 / width).min(room.1 / height).max(1).min(CAMERA_SCALE_DEFAULT);
+            let (w, h) = (width * scale, height * scale);
+            return WindowPlacement {
+                position: Some((
+                    bounds.x() + (bounds.width() as i32 - w as i32) / 2,
+                    bounds.y() + (bounds.height() as i32 - h as i32) / 2)),
+                width: w,
+                height: h,
+                borderless: false,
+            };
+        },
+    }
+}
+
+// the camera for a window of this size, in screen coordinates: the
+// playfield at the biggest whole multiple that fits, 1x when even that
+// doesn't, and centered, the rest letterbox
+pub fn window_fit(size: (u32, u32)) -> (u32, (i32, i32)) {
+    let (width, height) = (PLAYFIELD.width as u32, PLAYFIELD.height as u32);
+    let scale = (size.0 / width).min(size.1 / height).max(1);
+    let origin = (
+        (size.0 as i32 - (width * scale) as i32).max(0) / 2,
+        (size.1 as i32 - (height * scale) as i32).max(0) / 2);
+    return (scale, origin);
+}
+
+// a picture of playfield pixels at the biggest whole multiple that fits the
+// output's pixels, centered; None stretches it, when even 1x doesn't fit
+pub fn window_whole_rect(output: (u32, u32), picture: (u32, u32)) -> Option<Rect> {
+    if picture.0 == 0 || picture.1 == 0 {
+        return None;
+    }
+    let scale = (output.0 / picture.0).min(output.1 / picture.1);
+    if scale == 0 {
+        return None;
+    }
+    let (w, h) = (picture.0 * scale, picture.1 * scale);
+    return Some(Rect::new(
+        (output.0 - w) as i32 / 2,
+        (output.1 - h) as i32 / 2,
+        w,
+        h));
+}